@@ -0,0 +1,49 @@
+use rye::conversation::{Conversation, short_id};
+
+// `rye alias <id> mywork` names a conversation so `mywork` works wherever a
+// conversation ID does. Without a name it shows the ways to refer to it.
+
+/// Sets, removes, or shows the alias of conversation `id`
+pub async fn run(
+    id: &str,
+    name: Option<&str>,
+    remove: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut conversation =
+        Conversation::load_metadata(id).map_err(|_| format!("No conversation '{}'", id))?;
+    let label = conversation
+        .title
+        .clone()
+        .unwrap_or_else(|| conversation.id.clone());
+
+    if remove {
+        match conversation.alias.take() {
+            Some(alias) => {
+                conversation.set_alias(None).await?;
+                println!("Removed the alias {} from {}", alias, label);
+            }
+            None => println!("{} has no alias", label),
+        }
+        return Ok(());
+    }
+
+    if let Some(name) = name {
+        conversation
+            .set_alias(Some(name.to_string()))
+            .await
+            .map_err(|e| e.to_string())?;
+        println!("{} is now also {}", label, name);
+        return Ok(());
+    }
+
+    println!("{}", label);
+    if let Some(uuid) = &conversation.uuid {
+        println!("  UUID:     {}", uuid);
+        println!("  Short ID: {}", short_id(uuid));
+    }
+    println!(
+        "  Alias:    {}",
+        conversation.alias.as_deref().unwrap_or("none")
+    );
+    Ok(())
+}
@@ -0,0 +1,239 @@
+use crate::status::print_response_footer;
+use rye::conversation::Conversation;
+use rye::hooks;
+use rye::providers::{LLMProvider, collect_with, complete};
+use rye::render::render_markdown;
+use rye::secrets;
+use rye::spinner::Spinner;
+use rye::streaming::stream_and_render_response;
+use rye::tee;
+use rye::templates::{Constraints, Template};
+use rye::usage;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use std::time::Instant;
+
+// `rye ask`: one question, one answer, no chat loop. With `--schema` the
+// answer must be JSON matching a JSON Schema, and with `--template` it may
+// have to follow the template's constraints. Answers that don't are sent
+// back with what's wrong until one passes; only that answer is printed.
+
+/// Rejected answers are retried until this many attempts in total
+const MAX_ATTEMPTS: usize = 3;
+
+/// Keeps the model to bare JSON, overriding the usual markdown instruction
+pub const SCHEMA_SYSTEM_PROMPT: &str = "You are a JSON generator used by scripts. Reply with a single JSON value that matches the JSON Schema you are given, and nothing else: no prose, no markdown, no code fences.";
+
+/// The prompt from the arguments, or stdin when there are none. A template
+/// may not need one.
+pub fn read_prompt(words: &[String], optional: bool) -> Result<String, Box<dyn std::error::Error>> {
+    if !words.is_empty() {
+        return Ok(words.join(" "));
+    }
+    if io::stdin().is_terminal() && optional {
+        return Ok(String::new());
+    }
+    if io::stdin().is_terminal() {
+        return Err("Give a prompt, e.g. `rye ask \"What is a monad?\"`, or pipe one in".into());
+    }
+
+    let mut prompt = String::new();
+    io::stdin().read_to_string(&mut prompt)?;
+    if prompt.trim().is_empty() && !optional {
+        return Err("The prompt on stdin is empty".into());
+    }
+    Ok(prompt)
+}
+
+/// Answers the prompt in `words` (or stdin), filled into `template` if
+/// given, and saves the exchange
+pub async fn run(
+    llm_provider: &dyn LLMProvider,
+    words: &[String],
+    schema: Option<&Path>,
+    template: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let template = template.map(Template::load).transpose()?;
+    let input = read_prompt(words, template.is_some())?;
+    let prompt = match &template {
+        Some(template) => template.fill(&input),
+        None => input,
+    };
+    let Some(prompt) = secrets::check(&prompt)? else {
+        return Err("Cancelled, nothing sent".into());
+    };
+    let constraints = template
+        .as_ref()
+        .map(|template| &template.constraints)
+        .filter(|constraints| !constraints.is_empty());
+
+    let mut conversation = Conversation::new()?;
+    let result = if schema.is_some() || constraints.is_some() {
+        answer_checked(
+            llm_provider,
+            &mut conversation,
+            &prompt,
+            schema,
+            constraints,
+        )
+        .await
+    } else {
+        answer(llm_provider, &mut conversation, &prompt).await
+    };
+    if conversation.messages.is_empty() {
+        let _ = std::fs::remove_file(&conversation.file_path);
+        return result;
+    }
+
+    if let Some((_, first_user_message)) = conversation.messages.first() {
+        match llm_provider.generate_title(first_user_message).await {
+            Ok(title) => conversation.set_title(title).await?,
+            Err(e) => eprintln!("Warning: Could not generate title: {}", e),
+        }
+    }
+    if let Err(e) = hooks::on_save(&conversation.file_path).await {
+        eprintln!("Warning: on_save hook: {}", e);
+    }
+    result
+}
+
+async fn answer(
+    llm_provider: &dyn LLMProvider,
+    conversation: &mut Conversation,
+    prompt: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let messages = [("user".to_string(), prompt.to_string())];
+    let stream = llm_provider.generate_response_stream(&messages).await?;
+    conversation.add_message("user", prompt).await?;
+
+    // Scripts get the raw markdown
+    let response = if io::stdout().is_terminal() {
+        let request_started = Instant::now();
+        let response = stream_and_render_response(stream, None).await?;
+        print_response_footer(request_started.elapsed(), llm_provider)?;
+        response
+    } else {
+        let response = collect_with(stream, |chunk| {
+            print!("{}", chunk);
+            tee::write(chunk);
+        })
+        .await?;
+        tee::finish();
+        println!();
+        response
+    };
+    usage::record(llm_provider, Some(&conversation.id));
+
+    if !response.is_empty() {
+        if let Some(model) = llm_provider.routed_model() {
+            conversation.note_model(model).await?;
+        }
+        conversation.add_message("assistant", &response).await?;
+        if let Err(e) = hooks::post_response(&response, &conversation.file_path, false).await {
+            eprintln!("Warning: post_response hook: {}", e);
+        }
+    }
+    Ok(())
+}
+
+async fn answer_checked(
+    llm_provider: &dyn LLMProvider,
+    conversation: &mut Conversation,
+    prompt: &str,
+    schema_path: Option<&Path>,
+    constraints: Option<&Constraints>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut message = prompt.to_string();
+    let validator = match schema_path {
+        Some(schema_path) => {
+            let schema_text = std::fs::read_to_string(schema_path)
+                .map_err(|e| format!("Could not read {}: {}", schema_path.display(), e))?;
+            let schema: serde_json::Value = serde_json::from_str(&schema_text)
+                .map_err(|e| format!("{} is not valid JSON: {}", schema_path.display(), e))?;
+            let validator = jsonschema::validator_for(&schema).map_err(|e| {
+                format!(
+                    "{} is not a valid JSON Schema: {}",
+                    schema_path.display(),
+                    e
+                )
+            })?;
+            message.push_str(&format!(
+                "\n\nReply with only JSON matching this JSON Schema:\n\n{}",
+                schema_text.trim()
+            ));
+            Some(validator)
+        }
+        None => None,
+    };
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        conversation.add_message("user", &message).await?;
+        let response = collect_json(llm_provider, &conversation.messages).await?;
+        usage::record(llm_provider, Some(&conversation.id));
+        if let Some(model) = llm_provider.routed_model() {
+            conversation.note_model(model).await?;
+        }
+        conversation.add_message("assistant", &response).await?;
+
+        let mut problems = Vec::new();
+        let mut json = None;
+        if let Some(validator) = &validator {
+            match serde_json::from_str::<serde_json::Value>(response.trim()) {
+                Ok(value) => {
+                    problems.extend(
+                        validator
+                            .iter_errors(&value)
+                            .map(|error| format!("{} (at `{}`)", error, error.instance_path)),
+                    );
+                    json = Some(value);
+                }
+                Err(e) => problems.push(format!("Not valid JSON: {}", e)),
+            }
+        }
+        if let Some(constraints) = constraints {
+            problems.extend(constraints.violations(response.trim())?);
+        }
+
+        if problems.is_empty() {
+            match json {
+                Some(value) => println!("{}", serde_json::to_string_pretty(&value)?),
+                None if io::stdout().is_terminal() => render_markdown(&response)?,
+                None => println!("{}", response.trim()),
+            }
+            return Ok(());
+        }
+
+        eprintln!(
+            "Warning: Answer {} of {} was rejected: {}",
+            attempt,
+            MAX_ATTEMPTS,
+            problems.join("; ")
+        );
+        message = format!(
+            "That reply breaks these rules:\n\n- {}\n\nReply again, following them{}.",
+            problems.join("\n- "),
+            if validator.is_some() {
+                ", with only the corrected JSON"
+            } else {
+                ""
+            }
+        );
+    }
+
+    Err(format!("No acceptable answer after {} attempts", MAX_ATTEMPTS).into())
+}
+
+// The whole response, with a spinner while it's written when in a terminal
+async fn collect_json(
+    llm_provider: &dyn LLMProvider,
+    messages: &[(String, String)],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let spinner = io::stdout()
+        .is_terminal()
+        .then(|| Spinner::start("Waiting for JSON..."));
+    let result = complete(llm_provider, messages).await;
+    if let Some(spinner) = spinner {
+        spinner.stop().await;
+    }
+    result
+}
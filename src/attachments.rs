@@ -0,0 +1,103 @@
+use crate::paths;
+use std::io;
+use std::path::PathBuf;
+use tracing::{debug, warn};
+
+// Attached text, like a `/paste` or a piped diff, is kept once in
+// `attachments/<sha256>` in the data directory instead of in every
+// conversation that includes it. The conversation file has a line
+//
+//   [📎 attachment 3f2a9c1e, 1234 characters](attachment:3f2a9c1e...)
+//
+// in its place, and loading the conversation puts the text back, so the
+// model and everything else reading messages see the whole attachment.
+
+/// Attachments shorter than this stay in the conversation file
+const MIN_CHARS: usize = 500;
+
+const LINK_PREFIX: &str = "[📎 attachment ";
+const LINK_TARGET: &str = "](attachment:";
+
+/// An attachment in the store, with the line that stands for it
+pub struct Stored {
+    text: String,
+    reference: String,
+}
+
+fn dir() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("attachments"))
+}
+
+/// Keeps `text` in the store, once however often it's attached. `None` for
+/// text short enough to stay inline.
+pub fn store(text: &str) -> io::Result<Option<Stored>> {
+    let chars = text.chars().count();
+    if chars < MIN_CHARS {
+        return Ok(None);
+    }
+    let hash: String = hmac_sha256::Hash::hash(text.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let dir = dir().ok_or_else(|| io::Error::other("Could not determine the data directory"))?;
+    let path = dir.join(&hash);
+    if !path.exists() {
+        std::fs::create_dir_all(&dir)?;
+        // Written aside and renamed, so a half-written file never has the hash
+        let partial = dir.join(format!("{}.partial", hash));
+        std::fs::write(&partial, text)?;
+        std::fs::rename(&partial, &path)?;
+        debug!(path = %path.display(), chars, "Stored attachment");
+    }
+    Ok(Some(Stored {
+        text: text.to_string(),
+        reference: format!(
+            "{}{}, {} characters{}{})",
+            LINK_PREFIX,
+            &hash[..8],
+            chars,
+            LINK_TARGET,
+            hash
+        ),
+    }))
+}
+
+/// `content` with each stored attachment that's in it verbatim replaced by
+/// its reference, for the conversation file
+pub fn compact(content: &str, stored: &[Stored]) -> String {
+    let mut content = content.to_string();
+    for attachment in stored {
+        content = content.replacen(&attachment.text, &attachment.reference, 1);
+    }
+    content
+}
+
+// The hash a reference line points to
+fn referenced_hash(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix(LINK_PREFIX)?.strip_suffix(')')?;
+    let (_, hash) = rest.split_once(LINK_TARGET)?;
+    (hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())).then_some(hash)
+}
+
+/// `content` with reference lines replaced by the attachments they stand
+/// for. A reference whose attachment is missing, say on a machine the
+/// conversation was synced to, is left as it is.
+pub fn expand(content: &str) -> String {
+    if !content.contains(LINK_TARGET) {
+        return content.to_string();
+    }
+    content
+        .split('\n')
+        .map(|line| {
+            let Some(hash) = referenced_hash(line) else {
+                return line.to_string();
+            };
+            let text = dir().and_then(|dir| std::fs::read_to_string(dir.join(hash)).ok());
+            text.unwrap_or_else(|| {
+                warn!(hash, "Attachment missing from the store");
+                line.to_string()
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,177 @@
+use crate::{config, paths, secrets};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+use uuid::Uuid;
+
+// With `audit.enabled`, every request sent to the model API and every
+// response to it is appended to a JSON Lines file, `audit.jsonl` in the data
+// directory unless `audit.path` says otherwise. A request is written before
+// it's sent; its response, the parsed JSON or a streamed response's content
+// blocks, follows under the same `id`. `audit.redact` picks what's kept:
+// "none" keeps everything, "secrets" (the default) replaces what the
+// secrets check finds, "content" replaces all text with its SHA-256 and
+// length. API keys are never logged. Once a protected conversation has been
+// unlocked, the rest of the process logs as "content", so its decrypted
+// messages never reach the log in plain text.
+
+#[derive(Clone, Copy, PartialEq)]
+enum Redact {
+    None,
+    Secrets,
+    Content,
+}
+
+// Keys whose string values describe a request rather than carry text, kept
+// with `audit.redact = "content"`
+const STRUCTURAL_KEYS: [&str; 12] = [
+    "model",
+    "role",
+    "type",
+    "id",
+    "name",
+    "stop_reason",
+    "custom_id",
+    "tool_use_id",
+    "processing_status",
+    "results_url",
+    "url",
+    "title",
+];
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp: DateTime<Local>,
+    id: &'a str,
+    kind: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    body: Value,
+}
+
+// Set once a protected conversation's messages are in memory
+static PROTECTED_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Logs only hashes of text from now on, called when a protected
+/// conversation is unlocked
+pub fn hide_content() {
+    PROTECTED_OPEN.store(true, Ordering::Relaxed);
+}
+
+fn redact_mode() -> Redact {
+    if PROTECTED_OPEN.load(Ordering::Relaxed) {
+        return Redact::Content;
+    }
+    static MODE: OnceLock<Redact> = OnceLock::new();
+    *MODE.get_or_init(|| match config::get().audit.redact.as_str() {
+        "none" => Redact::None,
+        "secrets" => Redact::Secrets,
+        "content" => Redact::Content,
+        other => {
+            eprintln!(
+                "Warning: Unknown audit.redact '{}', expected none, secrets, or content; redacting secrets",
+                other
+            );
+            Redact::Secrets
+        }
+    })
+}
+
+fn log_path() -> Option<PathBuf> {
+    match &config::get().audit.path {
+        Some(path) => Some(PathBuf::from(path)),
+        None => paths::data_dir().map(|dir| dir.join("audit.jsonl")),
+    }
+}
+
+// `value` with its text redacted as `mode` says
+fn redact(value: Value, mode: Redact, key: Option<&str>) -> Value {
+    match value {
+        Value::String(text) => Value::String(match mode {
+            Redact::None => text,
+            Redact::Secrets => secrets::redact(&text, &secrets::scan(&text)),
+            Redact::Content if key.is_some_and(|key| STRUCTURAL_KEYS.contains(&key)) => text,
+            Redact::Content => {
+                let hash: String = hmac_sha256::Hash::hash(text.as_bytes())
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                format!("[sha256:{} {} chars]", hash, text.chars().count())
+            }
+        }),
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| redact(item, mode, key))
+            .collect(),
+        Value::Object(fields) => fields
+            .into_iter()
+            .map(|(name, field)| {
+                let field = redact(field, mode, Some(&name));
+                (name, field)
+            })
+            .collect(),
+        other => other,
+    }
+}
+
+fn append(entry: &Entry) {
+    let result = (|| {
+        let path = log_path().ok_or("Could not determine the data directory")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true).append(true);
+        // What was sent may well be private
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&path)?.write_all(line.as_bytes())?;
+        Ok::<_, Box<dyn std::error::Error>>(())
+    })();
+    if let Err(e) = result {
+        warn!(error = %e, "Could not write to the audit log");
+    }
+}
+
+/// Logs a request about to be sent to `url` and returns the ID its
+/// response is logged under, `None` when the audit log is off
+pub fn request(url: &str, body: &impl Serialize) -> Option<String> {
+    if !config::get().audit.enabled {
+        return None;
+    }
+    let id = Uuid::new_v4().to_string();
+    let body = serde_json::to_value(body).unwrap_or_default();
+    append(&Entry {
+        timestamp: Local::now(),
+        id: &id,
+        kind: "request",
+        url: Some(url),
+        status: None,
+        body: redact(body, redact_mode(), None),
+    });
+    Some(id)
+}
+
+/// Logs the response to request `id`, with its HTTP status unless none came
+pub fn response(id: Option<&str>, status: Option<u16>, body: Value) {
+    let Some(id) = id else {
+        return;
+    };
+    append(&Entry {
+        timestamp: Local::now(),
+        id,
+        kind: "response",
+        url: None,
+        status,
+        body: redact(body, redact_mode(), None),
+    });
+}
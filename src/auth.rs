@@ -0,0 +1,286 @@
+use crate::{config, paths};
+use chrono::{DateTime, Duration, Local};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use tracing::{debug, info};
+
+// `rye auth login` signs in to a service with the OAuth device authorization
+// flow instead of a manually created token: it shows a code to enter at the
+// service's verification page and polls until the login is approved. Tokens
+// are kept in `auth.json` in the data directory (mode 0600) and refreshed
+// when they expire, for services that issue refresh tokens. The OAuth app is
+// the user's own, its client ID comes from the service's setting.
+//
+// GitHub is the only service so far, used by `/gist`. No LLM provider signs
+// in this way: Anthropic only takes API keys and the local provider needs
+// none, so a provider with device flow (GitHub Models, Gemini) would add its
+// own `Service` along with the provider itself.
+
+/// A service `rye auth login` can sign in to
+pub struct Service {
+    pub name: &'static str,
+    device_code_url: &'static str,
+    token_url: &'static str,
+    scope: &'static str,
+    // The setting holding the OAuth app's client ID
+    client_id_key: &'static str,
+}
+
+pub const SERVICES: [Service; 1] = [Service {
+    name: "github",
+    device_code_url: "https://github.com/login/device/code",
+    token_url: "https://github.com/login/oauth/access_token",
+    scope: "gist",
+    client_id_key: "auth.github_client_id",
+}];
+
+/// The service called `name`
+pub fn service(name: &str) -> Result<&'static Service, String> {
+    SERVICES
+        .iter()
+        .find(|service| service.name == name)
+        .ok_or_else(|| {
+            let names: Vec<&str> = SERVICES.iter().map(|service| service.name).collect();
+            format!(
+                "Unknown service '{}', expected one of: {}",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+impl Service {
+    fn client_id(&self) -> Result<String, String> {
+        let client_id = match self.name {
+            "github" => config::get().auth.github_client_id.clone(),
+            _ => None,
+        };
+        client_id.filter(|id| !id.is_empty()).ok_or_else(|| {
+            format!(
+                "Set {} to the client ID of an OAuth app with device flow enabled, e.g. `rye config set {} <id>`",
+                self.client_id_key, self.client_id_key
+            )
+        })
+    }
+}
+
+/// A token from a login
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Token {
+    pub access_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    // When the access token stops working, `None` if it doesn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Local>>,
+    pub scope: String,
+}
+
+// A token endpoint's reply: a token or an OAuth error code
+#[derive(Deserialize)]
+struct TokenReply {
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+    scope: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+impl TokenReply {
+    fn into_token(self) -> Option<Token> {
+        Some(Token {
+            access_token: self.access_token?,
+            refresh_token: self.refresh_token,
+            expires_at: self
+                .expires_in
+                .map(|seconds| Local::now() + Duration::seconds(seconds)),
+            scope: self.scope.unwrap_or_default(),
+        })
+    }
+}
+
+fn store_path() -> Option<PathBuf> {
+    Some(paths::data_dir()?.join("auth.json"))
+}
+
+fn read_store() -> HashMap<String, Token> {
+    store_path()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_store(tokens: &HashMap<String, Token>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = store_path().ok_or("Could not determine the data directory")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&path)?
+        .write_all(serde_json::to_string_pretty(tokens)?.as_bytes())?;
+    Ok(())
+}
+
+async fn post_form(url: &str, form: &[(&str, &str)]) -> Result<Value, Box<dyn std::error::Error>> {
+    let response = Client::new()
+        .post(url)
+        .header("Accept", "application/json")
+        .header("User-Agent", "rye")
+        .form(form)
+        .send()
+        .await?;
+    let status = response.status();
+    let text = response.text().await?;
+    debug!(%status, "OAuth response received");
+    serde_json::from_str(&text).map_err(|_| format!("{} {}", status, text).into())
+}
+
+/// Signs in to `service` with the device flow, telling the user where to
+/// enter the code, and keeps the token
+pub async fn login(service: &Service) -> Result<Token, Box<dyn std::error::Error>> {
+    let client_id = service.client_id()?;
+    let device = post_form(
+        service.device_code_url,
+        &[("client_id", &client_id), ("scope", service.scope)],
+    )
+    .await?;
+    if let Some(error) = device["error"].as_str() {
+        return Err(format!(
+            "Could not start the login: {}",
+            device["error_description"].as_str().unwrap_or(error)
+        )
+        .into());
+    }
+    let field = |name: &str| {
+        device[name]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("The login reply has no {}", name))
+    };
+    let device_code = field("device_code")?;
+    let user_code = field("user_code")?;
+    let verification_uri = field("verification_uri")?;
+    let mut interval = device["interval"].as_u64().unwrap_or(5);
+    let deadline = std::time::Instant::now()
+        + std::time::Duration::from_secs(device["expires_in"].as_u64().unwrap_or(900));
+
+    println!("Open {} and enter the code {}", verification_uri, user_code);
+    println!("Waiting for the login to be approved...");
+
+    loop {
+        tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+        if std::time::Instant::now() >= deadline {
+            return Err("The code expired before the login was approved".into());
+        }
+        let reply: TokenReply = serde_json::from_value(
+            post_form(
+                service.token_url,
+                &[
+                    ("client_id", &client_id),
+                    ("device_code", &device_code),
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ],
+            )
+            .await?,
+        )?;
+        match reply.error.as_deref() {
+            Some("authorization_pending") => continue,
+            Some("slow_down") => {
+                interval += 5;
+                continue;
+            }
+            Some("expired_token") => {
+                return Err("The code expired before the login was approved".into());
+            }
+            Some("access_denied") => return Err("The login was denied".into()),
+            Some(error) => {
+                return Err(format!(
+                    "Login failed: {}",
+                    reply.error_description.as_deref().unwrap_or(error)
+                )
+                .into());
+            }
+            None => {}
+        }
+        let token = reply
+            .into_token()
+            .ok_or("The login reply has no access token")?;
+        let mut tokens = read_store();
+        tokens.insert(service.name.to_string(), token.clone());
+        write_store(&tokens)?;
+        info!(service = service.name, "Logged in");
+        return Ok(token);
+    }
+}
+
+/// Forgets the token of `service`, returning whether there was one
+pub fn logout(service: &Service) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut tokens = read_store();
+    let removed = tokens.remove(service.name).is_some();
+    if removed {
+        write_store(&tokens)?;
+    }
+    Ok(removed)
+}
+
+/// The kept token of every service logged in to
+pub fn logins() -> HashMap<String, Token> {
+    read_store()
+}
+
+/// A working access token for `service` from an earlier login, refreshed
+/// first when it's about to expire. `None` when not logged in.
+pub async fn token(service: &Service) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut tokens = read_store();
+    let Some(token) = tokens.get(service.name).cloned() else {
+        return Ok(None);
+    };
+    let expiring = token
+        .expires_at
+        .is_some_and(|expires_at| expires_at - Duration::seconds(60) <= Local::now());
+    if !expiring {
+        return Ok(Some(token.access_token));
+    }
+
+    let expired = || {
+        format!(
+            "The {} login expired, log in again with `rye auth login {}`",
+            service.name, service.name
+        )
+    };
+    let refresh_token = token.refresh_token.as_deref().ok_or_else(expired)?;
+    let reply: TokenReply = serde_json::from_value(
+        post_form(
+            service.token_url,
+            &[
+                ("client_id", &service.client_id()?),
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ],
+        )
+        .await?,
+    )?;
+    if let Some(error) = &reply.error {
+        debug!(service = service.name, error, "Token refresh failed");
+        return Err(expired().into());
+    }
+    let mut refreshed = reply.into_token().ok_or_else(expired)?;
+    // Refresh tokens that aren't replaced stay valid
+    if refreshed.refresh_token.is_none() {
+        refreshed.refresh_token = token.refresh_token;
+    }
+    let access_token = refreshed.access_token.clone();
+    tokens.insert(service.name.to_string(), refreshed);
+    write_store(&tokens)?;
+    info!(service = service.name, "Refreshed the access token");
+    Ok(Some(access_token))
+}
@@ -0,0 +1,151 @@
+use crate::config;
+use crossterm::terminal;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Background {
+    Light,
+    Dark,
+}
+
+static BACKGROUND: OnceLock<Background> = OnceLock::new();
+
+/// Resolves the background once at startup, from the `theme.background` config
+/// override or by asking the terminal. Must run before anything enables raw mode;
+/// later calls keep the first answer.
+pub fn init() {
+    if BACKGROUND.get().is_some() {
+        return;
+    }
+    let background = match config::get().theme.background.as_str() {
+        "light" => Background::Light,
+        "dark" => Background::Dark,
+        _ => detect().unwrap_or(Background::Dark),
+    };
+
+    let _ = BACKGROUND.set(background);
+}
+
+pub fn get() -> Background {
+    BACKGROUND.get().copied().unwrap_or(Background::Dark)
+}
+
+fn detect() -> Option<Background> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return None;
+    }
+
+    terminal::enable_raw_mode().ok()?;
+    let response = query_background_color();
+    terminal::disable_raw_mode().ok()?;
+
+    parse_osc11_response(&response?)
+}
+
+#[cfg(unix)]
+fn query_background_color() -> Option<String> {
+    use std::io::Write;
+    use std::os::fd::AsRawFd;
+    use std::time::Duration;
+
+    let mut stdout = io::stdout();
+
+    // OSC 11 asks for the background color. Not every terminal answers it, so
+    // a DA1 query follows, which every terminal answers, to know when to stop reading.
+    write!(stdout, "\x1b]11;?\x1b\\\x1b[c").ok()?;
+    stdout.flush().ok()?;
+
+    // Poll instead of blocking so nothing is left reading (and swallowing
+    // keystrokes) if the terminal never answers
+    let fd = io::stdin().as_raw_fd();
+    let mut response = Vec::new();
+    if read_until_da1(fd, &mut response, Duration::from_millis(500)) {
+        return Some(String::from_utf8_lossy(&response).to_string());
+    }
+
+    // An answer still on its way would turn up as typed input at the first
+    // prompt, so it's waited out a while longer and dropped
+    read_until_da1(fd, &mut response, Duration::from_millis(1000));
+    None
+}
+
+// Reads the terminal's answers into `response` until the DA1 one is complete,
+// or `timeout` passes without it
+#[cfg(unix)]
+fn read_until_da1(
+    fd: std::os::fd::RawFd,
+    response: &mut Vec<u8>,
+    timeout: std::time::Duration,
+) -> bool {
+    use std::time::Instant;
+
+    let deadline = Instant::now() + timeout;
+    while !is_complete_da1_response(response) {
+        let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+            return false;
+        };
+        let mut poll_fd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: poll_fd is a valid pollfd for the duration of the call
+        if unsafe { libc::poll(&mut poll_fd, 1, remaining.as_millis() as libc::c_int) } <= 0 {
+            return false;
+        }
+
+        let mut byte = 0u8;
+        // SAFETY: reads at most one byte into a valid one-byte buffer
+        if unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) } != 1 {
+            return false;
+        }
+        response.push(byte);
+    }
+    true
+}
+
+#[cfg(not(unix))]
+fn query_background_color() -> Option<String> {
+    None
+}
+
+// DA1 responses look like ESC [ ? 6 2 ; 2 2 c
+fn is_complete_da1_response(response: &[u8]) -> bool {
+    let Some(start) = response.windows(3).rposition(|w| w == b"\x1b[?") else {
+        return false;
+    };
+
+    response[start..].ends_with(b"c")
+}
+
+// Parses ESC ] 11 ; rgb:RRRR/GGGG/BBBB (BEL or ST terminated)
+fn parse_osc11_response(response: &str) -> Option<Background> {
+    let start = response.find("rgb:")? + "rgb:".len();
+    let color: String = response[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_hexdigit() || *c == '/')
+        .collect();
+
+    let channels: Vec<f64> = color
+        .split('/')
+        .map(|hex| {
+            let value = u32::from_str_radix(hex, 16).ok()? as f64;
+            let max = (16u32.checked_pow(hex.len() as u32)? - 1) as f64;
+            Some(value / max)
+        })
+        .collect::<Option<_>>()?;
+
+    let [r, g, b] = channels[..] else {
+        return None;
+    };
+
+    // Relative luminance
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+
+    Some(if luminance > 0.5 {
+        Background::Light
+    } else {
+        Background::Dark
+    })
+}
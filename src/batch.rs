@@ -0,0 +1,419 @@
+use chrono::{DateTime, Local};
+use rye::cache;
+use rye::conversation::{Conversation, unique_title};
+use rye::hooks;
+use rye::paths;
+use rye::providers::LLMProvider;
+use rye::providers::anthropic::{AnthropicProvider, BATCH_PRICE_FACTOR, Batch};
+use rye::secrets;
+use rye::spinner::Spinner;
+use rye::symbols::plain;
+use rye::usage::{self, UsageEntry};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// `rye batch submit prompts.jsonl`: every line's prompt goes out in one
+// Message Batch, answered within a day at a reduced price. Lines look like
+//
+//   {"prompt": "Summarize the attached...", "title": "Summary of chapter 1"}
+//
+// with `title` optional. Submitted batches are remembered in the `batches`
+// directory of the data directory until `rye batch fetch` has saved each
+// answer as a conversation tagged `batch`. Without `--async`, submit waits
+// for the batch and fetches it. With `cache.enabled`, prompts answered before
+// are saved from the cache and left out of the batch.
+
+/// Longest wait between status checks while waiting for a batch
+const MAX_POLL: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Line {
+    prompt: String,
+    title: Option<String>,
+}
+
+// What's kept about a submitted batch to turn its results into conversations
+#[derive(Serialize, Deserialize)]
+struct Submitted {
+    id: String,
+    model: String,
+    submitted: DateTime<Local>,
+    file: PathBuf,
+    // Index of the API key it was submitted with, its workspace's
+    #[serde(default)]
+    key: usize,
+    requests: Vec<Request>,
+    // `custom_id`s of answers saved so far, so a fetch that failed partway
+    // doesn't save them again
+    #[serde(default)]
+    saved: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Request {
+    custom_id: String,
+    prompt: String,
+    title: Option<String>,
+}
+
+fn batches_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    Ok(paths::data_dir()
+        .ok_or("Could not determine the data directory")?
+        .join("batches"))
+}
+
+// Submitted batches not fetched yet, oldest first
+fn submitted() -> Result<Vec<Submitted>, Box<dyn std::error::Error>> {
+    let dir = batches_dir()?;
+    let mut batches = Vec::new();
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Ok(batches);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| serde_json::from_str::<Submitted>(&text).map_err(|e| e.to_string()))
+        {
+            Ok(batch) => batches.push(batch),
+            Err(e) => eprintln!("Warning: Skipping {}: {}", path.display(), e),
+        }
+    }
+    batches.sort_by_key(|batch| batch.submitted);
+    Ok(batches)
+}
+
+// Writes `submitted` to its file in the batches directory
+fn write(submitted: &Submitted) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = batches_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(
+        dir.join(format!("{}.json", submitted.id)),
+        serde_json::to_string_pretty(submitted)?,
+    )?;
+    Ok(())
+}
+
+fn find(id: &str) -> Result<Submitted, Box<dyn std::error::Error>> {
+    submitted()?
+        .into_iter()
+        .find(|batch| batch.id == id || batch.id.ends_with(id))
+        .ok_or_else(|| format!("No unfetched batch {}. `rye batch status` lists them.", id).into())
+}
+
+fn read_lines(path: &Path) -> Result<Vec<Request>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let mut requests = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line: Line = serde_json::from_str(line)
+            .map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+        requests.push(Request {
+            custom_id: format!("request-{}", i + 1),
+            prompt: line.prompt,
+            title: line.title,
+        });
+    }
+    if requests.is_empty() {
+        return Err(format!("{} has no prompts", path.display()).into());
+    }
+    Ok(requests)
+}
+
+// The request a prompt is sent as, to look it up in the response cache.
+// Batches offer no tools.
+fn cache_body(llm_provider: &AnthropicProvider, prompt: &str) -> Value {
+    let mut body = llm_provider.request_body(&[("user".to_string(), prompt.to_string())]);
+    if let Some(fields) = body.as_object_mut() {
+        fields.remove("tools");
+    }
+    body
+}
+
+// The title a request's conversation is saved under
+fn title(request: &Request) -> String {
+    request.title.clone().unwrap_or_else(|| {
+        let first_line = request.prompt.lines().next().unwrap_or_default().trim();
+        let mut title: String = first_line.chars().take(60).collect();
+        if title.len() < first_line.len() {
+            title.push('…');
+        }
+        title
+    })
+}
+
+// Saves a prompt and its answer as a conversation tagged `batch` and returns
+// its ID
+async fn save(request: &Request, response: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut conversation = Conversation::new()?;
+    conversation.add_message("user", &request.prompt).await?;
+    conversation.add_message("assistant", response).await?;
+    if let Err(e) = hooks::post_response(response, &conversation.file_path, false).await {
+        eprintln!("Warning: post_response hook: {}", e);
+    }
+    conversation.set_tags(vec!["batch".to_string()]).await?;
+    let title = title(request);
+    let title = unique_title(&title, |n| format!("{} ({})", title, n))?;
+    conversation.set_title(title).await?;
+    if let Err(e) = hooks::on_save(&conversation.file_path).await {
+        eprintln!("Warning: on_save hook: {}", e);
+    }
+    Ok(conversation.id)
+}
+
+fn counts(batch: &Batch) -> String {
+    let counts = &batch.request_counts;
+    let mut parts = vec![format!("{} succeeded", counts.succeeded)];
+    for (count, label) in [
+        (counts.processing, "processing"),
+        (counts.errored, "errored"),
+        (counts.canceled, "canceled"),
+        (counts.expired, "expired"),
+    ] {
+        if count > 0 {
+            parts.push(format!("{} {}", count, label));
+        }
+    }
+    parts.join(", ")
+}
+
+/// Submits the prompts in `file` as a batch, then waits for it and fetches
+/// the results unless `detach`
+pub async fn submit(
+    llm_provider: &AnthropicProvider,
+    file: &Path,
+    detach: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(reason) = usage::budget_exceeded() {
+        return Err(reason.into());
+    }
+    let mut requests = read_lines(file)?;
+    for request in &mut requests {
+        let Some(prompt) = secrets::check(&request.prompt)? else {
+            return Err("Cancelled, nothing sent".into());
+        };
+        request.prompt = prompt;
+    }
+
+    let mut uncached = Vec::new();
+    let mut cached = 0;
+    for request in requests {
+        match cache::lookup(&cache_body(llm_provider, &request.prompt)) {
+            Some(response) => {
+                save(&request, &response).await?;
+                cached += 1;
+            }
+            None => uncached.push(request),
+        }
+    }
+    let requests = uncached;
+    if cached > 0 {
+        println!(
+            "{}",
+            plain(&format!(
+                "♻️  Saved {} answers from the cache as conversations tagged `batch`",
+                cached
+            ))
+        );
+    }
+    if requests.is_empty() {
+        return Ok(());
+    }
+
+    let batch = llm_provider
+        .create_batch(
+            &requests
+                .iter()
+                .map(|request| {
+                    (
+                        request.custom_id.clone(),
+                        vec![("user".to_string(), request.prompt.clone())],
+                    )
+                })
+                .collect::<Vec<_>>(),
+        )
+        .await?;
+
+    let submitted = Submitted {
+        id: batch.id.clone(),
+        model: llm_provider.model().to_string(),
+        submitted: Local::now(),
+        file: file.to_path_buf(),
+        key: batch.key,
+        requests,
+        saved: Vec::new(),
+    };
+    write(&submitted)?;
+    println!(
+        "{}",
+        plain(&format!(
+            "📦 Submitted {} prompts as batch {}",
+            submitted.requests.len(),
+            batch.id
+        ))
+    );
+
+    if detach {
+        println!(
+            "Check on it with `rye batch status` and save the answers with `rye batch fetch`."
+        );
+        return Ok(());
+    }
+
+    // The batch carries on without us, so stopping here loses nothing
+    println!("Waiting for it to finish. Ctrl-C stops waiting; `rye batch fetch` gets it later.");
+    let mut delay = Duration::from_secs(5);
+    loop {
+        let batch = llm_provider.batch(&batch.id, batch.key).await?;
+        if batch.processing_status == "ended" {
+            break;
+        }
+        let spinner = io::stdout().is_terminal().then(|| {
+            Spinner::start(&format!(
+                "Batch {}: {}",
+                batch.processing_status,
+                counts(&batch)
+            ))
+        });
+        tokio::time::sleep(delay).await;
+        if let Some(spinner) = spinner {
+            spinner.stop().await;
+        }
+        delay = (delay * 2).min(MAX_POLL);
+    }
+    fetch_one(llm_provider, submitted).await?;
+    Ok(())
+}
+
+/// Shows the status of batch `id`, or of every unfetched batch
+pub async fn status(
+    llm_provider: &AnthropicProvider,
+    id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let batches = match id {
+        Some(id) => vec![find(id)?],
+        None => submitted()?,
+    };
+    if batches.is_empty() {
+        println!("No batches waiting to be fetched.");
+        return Ok(());
+    }
+    for submitted in batches {
+        let batch = llm_provider.batch(&submitted.id, submitted.key).await?;
+        println!(
+            "{}  {}  {} prompts from {}, submitted {}: {} ({})",
+            submitted.id,
+            submitted.model,
+            submitted.requests.len(),
+            submitted.file.display(),
+            submitted.submitted.format("%Y-%m-%d %H:%M"),
+            batch.processing_status,
+            counts(&batch)
+        );
+    }
+    Ok(())
+}
+
+/// Saves the results of batch `id`, or of every ended batch, as conversations
+pub async fn fetch(
+    llm_provider: &AnthropicProvider,
+    id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let batches = match id {
+        Some(id) => vec![find(id)?],
+        None => submitted()?,
+    };
+    if batches.is_empty() {
+        println!("No batches waiting to be fetched.");
+        return Ok(());
+    }
+    for submitted in batches {
+        fetch_one(llm_provider, submitted).await?;
+    }
+    Ok(())
+}
+
+// Saves an ended batch's answers and forgets it. Batches still running are
+// left for later.
+async fn fetch_one(
+    llm_provider: &AnthropicProvider,
+    mut submitted: Submitted,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let batch = llm_provider.batch(&submitted.id, submitted.key).await?;
+    if batch.processing_status != "ended" {
+        println!(
+            "{}",
+            plain(&format!(
+                "⏳ Batch {} is still {} ({})",
+                batch.id,
+                batch.processing_status,
+                counts(&batch)
+            ))
+        );
+        return Ok(());
+    }
+
+    let mut results: HashMap<String, Result<(String, _), String>> = llm_provider
+        .batch_results(&batch)
+        .await?
+        .into_iter()
+        .map(|result| (result.custom_id, result.outcome))
+        .collect();
+
+    let mut saved = submitted.saved.len();
+    for (i, request) in submitted.requests.iter().enumerate() {
+        if submitted.saved.contains(&request.custom_id) {
+            continue;
+        }
+        let title = title(request);
+        let (response, usage) = match results.remove(&request.custom_id) {
+            Some(Ok(answer)) => answer,
+            Some(Err(e)) => {
+                eprintln!("Warning: Prompt {} ({}) failed: {}", i + 1, title, e);
+                continue;
+            }
+            None => {
+                eprintln!("Warning: Prompt {} ({}) has no result", i + 1, title);
+                continue;
+            }
+        };
+
+        cache::store(&cache_body(llm_provider, &request.prompt), &response);
+        let id = save(request, &response).await?;
+        usage::append(&UsageEntry {
+            timestamp: Local::now(),
+            model: submitted.model.clone(),
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cost: llm_provider.cost(&usage) * BATCH_PRICE_FACTOR,
+            conversation: Some(id),
+            latency: None,
+        });
+        submitted.saved.push(request.custom_id.clone());
+        write(&submitted)?;
+        saved += 1;
+    }
+
+    std::fs::remove_file(batches_dir()?.join(format!("{}.json", submitted.id)))?;
+    println!(
+        "{}",
+        plain(&format!(
+            "✅ Saved {} of {} answers from batch {} as conversations tagged `batch`",
+            saved,
+            submitted.requests.len(),
+            submitted.id
+        ))
+    );
+    Ok(())
+}
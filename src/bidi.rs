@@ -0,0 +1,86 @@
+use crate::render::{display_width, strip_ansi};
+use unicode_bidi::{BidiClass, BidiInfo, bidi_class};
+
+// Most terminals print characters in the order they arrive, so Arabic and
+// Hebrew come out backwards and hug the left margin. With `output.bidi`,
+// rendered lines holding right-to-left text are put in visual order with the
+// Unicode bidi algorithm after wrapping: right-to-left runs are reversed,
+// with brackets mirrored, and lines of right-to-left paragraphs are aligned
+// to the right edge. Such lines lose their styling, as escape sequences
+// can't be reordered along with the text, and table rows are left alone so
+// their columns stay lined up. Terminals that reorder text themselves want
+// this off.
+
+// Characters drawn as their mirror image in right-to-left text
+const MIRRORED: [(char, char); 12] = [
+    ('(', ')'),
+    (')', '('),
+    ('[', ']'),
+    (']', '['),
+    ('{', '}'),
+    ('}', '{'),
+    ('<', '>'),
+    ('>', '<'),
+    ('«', '»'),
+    ('»', '«'),
+    ('‹', '›'),
+    ('›', '‹'),
+];
+
+fn is_rtl(c: char) -> bool {
+    matches!(bidi_class(c), BidiClass::R | BidiClass::AL)
+}
+
+fn mirror(c: char) -> char {
+    MIRRORED
+        .iter()
+        .find(|(from, _)| *from == c)
+        .map_or(c, |(_, to)| *to)
+}
+
+/// Rendered markdown `width` columns wide with its right-to-left lines in
+/// visual order
+pub fn reorder(rendered: &str, width: usize) -> String {
+    if !rendered.chars().any(is_rtl) {
+        return rendered.to_string();
+    }
+    rendered
+        .split('\n')
+        .map(|line| {
+            if line.chars().any(is_rtl) && !line.contains(['│', '|']) {
+                reorder_line(line, width)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn reorder_line(line: &str, width: usize) -> String {
+    let plain = strip_ansi(line);
+    let margin = plain.len() - plain.trim_start().len();
+    let text = plain.trim();
+    let info = BidiInfo::new(text, None);
+    let Some(paragraph) = info.paragraphs.first() else {
+        return line.to_string();
+    };
+
+    let (levels, runs) = info.visual_runs(paragraph, paragraph.range.clone());
+    let mut visual = String::with_capacity(text.len());
+    for run in runs {
+        if levels[run.start].is_rtl() {
+            visual.extend(text[run].chars().rev().map(mirror));
+        } else {
+            visual.push_str(&text[run]);
+        }
+    }
+
+    if paragraph.level.is_rtl() {
+        // Against the right edge, as far from it as the left margin
+        let padding = width.saturating_sub(display_width(&visual) + margin);
+        format!("{}{}", " ".repeat(padding.max(margin)), visual)
+    } else {
+        format!("{}{}", " ".repeat(margin), visual)
+    }
+}
@@ -0,0 +1,338 @@
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+};
+use rye::conversation::{
+    Conversation, ConversationInfo, list_conversations, parse_tags, strip_frontmatter,
+};
+use rye::project;
+use rye::render::render_markdown_text;
+use rye::symbols;
+use unicode_width::UnicodeWidthStr;
+
+enum Mode {
+    Normal,
+    Rename,
+    Tag,
+    ConfirmDelete,
+}
+
+struct Browser {
+    conversations: Vec<ConversationInfo>,
+    list_state: ListState,
+    preview_scroll: u16,
+    mode: Mode,
+    input: String,
+    status: Option<String>,
+    // Set when the user picks a conversation to continue in the chat loop
+    continue_id: Option<String>,
+    running: bool,
+}
+
+impl Browser {
+    fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut browser = Self {
+            conversations: Vec::new(),
+            list_state: ListState::default(),
+            preview_scroll: 0,
+            mode: Mode::Normal,
+            input: String::new(),
+            status: None,
+            continue_id: None,
+            running: true,
+        };
+        browser.refresh()?;
+        Ok(browser)
+    }
+
+    fn refresh(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.conversations = list_conversations()?
+            .into_iter()
+            .filter(|info| project::in_scope(info.project.as_deref()))
+            .collect();
+
+        let selected = match self.list_state.selected() {
+            _ if self.conversations.is_empty() => None,
+            Some(i) => Some(i.min(self.conversations.len() - 1)),
+            None => Some(0),
+        };
+        self.list_state.select(selected);
+
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&ConversationInfo> {
+        self.list_state
+            .selected()
+            .and_then(|i| self.conversations.get(i))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main_area, footer_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main_area);
+
+        self.draw_list(frame, list_area);
+        self.draw_preview(frame, preview_area);
+        self.draw_footer(frame, footer_area);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .conversations
+            .iter()
+            .map(|conv| {
+                let mut spans = vec![Span::raw(
+                    conv.title.clone().unwrap_or_else(|| conv.id.clone()),
+                )];
+                for tag in &conv.tags {
+                    spans.push(Span::styled(
+                        format!(" #{}", tag),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_set(symbols::border())
+                    .title(format!("Conversations ({})", self.conversations.len())),
+            )
+            .highlight_style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(symbols::pick("➤ ", "> "));
+
+        frame.render_stateful_widget(list, area, &mut self.list_state);
+    }
+
+    fn draw_preview(&mut self, frame: &mut Frame, area: Rect) {
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_set(symbols::border())
+            .title(
+                self.selected()
+                    .map(|conv| conv.id.clone())
+                    .unwrap_or_default(),
+            );
+        let inner = block.inner(area);
+
+        let content = self
+            .selected()
+            .and_then(|conv| std::fs::read_to_string(&conv.file_path).ok())
+            .unwrap_or_default();
+        let text = render_markdown_text(strip_frontmatter(&content), inner.width as usize);
+
+        let max_scroll = (text.lines.len() as u16).saturating_sub(inner.height);
+        self.preview_scroll = self.preview_scroll.min(max_scroll);
+
+        frame.render_widget(
+            Paragraph::new(text)
+                .block(block)
+                .scroll((self.preview_scroll, 0)),
+            area,
+        );
+    }
+
+    fn draw_footer(&self, frame: &mut Frame, area: Rect) {
+        let (text, style) = match self.mode {
+            Mode::Rename => (
+                format!("New title: {}", self.input),
+                Style::default().fg(Color::Cyan),
+            ),
+            Mode::Tag => (
+                format!("Tags (comma separated): {}", self.input),
+                Style::default().fg(Color::Cyan),
+            ),
+            Mode::ConfirmDelete => (
+                "Delete this conversation? (y/n)".to_string(),
+                Style::default().fg(Color::Red),
+            ),
+            Mode::Normal => (
+                self.status.clone().unwrap_or_else(|| {
+                    "↑/↓ select · Enter continue · r rename · t tag · a archive · d delete · PgUp/PgDn scroll · q quit".to_string()
+                }),
+                Style::default().fg(Color::DarkGray),
+            ),
+        };
+
+        frame.render_widget(Paragraph::new(symbols::plain(&text)).style(style), area);
+
+        if matches!(self.mode, Mode::Rename | Mode::Tag) {
+            let offset = text.width() as u16;
+            frame.set_cursor_position((area.x + offset.min(area.width), area.y));
+        }
+    }
+
+    async fn handle_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            self.running = false;
+            return Ok(());
+        }
+
+        match self.mode {
+            Mode::Normal => self.handle_normal_key(key).await?,
+            Mode::Rename | Mode::Tag => self.handle_input_key(key).await?,
+            Mode::ConfirmDelete => {
+                if key.code == KeyCode::Char('y') {
+                    self.apply(|conv| async move {
+                        conv.delete().await?;
+                        Ok("Conversation deleted.".to_string())
+                    })
+                    .await?;
+                } else {
+                    self.status = Some("Delete cancelled.".to_string());
+                }
+                self.mode = Mode::Normal;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_normal_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+        self.status = None;
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.running = false,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.list_state.select_previous();
+                self.preview_scroll = 0;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.list_state.select_next();
+                self.preview_scroll = 0;
+            }
+            KeyCode::PageUp => self.preview_scroll = self.preview_scroll.saturating_sub(10),
+            KeyCode::PageDown => self.preview_scroll = self.preview_scroll.saturating_add(10),
+            KeyCode::Enter | KeyCode::Char('c') => {
+                if let Some(conv) = self.selected() {
+                    self.continue_id = Some(conv.id.clone());
+                    self.running = false;
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some(conv) = self.selected() {
+                    self.input = conv.title.clone().unwrap_or_default();
+                    self.mode = Mode::Rename;
+                }
+            }
+            KeyCode::Char('t') => {
+                if let Some(conv) = self.selected() {
+                    self.input = conv.tags.join(", ");
+                    self.mode = Mode::Tag;
+                }
+            }
+            KeyCode::Char('a') => {
+                self.apply(|conv| async move {
+                    let archived_path = conv.archive().await?;
+                    Ok(format!("Archived to: {}", archived_path.display()))
+                })
+                .await?;
+            }
+            KeyCode::Char('d') if self.selected().is_some() => self.mode = Mode::ConfirmDelete,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    async fn handle_input_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+        match key.code {
+            KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => {
+                let input = self.input.trim().to_string();
+                match self.mode {
+                    Mode::Rename if !input.is_empty() => {
+                        self.apply(|mut conv| async move {
+                            conv.set_title(input).await?;
+                            Ok("Conversation renamed.".to_string())
+                        })
+                        .await?
+                    }
+                    Mode::Tag => {
+                        self.apply(|mut conv| async move {
+                            conv.set_tags(parse_tags(&input)).await?;
+                            Ok("Tags updated.".to_string())
+                        })
+                        .await?
+                    }
+                    _ => {}
+                }
+                self.mode = Mode::Normal;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    // Loads the selected conversation, runs an operation on it, and reports the outcome
+    async fn apply<F>(
+        &mut self,
+        operation: impl FnOnce(Conversation) -> F,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: Future<Output = std::io::Result<String>>,
+    {
+        let Some(id) = self.selected().map(|conv| conv.id.clone()) else {
+            return Ok(());
+        };
+
+        let result = match Conversation::load_metadata(&id) {
+            Ok(conv) => operation(conv).await,
+            Err(e) => Err(e),
+        };
+        self.status = Some(match result {
+            Ok(message) => message,
+            Err(e) => format!("Error: {}", e),
+        });
+
+        self.refresh()
+    }
+}
+
+async fn run_browser(
+    terminal: &mut DefaultTerminal,
+    browser: &mut Browser,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while browser.running {
+        terminal.draw(|frame| browser.draw(frame))?;
+
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            browser.handle_key(key).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs the two-pane conversation browser. Returns the ID of the conversation
+/// to continue, if the user picked one.
+pub async fn run() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut browser = Browser::new()?;
+
+    let mut terminal = ratatui::init();
+    let result = run_browser(&mut terminal, &mut browser).await;
+    ratatui::restore();
+
+    result.map(|_| browser.continue_id)
+}
@@ -0,0 +1,213 @@
+use crate::config;
+use crate::paths;
+use crate::providers::{LLMProvider, Latency, ResponseStream, Usage};
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, info};
+
+// With `cache.enabled`, answers to one-shot requests (`ask`, `pipeline`,
+// `compare`, `batch`) are kept in the cache directory, keyed on a hash of the
+// provider and the full request body: model, system prompt, messages and
+// parameters. Sending the same request again returns the saved answer at
+// once, without tokens or cost. Requests that offer tools aren't cached,
+// since their answers depend on what the tools return.
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    created: DateTime<Local>,
+    model: String,
+    response: String,
+}
+
+fn dir() -> Option<PathBuf> {
+    paths::cache_dir().map(|dir| dir.join("responses"))
+}
+
+// The cache file for `body`, `None` for a request that can't be cached
+fn entry_path(body: &Value) -> Option<PathBuf> {
+    if body["tools"]
+        .as_array()
+        .is_some_and(|tools| !tools.is_empty())
+    {
+        return None;
+    }
+    // Streamed or not, the answer is the same
+    let mut body = body.clone();
+    if let Some(fields) = body.as_object_mut() {
+        fields.remove("stream");
+    }
+    let key = format!("{}\n{}", config::get().provider, body);
+    let hash: String = hmac_sha256::Hash::hash(key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Some(dir()?.join(format!("{}.json", hash)))
+}
+
+/// The saved answer to the request `body`, if caching is on and it hasn't
+/// expired
+pub fn lookup(body: &Value) -> Option<String> {
+    let cache = &config::get().cache;
+    if !cache.enabled {
+        return None;
+    }
+    let path = entry_path(body)?;
+    let entry: Entry = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+    let age = Local::now() - entry.created;
+    if cache.max_age_days > 0 && age.num_days() >= cache.max_age_days as i64 {
+        debug!(path = %path.display(), "Cached response expired");
+        let _ = std::fs::remove_file(&path);
+        return None;
+    }
+    info!(path = %path.display(), "Answering from the response cache");
+    Some(entry.response)
+}
+
+/// Saves `response` as the answer to the request `body`, if caching is on
+pub fn store(body: &Value, response: &str) {
+    if !config::get().cache.enabled || response.is_empty() {
+        return;
+    }
+    let Some(path) = entry_path(body) else {
+        return;
+    };
+    let entry = Entry {
+        created: Local::now(),
+        model: body["model"].as_str().unwrap_or_default().to_string(),
+        response: response.to_string(),
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| serde_json::to_string(&entry).map_err(std::io::Error::other))
+        .and_then(|content| std::fs::write(&path, content));
+    // A failed write only costs a request later
+    if let Err(e) = result {
+        debug!(error = %e, path = %path.display(), "Could not save response to the cache");
+    }
+}
+
+/// `llm_provider` answering from the cache when `cache.enabled` is on
+pub fn wrap(llm_provider: Box<dyn LLMProvider>) -> Box<dyn LLMProvider> {
+    if config::get().cache.enabled {
+        Box::new(CachedProvider {
+            inner: llm_provider,
+            hit: Mutex::new(false),
+        })
+    } else {
+        llm_provider
+    }
+}
+
+struct CachedProvider {
+    inner: Box<dyn LLMProvider>,
+    // Whether the last response came from the cache, so it used nothing
+    hit: Mutex<bool>,
+}
+
+#[async_trait]
+impl LLMProvider for CachedProvider {
+    async fn generate_response_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<ResponseStream, Box<dyn std::error::Error>> {
+        let (stream, _) = self.generate_routed_stream(messages).await?;
+        Ok(stream)
+    }
+
+    async fn generate_routed_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<(ResponseStream, Option<String>), Box<dyn std::error::Error>> {
+        let body = self.inner.request_body(messages);
+        if let Some(response) = lookup(&body) {
+            *self.hit.lock().unwrap() = true;
+            return Ok((Box::pin(futures::stream::iter([Ok(response)])), None));
+        }
+        *self.hit.lock().unwrap() = false;
+
+        // Saved once the whole response has arrived without an error
+        let (stream, model) = self.inner.generate_routed_stream(messages).await?;
+        let response = Arc::new(Mutex::new(Some(String::new())));
+        let collected = response.clone();
+        let stream = stream.map(move |chunk| {
+            let mut collected = collected.lock().unwrap();
+            match &chunk {
+                Ok(text) => {
+                    if let Some(response) = collected.as_mut() {
+                        response.push_str(text);
+                    }
+                }
+                Err(_) => *collected = None,
+            }
+            chunk
+        });
+        let save = futures::stream::once(async move {
+            if let Some(response) = response.lock().unwrap().take() {
+                store(&body, &response);
+            }
+            None::<Result<String, Box<dyn std::error::Error + Send>>>
+        })
+        .filter_map(|chunk| async move { chunk });
+        Ok((Box::pin(stream.chain(save)), model))
+    }
+
+    async fn generate_title(
+        &self,
+        user_message: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.inner.generate_title(user_message).await
+    }
+
+    async fn generate_followups(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.inner.generate_followups(messages).await
+    }
+
+    fn request_body(&self, messages: &[(String, String)]) -> Value {
+        self.inner.request_body(messages)
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn routed_model(&self) -> Option<&str> {
+        if *self.hit.lock().unwrap() {
+            None
+        } else {
+            self.inner.routed_model()
+        }
+    }
+
+    fn last_usage(&self) -> Usage {
+        if *self.hit.lock().unwrap() {
+            Usage::default()
+        } else {
+            self.inner.last_usage()
+        }
+    }
+
+    fn last_latency(&self) -> Option<Latency> {
+        if *self.hit.lock().unwrap() {
+            None
+        } else {
+            self.inner.last_latency()
+        }
+    }
+
+    fn context_window(&self) -> u32 {
+        self.inner.context_window()
+    }
+
+    fn cost(&self, usage: &Usage) -> f64 {
+        self.inner.cost(usage)
+    }
+}
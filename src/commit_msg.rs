@@ -0,0 +1,118 @@
+use crate::context;
+use crate::editor;
+use rye::prompt;
+use rye::providers::{LLMProvider, complete};
+use rye::secrets;
+use rye::spinner::Spinner;
+use rye::usage;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use tokio::process::Command;
+
+// `rye commit-msg`: writes a conventional commit message for the staged
+// changes, then lets the message be edited, regenerated, or committed.
+
+/// Asks for nothing but the message, so the reply can go straight to git
+pub const SYSTEM_PROMPT: &str = "You write git commit messages in the Conventional Commits format. Given a staged diff, reply with only the commit message and no other text or code fences: a subject line of the form `type(scope): summary` (types: feat, fix, docs, style, refactor, perf, test, build, ci, chore; scope optional) in the imperative mood and under 72 characters, then, if the change needs explaining, a blank line and a body wrapped at 72 characters that says what changed and why. Add a `BREAKING CHANGE:` footer when the change breaks compatibility.";
+
+async fn generate(
+    llm_provider: &dyn LLMProvider,
+    diff: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let prompt = format!("Write the commit message for this diff:\n\n{}", diff);
+    // Piped output is just the message
+    let spinner = io::stdout()
+        .is_terminal()
+        .then(|| Spinner::start("Writing commit message..."));
+    let result = complete(llm_provider, &[("user".to_string(), prompt)]).await;
+    if let Some(spinner) = spinner {
+        spinner.stop().await;
+    }
+    usage::record(llm_provider, None);
+
+    Ok(result?.trim().to_string())
+}
+
+// Opens the message in the editor and returns the result
+fn edit(path: &Path, message: &str) -> Result<String, Box<dyn std::error::Error>> {
+    std::fs::write(path, message)?;
+    editor::open(path)?;
+
+    // Comment lines are dropped, like git does
+    let edited = std::fs::read_to_string(path)?;
+    Ok(edited
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string())
+}
+
+/// Generates a message for the staged changes. With `commit`, commits with
+/// it straight away; otherwise asks what to do when run in a terminal and
+/// just prints it when not.
+pub async fn run(
+    llm_provider: &dyn LLMProvider,
+    commit: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let diff = context::git(&["diff", "--staged"]).await?;
+    if diff.trim().is_empty() {
+        return Err("Nothing is staged. Stage changes with `git add` first.".into());
+    }
+    let Some(diff) = secrets::check(&diff)? else {
+        return Err("Cancelled, nothing sent".into());
+    };
+
+    let mut message = generate(llm_provider, &diff).await?;
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    if !commit && !interactive {
+        println!("{}", message);
+        return Ok(());
+    }
+
+    let message_file =
+        std::env::temp_dir().join(format!("rye-commit-msg-{}.txt", std::process::id()));
+    if !commit {
+        loop {
+            println!("\n{}\n", message);
+            let choice = prompt::choose(
+                "[c]ommit, [e]dit, [r]egenerate, or [q]uit?",
+                &["commit", "edit", "regenerate", "quit"],
+                // Enter asks again rather than throwing the message away
+                None,
+            )?;
+            match choice {
+                Some('c') => break,
+                Some('e') => match edit(&message_file, &message) {
+                    Ok(edited) if edited.is_empty() => {
+                        eprintln!("Warning: Edited message is empty, keeping the previous one")
+                    }
+                    Ok(edited) => message = edited,
+                    Err(e) => eprintln!("Warning: {}", e),
+                },
+                Some('r') => message = generate(llm_provider, &diff).await?,
+                // End of input quits too
+                _ => {
+                    let _ = std::fs::remove_file(&message_file);
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // git's own hooks and output go straight to the terminal
+    std::fs::write(&message_file, format!("{}\n", message))?;
+    let status = Command::new("git")
+        .args(["commit", "-F"])
+        .arg(&message_file)
+        .status()
+        .await
+        .map_err(|e| format!("Could not run git: {}", e))?;
+    let _ = std::fs::remove_file(&message_file);
+    if !status.success() {
+        println!("\n{}", message);
+        return Err(format!("git commit exited with {}", status).into());
+    }
+    Ok(())
+}
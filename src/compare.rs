@@ -0,0 +1,131 @@
+use crate::ask::read_prompt;
+use crate::status::response_footer;
+use crossterm::{
+    execute,
+    style::{Attribute, Print, SetAttribute},
+};
+use futures::future::join_all;
+use rye::conversation::Conversation;
+use rye::hooks;
+use rye::providers::{LLMProvider, complete};
+use rye::render::render_markdown;
+use rye::secrets;
+use rye::spinner::Spinner;
+use rye::symbols::plain;
+use rye::tee;
+use rye::usage;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+
+// `rye compare -m <model> -m <model> "question"`: the prompt goes to every
+// model at once, the answers are shown one after another under the model's
+// name, and each is saved as its own conversation tagged `compare`, so any
+// of them can be continued.
+
+async fn answer(
+    llm_provider: &dyn LLMProvider,
+    prompt: &str,
+) -> (Result<String, String>, Duration) {
+    let started = Instant::now();
+    let messages = [("user".to_string(), prompt.to_string())];
+    let result = complete(llm_provider, &messages)
+        .await
+        .map_err(|e| e.to_string());
+    (result, started.elapsed())
+}
+
+/// Asks every provider the prompt in `words` (or stdin) concurrently
+pub async fn run(
+    llm_providers: &[Box<dyn LLMProvider>],
+    words: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let prompt = read_prompt(words, false)?;
+    let Some(prompt) = secrets::check(&prompt)? else {
+        return Err("Cancelled, nothing sent".into());
+    };
+    let interactive = io::stdout().is_terminal();
+
+    let spinner =
+        interactive.then(|| Spinner::start(&format!("Asking {} models...", llm_providers.len())));
+    let answers = join_all(
+        llm_providers
+            .iter()
+            .map(|llm_provider| answer(llm_provider.as_ref(), &prompt)),
+    )
+    .await;
+    if let Some(spinner) = spinner {
+        spinner.stop().await;
+    }
+
+    let mut title = None;
+    let mut saved = Vec::new();
+    for (llm_provider, (result, elapsed)) in llm_providers.iter().zip(answers) {
+        let model = llm_provider.model();
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Warning: {} failed: {}", model, e);
+                continue;
+            }
+        };
+
+        if interactive {
+            render_markdown(&format!("## {}\n\n{}", model, response))?;
+            let usage = llm_provider.last_usage();
+            execute!(
+                io::stdout(),
+                SetAttribute(Attribute::Dim),
+                Print(plain(&format!(
+                    "  {} · ${:.4}\n\n",
+                    response_footer(elapsed, llm_provider.as_ref()),
+                    llm_provider.cost(&usage)
+                ))),
+                SetAttribute(Attribute::Reset),
+            )?;
+        } else {
+            println!("## {}\n\n{}\n", model, response.trim_end());
+        }
+        // Answers stream in concurrently, so each is written whole
+        tee::write(&format!("## {}\n\n{}", model, response.trim_end()));
+        tee::finish();
+
+        // One title for the whole comparison, told apart by model
+        if title.is_none() {
+            title = match llm_provider.generate_title(&prompt).await {
+                Ok(title) => Some(title),
+                Err(e) => {
+                    eprintln!("Warning: Could not generate title: {}", e);
+                    Some("Comparison".to_string())
+                }
+            };
+        }
+
+        let mut conversation = Conversation::new()?;
+        usage::record(llm_provider.as_ref(), Some(&conversation.id));
+        conversation.add_message("user", &prompt).await?;
+        conversation.add_message("assistant", &response).await?;
+        if let Err(e) = hooks::post_response(&response, &conversation.file_path, false).await {
+            eprintln!("Warning: post_response hook: {}", e);
+        }
+        conversation.set_tags(vec!["compare".to_string()]).await?;
+        let title = title.as_deref().unwrap_or("Comparison");
+        conversation
+            .set_title(format!("{} ({})", title, model))
+            .await?;
+        if let Err(e) = hooks::on_save(&conversation.file_path).await {
+            eprintln!("Warning: on_save hook: {}", e);
+        }
+        saved.push(conversation.file_path);
+    }
+
+    if saved.is_empty() {
+        return Err("No model answered".into());
+    }
+    if interactive {
+        println!("Answers saved to:");
+        for path in saved {
+            println!("  {}", path.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1015 @@
+use crate::paths;
+use crossterm::style::Color;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+// Settings read from $XDG_CONFIG_HOME/rye/config.toml (or RYE_CONFIG).
+// Each one can be overridden by an environment variable, and those by the
+// command line, see `KEYS`.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub provider: String,
+    // The provider's own default when unset
+    pub model: Option<String>,
+    // The XDG data directory when unset
+    pub conversations_dir: Option<String>,
+    // Markdown file sent as the system prompt instead of the built-in
+    // instruction, relative to the current directory
+    pub system_prompt_file: Option<String>,
+    // Seconds a response must take before a desktop notification, 0 disables
+    pub notify_after: u64,
+    // Language of the user interface, like `de`; `LC_ALL`, `LC_MESSAGES`
+    // or `LANG` when unset, see `i18n.rs`
+    pub locale: Option<String>,
+    pub theme: ThemeConfig,
+    pub images: ImagesConfig,
+    pub code: CodeConfig,
+    pub output: OutputConfig,
+    pub hooks: HooksConfig,
+    pub store: StoreConfig,
+    pub voice: VoiceConfig,
+    pub speech: SpeechConfig,
+    pub context: ContextConfig,
+    pub memory: MemoryConfig,
+    pub project: ProjectConfig,
+    pub budget: BudgetConfig,
+    pub secrets: SecretsConfig,
+    pub queue: QueueConfig,
+    pub followups: FollowupsConfig,
+    pub web_search: WebSearchConfig,
+    pub edits: EditsConfig,
+    pub cache: CacheConfig,
+    pub rotation: RotationConfig,
+    pub audit: AuditConfig,
+    pub api_keys: ApiKeysConfig,
+    pub workspace: WorkspaceConfig,
+    pub auth: AuthConfig,
+    pub local: LocalConfig,
+    pub router: RouterConfig,
+    pub plugins: PluginsConfig,
+    pub tools: ToolsConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            provider: "anthropic".to_string(),
+            model: None,
+            conversations_dir: None,
+            system_prompt_file: None,
+            notify_after: 20,
+            locale: None,
+            theme: ThemeConfig::default(),
+            images: ImagesConfig::default(),
+            code: CodeConfig::default(),
+            output: OutputConfig::default(),
+            hooks: HooksConfig::default(),
+            store: StoreConfig::default(),
+            voice: VoiceConfig::default(),
+            speech: SpeechConfig::default(),
+            context: ContextConfig::default(),
+            memory: MemoryConfig::default(),
+            project: ProjectConfig::default(),
+            budget: BudgetConfig::default(),
+            secrets: SecretsConfig::default(),
+            queue: QueueConfig::default(),
+            followups: FollowupsConfig::default(),
+            web_search: WebSearchConfig::default(),
+            edits: EditsConfig::default(),
+            cache: CacheConfig::default(),
+            rotation: RotationConfig::default(),
+            audit: AuditConfig::default(),
+            api_keys: ApiKeysConfig::default(),
+            workspace: WorkspaceConfig::default(),
+            auth: AuthConfig::default(),
+            local: LocalConfig::default(),
+            router: RouterConfig::default(),
+            plugins: PluginsConfig::default(),
+            tools: ToolsConfig::default(),
+        }
+    }
+}
+
+// Renderer colors and margins. `name` selects a built-in theme, the other
+// fields override individual parts of it.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub name: String,
+    // "auto" asks the terminal, "light" or "dark" skip detection
+    pub background: String,
+    pub headers: Option<String>,
+    pub bold: Option<String>,
+    pub italic: Option<String>,
+    pub inline_code: Option<String>,
+    pub code_block: Option<String>,
+    pub margin: Option<usize>,
+    pub code_margin: Option<usize>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            background: "auto".to_string(),
+            headers: None,
+            bold: None,
+            italic: None,
+            inline_code: None,
+            code_block: None,
+            margin: None,
+            code_margin: None,
+        }
+    }
+}
+
+// Inline image display: "auto" detects the terminal, or one of
+// "kitty", "iterm2", "sixel", "none"
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct ImagesConfig {
+    pub protocol: String,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            protocol: "auto".to_string(),
+        }
+    }
+}
+
+// Fenced code block display
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct CodeConfig {
+    pub line_numbers: bool,
+}
+
+// Streamed responses and code blocks longer than `collapse_lines` are shown
+// as their first and last lines until `/expand`. 0 shows everything.
+// `ascii` replaces emoji, box drawing and arrows with plain ASCII, and
+// `screen_reader` adds linear output without redraws, see `symbols.rs`.
+// `bidi` puts right-to-left text in visual order, see `bidi.rs`.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub collapse_lines: usize,
+    pub ascii: bool,
+    pub screen_reader: bool,
+    pub bidi: bool,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            collapse_lines: 150,
+            ascii: false,
+            screen_reader: false,
+            bidi: true,
+        }
+    }
+}
+
+// Shell commands run around each exchange, see `hooks.rs`. `pre_send` gets
+// the message on stdin and prints the one to send, `post_response` gets the
+// response, `on_save` runs after the conversation file is written.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct HooksConfig {
+    pub pre_send: Option<String>,
+    pub post_response: Option<String>,
+    pub on_save: Option<String>,
+}
+
+// A remote store conversations are synced with, see `sync.rs`. `backend` is
+// "webdav" or "s3"; unset keeps conversations on disk only. For S3, `url` is
+// `https://<endpoint>/<bucket>/<prefix>` and `username`/`password` are the
+// access key ID and secret.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    pub backend: Option<String>,
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub region: String,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            url: None,
+            username: None,
+            password: None,
+            region: "us-east-1".to_string(),
+        }
+    }
+}
+
+// Shell commands for `/voice`, see `voice.rs`. Both get the recording's path
+// in RYE_AUDIO_FILE; `record` runs until interrupted, `transcribe` prints
+// the text.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct VoiceConfig {
+    pub record: String,
+    pub transcribe: Option<String>,
+}
+
+impl Default for VoiceConfig {
+    fn default() -> Self {
+        let record = if cfg!(windows) {
+            "sox -q -t waveaudio -d -r 16000 -c 1 -b 16 %RYE_AUDIO_FILE%"
+        } else {
+            "sox -q -d -r 16000 -c 1 -b 16 \"$RYE_AUDIO_FILE\""
+        };
+        Self {
+            record: record.to_string(),
+            transcribe: None,
+        }
+    }
+}
+
+// Spoken responses, see `speech.rs`. `command` reads one sentence at a time
+// on stdin and returns once it has been spoken.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct SpeechConfig {
+    pub enabled: bool,
+    pub command: String,
+}
+
+impl Default for SpeechConfig {
+    fn default() -> Self {
+        let command = if cfg!(target_os = "macos") {
+            "say"
+        } else if cfg!(windows) {
+            "powershell -NoProfile -Command \"Add-Type -AssemblyName System.Speech; (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak([Console]::In.ReadToEnd())\""
+        } else {
+            "espeak-ng"
+        };
+        Self {
+            enabled: false,
+            command: command.to_string(),
+        }
+    }
+}
+
+// Text attached to the next message by commands like `/paste` is cut off
+// after `max_chars` characters. `confirm` shows what each message will cost
+// in tokens and asks before sending it; a message estimated past
+// `warn_tokens` (0 for no limit) or the context window is held back with a
+// warning either way. See `confirm.rs`. Only the newest
+// `max_history_messages` messages, and only as many as fit in
+// `max_history_tokens`, are sent with each request (0 for all).
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub max_chars: usize,
+    pub confirm: bool,
+    pub warn_tokens: u32,
+    pub max_history_messages: usize,
+    pub max_history_tokens: u32,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            max_chars: 20_000,
+            confirm: false,
+            warn_tokens: 20_000,
+            max_history_messages: 0,
+            max_history_tokens: 0,
+        }
+    }
+}
+
+// `memory.md` next to the config file is sent as part of the system prompt,
+// see `memory.rs`
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct MemoryConfig {
+    pub enabled: bool,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+// `context` sends the project's `.rye/context.md` or `RYE.md` as part of
+// the system prompt, `scope` keeps each git repository's conversations
+// apart. See `project.rs`.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    pub context: bool,
+    pub scope: bool,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        Self {
+            context: true,
+            scope: false,
+        }
+    }
+}
+
+// Spending caps in USD, checked against the usage log before every request,
+// 0 for none. See `usage.rs`.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct BudgetConfig {
+    pub daily: f64,
+    pub monthly: f64,
+}
+
+// `check` scans outgoing messages for keys, credentials and email addresses
+// and offers to redact them, see `secrets.rs`
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    pub check: bool,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        Self { check: true }
+    }
+}
+
+// `enabled` keeps a message that couldn't be sent because the API was
+// unreachable queued in its conversation instead of failing, see `queue.rs`
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct QueueConfig {
+    pub enabled: bool,
+}
+
+// `enabled` asks the model for a few follow-up questions after each chat
+// response, sent by typing their number
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct FollowupsConfig {
+    pub enabled: bool,
+}
+
+// `enabled` lets the model search the web through Anthropic's server-side
+// web search tool, at most `max_uses` times per response. Cited sources are
+// numbered and listed at the end of the response.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebSearchConfig {
+    pub enabled: bool,
+    pub max_uses: u32,
+}
+
+impl Default for WebSearchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_uses: 5,
+        }
+    }
+}
+
+// `enabled` gives the chat model an `edit_file` tool to propose changes to
+// files under the current directory as diffs, reviewed hunk by hunk, see
+// `edits.rs`
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct EditsConfig {
+    pub enabled: bool,
+}
+
+// `enabled` saves the answers to one-shot requests and returns them for
+// identical requests instead of sending them, see `cache.rs`. Saved answers
+// expire after `max_age_days`, 0 keeps them.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    pub max_age_days: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age_days: 30,
+        }
+    }
+}
+
+// A conversation whose file grows past `max_kb` kilobytes, or past
+// `max_turns` exchanges, continues in a new part that starts from a summary,
+// see `rotate.rs`. 0 disables either limit.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct RotationConfig {
+    pub max_kb: u64,
+    pub max_turns: usize,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            max_kb: 2048,
+            max_turns: 0,
+        }
+    }
+}
+
+// `enabled` appends every API request and response to `path`, by default
+// `audit.jsonl` in the data directory, see `audit.rs`. `redact` is "none",
+// "secrets" or "content".
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub enabled: bool,
+    pub path: Option<String>,
+    pub redact: String,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            redact: "secrets".to_string(),
+        }
+    }
+}
+
+// How requests pick among several API keys, see `providers/keys.rs`:
+// "on-429" sticks to a key until it's rate limited, "round-robin" takes the
+// next key for every request
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct ApiKeysConfig {
+    pub rotation: String,
+}
+
+impl Default for ApiKeysConfig {
+    fn default() -> Self {
+        Self {
+            rotation: "on-429".to_string(),
+        }
+    }
+}
+
+// `key_env` names the environment variable holding the API key of the
+// Anthropic workspace to bill usage to, used instead of `ANTHROPIC_API_KEY`
+// and `ANTHROPIC_API_KEYS`
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    pub key_env: Option<String>,
+}
+
+// Client IDs of the OAuth apps `rye auth login` signs in with, see
+// `auth.rs`. The apps need the device flow enabled.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub github_client_id: Option<String>,
+}
+
+// `provider = "local"` runs the GGUF model `model` points to in process,
+// with the `local` cargo feature, see `providers/local.rs`. `tokenizer` is
+// `tokenizer.json` next to the model when unset.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct LocalConfig {
+    pub tokenizer: Option<String>,
+    pub max_tokens: usize,
+    pub temperature: f64,
+}
+
+impl Default for LocalConfig {
+    fn default() -> Self {
+        Self {
+            tokenizer: None,
+            max_tokens: 1024,
+            temperature: 0.8,
+        }
+    }
+}
+
+// With `enabled`, each message of a conversation using the default model is
+// sent to `fast_model` when it looks simple, see `router.rs`: at most
+// `max_chars` characters without code, or with `classify` whatever the fast
+// model itself judges simple.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct RouterConfig {
+    pub enabled: bool,
+    pub fast_model: String,
+    pub max_chars: usize,
+    pub classify: bool,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fast_model: "claude-haiku-4-5".to_string(),
+            max_chars: 400,
+            classify: false,
+        }
+    }
+}
+
+// Seconds a plugin gets to answer a request, `describe` at startup included,
+// before it's killed
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    pub timeout: u64,
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self { timeout: 30 }
+    }
+}
+
+// Rounds of tool calls one response may take before it's stopped with an
+// error, so a model that keeps calling tools can't run up costs forever
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct ToolsConfig {
+    pub max_rounds: u64,
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self { max_rounds: 10 }
+    }
+}
+
+pub const THEMES: [&str; 3] = ["default", "solarized", "monochrome"];
+
+impl ThemeConfig {
+    fn validate(&self) {
+        if !THEMES.contains(&self.name.as_str()) {
+            eprintln!(
+                "Warning: Unknown theme '{}', using 'default'. Available: {}",
+                self.name,
+                THEMES.join(", ")
+            );
+        }
+
+        if !["auto", "light", "dark"].contains(&self.background.as_str()) {
+            eprintln!(
+                "Warning: Unknown theme background '{}', expected auto, light, or dark",
+                self.background
+            );
+        }
+
+        let colors = [
+            &self.headers,
+            &self.bold,
+            &self.italic,
+            &self.inline_code,
+            &self.code_block,
+        ];
+        for color in colors.into_iter().flatten() {
+            if parse_color(color).is_none() {
+                eprintln!("Warning: Unknown theme color '{}'", color);
+            }
+        }
+    }
+}
+
+// Accepts color names ("cyan", "dark_grey"), "#rrggbb", and ANSI values ("208")
+pub fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim().to_lowercase();
+
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        });
+    }
+
+    if let Ok(ansi) = value.parse::<u8>() {
+        return Some(Color::AnsiValue(ansi));
+    }
+
+    let color = match value.replace('-', "_").as_str() {
+        "reset" | "none" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        "grey" | "gray" => Color::Grey,
+        "dark_red" => Color::DarkRed,
+        "dark_green" => Color::DarkGreen,
+        "dark_yellow" => Color::DarkYellow,
+        "dark_blue" => Color::DarkBlue,
+        "dark_magenta" => Color::DarkMagenta,
+        "dark_cyan" => Color::DarkCyan,
+        "dark_grey" | "dark_gray" => Color::DarkGrey,
+        _ => return None,
+    };
+
+    Some(color)
+}
+
+// How a setting's value is parsed, see `parse_value()`. A new kind that
+// parses to another `Value` type needs an arm in `set_in_file()` too.
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    Number,
+    // Dollars and cents
+    Amount,
+    // A fraction, like a sampling temperature
+    Decimal,
+    Flag,
+}
+
+/// Every setting as `section.key`, each overridable with `RYE_SECTION_KEY`
+pub const KEYS: [&str; 68] = [
+    "provider",
+    "model",
+    "conversations_dir",
+    "system_prompt_file",
+    "notify_after",
+    "locale",
+    "theme.name",
+    "theme.background",
+    "theme.headers",
+    "theme.bold",
+    "theme.italic",
+    "theme.inline_code",
+    "theme.code_block",
+    "theme.margin",
+    "theme.code_margin",
+    "images.protocol",
+    "code.line_numbers",
+    "output.collapse_lines",
+    "output.ascii",
+    "output.screen_reader",
+    "output.bidi",
+    "hooks.pre_send",
+    "hooks.post_response",
+    "hooks.on_save",
+    "store.backend",
+    "store.url",
+    "store.username",
+    "store.password",
+    "store.region",
+    "voice.record",
+    "voice.transcribe",
+    "speech.enabled",
+    "speech.command",
+    "context.max_chars",
+    "context.confirm",
+    "context.warn_tokens",
+    "context.max_history_messages",
+    "context.max_history_tokens",
+    "memory.enabled",
+    "project.context",
+    "project.scope",
+    "budget.daily",
+    "budget.monthly",
+    "secrets.check",
+    "queue.enabled",
+    "followups.enabled",
+    "web_search.enabled",
+    "web_search.max_uses",
+    "edits.enabled",
+    "cache.enabled",
+    "cache.max_age_days",
+    "rotation.max_kb",
+    "rotation.max_turns",
+    "audit.enabled",
+    "audit.path",
+    "audit.redact",
+    "api_keys.rotation",
+    "workspace.key_env",
+    "auth.github_client_id",
+    "local.tokenizer",
+    "local.max_tokens",
+    "local.temperature",
+    "router.enabled",
+    "router.fast_model",
+    "router.max_chars",
+    "router.classify",
+    "plugins.timeout",
+    "tools.max_rounds",
+];
+
+fn kind(key: &str) -> Kind {
+    match key {
+        "notify_after"
+        | "theme.margin"
+        | "theme.code_margin"
+        | "output.collapse_lines"
+        | "context.max_chars"
+        | "context.warn_tokens"
+        | "context.max_history_messages"
+        | "context.max_history_tokens"
+        | "web_search.max_uses"
+        | "cache.max_age_days"
+        | "rotation.max_kb"
+        | "rotation.max_turns"
+        | "local.max_tokens"
+        | "router.max_chars"
+        | "plugins.timeout"
+        | "tools.max_rounds" => Kind::Number,
+        "code.line_numbers"
+        | "output.ascii"
+        | "output.screen_reader"
+        | "output.bidi"
+        | "speech.enabled"
+        | "context.confirm"
+        | "memory.enabled"
+        | "project.context"
+        | "project.scope"
+        | "secrets.check"
+        | "queue.enabled"
+        | "followups.enabled"
+        | "web_search.enabled"
+        | "edits.enabled"
+        | "cache.enabled"
+        | "audit.enabled"
+        | "router.enabled"
+        | "router.classify" => Kind::Flag,
+        "budget.daily" | "budget.monthly" => Kind::Amount,
+        "local.temperature" => Kind::Decimal,
+        _ => Kind::Text,
+    }
+}
+
+// Environment variables from before `RYE_<KEY>` existed, which still work
+// but lose to it
+const ENV_ALIASES: [(&str, &str); 3] = [
+    ("model", "ANTHROPIC_MODEL"),
+    ("conversations_dir", "RYE_CONVERSATIONS"),
+    ("notify_after", "RYE_NOTIFY_AFTER"),
+];
+
+/// The environment variable that overrides `key`
+pub fn env_var(key: &str) -> String {
+    format!("RYE_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// Where the effective value of a setting came from
+#[derive(Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File,
+    // The variable's name
+    Env(String),
+    Cli,
+}
+
+/// A setting's effective value, `None` if unset
+pub struct Setting {
+    pub key: &'static str,
+    pub value: Option<Value>,
+    pub source: Source,
+}
+
+fn lookup<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    match key.split_once('.') {
+        Some((section, field)) => table.get(section)?.as_table()?.get(field),
+        None => table.get(key),
+    }
+}
+
+fn insert(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((section, field)) => {
+            let section = table
+                .entry(section)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(section) = section {
+                section.insert(field.to_string(), value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn parse_value(key: &str, raw: &str) -> Result<Value, String> {
+    let raw = raw.trim();
+    match kind(key) {
+        Kind::Text => Ok(Value::String(raw.to_string())),
+        Kind::Number => raw
+            .parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("'{}' expects a number, got '{}'", key, raw)),
+        Kind::Amount => raw
+            .trim_start_matches('$')
+            .parse::<f64>()
+            .ok()
+            .filter(|amount| *amount >= 0.0)
+            .map(Value::Float)
+            .ok_or_else(|| format!("'{}' expects an amount, got '{}'", key, raw)),
+        Kind::Decimal => raw
+            .parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| format!("'{}' expects a decimal number, got '{}'", key, raw)),
+        Kind::Flag => match raw {
+            "true" | "1" | "yes" | "on" => Ok(Value::Boolean(true)),
+            "false" | "0" | "no" | "off" => Ok(Value::Boolean(false)),
+            _ => Err(format!("'{}' expects true or false, got '{}'", key, raw)),
+        },
+    }
+}
+
+fn check_key(key: &str) -> Result<&'static str, String> {
+    KEYS.iter().copied().find(|k| *k == key).ok_or_else(|| {
+        format!(
+            "Unknown setting '{}'. Run `rye config list` to see them all",
+            key
+        )
+    })
+}
+
+static CLI_OVERRIDES: OnceLock<Vec<(&'static str, Value)>> = OnceLock::new();
+
+/// Registers settings given on the command line, which win over the
+/// environment and the config file. Call before the config is first used.
+pub fn set_overrides(overrides: &[(String, String)]) -> Result<(), String> {
+    let overrides = overrides
+        .iter()
+        .map(|(key, raw)| {
+            let key = check_key(key)?;
+            Ok((key, parse_value(key, raw)?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let _ = CLI_OVERRIDES.set(overrides);
+    Ok(())
+}
+
+fn read_file() -> Table {
+    let Some(path) = config_path() else {
+        return Table::new();
+    };
+
+    let Ok(content) = fs::read_to_string(&path) else {
+        return Table::new();
+    };
+
+    content.parse::<Table>().unwrap_or_else(|e| {
+        eprintln!(
+            "Warning: Could not parse config file {}: {}",
+            path.display(),
+            e
+        );
+        Table::new()
+    })
+}
+
+// Defaults, then the config file, then the environment, then the command line
+fn resolve() -> (Table, HashMap<&'static str, Source>) {
+    let mut table = Table::try_from(Config::default()).unwrap_or_default();
+    let mut sources: HashMap<&'static str, Source> =
+        KEYS.iter().map(|key| (*key, Source::Default)).collect();
+
+    let file = read_file();
+    for key in KEYS {
+        if let Some(value) = lookup(&file, key) {
+            insert(&mut table, key, value.clone());
+            sources.insert(key, Source::File);
+        }
+    }
+
+    for key in KEYS {
+        let alias = ENV_ALIASES
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, var)| var.to_string());
+        let Some((var, raw)) = std::iter::once(env_var(key))
+            .chain(alias)
+            .find_map(|var| env::var(&var).ok().map(|raw| (var, raw)))
+        else {
+            continue;
+        };
+
+        match parse_value(key, &raw) {
+            Ok(value) => {
+                insert(&mut table, key, value);
+                sources.insert(key, Source::Env(var));
+            }
+            Err(e) => eprintln!("Warning: Ignoring {}: {}", var, e),
+        }
+    }
+
+    for (key, value) in CLI_OVERRIDES.get().into_iter().flatten() {
+        insert(&mut table, key, value.clone());
+        sources.insert(key, Source::Cli);
+    }
+
+    (table, sources)
+}
+
+/// Every setting with its effective value and where it came from
+pub fn settings() -> Vec<Setting> {
+    let (table, sources) = resolve();
+
+    KEYS.iter()
+        .map(|key| Setting {
+            key,
+            value: lookup(&table, key).cloned(),
+            source: sources[key].clone(),
+        })
+        .collect()
+}
+
+/// Writes `key = value` to the config file, keeping its comments and layout.
+/// Returns the file's path.
+pub fn set_in_file(key: &str, raw: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let key = check_key(key)?;
+    let value = match parse_value(key, raw)? {
+        Value::Integer(n) => toml_edit::value(n),
+        Value::Float(f) => toml_edit::value(f),
+        Value::Boolean(b) => toml_edit::value(b),
+        Value::String(s) => toml_edit::value(s),
+        value => {
+            return Err(format!("Can't write '{}' = {} to the config file", key, value).into());
+        }
+    };
+
+    let path = config_path().ok_or("Could not find the config directory")?;
+    let content = fs::read_to_string(&path).unwrap_or_default();
+    let mut document = content.parse::<toml_edit::DocumentMut>()?;
+
+    match key.split_once('.') {
+        Some((section, field)) => {
+            let section = document
+                .entry(section)
+                .or_insert_with(toml_edit::table)
+                .as_table_mut()
+                .ok_or_else(|| format!("'{}' in {} is not a table", section, path.display()))?;
+            section[field] = value;
+        }
+        None => document[key] = value,
+    }
+
+    // Refuse to write a file that wouldn't load
+    let content = document.to_string();
+    toml::from_str::<Config>(&content)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content)?;
+    Ok(path)
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    if let Ok(custom_path) = env::var("RYE_CONFIG") {
+        return Some(PathBuf::from(custom_path));
+    }
+
+    paths::config_dir().map(|dir| dir.join("config.toml"))
+}
+
+fn load() -> Config {
+    let (table, _) = resolve();
+
+    match Value::Table(table).try_into::<Config>() {
+        Ok(config) => {
+            config.theme.validate();
+            config
+        }
+        Err(e) => {
+            eprintln!("Warning: Invalid settings, using defaults: {}", e);
+            Config::default()
+        }
+    }
+}
+
+// Loaded once on first use
+pub fn get() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(load)
+}
@@ -0,0 +1,354 @@
+use crate::context::Attachments;
+use crate::editor;
+use crate::status::format_tokens;
+use crossterm::style::Stylize;
+use rye::config;
+use rye::memory;
+use rye::persona;
+use rye::project;
+use rye::prompt;
+use rye::providers::{LLMProvider, Usage, complete, estimate_tokens, history_window};
+use rye::spinner::Spinner;
+use rye::symbols::plain;
+use rye::usage;
+
+// With `context.confirm`, the chat loop shows roughly how many tokens a
+// message will send, history and attachments included, and asks before
+// sending it. Counts are estimates from the text, not the provider's.
+// Regardless of it, a message estimated past `context.warn_tokens`, or one
+// that would overflow the context window, is held back by `preflight()`.
+
+// The part of `history` that goes out with `message`, as far as the
+// history window allows
+fn sent_history<'a>(history: &'a [(String, String)], message: &str) -> &'a [(String, String)] {
+    let mut messages = history.to_vec();
+    messages.push(("user".to_string(), message.to_string()));
+    let sent = history_window(&messages).len();
+    &history[history.len() + 1 - sent..]
+}
+
+// Tokens left free for the response when cutting a message to fit the window
+const RESPONSE_RESERVE: u32 = 8_192;
+
+/// The message to send after checking its size: as is when it's small
+/// enough, otherwise cut short, summarized or unchanged as the user picks.
+/// `None` when the user cancels.
+pub async fn preflight(
+    llm_provider: &dyn LLMProvider,
+    history: &[(String, String)],
+    message: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let warn_tokens = config::get().context.warn_tokens;
+    let window = llm_provider.context_window();
+    let history_tokens: u32 = sent_history(history, message)
+        .iter()
+        .map(|(_, content)| estimate_tokens(content))
+        .sum();
+    let tokens = estimate_tokens(message);
+    let overflows = history_tokens + tokens > window;
+    if !overflows && (warn_tokens == 0 || tokens <= warn_tokens) {
+        return Ok(Some(message.to_string()));
+    }
+
+    if overflows {
+        eprintln!(
+            "Warning: This message is ~{} tokens, and with the history (~{}) it's more than the model's {} token context window.",
+            format_tokens(tokens.into()),
+            format_tokens(history_tokens.into()),
+            format_tokens(window.into())
+        );
+    } else {
+        eprintln!(
+            "Warning: This message is ~{} tokens, more than context.warn_tokens ({}).",
+            format_tokens(tokens.into()),
+            format_tokens(warn_tokens.into())
+        );
+    }
+    // Cut to the warning threshold, or to whatever the window has room for
+    let room = window.saturating_sub(history_tokens + RESPONSE_RESERVE);
+    let target = if overflows || warn_tokens == 0 {
+        room
+    } else {
+        warn_tokens.min(room)
+    };
+
+    let question = format!(
+        "[T]runcate to ~{}, [s]ummarize it first, send [a]nyway, or [c]ancel?",
+        format_tokens(target.into())
+    );
+    loop {
+        let choices = ["truncate", "summarize", "anyway", "cancel"];
+        match prompt::choose(&question, &choices, Some('t'))? {
+            Some('t') => return Ok(Some(truncate(message, target))),
+            Some('s') => match summarize_message(llm_provider, message).await {
+                Ok(summary) => {
+                    println!(
+                        "Summarized to ~{} tokens.",
+                        format_tokens(estimate_tokens(&summary).into())
+                    );
+                    return Ok(Some(summary));
+                }
+                Err(e) => eprintln!("Warning: Could not summarize: {}", e),
+            },
+            Some('a') => return Ok(Some(message.to_string())),
+            _ => return Ok(None),
+        }
+    }
+}
+
+// The start of `message`, about `tokens` long, with a note of what was cut
+fn truncate(message: &str, tokens: u32) -> String {
+    let keep = tokens as usize * 4;
+    let total = message.chars().count();
+    if total <= keep {
+        return message.to_string();
+    }
+    let mut truncated: String = message.chars().take(keep).collect();
+    truncated.push_str(&format!(
+        "\n\n[… {} more characters cut before sending]",
+        total - keep
+    ));
+    truncated
+}
+
+// Has the model condense the message in a request of its own
+async fn summarize_message(
+    llm_provider: &dyn LLMProvider,
+    message: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let prompt = format!(
+        "Condense the text below so it can stand in for it in a conversation. Keep any question or request in it word for word, and keep names, numbers, code identifiers and error messages. Respond with ONLY the condensed text.\n\n{}",
+        message
+    );
+    let spinner = Spinner::start("Summarizing...");
+    let result = complete(llm_provider, &[("user".to_string(), prompt)]).await;
+    spinner.stop().await;
+    usage::record(llm_provider, None);
+    let summary = result?;
+    if summary.trim().is_empty() {
+        return Err("The summary came back empty".into());
+    }
+    Ok(summary.trim().to_string())
+}
+
+/// The message to send after showing what it will cost: as is, edited in
+/// `$EDITOR`, or `None` when the user cancels
+pub fn review(
+    llm_provider: &dyn LLMProvider,
+    history: &[(String, String)],
+    message: &str,
+    attachments: usize,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut message = message.to_string();
+    let message_file = std::env::temp_dir().join(format!("rye-message-{}.md", std::process::id()));
+    loop {
+        summarize(llm_provider, history, &message, attachments);
+        let choices = ["send", "edit", "cancel"];
+        match prompt::choose("[S]end, [e]dit, or [c]ancel?", &choices, Some('s'))? {
+            Some('s') => break,
+            Some('e') => {
+                std::fs::write(&message_file, &message)?;
+                let edited = editor::open(&message_file)
+                    .and_then(|_| Ok(std::fs::read_to_string(&message_file)?));
+                match edited {
+                    Ok(edited) if edited.trim().is_empty() => {
+                        eprintln!("Warning: Edited message is empty, keeping the previous one")
+                    }
+                    Ok(edited) => message = edited.trim().to_string(),
+                    Err(e) => eprintln!("Warning: {}", e),
+                }
+            }
+            _ => {
+                let _ = std::fs::remove_file(&message_file);
+                return Ok(None);
+            }
+        }
+    }
+    let _ = std::fs::remove_file(&message_file);
+    Ok(Some(message))
+}
+
+fn summarize(
+    llm_provider: &dyn LLMProvider,
+    history: &[(String, String)],
+    message: &str,
+    attachments: usize,
+) {
+    let mut messages = history.to_vec();
+    messages.push(("user".to_string(), message.to_string()));
+    // The system prompt and tool definitions are whatever the body holds
+    // besides the messages
+    let body = llm_provider.request_body(&messages).to_string();
+    let history = sent_history(history, message);
+    let history_tokens: u32 = history
+        .iter()
+        .map(|(_, content)| estimate_tokens(content))
+        .sum();
+    let message_tokens = estimate_tokens(message);
+    let total = estimate_tokens(&body).max(history_tokens + message_tokens);
+    let other_tokens = total - history_tokens - message_tokens;
+
+    let row = |label: String, tokens: u32| {
+        println!("  {:<28} ~{:>7}", label, format_tokens(tokens.into()));
+    };
+    println!("{}", "About to send".bold());
+    row(
+        format!(
+            "History ({} message{})",
+            history.len(),
+            if history.len() == 1 { "" } else { "s" }
+        ),
+        history_tokens,
+    );
+    row(
+        match attachments {
+            0 => "This message".to_string(),
+            1 => "This message, 1 attachment".to_string(),
+            n => format!("This message, {} attachments", n),
+        },
+        message_tokens,
+    );
+    row("System prompt, tools, etc.".to_string(), other_tokens);
+
+    let window = llm_provider.context_window();
+    let cost = llm_provider.cost(&Usage {
+        input_tokens: total,
+        output_tokens: 0,
+    });
+    println!(
+        "{}",
+        plain(&format!(
+            "  {:<28} ~{:>7} of {} · ~${:.4} input",
+            "Total",
+            format_tokens(total.into()),
+            format_tokens(window.into()),
+            cost
+        ))
+    );
+    if total > window {
+        eprintln!("Warning: This is more than the model's context window and will likely fail.");
+    }
+}
+
+/// Lists what the next request will include, each part with its estimated
+/// tokens, for `/context`
+pub fn show_context(
+    llm_provider: &dyn LLMProvider,
+    history: &[(String, String)],
+    persona: Option<&str>,
+    attachments: &Attachments,
+) {
+    let row = |label: String, tokens: u32| {
+        println!("  {:<52} ~{:>7}", label, format_tokens(tokens.into()));
+    };
+    // One line of a longer text, cut to fit the row
+    let excerpt = |text: &str, width: usize| {
+        let line = text.trim().lines().next().unwrap_or_default();
+        let mut excerpt: String = line.chars().take(width).collect();
+        if excerpt.len() < text.trim().len() {
+            excerpt.push('…');
+        }
+        excerpt
+    };
+
+    // The request as the provider builds it, so per-message instructions
+    // and tool definitions are counted as they'll be sent
+    let body = llm_provider.request_body(history);
+    println!(
+        "{}",
+        "The next request, in tokens estimated at four characters each".bold()
+    );
+
+    let system = body["system"].as_str().unwrap_or_default();
+    let system_tokens = estimate_tokens(system);
+    row("System prompt".to_string(), system_tokens);
+    let mut accounted = 0;
+    if let Some(memory) = memory::read() {
+        let entries = memory
+            .lines()
+            .filter(|line| line.trim_start().starts_with(['-', '*']))
+            .count();
+        let tokens = estimate_tokens(&memory);
+        accounted += tokens;
+        row(format!("  Memory ({} entries)", entries), tokens);
+    }
+    if let Some(context) = project::context() {
+        let tokens = estimate_tokens(&context);
+        accounted += tokens;
+        let path = project::context_file()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        row(
+            format!("  {}", excerpt(&format!("Project context ({})", path), 48)),
+            tokens,
+        );
+    }
+    if system_tokens > accounted {
+        row("  Instructions".to_string(), system_tokens - accounted);
+    }
+
+    let tools = body["tools"].as_array().cloned().unwrap_or_default();
+    if !tools.is_empty() {
+        let names: Vec<&str> = tools
+            .iter()
+            .filter_map(|tool| tool["name"].as_str())
+            .collect();
+        row(
+            excerpt(&format!("Tools: {}", names.join(", ")), 50),
+            estimate_tokens(&serde_json::Value::Array(tools.clone()).to_string()),
+        );
+    }
+
+    let messages = body["messages"].as_array().cloned().unwrap_or_default();
+    let mut messages_tokens = 0;
+    let label = if messages.len() < history.len() {
+        format!(
+            "Messages ({} newest of {}, see context.max_history_*)",
+            messages.len(),
+            history.len()
+        )
+    } else {
+        format!("Messages ({})", messages.len())
+    };
+    println!("  {}", label);
+    for (i, message) in messages.iter().enumerate() {
+        let content = match &message["content"] {
+            serde_json::Value::String(text) => text.clone(),
+            content => content.to_string(),
+        };
+        let role = if message["role"] == "user" {
+            "You"
+        } else {
+            "Assistant"
+        };
+        let tokens = estimate_tokens(&content);
+        messages_tokens += tokens;
+        row(
+            format!("  {:>3}. {:<10} {}", i + 1, role, excerpt(&content, 34)),
+            tokens,
+        );
+    }
+
+    // What goes with the next typed message
+    let mut pending_tokens = 0;
+    for attachment in attachments.iter() {
+        let label = attachment.split(":\n").next().unwrap_or_default();
+        let tokens = estimate_tokens(attachment);
+        pending_tokens += tokens;
+        row(excerpt(&format!("Attachment: {}", label), 50), tokens);
+    }
+    if let Some(persona) = persona {
+        let tokens = estimate_tokens(&persona::instruction(persona));
+        pending_tokens += tokens;
+        row(format!("Persona: {}", persona), tokens);
+    }
+
+    let total =
+        estimate_tokens(&body.to_string()).max(system_tokens + messages_tokens) + pending_tokens;
+    println!(
+        "  {:<52} ~{:>7} of {}, plus the message you type",
+        "Total",
+        format_tokens(total.into()),
+        format_tokens(llm_provider.context_window().into())
+    );
+}
@@ -0,0 +1,273 @@
+use rye::attachments::{self, Stored};
+use rye::config;
+use rye::inline_image;
+use rye::providers::{MAX_IMAGE_BYTES, image_media_type};
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::debug;
+
+// Text that commands like `/paste` attach to the next message. Each
+// attachment is a labelled fenced block, cut off at `context.max_chars`, and
+// is sent ahead of the typed message. Longer blocks are saved in the
+// attachment store, with only a reference in the conversation file. Images
+// from `/image` are a `![name](path)` line each.
+
+/// Attachments waiting for the next message
+#[derive(Default)]
+pub struct Attachments {
+    blocks: Vec<String>,
+    // The fenced part of each block, what goes in the store
+    fenced: Vec<String>,
+    // Those put into the last message, for saving it
+    sent: Vec<Stored>,
+}
+
+impl Attachments {
+    /// Adds `content` under `label` and returns how many characters were kept
+    pub fn add(&mut self, label: &str, content: &str, language: &str) -> usize {
+        let max_chars = config::get().context.max_chars;
+        let total = content.chars().count();
+        let kept = if max_chars > 0 {
+            total.min(max_chars)
+        } else {
+            total
+        };
+
+        let mut text: String = content.chars().take(kept).collect();
+        if kept < total {
+            text.push_str(&format!("\n… (cut off, {} more characters)", total - kept));
+        }
+
+        // A fence longer than any backtick run inside can't be closed early
+        let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+
+        let fenced = format!("{}{}\n{}\n{}", fence, language, text.trim_end(), fence);
+        self.blocks.push(format!("{}:\n\n{}", label, fenced));
+        self.fenced.push(fenced);
+        kept
+    }
+
+    /// Adds the image at `path` as a `![name](path)` line, which the provider
+    /// sends as the image itself
+    pub fn add_image(&mut self, path: &Path) -> Result<(), String> {
+        let path = std::fs::canonicalize(path)
+            .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        if image_media_type(&path).is_none() {
+            return Err(format!(
+                "{} isn't a PNG, JPEG, GIF or WebP image",
+                path.display()
+            ));
+        }
+        let size = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+        if size > MAX_IMAGE_BYTES {
+            return Err(format!(
+                "{} is larger than the API's {} MB limit",
+                path.display(),
+                MAX_IMAGE_BYTES / 1024 / 1024
+            ));
+        }
+        inline_image::allow(&path);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        self.blocks.push(format!("![{}]({})", name, path.display()));
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Each attachment as it will be sent, label first
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.blocks.iter().map(String::as_str)
+    }
+
+    /// Puts the attachments ahead of `message` and clears them
+    pub fn take_into(&mut self, message: &str) -> String {
+        self.sent = std::mem::take(&mut self.fenced)
+            .iter()
+            .filter_map(|fenced| match attachments::store(fenced) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    eprintln!(
+                        "Warning: Could not store attachment, keeping it inline: {}",
+                        e
+                    );
+                    None
+                }
+            })
+            .collect();
+        if self.blocks.is_empty() {
+            return message.to_string();
+        }
+
+        let mut combined = std::mem::take(&mut self.blocks).join("\n\n");
+        combined.push_str("\n\n");
+        combined.push_str(message);
+        combined
+    }
+
+    /// The stored attachments `take_into` put into the last message, to pass
+    /// to `Conversation::add_message_with_attachments`
+    pub fn sent(&self) -> &[Stored] {
+        &self.sent
+    }
+}
+
+/// Reads everything piped into stdin, as in `git diff | rye`, then makes the
+/// terminal stdin so the session can still be typed into. Without a terminal
+/// to continue on, as in scripts, stdin is left alone and read as the
+/// session's input, and this returns `None`.
+pub fn take_piped_stdin() -> io::Result<Option<String>> {
+    let Some(tty) = open_terminal() else {
+        return Ok(None);
+    };
+    let mut piped = String::new();
+    io::stdin().read_to_string(&mut piped)?;
+    reattach_terminal(tty)?;
+    Ok(Some(piped))
+}
+
+#[cfg(unix)]
+fn open_terminal() -> Option<std::fs::File> {
+    std::fs::File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .inspect_err(|e| debug!(error = %e, "No terminal, reading stdin as the session"))
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn open_terminal() -> Option<std::fs::File> {
+    None
+}
+
+#[cfg(unix)]
+fn reattach_terminal(tty: std::fs::File) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: both descriptors are open; stdin becomes a copy of the terminal's
+    if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn reattach_terminal(_tty: std::fs::File) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Piping into an interactive session needs a unix terminal. `rye ask` answers piped prompts.",
+    ))
+}
+
+/// The system clipboard's text, read with the platform's paste command
+pub async fn clipboard() -> Result<String, Box<dyn std::error::Error>> {
+    let candidates: &[&[&str]] = if cfg!(target_os = "macos") {
+        &[&["pbpaste"]]
+    } else if cfg!(windows) {
+        &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"]]
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        &[
+            &["wl-paste", "--no-newline"],
+            &["xclip", "-selection", "clipboard", "-o"],
+        ]
+    } else {
+        &[
+            &["xclip", "-selection", "clipboard", "-o"],
+            &["xsel", "--clipboard", "--output"],
+        ]
+    };
+
+    let mut last_error = String::new();
+    for candidate in candidates {
+        match Command::new(candidate[0])
+            .args(&candidate[1..])
+            .output()
+            .await
+        {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+            Ok(output) => {
+                last_error = format!(
+                    "{} failed: {}",
+                    candidate[0],
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            Err(e) => last_error = format!("{}: {}", candidate[0], e),
+        }
+    }
+
+    Err(format!("Could not read the clipboard ({})", last_error).into())
+}
+
+/// Puts `text` on the system clipboard with the platform's copy command
+pub async fn copy_to_clipboard(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let candidates: &[&[&str]] = if cfg!(target_os = "macos") {
+        &[&["pbcopy"]]
+    } else if cfg!(windows) {
+        &[&["clip"]]
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        &[&["wl-copy"], &["xclip", "-selection", "clipboard"]]
+    } else {
+        &[
+            &["xclip", "-selection", "clipboard"],
+            &["xsel", "--clipboard", "--input"],
+        ]
+    };
+
+    let mut last_error = String::new();
+    for candidate in candidates {
+        let child = Command::new(candidate[0])
+            .args(&candidate[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                last_error = format!("{}: {}", candidate[0], e);
+                continue;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).await?;
+        }
+        let output = child.wait_with_output().await?;
+        if output.status.success() {
+            return Ok(());
+        }
+        last_error = format!(
+            "{} failed: {}",
+            candidate[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Err(format!("Could not copy to the clipboard ({})", last_error).into())
+}
+
+/// Output of `git args...` run in the current directory
+pub async fn git(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("Could not run git: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -1,24 +1,77 @@
-use std::env;
+use crate::attachments::{self, Stored};
+use crate::protect::{self, Key};
+use crate::{audit, config, paths, project, sync};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tracing::debug;
 use uuid::Uuid;
 
+/// Listing entry for a stored conversation, without its messages
 #[derive(Clone)]
 pub struct ConversationInfo {
     pub id: String,
     pub title: Option<String>,
+    pub tags: Vec<String>,
+    /// ID of the conversation this one was forked from
+    pub parent: Option<String>,
+    /// Repository the conversation was started in, with `project.scope` on
+    pub project: Option<String>,
+    /// The UUID the conversation was created with, which outlives renames
+    pub uuid: Option<String>,
+    /// Name given with `rye alias`, usable in place of the ID
+    pub alias: Option<String>,
     pub file_path: PathBuf,
 }
 
+/// A conversation backed by a markdown file. Every change is written to the
+/// file immediately, through `tokio::fs` so disk latency doesn't stall the
+/// runtime.
 pub struct Conversation {
     pub id: String,
     pub file_path: PathBuf,
     pub messages: Vec<(String, String)>, // (role, content)
     pub title: Option<String>,
+    pub tags: Vec<String>,
+    /// ID of the conversation this one was forked from, kept in the frontmatter
+    pub parent: Option<String>,
+    /// Repository the conversation was started in, with `project.scope` on
+    pub project: Option<String>,
+    /// The last message is waiting for the API to be reachable, see `rye flush`
+    pub queued: bool,
+    /// The UUID the conversation was created with. It stays in the
+    /// frontmatter when the file is renamed after the title, so the
+    /// conversation can still be loaded by it.
+    pub uuid: Option<String>,
+    /// Name given with `rye alias`, usable in place of the ID
+    pub alias: Option<String>,
+    /// The persona answers are given as, from the last `## Persona:` line
+    pub persona: Option<String>,
+    /// The messages are stored encrypted, see `protect.rs`
+    pub protected: bool,
+    // The key to a protected conversation's messages, once unlocked
+    key: Option<Key>,
+    // The file as this process last read or wrote it, to tell when it was
+    // changed elsewhere
+    stamp: Option<FileStamp>,
+}
+
+// Modification time and size of a file
+type FileStamp = (SystemTime, u64);
+
+fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
 }
 
 impl Conversation {
+    /// Creates an empty conversation with a fresh UUID and writes its file
     pub fn new() -> io::Result<Self> {
         let id = Uuid::new_v4().to_string();
         let conversations_dir = get_conversations_dir()?;
@@ -26,32 +79,58 @@ impl Conversation {
 
         let file_path = conversations_dir.join(format!("{}.md", id));
 
-        let conversation = Self {
+        let mut conversation = Self {
             id: id.clone(),
             file_path,
             messages: Vec::new(),
             title: None,
+            tags: Vec::new(),
+            parent: None,
+            project: project::scope(),
+            queued: false,
+            uuid: Some(id),
+            alias: None,
+            persona: None,
+            protected: false,
+            key: None,
+            stamp: None,
         };
 
         conversation.write_header()?;
+        conversation.stamp = file_stamp(&conversation.file_path);
+        debug!(path = %conversation.file_path.display(), "Created conversation file");
         Ok(conversation)
     }
 
+    /// Loads a conversation by file name, ID, UUID, or partial ID
     pub fn load(id: &str) -> io::Result<Self> {
+        let mut conversation = Self::load_metadata(id)?;
+        conversation.load_messages()?;
+        Ok(conversation)
+    }
+
+    /// Loads only the title and tags. `messages` stays empty until
+    /// `load_messages()`, so renaming or tagging doesn't parse the whole file.
+    pub fn load_metadata(id: &str) -> io::Result<Self> {
         let conversations_dir = get_conversations_dir()?;
 
-        // First try exact match
+        // First try exact match, only for plain file names so an ID can't
+        // reach outside the store
         let file_path = conversations_dir.join(format!("{}.md", id));
+        let plain_name = !id.contains(['/', '\\']);
 
-        let final_file_path = if file_path.exists() {
+        let final_file_path = if plain_name && file_path.exists() {
             file_path
+        } else if let Some(info) = resolve(&list_conversations()?, id) {
+            // Renamed after its title, found by its UUID, short ID, or alias
+            info.file_path.clone()
         } else {
             // If not found, search for files containing the id as a substring
             find_conversation_file(&conversations_dir, id)?
         };
 
-        let content = fs::read_to_string(&final_file_path)?;
-        let (messages, title) = parse_markdown_conversation(&content);
+        let header = read_header(&final_file_path)?;
+        let (frontmatter, body) = split_frontmatter(&header);
 
         // Extract the actual ID from the filename
         let actual_id = final_file_path
@@ -61,65 +140,538 @@ impl Conversation {
             .to_string();
 
         Ok(Self {
+            uuid: frontmatter_uuid(&frontmatter, &actual_id),
             id: actual_id,
+            stamp: file_stamp(&final_file_path),
             file_path: final_file_path,
-            messages,
-            title,
+            messages: Vec::new(),
+            title: parse_title(body),
+            tags: frontmatter_tags(&frontmatter),
+            parent: frontmatter_value(&frontmatter, "parent"),
+            project: frontmatter_value(&frontmatter, "project"),
+            queued: frontmatter_value(&frontmatter, "queued").as_deref() == Some("true"),
+            alias: frontmatter_value(&frontmatter, "alias"),
+            persona: None,
+            protected: frontmatter_value(&frontmatter, "protected").as_deref() == Some("true"),
+            key: None,
         })
     }
 
-    fn write_header(&self) -> io::Result<()> {
-        let header = if let Some(ref title) = self.title {
-            format!("# {}\n\n", title)
-        } else {
-            format!("# Conversation {}\n\n", self.id)
+    /// Reads and parses the messages from the file. A protected
+    /// conversation has to be unlocked first.
+    pub fn load_messages(&mut self) -> io::Result<()> {
+        self.stamp = file_stamp(&self.file_path);
+        let content = self.markdown()?;
+        let messages: Vec<(String, String)> =
+            parse_markdown_conversation(strip_frontmatter(&content))
+                .into_iter()
+                .map(|(role, content)| (role, attachments::expand(&content)))
+                .collect();
+        // Only rye's own lines between messages start at the margin, see
+        // `escape_headers()`
+        self.persona = content
+            .lines()
+            .rev()
+            .find_map(|line| line.strip_prefix(PERSONA_HEADER))
+            .map(str::trim)
+            .filter(|name| *name != DEFAULT_PERSONA)
+            .map(str::to_string);
+        debug!(
+            path = %self.file_path.display(),
+            messages = messages.len(),
+            "Loaded conversation"
+        );
+
+        self.messages = messages;
+        Ok(())
+    }
+
+    /// The file's content, with a protected conversation's messages
+    /// decrypted
+    pub fn markdown(&self) -> io::Result<String> {
+        let content = fs::read_to_string(&self.file_path)?;
+        if !self.protected {
+            return Ok(content);
+        }
+        let (header, body) = split_header(&content);
+        Ok(format!("{}{}", header, self.unseal(body)?))
+    }
+
+    // A protected conversation's messages, from the sealed section in `body`
+    fn unseal(&self, body: &str) -> io::Result<String> {
+        let Some(key) = &self.key else {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{} is protected, open it with `rye show` or `rye --continue` to enter its passphrase",
+                    self.title.as_deref().unwrap_or(&self.id)
+                ),
+            ));
         };
-        fs::write(&self.file_path, header)?;
+        // Protected before its first message
+        if body.trim().is_empty() {
+            return Ok(String::new());
+        }
+        protect::open_with(key, body)
+    }
+
+    /// Opens a protected conversation with its passphrase and loads its
+    /// messages
+    pub fn unlock(&mut self, passphrase: &str) -> io::Result<()> {
+        let content = fs::read_to_string(&self.file_path)?;
+        let (key, _) = protect::open(passphrase, split_header(&content).1)?;
+        self.key = Some(key);
+        audit::hide_content();
+        self.load_messages()
+    }
+
+    /// Encrypts the messages under `passphrase`, leaving the title and
+    /// frontmatter readable
+    pub async fn protect(&mut self, passphrase: &str) -> io::Result<()> {
+        if self.protected {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "The conversation is already protected",
+            ));
+        }
+        let key = protect::new_key(passphrase)?;
+        let mut file = open_locked(&self.file_path, false).await?;
+        let content = read_all(&mut file).await?;
+        self.protected = true;
+        let sealed = format!(
+            "{}{}",
+            self.header(),
+            protect::seal(&key, split_header(&content).1)?
+        );
+        replace_contents(&mut file, &sealed).await?;
+        self.key = Some(key);
+        audit::hide_content();
+        self.stamp = file_stamp(&self.file_path);
+        debug!(path = %self.file_path.display(), "Protected conversation");
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
+        Ok(())
+    }
+
+    /// Stores an unlocked protected conversation as plain markdown again
+    pub async fn unprotect(&mut self) -> io::Result<()> {
+        if !self.protected {
+            return Ok(());
+        }
+        let mut file = open_locked(&self.file_path, false).await?;
+        let content = read_all(&mut file).await?;
+        let messages = self.unseal(split_header(&content).1)?;
+        self.protected = false;
+        self.key = None;
+        replace_contents(&mut file, &format!("{}{}", self.header(), messages)).await?;
+        self.stamp = file_stamp(&self.file_path);
+        debug!(path = %self.file_path.display(), "Unprotected conversation");
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
         Ok(())
     }
 
-    pub fn set_title(&mut self, title: String) -> io::Result<()> {
-        let sanitized_title = sanitize_filename(&title);
+    // Appends `text` to the file locked in `file`, after a newline if the
+    // file doesn't end with one. Protected messages are decrypted, extended
+    // and sealed again under a fresh nonce.
+    async fn append_locked(&self, file: &mut tokio::fs::File, text: &str) -> io::Result<()> {
+        if !self.protected {
+            // A header glued to a last line without a newline, say one a
+            // sync cut short, wouldn't start a section
+            let separator = if ends_with_newline(file).await? {
+                ""
+            } else {
+                "\n"
+            };
+            // One write, so another writer's can't land inside it
+            file.write_all(format!("{}{}", separator, text).as_bytes())
+                .await?;
+            return file.flush().await;
+        }
+
+        let content = read_all(file).await?;
+        let (header, body) = split_header(&content);
+        let mut messages = self.unseal(body)?;
+        if !messages.is_empty() && !messages.ends_with('\n') {
+            messages.push('\n');
+        }
+        messages.push_str(text);
+        let key = self.key.as_ref().expect("unsealed with the key");
+        let sealed = format!("{}{}", header, protect::seal(key, &messages)?);
+        replace_contents(file, &sealed).await
+    }
+
+    /// Whether the file changed since this conversation last read or wrote
+    /// it, say edited in another app or synced from another machine
+    pub fn changed_on_disk(&self) -> bool {
+        self.stamp.is_some() && file_stamp(&self.file_path) != self.stamp
+    }
+
+    /// Reads the conversation from its file again if it changed on disk, so
+    /// what's appended next follows the file as it is, and returns whether
+    /// it did
+    pub fn reload_if_changed(&mut self) -> io::Result<bool> {
+        if !self.changed_on_disk() {
+            return Ok(false);
+        }
+        if !self.file_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} was moved or deleted", self.file_path.display()),
+            ));
+        }
+        let stem = self
+            .file_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut reloaded = Self::load_metadata(&stem)?;
+        reloaded.key = self.key.clone();
+        reloaded.load_messages()?;
+        debug!(path = %self.file_path.display(), "Reloaded conversation changed on disk");
+        *self = reloaded;
+        Ok(true)
+    }
+
+    fn header(&self) -> String {
+        let mut header = String::new();
+
+        // Until the file is renamed after a title, its name is the UUID
+        let uuid = self
+            .uuid
+            .as_ref()
+            .filter(|uuid| self.file_path.file_stem() != Some(uuid.as_ref()));
+        if !self.tags.is_empty()
+            || self.parent.is_some()
+            || self.project.is_some()
+            || self.queued
+            || uuid.is_some()
+            || self.alias.is_some()
+            || self.protected
+        {
+            header.push_str("---\n");
+            if let Some(uuid) = uuid {
+                header.push_str(&format!("uuid: {}\n", uuid));
+            }
+            if let Some(alias) = &self.alias {
+                header.push_str(&format!("alias: {}\n", alias));
+            }
+            if !self.tags.is_empty() {
+                header.push_str(&format!("tags: {}\n", self.tags.join(", ")));
+            }
+            if let Some(parent) = &self.parent {
+                header.push_str(&format!("parent: {}\n", parent));
+            }
+            if let Some(project) = &self.project {
+                header.push_str(&format!("project: {}\n", project));
+            }
+            if self.queued {
+                header.push_str("queued: true\n");
+            }
+            if self.protected {
+                header.push_str("protected: true\n");
+            }
+            header.push_str("---\n");
+        }
+
+        if let Some(ref title) = self.title {
+            header.push_str(&format!("# {}\n\n", title));
+        } else {
+            header.push_str(&format!("# Conversation {}\n\n", self.id));
+        }
+
+        header
+    }
+
+    fn write_header(&self) -> io::Result<()> {
+        fs::write(&self.file_path, self.header())?;
+        Ok(())
+    }
+
+    /// Sets the title and renames the file after it. When another
+    /// conversation already has that file name, a short UUID (or, for old
+    /// conversations without one, today's date) is added to the name.
+    pub async fn set_title(&mut self, title: String) -> io::Result<()> {
         let conversations_dir = get_conversations_dir()?;
-        let new_file_path = conversations_dir.join(format!("{}.md", sanitized_title));
+        let new_file_path = self.titled_file_path(&conversations_dir, &title);
 
         // Rename the file
-        fs::rename(&self.file_path, &new_file_path)?;
+        tokio::fs::rename(&self.file_path, &new_file_path).await?;
+        debug!(
+            from = %self.file_path.display(),
+            to = %new_file_path.display(),
+            "Renamed conversation file"
+        );
 
         self.title = Some(title.clone());
-        self.file_path = new_file_path;
+        let old_file_path = std::mem::replace(&mut self.file_path, new_file_path);
 
         // Rewrite the file with the new title
-        self.rewrite_file_with_title()?;
+        self.rewrite_file_with_title().await?;
+        sync::remove(&conversations_dir, &old_file_path).await;
+
+        // Forks refer to this conversation by its ID, which just changed
+        if let (Some(old_id), Some(new_id)) =
+            (old_file_path.file_stem(), self.file_path.file_stem())
+        {
+            let (old_id, new_id) = (old_id.to_string_lossy(), new_id.to_string_lossy());
+            for info in list_conversations()? {
+                if info.parent.as_deref() == Some(&*old_id) {
+                    let mut child = Self::load_metadata(&info.id)?;
+                    child.parent = Some(new_id.to_string());
+                    child.rewrite_file_with_title().await?;
+                }
+            }
+        }
         Ok(())
     }
 
-    fn rewrite_file_with_title(&self) -> io::Result<()> {
-        let mut content = String::new();
+    // `<title>.md`, or `<title> (<suffix>).md` when that belongs to another
+    // conversation
+    fn titled_file_path(&self, conversations_dir: &Path, title: &str) -> PathBuf {
+        let sanitized_title = sanitize_filename(title);
+        let is_free = |path: &PathBuf| *path == self.file_path || !path.exists();
+        let path = conversations_dir.join(format!("{}.md", sanitized_title));
+        if is_free(&path) {
+            return path;
+        }
 
-        // Write header with title
-        if let Some(ref title) = self.title {
-            content.push_str(&format!("# {}\n\n", title));
-        } else {
-            content.push_str(&format!("# Conversation {}\n\n", self.id));
+        let suffix = match &self.uuid {
+            Some(uuid) => uuid.chars().take(8).collect(),
+            None => chrono::Local::now().format("%Y-%m-%d").to_string(),
+        };
+        let mut path = conversations_dir.join(format!("{} ({}).md", sanitized_title, suffix));
+        let mut n = 2;
+        while !is_free(&path) {
+            path = conversations_dir.join(format!("{} ({} {}).md", sanitized_title, suffix, n));
+            n += 1;
         }
+        path
+    }
 
-        // Write all messages
-        for (role, message_content) in &self.messages {
-            let role_header = if role == "user" {
-                "## You"
-            } else {
-                "## Assistant"
-            };
-            content.push_str(&format!("\n{}\n\n{}\n\n", role_header, message_content));
+    /// Creates an empty conversation to carry this one on in, with its tags
+    /// and project. A protected one's key comes along, so what's added to
+    /// the new one is sealed as well.
+    pub fn successor(&self) -> io::Result<Self> {
+        let mut next = Self::new()?;
+        next.tags = self.tags.clone();
+        next.project = self.project.clone();
+        next.protected = self.protected;
+        next.key = self.key.clone();
+        next.write_header()?;
+        next.stamp = file_stamp(&next.file_path);
+        Ok(next)
+    }
+
+    /// Copies the conversation so far into a new conversation that records
+    /// this one as its parent. The copy is titled `<title> (fork)`.
+    pub async fn fork(&self) -> io::Result<Self> {
+        let mut fork = Self::new()?;
+        fork.tags = self.tags.clone();
+        fork.project = self.project.clone();
+        fork.parent = self
+            .file_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned());
+        fork.messages = self.messages.clone();
+        // The sealed messages are copied as they are, under the same key
+        fork.protected = self.protected;
+        fork.key = self.key.clone();
+
+        let existing = tokio::fs::read_to_string(&self.file_path).await?;
+        let mut content = fork.header();
+        content.push_str(split_header(strip_frontmatter(&existing)).1);
+        tokio::fs::write(&fork.file_path, content).await?;
+
+        // A numbered title keeps a second fork from replacing the first
+        let base = self
+            .title
+            .clone()
+            .unwrap_or_else(|| format!("Conversation {}", self.id));
+        let title = unique_title(&format!("{} (fork)", base), |n| {
+            format!("{} (fork {})", base, n)
+        })?;
+        fork.set_title(title).await?;
+        debug!(from = %self.file_path.display(), to = %fork.file_path.display(), "Forked conversation");
+        Ok(fork)
+    }
+
+    /// Replaces the tags stored in the frontmatter
+    pub async fn set_tags(&mut self, tags: Vec<String>) -> io::Result<()> {
+        self.tags = tags;
+        self.rewrite_file_with_title().await
+    }
+
+    /// Names the conversation so the name can be used in place of its ID,
+    /// or removes the name with `None`. Names must be single words that no
+    /// other conversation uses as a name or ID.
+    pub async fn set_alias(&mut self, alias: Option<String>) -> io::Result<()> {
+        if let Some(alias) = &alias {
+            let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+            if alias.is_empty() || alias.contains(char::is_whitespace) || alias.contains('/') {
+                return Err(invalid(format!(
+                    "'{}' can't be an alias, use a single word",
+                    alias
+                )));
+            }
+            if let Some(other) = list_conversations()?.into_iter().find(|info| {
+                info.file_path != self.file_path
+                    && (info.id == *alias
+                        || info.uuid.as_ref() == Some(alias)
+                        || info.alias.as_ref() == Some(alias))
+            }) {
+                return Err(invalid(format!(
+                    "'{}' already refers to {}",
+                    alias,
+                    other.title.as_deref().unwrap_or(&other.id)
+                )));
+            }
+        }
+        self.alias = alias;
+        self.rewrite_file_with_title().await
+    }
+
+    /// Marks the last message as waiting to be sent, or as sent
+    pub async fn set_queued(&mut self, queued: bool) -> io::Result<()> {
+        if self.queued == queued {
+            return Ok(());
         }
+        self.queued = queued;
+        self.rewrite_file_with_title().await
+    }
+
+    /// Moves the file into the `archive` subdirectory, which listings skip
+    pub async fn archive(self) -> io::Result<PathBuf> {
+        let conversations_dir = get_conversations_dir()?;
+        let archive_dir = conversations_dir.join("archive");
+        fs::create_dir_all(&archive_dir)?;
+
+        let file_name = self
+            .file_path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid file path"))?;
+        let archived_path = archive_dir.join(file_name);
+        fs::rename(&self.file_path, &archived_path)?;
+        debug!(path = %archived_path.display(), "Archived conversation");
 
-        fs::write(&self.file_path, content)?;
+        sync::push(&conversations_dir, &archived_path).await;
+        sync::remove(&conversations_dir, &self.file_path).await;
+
+        Ok(archived_path)
+    }
+
+    /// Removes the conversation file
+    pub async fn delete(self) -> io::Result<()> {
+        debug!(path = %self.file_path.display(), "Deleting conversation");
+        fs::remove_file(&self.file_path)?;
+        sync::remove(&get_conversations_dir()?, &self.file_path).await;
+        Ok(())
+    }
+
+    /// Sends the file to the remote store, if one is configured, after it
+    /// was changed outside `Conversation`
+    pub async fn push(&self) -> io::Result<()> {
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
         Ok(())
     }
 
-    pub fn add_message(&mut self, role: &str, content: &str) -> io::Result<()> {
+    // Replaces the frontmatter and title, keeping the messages already in the file
+    async fn rewrite_file_with_title(&mut self) -> io::Result<()> {
+        // Held until the rewrite is done, so an append can't slip in between
+        let _lock = open_locked(&self.file_path, false).await?;
+        let existing = tokio::fs::read_to_string(&self.file_path).await?;
+        let mut content = self.header();
+        content.push_str(split_header(&existing).1);
+
+        tokio::fs::write(&self.file_path, content).await?;
+        self.stamp = file_stamp(&self.file_path);
+        debug!(path = %self.file_path.display(), "Rewrote conversation file");
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
+        Ok(())
+    }
+
+    /// Switches the persona for the following answers, `None` for the
+    /// default, and notes the switch in the file
+    pub async fn set_persona(&mut self, persona: Option<String>) -> io::Result<()> {
+        let line = format!(
+            "\n{}{}\n\n",
+            PERSONA_HEADER,
+            persona.as_deref().unwrap_or(DEFAULT_PERSONA)
+        );
+        let mut file = open_locked(&self.file_path, false).await?;
+        self.append_locked(&mut file, &line).await?;
+        self.stamp = file_stamp(&self.file_path);
+        self.persona = persona;
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
+        Ok(())
+    }
+
+    /// Notes which model the next answer comes from, when a router picked it
+    pub async fn note_model(&mut self, model: &str) -> io::Result<()> {
+        let line = format!("\n{}{}\n\n", MODEL_HEADER, model);
+        let mut file = open_locked(&self.file_path, false).await?;
+        self.append_locked(&mut file, &line).await?;
+        self.stamp = file_stamp(&self.file_path);
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
+        Ok(())
+    }
+
+    /// Notes at the end of the file that the conversation goes on in `next`,
+    /// with a link to its file
+    pub async fn link_continuation(&self, next: &Conversation) -> io::Result<()> {
+        let title = next.title.clone().unwrap_or_else(|| next.id.clone());
+        let file_name = next
+            .file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let line = format!("\n{}[{}](<{}>)\n\n", CONTINUED_HEADER, title, file_name);
+        let mut file = open_locked(&self.file_path, false).await?;
+        self.append_locked(&mut file, &line).await?;
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
+        Ok(())
+    }
+
+    /// Appends a message to the conversation and its file. `role` is
+    /// `"user"` or `"assistant"`.
+    pub async fn add_message(&mut self, role: &str, content: &str) -> io::Result<()> {
+        self.add_message_with_attachments(role, content, &[]).await
+    }
+
+    /// Appends a message whose `attachments` are saved in the file as
+    /// references to the attachment store
+    pub async fn add_message_with_attachments(
+        &mut self,
+        role: &str,
+        content: &str,
+        attachments: &[Stored],
+    ) -> io::Result<()> {
+        let mut file = open_locked(&self.file_path, true).await?;
+
+        // Another process may have appended, or a sync replaced the file,
+        // since this conversation last read or wrote it. Appending after
+        // stale messages would leave them out of step, so they are read
+        // again first; a file that can't be is left alone.
+        if self.changed_on_disk() {
+            let known = self.messages.clone();
+            self.reload_if_changed().map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!(
+                        "The conversation changed on disk and couldn't be read again: {}",
+                        e
+                    ),
+                )
+            })?;
+            if self.messages.starts_with(&known) {
+                debug!(path = %self.file_path.display(), "Picked up messages appended elsewhere");
+            } else {
+                eprintln!(
+                    "Warning: {} was changed elsewhere and no longer has all of this session's messages; continuing from the file as it is now",
+                    self.file_path.display()
+                );
+            }
+        }
         self.messages.push((role.to_string(), content.to_string()));
+        let content = escape_headers(&attachments::compact(content, attachments));
 
         let role_header = if role == "user" {
             "## You"
@@ -127,15 +679,244 @@ impl Conversation {
             "## Assistant"
         };
         let message_content = format!("\n{}\n\n{}\n\n", role_header, content);
+        self.append_locked(&mut file, &message_content).await?;
+        self.stamp = file_stamp(&self.file_path);
+        debug!(
+            path = %self.file_path.display(),
+            role,
+            bytes = message_content.len(),
+            "Appended message"
+        );
+        sync::push(&get_conversations_dir()?, &self.file_path).await;
+        Ok(())
+    }
+}
 
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.file_path)?;
+// Opens `path` for reading and appending and takes an exclusive lock on it,
+// held until the file is closed, so processes writing the same conversation
+// take turns. The lock is advisory and only taken on unix; it's waited for
+// on a blocking thread, so a slow writer elsewhere doesn't stall the runtime.
+async fn open_locked(path: &Path, create: bool) -> io::Result<tokio::fs::File> {
+    let file = OpenOptions::new()
+        .create(create)
+        .read(true)
+        .append(true)
+        .open(path)
+        .await?;
+    #[cfg(unix)]
+    let file = {
+        let file = file.into_std().await;
+        let file = tokio::task::spawn_blocking(move || {
+            use std::os::fd::AsRawFd;
+            // SAFETY: the descriptor belongs to `file`, which is open for the
+            // whole call
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(file)
+        })
+        .await
+        .map_err(io::Error::other)??;
+        tokio::fs::File::from_std(file)
+    };
+    Ok(file)
+}
 
-        file.write_all(message_content.as_bytes())?;
-        Ok(())
+// The whole of a file opened with `open_locked`
+async fn read_all(file: &mut tokio::fs::File) -> io::Result<String> {
+    let mut content = String::new();
+    file.seek(io::SeekFrom::Start(0)).await?;
+    file.read_to_string(&mut content).await?;
+    Ok(content)
+}
+
+// Replaces the contents of a file opened with `open_locked`, which appends,
+// so the write lands at the start once it's emptied
+async fn replace_contents(file: &mut tokio::fs::File, content: &str) -> io::Result<()> {
+    file.set_len(0).await?;
+    file.write_all(content.as_bytes()).await?;
+    file.flush().await
+}
+
+// Whether `file` is empty or ends with a newline
+async fn ends_with_newline(file: &mut tokio::fs::File) -> io::Result<bool> {
+    if file.metadata().await?.len() == 0 {
+        return Ok(true);
+    }
+    let mut last = [0u8];
+    file.seek(io::SeekFrom::End(-1)).await?;
+    file.read_exact(&mut last).await?;
+    Ok(last[0] == b'\n')
+}
+
+// Marks a persona switch in the file. Not a `## You` or `## Assistant`
+// section, so it ends the message before it and is otherwise skipped.
+const PERSONA_HEADER: &str = "## Persona: ";
+
+// Ends a conversation that was rolled over into a new part, linking to it.
+// Skipped by the parser like a persona switch.
+const CONTINUED_HEADER: &str = "## Continued in: ";
+
+// Names the model a router sent the answer after it to. Skipped by the
+// parser like a persona switch.
+const MODEL_HEADER: &str = "## Model: ";
+const DEFAULT_PERSONA: &str = "default";
+
+// Lines rye writes between messages. A message line that starts like one is
+// saved indented by a space, so a response can't start a message of its own
+// or switch the persona; markdown still shows it the same.
+const SECTION_HEADERS: [&str; 5] = [
+    "## You",
+    "## Assistant",
+    PERSONA_HEADER,
+    CONTINUED_HEADER,
+    MODEL_HEADER,
+];
+
+fn is_section_header(line: &str) -> bool {
+    SECTION_HEADERS
+        .iter()
+        .any(|header| line.starts_with(header))
+}
+
+fn escape_headers(content: &str) -> String {
+    if !content.lines().any(is_section_header) {
+        return content.to_string();
+    }
+    content
+        .lines()
+        .map(|line| {
+            if is_section_header(line) {
+                format!(" {}", line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// A message line as it was before `escape_headers()`
+fn unescape_header(line: &str) -> &str {
+    line.strip_prefix(' ')
+        .filter(|rest| is_section_header(rest))
+        .unwrap_or(line)
+}
+
+/// `title`, or the first of `numbered(2)`, `numbered(3)`, ... that no
+/// conversation file is named after yet
+pub fn unique_title(title: &str, numbered: impl Fn(usize) -> String) -> io::Result<String> {
+    let conversations_dir = get_conversations_dir()?;
+    let mut title = title.to_string();
+    let mut n = 2;
+    while conversations_dir
+        .join(format!("{}.md", sanitize_filename(&title)))
+        .exists()
+    {
+        title = numbered(n);
+        n += 1;
+    }
+    Ok(title)
+}
+
+// The conversation `id` names exactly: by ID, UUID or alias, or by a prefix
+// of its UUID (its short ID, or as few as four characters) that no other
+// conversation's starts with
+fn resolve<'a>(conversations: &'a [ConversationInfo], id: &str) -> Option<&'a ConversationInfo> {
+    let exact = conversations.iter().find(|info| {
+        info.id == id || info.uuid.as_deref() == Some(id) || info.alias.as_deref() == Some(id)
+    });
+    if exact.is_some() || id.len() < MIN_PREFIX_LEN {
+        return exact;
+    }
+    let mut prefixed = conversations
+        .iter()
+        .filter(|info| info.uuid.as_ref().is_some_and(|uuid| uuid.starts_with(id)));
+    match (prefixed.next(), prefixed.next()) {
+        (Some(info), None) => Some(info),
+        _ => None,
+    }
+}
+
+// Shortest UUID prefix that's looked up
+const MIN_PREFIX_LEN: usize = 4;
+
+/// The short ID of the conversation created with `uuid`: the first eight
+/// characters of it, like the suffix of a retitled file. Always the same
+/// length, so a noted short ID keeps working as conversations are added
+/// (until two UUIDs share their first eight characters, about one in four
+/// billion).
+pub fn short_id(uuid: &str) -> String {
+    uuid.chars().take(8).collect()
+}
+
+/// Conversations matching `query`, for `--continue <query>`: the one with
+/// that ID, UUID, short ID or alias, or else the best matches among titles and IDs containing
+/// it, titles containing each of its words, titles containing its letters
+/// in order, and conversations mentioning each of its words
+pub fn search(query: &str) -> io::Result<Vec<ConversationInfo>> {
+    let conversations = list_conversations()?;
+    if let Some(exact) = resolve(&conversations, query) {
+        return Ok(vec![exact.clone()]);
+    }
+
+    let query = query.to_lowercase();
+    let words: Vec<&str> = query.split_whitespace().collect();
+    if words.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut best = 0;
+    let mut matches = Vec::new();
+    for info in conversations {
+        let title = info.title.as_deref().unwrap_or_default().to_lowercase();
+        let score = if title.contains(&query) || info.id.to_lowercase().contains(&query) {
+            4
+        } else if words.iter().all(|word| title.contains(word)) {
+            3
+        } else if is_fuzzy_match(&title, &query) {
+            2
+        } else if fs::read_to_string(&info.file_path).is_ok_and(|content| {
+            let content = content.to_lowercase();
+            words.iter().all(|word| content.contains(word))
+        }) {
+            1
+        } else {
+            continue;
+        };
+        if score > best {
+            best = score;
+            matches.clear();
+        }
+        if score == best {
+            matches.push(info);
+        }
     }
+    Ok(matches)
+}
+
+// Whether `query`'s letters appear in `text` in order and close together,
+// so `tkio rt` finds "Tokio runtime" but not every long title
+fn is_fuzzy_match(text: &str, query: &str) -> bool {
+    let query: Vec<char> = query.chars().filter(|c| !c.is_whitespace()).collect();
+    let Some(&first) = query.first() else {
+        return false;
+    };
+    let text: Vec<char> = text.chars().collect();
+    // The tightest span starting at each occurrence of the first letter
+    text.iter()
+        .enumerate()
+        .filter(|(_, c)| **c == first)
+        .any(|(start, _)| {
+            let mut remaining = query[1..].iter().peekable();
+            let mut end = start;
+            for (i, c) in text.iter().enumerate().skip(start + 1) {
+                if remaining.peek() == Some(&c) {
+                    remaining.next();
+                    end = i;
+                }
+            }
+            remaining.peek().is_none() && end - start < query.len() * 2
+        })
 }
 
 fn find_conversation_file(conversations_dir: &PathBuf, id: &str) -> io::Result<PathBuf> {
@@ -145,7 +926,7 @@ fn find_conversation_file(conversations_dir: &PathBuf, id: &str) -> io::Result<P
         let entry = entry?;
         let path = entry.path();
 
-        if path.ends_with("md")
+        if path.extension().and_then(|s| s.to_str()) == Some("md")
             && let Some(filename) = path.to_str()
         {
             // Check if the filename contains the id (for partial matches)
@@ -161,16 +942,30 @@ fn find_conversation_file(conversations_dir: &PathBuf, id: &str) -> io::Result<P
     ))
 }
 
+/// Fetches conversations from the remote store, if one is configured, into
+/// the conversations directory
+pub async fn pull_remote() -> io::Result<()> {
+    if sync::remote().is_some() {
+        let conversations_dir = get_conversations_dir()?;
+        fs::create_dir_all(&conversations_dir)?;
+        sync::pull(&conversations_dir).await;
+    }
+    Ok(())
+}
+
 fn get_conversations_dir() -> io::Result<PathBuf> {
-    if let Ok(custom_path) = env::var("RYE_CONVERSATIONS") {
-        let path = PathBuf::from(custom_path);
+    if let Some(custom_path) = &config::get().conversations_dir {
+        let path = match (custom_path.strip_prefix("~/"), dirs::home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest),
+            _ => PathBuf::from(custom_path),
+        };
         if path.exists() || path.parent().is_some_and(|p| p.exists()) {
             return Ok(path);
         }
     }
 
-    if let Some(home_dir) = dirs::home_dir() {
-        Ok(home_dir.join(".rye"))
+    if let Some(data_dir) = paths::data_dir() {
+        Ok(data_dir)
     } else {
         Err(io::Error::new(
             io::ErrorKind::NotFound,
@@ -179,33 +974,133 @@ fn get_conversations_dir() -> io::Result<PathBuf> {
     }
 }
 
+// Device names Windows reserves regardless of extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
 fn sanitize_filename(title: &str) -> String {
-    title
+    let sanitized = title
         .chars()
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
             c if c.is_control() => '_',
             c => c,
         })
-        .collect::<String>()
+        .collect::<String>();
+    // Windows drops trailing dots and spaces from file names
+    let sanitized = sanitized
         .trim()
-        .to_string()
+        .trim_end_matches('.')
+        .trim_end()
+        .to_string();
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.contains(&stem.to_uppercase().as_str()) {
+        format!("_{}", sanitized)
+    } else {
+        sanitized
+    }
+}
+
+// Reads a file up to its first `## ` section: the frontmatter and title
+fn read_header(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut header = String::new();
+    let mut line = String::new();
+
+    while reader.read_line(&mut line)? > 0 && !line.starts_with("## ") {
+        header.push_str(&line);
+        line.clear();
+    }
+
+    Ok(header)
+}
+
+// Splits file content into its header and the messages, starting at the
+// newline before the first `## ` section
+fn split_header(content: &str) -> (&str, &str) {
+    let start = if content.starts_with("## ") {
+        0
+    } else {
+        content.find("\n## ").unwrap_or(content.len())
+    };
+    content.split_at(start)
+}
+
+// Splits an optional `---` delimited block of `key: value` lines off the top of the file
+fn split_frontmatter(content: &str) -> (Vec<(String, String)>, &str) {
+    let Some(rest) = content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    else {
+        return (Vec::new(), content);
+    };
+    // Files edited on Windows may use CRLF line endings
+    let Some((end, delimiter)) = ["\n---\n", "\n---\r\n"]
+        .into_iter()
+        .filter_map(|delimiter| rest.find(delimiter).map(|end| (end, delimiter)))
+        .min()
+    else {
+        return (Vec::new(), content);
+    };
+
+    let fields = rest[..end]
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    (fields, &rest[end + delimiter.len()..])
+}
+
+/// Returns a conversation file's content without its `---` frontmatter
+pub fn strip_frontmatter(content: &str) -> &str {
+    split_frontmatter(content).1
+}
+
+fn frontmatter_value(frontmatter: &[(String, String)], key: &str) -> Option<String> {
+    frontmatter
+        .iter()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.clone())
+        .filter(|value| !value.is_empty())
+}
+
+// The `uuid` field, or the file name for conversations not renamed yet
+fn frontmatter_uuid(frontmatter: &[(String, String)], id: &str) -> Option<String> {
+    frontmatter_value(frontmatter, "uuid")
+        .or_else(|| Uuid::parse_str(id).is_ok().then(|| id.to_string()))
 }
 
-fn parse_markdown_conversation(content: &str) -> (Vec<(String, String)>, Option<String>) {
+fn frontmatter_tags(frontmatter: &[(String, String)]) -> Vec<String> {
+    frontmatter
+        .iter()
+        .find(|(key, _)| key == "tags")
+        .map(|(_, value)| parse_tags(value))
+        .unwrap_or_default()
+}
+
+/// Parses a comma-separated tag list, dropping `#` prefixes and empty tags
+pub fn parse_tags(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches('#').to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+// The `# Title` line, unless it's the `# Conversation <id>` placeholder
+fn parse_title(content: &str) -> Option<String> {
+    let title_text = content.lines().next()?.strip_prefix("# ")?.trim();
+    (!title_text.starts_with("Conversation ")).then(|| title_text.to_string())
+}
+
+fn parse_markdown_conversation(content: &str) -> Vec<(String, String)> {
     let mut messages = Vec::new();
     let lines: Vec<&str> = content.lines().collect();
     let mut i = 0;
-    let mut title = None;
-
-    // Extract title from first line if it starts with #
-    if !lines.is_empty() && lines[0].starts_with("# ") {
-        let title_text = lines[0].trim_start_matches("# ").trim();
-        if !title_text.starts_with("Conversation ") {
-            title = Some(title_text.to_string());
-        }
-        i = 1; // Skip the title line
-    }
 
     while i < lines.len() {
         if lines[i].starts_with("## You") {
@@ -213,14 +1108,14 @@ fn parse_markdown_conversation(content: &str) -> (Vec<(String, String)>, Option<
             let mut user_content = Vec::new();
             // Collect all lines until next header
             while i < lines.len() && !lines[i].starts_with("## ") {
-                user_content.push(lines[i]);
+                user_content.push(unescape_header(lines[i]));
                 i += 1;
             }
             // Trim leading and trailing empty lines
-            while user_content.first().map_or(false, |l| l.trim().is_empty()) {
+            while user_content.first().is_some_and(|l| l.trim().is_empty()) {
                 user_content.remove(0);
             }
-            while user_content.last().map_or(false, |l| l.trim().is_empty()) {
+            while user_content.last().is_some_and(|l| l.trim().is_empty()) {
                 user_content.pop();
             }
             if !user_content.is_empty() {
@@ -231,19 +1126,19 @@ fn parse_markdown_conversation(content: &str) -> (Vec<(String, String)>, Option<
             let mut assistant_content = Vec::new();
             // Collect all lines until next header
             while i < lines.len() && !lines[i].starts_with("## ") {
-                assistant_content.push(lines[i]);
+                assistant_content.push(unescape_header(lines[i]));
                 i += 1;
             }
             // Trim leading and trailing empty lines
             while assistant_content
                 .first()
-                .map_or(false, |l| l.trim().is_empty())
+                .is_some_and(|l| l.trim().is_empty())
             {
                 assistant_content.remove(0);
             }
             while assistant_content
                 .last()
-                .map_or(false, |l| l.trim().is_empty())
+                .is_some_and(|l| l.trim().is_empty())
             {
                 assistant_content.pop();
             }
@@ -255,9 +1150,223 @@ fn parse_markdown_conversation(content: &str) -> (Vec<(String, String)>, Option<
         }
     }
 
-    (messages, title)
+    messages
 }
 
+// Where `check_file` is in a conversation file
+enum Section<'a> {
+    // Between the title and the first message
+    Preamble,
+    Message {
+        role: &'a str,
+        line: usize,
+        has_text: bool,
+    },
+    // After a `## ` line that isn't a role header, which the parser skips up
+    // to the next message; `reported` once a problem there has been listed
+    Skipped {
+        reported: bool,
+    },
+}
+
+/// Problems in a conversation file that `Conversation::load` silently reads
+/// around: malformed role headers, `## ` headings that cut a message short,
+/// empty messages, text outside any message and a missing title or
+/// frontmatter end. Each starts with its line number.
+pub fn check_file(content: &str) -> Vec<String> {
+    let lines: Vec<&str> = content
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .collect();
+    let mut problems = Vec::new();
+
+    let mut start = 0;
+    if lines.first() == Some(&"---") {
+        match lines.iter().skip(1).position(|line| *line == "---") {
+            Some(end) => start = end + 2,
+            None => problems.push(
+                "Line 1: the frontmatter has no closing `---`, so its fields are ignored"
+                    .to_string(),
+            ),
+        }
+    }
+    match lines.get(start) {
+        Some(line) if line.starts_with("# ") => start += 1,
+        _ => problems.push(format!(
+            "Line {}: the first line isn't a `# Title`, so the conversation has no title",
+            start + 1
+        )),
+    }
+
+    let mut section = Section::Preamble;
+    let mut first_role = None;
+    let finish = |section: &Section, problems: &mut Vec<String>| {
+        if let Section::Message {
+            role,
+            line,
+            has_text: false,
+        } = section
+        {
+            problems.push(format!(
+                "Line {}: the `## {}` message is empty and is skipped",
+                line, role
+            ));
+        }
+    };
+    for (i, line) in lines.iter().enumerate().skip(start) {
+        let number = i + 1;
+        let role = if line.starts_with("## You") {
+            Some("You")
+        } else if line.starts_with("## Assistant") {
+            Some("Assistant")
+        } else {
+            None
+        };
+
+        if let Some(role) = role {
+            finish(&section, &mut problems);
+            if line.trim_end() != format!("## {}", role) {
+                problems.push(format!(
+                    "Line {}: `{}` is read as a `## {}` header and the rest of the line is dropped",
+                    number, line, role
+                ));
+            }
+            first_role.get_or_insert((role, number));
+            section = Section::Message {
+                role,
+                line: number,
+                has_text: false,
+            };
+        } else if line.starts_with(PERSONA_HEADER)
+            || line.starts_with(CONTINUED_HEADER)
+            || line.starts_with(MODEL_HEADER)
+        {
+            finish(&section, &mut problems);
+            section = Section::Skipped { reported: false };
+        } else if line.starts_with("## ") {
+            finish(&section, &mut problems);
+            let problem = match role_header_like(line) {
+                Some(header) => format!(
+                    "Line {}: `{}` isn't a role header, write it as `{}`",
+                    number, line, header
+                ),
+                None => format!("Line {}: `{}` isn't a role header", number, line.trim_end()),
+            };
+            problems.push(format!(
+                "{}. It ends the message before it, and the lines after it up to the next `## You` or `## Assistant` are dropped",
+                problem
+            ));
+            section = Section::Skipped { reported: true };
+        } else if let Some(header) = role_header_like(line) {
+            problems.push(format!(
+                "Line {}: `{}` looks like a role header but isn't one, write it as `{}`",
+                number,
+                line.trim_end(),
+                header
+            ));
+        } else if !line.trim().is_empty() {
+            match &mut section {
+                Section::Message { has_text, .. } => *has_text = true,
+                Section::Preamble | Section::Skipped { reported: false } => {
+                    problems.push(format!(
+                        "Line {}: text outside a `## You` or `## Assistant` message is ignored",
+                        number
+                    ));
+                    section = Section::Skipped { reported: true };
+                }
+                Section::Skipped { reported: true } => {}
+            }
+        }
+    }
+    finish(&section, &mut problems);
+
+    if let Some(("Assistant", line)) = first_role {
+        problems.push(format!(
+            "Line {}: the first message is the Assistant's, but requests have to start with a `## You` message",
+            line
+        ));
+    }
+    problems
+}
+
+// The role header a line like `### you` or `# Assistant:` was meant to be
+fn role_header_like(line: &str) -> Option<&'static str> {
+    let name = line.strip_prefix('#')?.trim_start_matches('#').trim();
+    match name.trim_end_matches(':').trim().to_lowercase().as_str() {
+        "you" | "user" | "human" => Some("## You"),
+        "assistant" | "claude" => Some("## Assistant"),
+        _ => None,
+    }
+}
+
+// Titles and tags parsed from each file, keyed by path. An entry is reused
+// while the file's mtime is unchanged, so listing doesn't re-read every
+// conversation. Kept in memory and in the cache directory.
+#[derive(Serialize, Deserialize, Default)]
+struct ListCache {
+    entries: HashMap<String, CachedInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedInfo {
+    modified: SystemTime,
+    title: Option<String>,
+    tags: Vec<String>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    uuid: Option<String>,
+    #[serde(default)]
+    alias: Option<String>,
+}
+
+const LIST_CACHE_FILE: &str = "list-cache.json";
+
+static LIST_CACHE: Mutex<Option<ListCache>> = Mutex::new(None);
+
+fn load_list_cache() -> ListCache {
+    paths::cache_dir()
+        .and_then(|dir| fs::read_to_string(dir.join(LIST_CACHE_FILE)).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_list_cache(cache: &ListCache) {
+    let Some(cache_dir) = paths::cache_dir() else {
+        return;
+    };
+
+    let result = fs::create_dir_all(&cache_dir)
+        .and_then(|_| serde_json::to_string(cache).map_err(io::Error::other))
+        .and_then(|content| fs::write(cache_dir.join(LIST_CACHE_FILE), content));
+
+    if let Err(e) = result {
+        debug!(error = %e, "Could not save conversation list cache");
+    }
+}
+
+fn read_info(path: &Path, modified: SystemTime) -> io::Result<CachedInfo> {
+    let header = read_header(path)?;
+    let (frontmatter, body) = split_frontmatter(&header);
+
+    Ok(CachedInfo {
+        modified,
+        title: parse_title(body),
+        tags: frontmatter_tags(&frontmatter),
+        parent: frontmatter_value(&frontmatter, "parent"),
+        project: frontmatter_value(&frontmatter, "project"),
+        uuid: frontmatter_uuid(
+            &frontmatter,
+            &path.file_stem().unwrap_or_default().to_string_lossy(),
+        ),
+        alias: frontmatter_value(&frontmatter, "alias"),
+    })
+}
+
+/// Lists the conversations in the conversations directory, excluding archived
+/// ones. Files unchanged since the last listing are not re-read.
 pub fn list_conversations() -> io::Result<Vec<ConversationInfo>> {
     let conversations_dir = get_conversations_dir()?;
 
@@ -265,36 +1374,75 @@ pub fn list_conversations() -> io::Result<Vec<ConversationInfo>> {
         return Ok(Vec::new());
     }
 
+    let mut guard = LIST_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = guard.get_or_insert_with(load_list_cache);
+    let mut changed = false;
+    let mut seen = HashSet::new();
     let mut conversations = Vec::new();
 
     for entry in fs::read_dir(&conversations_dir)? {
         let entry = entry?;
         let path = entry.path();
 
-        if path.extension().and_then(|s| s.to_str()) == Some("md") {
-            let content = fs::read_to_string(&path)?;
-            let (_, title) = parse_markdown_conversation(&content);
+        if path.extension().and_then(|s| s.to_str()) != Some("md") {
+            continue;
+        }
+
+        let name = path.to_string_lossy().into_owned();
+        let modified = entry.metadata()?.modified()?;
 
-            let id = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("unknown")
-                .to_string();
+        let info = match cache.entries.get(&name) {
+            Some(cached) if cached.modified == modified => cached.clone(),
+            _ => {
+                let info = read_info(&path, modified)?;
+                cache.entries.insert(name.clone(), info.clone());
+                changed = true;
+                info
+            }
+        };
+        seen.insert(name);
+
+        let id = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown")
+            .to_string();
 
-            conversations.push(ConversationInfo {
+        conversations.push((
+            modified,
+            ConversationInfo {
                 id,
-                title,
+                title: info.title,
+                tags: info.tags,
+                parent: info.parent,
+                project: info.project,
+                uuid: info.uuid,
+                alias: info.alias,
                 file_path: path,
-            });
-        }
+            },
+        ));
+    }
+
+    // Forget files in this directory that were deleted, renamed, or archived
+    let cached_count = cache.entries.len();
+    cache
+        .entries
+        .retain(|name, _| seen.contains(name) || !Path::new(name).starts_with(&conversations_dir));
+    changed |= cache.entries.len() != cached_count;
+
+    if changed {
+        save_list_cache(cache);
     }
 
+    debug!(
+        dir = %conversations_dir.display(),
+        count = conversations.len(),
+        changed,
+        "Listed conversations"
+    );
+
     // Sort by modification time (newest first)
-    conversations.sort_by(|a, b| {
-        let a_time = fs::metadata(&a.file_path).and_then(|m| m.modified()).ok();
-        let b_time = fs::metadata(&b.file_path).and_then(|m| m.modified()).ok();
-        b_time.cmp(&a_time)
-    });
+    conversations.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
 
-    Ok(conversations)
+    Ok(conversations.into_iter().map(|(_, info)| info).collect())
 }
@@ -0,0 +1,451 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use rye::conversation::{ConversationInfo, list_conversations};
+use rye::providers::{LLMProvider, Latency, ResponseStream, Timing, Usage, keys, timed};
+use rye::symbols::plain;
+use rye::{config, paths};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::io::{self, BufRead, BufReader as StdBufReader, Write};
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tracing::debug;
+
+// `rye daemon` keeps a provider (and with it warm HTTPS connections) and the
+// conversation list in memory. The CLI and TUI talk to it over a unix
+// socket, one JSON request per connection and one JSON reply per line, and
+// fall back to calling the API themselves when no daemon is running, or when
+// the daemon is set up differently (see `setup()`).
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Request {
+    Response {
+        model: String,
+        setup: String,
+        messages: Vec<(String, String)>,
+    },
+    Title {
+        model: String,
+        setup: String,
+        message: String,
+    },
+    Followups {
+        model: String,
+        setup: String,
+        messages: Vec<(String, String)>,
+    },
+    List,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Reply {
+    // The daemon's provider uses another model, or is otherwise set up
+    // differently, so the client should answer the request itself
+    Differs(String),
+    Started,
+    Chunk(String),
+    Done {
+        input_tokens: u32,
+        output_tokens: u32,
+        // Requests the daemon's provider sent again
+        #[serde(default)]
+        retries: u32,
+    },
+    Title(String),
+    Followups(Vec<String>),
+    Conversations(Vec<ListEntry>),
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct ListEntry {
+    id: String,
+    title: Option<String>,
+    tags: Vec<String>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    uuid: Option<String>,
+    #[serde(default)]
+    alias: Option<String>,
+    file_path: PathBuf,
+}
+
+// What decides how a request is answered besides its messages: the API
+// keys, every setting (`--set` and environment overrides included) and, for
+// responses, the request body with its system prompt and tools. Hashed, so
+// neither keys nor prompts go over the socket.
+fn setup(body: Option<&Value>) -> String {
+    let setup = json!({
+        "keys": keys::anthropic().fingerprint(),
+        "config": config::get(),
+        "body": body,
+    });
+    hmac_sha256::Hash::hash(setup.to_string().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn line(message: &impl Serialize) -> Vec<u8> {
+    let mut line = serde_json::to_vec(message).unwrap_or_default();
+    line.push(b'\n');
+    line
+}
+
+/// Listens on `paths::socket_path()` until interrupted
+pub async fn run(llm_provider: Arc<dyn LLMProvider>) -> Result<(), Box<dyn std::error::Error>> {
+    let socket_path = paths::socket_path().ok_or("Could not determine socket path")?;
+    if StdUnixStream::connect(&socket_path).is_ok() {
+        return Err(format!("A daemon is already listening on {}", socket_path.display()).into());
+    }
+    // Left behind by a daemon that didn't shut down cleanly
+    let _ = std::fs::remove_file(&socket_path);
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let listener = UnixListener::bind(&socket_path)?;
+    println!(
+        "{}",
+        plain(&format!("🥃 Daemon listening on {}", socket_path.display()))
+    );
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = accepted?;
+                let llm_provider = llm_provider.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_connection(stream, llm_provider.as_ref()).await {
+                        debug!(error = %e, "Daemon connection failed");
+                    }
+                });
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    let _ = std::fs::remove_file(&socket_path);
+    Ok(())
+}
+
+async fn serve_connection(stream: UnixStream, llm_provider: &dyn LLMProvider) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut request = String::new();
+    BufReader::new(reader).read_line(&mut request).await?;
+
+    let request: Request = match serde_json::from_str(&request) {
+        Ok(request) => request,
+        Err(e) => {
+            return writer
+                .write_all(&line(&Reply::Error(format!("Invalid request: {}", e))))
+                .await;
+        }
+    };
+
+    // Compared before anything is sent, a difference is answered locally
+    let differs = match &request {
+        Request::Response { model, .. }
+        | Request::Title { model, .. }
+        | Request::Followups { model, .. }
+            if model != llm_provider.model() =>
+        {
+            Some(format!("the model is {}", llm_provider.model()))
+        }
+        Request::Response {
+            setup: theirs,
+            messages,
+            ..
+        } if *theirs != setup(Some(&llm_provider.request_body(messages))) => {
+            Some("the request, settings or API key differ".to_string())
+        }
+        Request::Title { setup: theirs, .. } | Request::Followups { setup: theirs, .. }
+            if *theirs != setup(None) =>
+        {
+            Some("the settings or API key differ".to_string())
+        }
+        _ => None,
+    };
+    if let Some(reason) = differs {
+        return writer.write_all(&line(&Reply::Differs(reason))).await;
+    }
+
+    match request {
+        Request::Response { messages, .. } => {
+            // The provider's error isn't Send, so it's turned into text first
+            let stream = llm_provider
+                .generate_response_stream(&messages)
+                .await
+                .map_err(|e| e.to_string());
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => return writer.write_all(&line(&Reply::Error(e))).await,
+            };
+            writer.write_all(&line(&Reply::Started)).await?;
+
+            while let Some(result) = stream.next().await {
+                let reply = match result {
+                    Ok(chunk) => Reply::Chunk(chunk),
+                    Err(e) => Reply::Error(e.to_string()),
+                };
+                writer.write_all(&line(&reply)).await?;
+                if matches!(reply, Reply::Error(_)) {
+                    return Ok(());
+                }
+            }
+
+            let usage = llm_provider.last_usage();
+            let reply = Reply::Done {
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+                retries: llm_provider
+                    .last_latency()
+                    .map_or(0, |latency| latency.retries),
+            };
+            writer.write_all(&line(&reply)).await?;
+        }
+        Request::Title { message, .. } => {
+            let reply = match llm_provider.generate_title(&message).await {
+                Ok(title) => Reply::Title(title),
+                Err(e) => Reply::Error(e.to_string()),
+            };
+            writer.write_all(&line(&reply)).await?;
+        }
+        Request::Followups { messages, .. } => {
+            let reply = match llm_provider.generate_followups(&messages).await {
+                Ok(followups) => Reply::Followups(followups),
+                Err(e) => Reply::Error(e.to_string()),
+            };
+            writer.write_all(&line(&reply)).await?;
+        }
+        Request::List => {
+            let reply = match list_conversations() {
+                Ok(conversations) => Reply::Conversations(
+                    conversations
+                        .into_iter()
+                        .map(|info| ListEntry {
+                            id: info.id,
+                            title: info.title,
+                            tags: info.tags,
+                            parent: info.parent,
+                            project: info.project,
+                            uuid: info.uuid,
+                            alias: info.alias,
+                            file_path: info.file_path,
+                        })
+                        .collect(),
+                ),
+                Err(e) => Reply::Error(e.to_string()),
+            };
+            writer.write_all(&line(&reply)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The conversation list from a running daemon, or `None` without one
+pub fn list_conversations_from_daemon() -> Option<Vec<ConversationInfo>> {
+    let mut stream = StdUnixStream::connect(paths::socket_path()?).ok()?;
+    stream.write_all(&line(&Request::List)).ok()?;
+
+    let mut reply = String::new();
+    StdBufReader::new(stream).read_line(&mut reply).ok()?;
+    match serde_json::from_str(&reply).ok()? {
+        Reply::Conversations(entries) => Some(
+            entries
+                .into_iter()
+                .map(|entry| ConversationInfo {
+                    id: entry.id,
+                    title: entry.title,
+                    tags: entry.tags,
+                    parent: entry.parent,
+                    project: entry.project,
+                    uuid: entry.uuid,
+                    alias: entry.alias,
+                    file_path: entry.file_path,
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// A provider that sends requests through a running daemon and falls back to
+/// `local` when there is none or it uses another model
+pub struct DaemonClient {
+    local: Box<dyn LLMProvider>,
+    usage: Arc<Mutex<Usage>>,
+    // Measured here, so it includes the trip through the daemon
+    timing: Arc<Mutex<Timing>>,
+}
+
+impl DaemonClient {
+    pub fn new(local: Box<dyn LLMProvider>) -> Self {
+        Self {
+            local,
+            usage: Arc::default(),
+            timing: Arc::default(),
+        }
+    }
+
+    // Sends `request` and returns the first reply line with the rest of the
+    // connection, or `None` when the request should be handled locally
+    async fn send(&self, request: &Request) -> Option<(Reply, BufReader<UnixStream>)> {
+        let mut stream = UnixStream::connect(paths::socket_path()?).await.ok()?;
+        stream.write_all(&line(request)).await.ok()?;
+
+        let mut reader = BufReader::new(stream);
+        let mut reply = String::new();
+        reader.read_line(&mut reply).await.ok()?;
+        match serde_json::from_str(&reply).ok()? {
+            Reply::Differs(reason) => {
+                debug!(reason, "Daemon is set up differently, answering locally");
+                None
+            }
+            reply => Some((reply, reader)),
+        }
+    }
+}
+
+#[async_trait]
+impl LLMProvider for DaemonClient {
+    async fn generate_response_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<ResponseStream, Box<dyn std::error::Error>> {
+        *self.usage.lock().unwrap() = Usage::default();
+        *self.timing.lock().unwrap() = Timing::default();
+        let request = Request::Response {
+            model: self.local.model().to_string(),
+            setup: setup(Some(&self.local.request_body(messages))),
+            messages: messages.to_vec(),
+        };
+        let started = Instant::now();
+        let Some((reply, reader)) = self.send(&request).await else {
+            return self.local.generate_response_stream(messages).await;
+        };
+        *self.timing.lock().unwrap() = Timing::new(started);
+
+        match reply {
+            Reply::Started => {}
+            Reply::Error(e) => return Err(e.into()),
+            _ => return Err("Unexpected reply from daemon".into()),
+        }
+        debug!("Streaming response through daemon");
+
+        let usage = self.usage.clone();
+        let timing = self.timing.clone();
+        let lines = futures::stream::unfold(reader, |mut reader| async move {
+            let mut line = String::new();
+            match reader.read_line(&mut line).await {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some((line, reader)),
+            }
+        });
+        let stream = lines.filter_map(move |line| {
+            let usage = usage.clone();
+            let timing = timing.clone();
+            async move {
+                match serde_json::from_str(&line) {
+                    Ok(Reply::Chunk(chunk)) => Some(Ok(chunk)),
+                    Ok(Reply::Done {
+                        input_tokens,
+                        output_tokens,
+                        retries,
+                    }) => {
+                        *usage.lock().unwrap() = Usage {
+                            input_tokens,
+                            output_tokens,
+                        };
+                        timing.lock().unwrap().retries = retries;
+                        None
+                    }
+                    Ok(Reply::Error(e)) => Some(Err(
+                        Box::new(io::Error::other(e)) as Box<dyn std::error::Error + Send>
+                    )),
+                    _ => None,
+                }
+            }
+        });
+
+        Ok(timed(Box::pin(stream), self.timing.clone()))
+    }
+
+    async fn generate_title(
+        &self,
+        user_message: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let request = Request::Title {
+            model: self.local.model().to_string(),
+            setup: setup(None),
+            message: user_message.to_string(),
+        };
+        match self.send(&request).await {
+            Some((Reply::Title(title), _)) => Ok(title),
+            Some((Reply::Error(e), _)) => Err(e.into()),
+            Some(_) => Err("Unexpected reply from daemon".into()),
+            None => self.local.generate_title(user_message).await,
+        }
+    }
+
+    async fn generate_followups(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let request = Request::Followups {
+            model: self.local.model().to_string(),
+            setup: setup(None),
+            messages: messages.to_vec(),
+        };
+        match self.send(&request).await {
+            Some((Reply::Followups(followups), _)) => Ok(followups),
+            Some((Reply::Error(e), _)) => Err(e.into()),
+            Some(_) => Err("Unexpected reply from daemon".into()),
+            None => self.local.generate_followups(messages).await,
+        }
+    }
+
+    fn request_body(&self, messages: &[(String, String)]) -> serde_json::Value {
+        self.local.request_body(messages)
+    }
+
+    fn model(&self) -> &str {
+        self.local.model()
+    }
+
+    fn last_usage(&self) -> Usage {
+        // Responses answered locally record usage in the local provider
+        let usage = *self.usage.lock().unwrap();
+        if usage.total() > 0 {
+            usage
+        } else {
+            self.local.last_usage()
+        }
+    }
+
+    fn last_latency(&self) -> Option<Latency> {
+        self.timing
+            .lock()
+            .unwrap()
+            .latency()
+            .or_else(|| self.local.last_latency())
+    }
+
+    fn context_window(&self) -> u32 {
+        self.local.context_window()
+    }
+
+    fn cost(&self, usage: &Usage) -> f64 {
+        self.local.cost(usage)
+    }
+}
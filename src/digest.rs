@@ -0,0 +1,145 @@
+use crate::status::print_response_footer;
+use crate::usage_report::parse_since;
+use chrono::{DateTime, Local};
+use rye::conversation::{Conversation, list_conversations};
+use rye::providers::{LLMProvider, complete, excerpt};
+use rye::secrets;
+use rye::spinner::Spinner;
+use rye::streaming::stream_and_render_response;
+use rye::symbols::plain;
+use rye::tee;
+use rye::usage;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use std::time::Instant;
+
+// `rye digest --since 7d`: one markdown summary of every conversation
+// changed in a period, grouped by topic with the key answers and links to
+// the conversation files, for a work journal. Each conversation is cut to
+// an excerpt so a busy week still fits in one request.
+
+/// Instructions for writing the digest
+pub const SYSTEM_PROMPT: &str = "You write work-journal digests. You are given excerpts of the conversations someone had with an AI assistant over a period, each with its date and a markdown link to its file. Write a markdown digest of the period: a `#` heading naming the period, a short overview, then a `##` section per topic that groups related conversations, with the key answers, decisions and snippets worth keeping as bullets and the links to the conversations it draws on, copied exactly as given. End with a section of open questions if any were left unresolved. Be concise and leave out small talk.";
+
+/// Characters of each conversation given to the model
+const CONVERSATION_CHARS: usize = 4_000;
+
+/// Characters of conversations in one digest request; the oldest are left
+/// out past this
+const TOTAL_CHARS: usize = 200_000;
+
+/// Writes a digest of the conversations changed since `since` to `output`,
+/// or shows it when there is none
+pub async fn run(
+    llm_provider: &dyn LLMProvider,
+    since: &str,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cutoff = parse_since(since, "--since")?;
+
+    let mut conversations = Vec::new();
+    for info in list_conversations()? {
+        let modified: Option<DateTime<Local>> = std::fs::metadata(&info.file_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(DateTime::from);
+        let Some(modified) = modified.filter(|modified| *modified >= cutoff) else {
+            continue;
+        };
+        match Conversation::load(&info.id) {
+            Ok(conversation) if !conversation.messages.is_empty() => {
+                conversations.push((modified, conversation))
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Warning: Skipping {}: {}", info.file_path.display(), e),
+        }
+    }
+    if conversations.is_empty() {
+        println!(
+            "No conversations since {}.",
+            cutoff.format("%Y-%m-%d %H:%M")
+        );
+        return Ok(());
+    }
+
+    // Newest first while filling the budget, then in the order they happened
+    conversations.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    let mut sections = Vec::new();
+    let mut total = 0;
+    for (modified, conversation) in &conversations {
+        let section = format!(
+            "### {}\n\nDate: {}\nLink: [{}](<{}>)\n\n{}",
+            conversation.title.as_deref().unwrap_or(&conversation.id),
+            modified.format("%Y-%m-%d"),
+            conversation.title.as_deref().unwrap_or(&conversation.id),
+            conversation.file_path.display(),
+            excerpt(&conversation.messages, CONVERSATION_CHARS)
+        );
+        total += section.len();
+        if total > TOTAL_CHARS && !sections.is_empty() {
+            break;
+        }
+        sections.push(section);
+    }
+    if sections.len() < conversations.len() {
+        eprintln!(
+            "Warning: The digest covers the newest {} of {} conversations, the rest don't fit in one request",
+            sections.len(),
+            conversations.len()
+        );
+    }
+    sections.reverse();
+
+    let prompt = format!(
+        "Write a digest of these {} conversations from {} to {}.\n\n{}",
+        sections.len(),
+        cutoff.format("%Y-%m-%d"),
+        Local::now().format("%Y-%m-%d"),
+        sections.join("\n\n")
+    );
+    let Some(prompt) = secrets::check(&prompt)? else {
+        return Err("Cancelled, nothing sent".into());
+    };
+    let messages = [("user".to_string(), prompt)];
+
+    let digest = match output {
+        Some(path) => {
+            let spinner = io::stderr()
+                .is_terminal()
+                .then(|| Spinner::start("Writing the digest..."));
+            let digest = complete(llm_provider, &messages).await;
+            if let Some(spinner) = spinner {
+                spinner.stop().await;
+            }
+            let digest = digest?;
+            std::fs::write(path, format!("{}\n", digest.trim()))
+                .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            eprintln!(
+                "{}",
+                plain(&format!("📰 Wrote the digest to {}", path.display()))
+            );
+            digest
+        }
+        // Scripts get the raw markdown
+        None if io::stdout().is_terminal() => {
+            let request_started = Instant::now();
+            let stream = llm_provider.generate_response_stream(&messages).await?;
+            let spinner = Spinner::start("Writing the digest...");
+            let digest = stream_and_render_response(stream, Some(spinner)).await?;
+            print_response_footer(request_started.elapsed(), llm_provider)?;
+            digest
+        }
+        None => {
+            let digest = complete(llm_provider, &messages).await?;
+            tee::write(&digest);
+            tee::finish();
+            println!("{}", digest.trim());
+            digest
+        }
+    };
+    usage::record(llm_provider, None);
+    if digest.trim().is_empty() {
+        return Err("The model returned an empty digest".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use crate::editor;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use rye::paths;
+use rye::prompt;
+use rye::symbols::plain;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use tracing::warn;
+use unicode_width::UnicodeWidthChar;
+
+// A message that's been typed but not sent is kept in `draft.md` in the data
+// directory: the TUI's input box and the line-based chat's input line on
+// every change, and the message until it's saved to the conversation. A
+// crash, a closed terminal, Ctrl-C or a cancelled send leaves it behind, and
+// the next session offers it back. A protected conversation's messages are
+// never kept, the file would have them in the clear.
+
+fn path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("draft.md"))
+}
+
+/// Keeps `text` as the draft, or removes the draft when `text` is blank
+pub fn save(text: &str) {
+    let Some(path) = path() else {
+        return;
+    };
+    let result = if text.trim().is_empty() {
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    } else {
+        path.parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, text))
+    };
+    // Called on every keystroke in the TUI, so failures are only logged
+    if let Err(e) = result {
+        warn!(error = %e, path = %path.display(), "Could not save draft");
+    }
+}
+
+/// Removes the draft once its message is sent
+pub fn clear() {
+    save("");
+}
+
+/// Reads the rest of an input line that started with `first`, with the
+/// terminal in raw mode, keeping it as the draft after every key until Enter
+/// when `keep`; a message is kept again until it's sent. `None` when Ctrl-C
+/// cut it short, leaving the draft for the next session.
+pub fn read_line(first: char, keep: bool) -> io::Result<Option<String>> {
+    let save = |text: &str| {
+        if keep {
+            save(text);
+        }
+    };
+    let mut line = first.to_string();
+    save(&line);
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+        match key.code {
+            KeyCode::Enter => {
+                clear();
+                return Ok(Some(line));
+            }
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(None);
+            }
+            KeyCode::Char(c) => {
+                line.push(c);
+                print!("{}", c);
+            }
+            KeyCode::Backspace => {
+                if let Some(c) = line.pop() {
+                    let width = c.width().unwrap_or(1);
+                    print!("{0}{1}{0}", "\u{8}".repeat(width), " ".repeat(width));
+                }
+            }
+            _ => continue,
+        }
+        io::stdout().flush()?;
+        save(&line);
+    }
+}
+
+/// The draft left by an earlier session, if any, trimmed
+pub fn load() -> Option<String> {
+    std::fs::read_to_string(path()?)
+        .ok()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// Offers the draft left by an earlier session at the start of a line-based
+/// chat. `Some` message to send right away, possibly edited in `$EDITOR`
+/// first; `None` when there is no draft or it's kept for later or discarded.
+pub fn offer() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let (Some(path), Some(draft)) = (path(), load()) else {
+        return Ok(None);
+    };
+    let first_line = draft.lines().next().unwrap_or_default();
+    let mut preview: String = first_line.chars().take(60).collect();
+    if preview.len() < draft.len() {
+        preview.push('…');
+    }
+    println!(
+        "{}",
+        plain(&format!(
+            "📝 Unsent draft from last time ({} characters): {}",
+            draft.chars().count(),
+            preview
+        ))
+    );
+
+    let choice = prompt::choose(
+        "[E]dit it, [s]end it, [k]eep it for later, or [d]iscard it?",
+        &["edit", "send", "keep", "discard"],
+        Some('e'),
+    )?;
+    match choice {
+        Some('e') => {
+            if let Err(e) = editor::open(&path) {
+                eprintln!("Warning: {}. The draft is kept for later.", e);
+                return Ok(None);
+            }
+            let edited = load();
+            if edited.is_none() {
+                println!("The draft is empty now, nothing sent.");
+            }
+            Ok(edited)
+        }
+        Some('s') => Ok(Some(draft)),
+        Some('d') => {
+            clear();
+            Ok(None)
+        }
+        // Kept for later, also at the end of input
+        _ => Ok(None),
+    }
+}
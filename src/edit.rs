@@ -0,0 +1,122 @@
+use crate::editor;
+use rye::conversation::{Conversation, check_file};
+use rye::hooks;
+use rye::prompt;
+use rye::symbols::plain;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+// `rye edit <id>` opens a conversation file in `$EDITOR` for manual cleanup,
+// then checks that it still reads the way it looks: a mistyped `## You`
+// would otherwise silently merge or drop messages the next time it loads.
+// `/open` does the same from the chat and carries on with the edited file.
+
+/// Edits a conversation's file and checks it afterwards, offering to edit
+/// again or revert when something is off
+pub async fn run(id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let conversation =
+        Conversation::load_metadata(id).map_err(|_| format!("No conversation '{}'", id))?;
+    let (path, old_title) = (conversation.file_path, conversation.title);
+    let original = std::fs::read_to_string(&path)?;
+
+    let content = loop {
+        editor::open(&path)?;
+        let content = std::fs::read_to_string(&path)?;
+        let problems = check_file(&content);
+        if problems.is_empty() {
+            break content;
+        }
+
+        eprintln!("Warning: {} won't load the way it looks:", path.display());
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        if !io::stdin().is_terminal() {
+            break content;
+        }
+        let choice = prompt::choose(
+            "[E]dit again, [k]eep it as it is, or [r]evert your changes?",
+            &["edit", "keep", "revert"],
+            Some('e'),
+        )?;
+        match choice {
+            Some('e') => {}
+            Some('r') => {
+                std::fs::write(&path, &original)?;
+                println!("Reverted, the conversation is as it was.");
+                return Ok(());
+            }
+            // Kept, also at the end of input
+            _ => break content,
+        }
+    };
+
+    if content == original {
+        println!("No changes.");
+        return Ok(());
+    }
+
+    let conversation = reload(&path, old_title.as_ref()).await?;
+    println!(
+        "{}",
+        plain(&format!(
+            "✏️  Saved {} ({} messages).",
+            conversation.title.as_deref().unwrap_or(&conversation.id),
+            conversation.messages.len()
+        ))
+    );
+    Ok(())
+}
+
+/// Opens the chat's conversation file for `/open` and returns the
+/// conversation as it reads afterwards, `None` when it's unchanged
+pub async fn open_in_chat(
+    conversation: &Conversation,
+) -> Result<Option<Conversation>, Box<dyn std::error::Error>> {
+    let path = &conversation.file_path;
+    let original = std::fs::read_to_string(path)?;
+    if !editor::launch(path)? {
+        print!(
+            "Opened {}. Press Enter once you've saved your changes... ",
+            path.display()
+        );
+        io::stdout().flush()?;
+        io::stdin().read_line(&mut String::new())?;
+    }
+
+    let content = std::fs::read_to_string(path)?;
+    if content == original {
+        println!("No changes.");
+        return Ok(None);
+    }
+    let problems = check_file(&content);
+    if !problems.is_empty() {
+        eprintln!("Warning: {} doesn't read the way it looks:", path.display());
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        eprintln!("Continuing with what it reads as; /open again to fix it.");
+    }
+    Ok(Some(reload(path, conversation.title.as_ref()).await?))
+}
+
+// Loads an edited conversation file, pushes it, and runs the `on_save` hook
+async fn reload(
+    path: &Path,
+    old_title: Option<&String>,
+) -> Result<Conversation, Box<dyn std::error::Error>> {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or("Invalid conversation file name")?;
+    let mut conversation = Conversation::load(stem)?;
+    // A changed `# Title` renames the file, as a generated one would
+    match conversation.title.clone() {
+        Some(title) if Some(&title) != old_title => conversation.set_title(title).await?,
+        _ => conversation.push().await?,
+    }
+    if let Err(e) = hooks::on_save(&conversation.file_path).await {
+        eprintln!("Warning: on_save hook: {}", e);
+    }
+    Ok(conversation)
+}
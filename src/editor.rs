@@ -0,0 +1,50 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Opens `path` in `$VISUAL` or `$EDITOR` (vi by default) and waits for it
+/// to close
+pub fn open(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Editors are often set with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or("$EDITOR is empty")?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Could not run {}: {}", program, e))?;
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status).into());
+    }
+    Ok(())
+}
+
+/// Opens `path` in `$VISUAL` or `$EDITOR` when either is set, otherwise in
+/// the desktop's app for it (`open` on macOS, `xdg-open` elsewhere), falling
+/// back to vi without one. Returns whether it waited for the editor to close,
+/// which the desktop's app usually doesn't.
+pub fn launch(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    if std::env::var_os("VISUAL").is_some() || std::env::var_os("EDITOR").is_some() {
+        open(path)?;
+        return Ok(true);
+    }
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    match Command::new(opener)
+        .arg(path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+    {
+        Ok(status) if status.success() => Ok(false),
+        _ => {
+            open(path)?;
+            Ok(true)
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use async_trait::async_trait;
+use rye::plugins::Plugins;
+use rye::prompt;
+use rye::providers::{ToolDefinition, ToolRunner};
+use rye::render::{format_code, terminal_width};
+use rye::symbols::plain;
+use serde_json::{Value, json};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// With `edits.enabled` the chat model gets an `edit_file` tool that proposes
+// changes to files under the current directory as unified diffs. A proposal
+// is only checked against the file while the response streams; once it's
+// done, each hunk is shown and applied if confirmed, and what was applied is
+// kept with the response so the model sees it on the next turn.
+
+const TOOL_NAME: &str = "edit_file";
+
+// A `@@` section of a unified diff
+struct Hunk {
+    // Line the old text starts on, from 1; 0 for a new file
+    old_start: usize,
+    old: Vec<String>,
+    new: Vec<String>,
+    // As the model wrote it, `@@` line included
+    text: String,
+}
+
+struct Proposal {
+    // As the model gave it
+    path: String,
+    full_path: PathBuf,
+    hunks: Vec<Hunk>,
+}
+
+/// The edits the model proposed during the current response
+#[derive(Default)]
+pub struct FileEdits {
+    proposals: Mutex<Vec<Proposal>>,
+}
+
+impl FileEdits {
+    fn definition() -> ToolDefinition {
+        let dir = std::env::current_dir()
+            .map(|dir| dir.display().to_string())
+            .unwrap_or_else(|_| ".".to_string());
+        ToolDefinition {
+            name: TOOL_NAME.to_string(),
+            description: format!(
+                "Propose a change to a text file under the user's current directory ({}) as a unified diff. Give `@@ -start,count +start,count @@` hunks with a few unchanged context lines, copied exactly from the file; to create a file, use one `@@ -0,0 +1,N @@` hunk of added lines. The user reviews each hunk after your response and the outcome is added to the conversation, so don't assume the change was made until then.",
+                dir
+            ),
+            input_schema: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path of the file, relative to the current directory"
+                    },
+                    "diff": {
+                        "type": "string",
+                        "description": "Unified diff of the change"
+                    }
+                },
+                "required": ["path", "diff"]
+            }),
+        }
+    }
+
+    // Checks that the diff applies to the file as it is and keeps it for review
+    fn propose(&self, input: Value) -> Result<String, String> {
+        let path = input["path"].as_str().ok_or("`path` is missing")?;
+        let diff = input["diff"].as_str().ok_or("`diff` is missing")?;
+        let full_path = resolve(path)?;
+        let hunks = parse_diff(diff)?;
+
+        let mut content = read_lines(&full_path)?;
+        for (i, hunk) in hunks.iter().enumerate() {
+            content = apply(&content, hunk).map_err(|e| format!("Hunk {}: {}", i + 1, e))?;
+        }
+
+        let count = hunks.len();
+        self.proposals.lock().unwrap().push(Proposal {
+            path: path.to_string(),
+            full_path,
+            hunks,
+        });
+        Ok(format!(
+            "The change to {} ({} hunk{}) applies cleanly. The user will review it after your response.",
+            path,
+            count,
+            if count == 1 { "" } else { "s" }
+        ))
+    }
+
+    /// Drops the proposals of a response that failed, so they aren't
+    /// reviewed with the next one
+    pub fn discard(&self) {
+        self.proposals.lock().unwrap().clear();
+    }
+
+    /// Shows each hunk proposed during the response just finished, applies
+    /// the ones confirmed and returns a markdown record of the outcome, empty
+    /// when nothing was proposed
+    pub fn review(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let proposals = std::mem::take(&mut *self.proposals.lock().unwrap());
+        let mut records = Vec::new();
+        let mut all = false;
+        let mut quit = false;
+
+        for proposal in proposals {
+            let total = proposal.hunks.len();
+            println!(
+                "{}",
+                plain(&format!(
+                    "\n✏️  Proposed edit to {} ({} hunk{})",
+                    proposal.path,
+                    total,
+                    if total == 1 { "" } else { "s" }
+                ))
+            );
+            let mut content = read_lines(&proposal.full_path)?;
+            let mut applied = Vec::new();
+
+            for (i, hunk) in proposal.hunks.iter().enumerate() {
+                if quit {
+                    break;
+                }
+                let block = format!("```diff\n{}\n```\n", hunk.text.trim_end());
+                println!("{}", format_code(&block, i + 1, terminal_width()));
+                if !all {
+                    match ask_hunk()? {
+                        'n' => continue,
+                        'q' => {
+                            quit = true;
+                            break;
+                        }
+                        'a' => all = true,
+                        _ => {}
+                    }
+                }
+                // Earlier hunks may have been skipped, or the file changed
+                // since the proposal was checked
+                match apply(&content, hunk) {
+                    Ok(edited) => {
+                        content = edited;
+                        applied.push(hunk);
+                    }
+                    Err(e) => eprintln!("Warning: Hunk {} not applied: {}", i + 1, e),
+                }
+            }
+
+            if applied.is_empty() {
+                println!("Left {} unchanged.", proposal.path);
+                records.push(format!(
+                    "*Edit to `{}` declined, 0 of {} hunks applied.*",
+                    proposal.path, total
+                ));
+                continue;
+            }
+            write_lines(&proposal.full_path, &content)?;
+            println!(
+                "Applied {} of {} hunks to {}.",
+                applied.len(),
+                total,
+                proposal.path
+            );
+            let patch: Vec<&str> = applied.iter().map(|hunk| hunk.text.trim_end()).collect();
+            records.push(format!(
+                "*Edited `{}`, {} of {} hunks applied:*\n\n```diff\n--- a/{}\n+++ b/{}\n{}\n```",
+                proposal.path,
+                applied.len(),
+                total,
+                proposal.path,
+                proposal.path,
+                patch.join("\n")
+            ));
+        }
+        Ok(records.join("\n\n"))
+    }
+}
+
+fn ask_hunk() -> io::Result<char> {
+    let answer = prompt::choose(
+        "Apply this hunk? [y]es, [n]o, [a]ll the rest, [q]uit",
+        &["yes", "no", "all", "quit"],
+        None,
+    )?;
+    Ok(answer.unwrap_or('q'))
+}
+
+// Paths stay under the current directory
+fn resolve(path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(path);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(format!(
+            "'{}' isn't under the current directory, give a relative path without `..`",
+            path
+        ));
+    }
+    std::env::current_dir()
+        .map(|dir| dir.join(relative))
+        .map_err(|e| e.to_string())
+}
+
+// The file's lines, none for a file that doesn't exist yet
+fn read_lines(path: &Path) -> Result<Vec<String>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(content.lines().map(str::to_string).collect()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Could not read {}: {}", path.display(), e)),
+    }
+}
+
+// Keeps the file's line endings, and ends it with one
+fn write_lines(path: &Path, lines: &[String]) -> io::Result<()> {
+    let newline = match std::fs::read_to_string(path) {
+        Ok(content) if content.contains("\r\n") => "\r\n",
+        _ => "\n",
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut content = lines.join(newline);
+    content.push_str(newline);
+    std::fs::write(path, content)
+}
+
+fn parse_diff(diff: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks: Vec<Hunk> = Vec::new();
+    for line in diff.lines() {
+        if let Some(header) = line.strip_prefix("@@") {
+            // `-12,5 +12,7 @@`, counts optional
+            let old_start = header
+                .split_whitespace()
+                .find_map(|range| range.strip_prefix('-'))
+                .and_then(|range| range.split(',').next())
+                .and_then(|start| start.parse().ok())
+                .ok_or_else(|| format!("Can't read the hunk header `{}`", line))?;
+            hunks.push(Hunk {
+                old_start,
+                old: Vec::new(),
+                new: Vec::new(),
+                text: format!("{}\n", line),
+            });
+            continue;
+        }
+        // File headers and anything else before the first hunk
+        let Some(hunk) = hunks.last_mut() else {
+            continue;
+        };
+        match line.chars().next() {
+            Some('+') => hunk.new.push(line[1..].to_string()),
+            Some('-') => hunk.old.push(line[1..].to_string()),
+            Some(' ') => {
+                hunk.old.push(line[1..].to_string());
+                hunk.new.push(line[1..].to_string());
+            }
+            // `\ No newline at end of file`
+            Some('\\') => continue,
+            // A blank context line that lost its leading space
+            None => {
+                hunk.old.push(String::new());
+                hunk.new.push(String::new());
+            }
+            Some(_) => return Err(format!("Unexpected line in hunk: `{}`", line)),
+        }
+        hunk.text.push_str(line);
+        hunk.text.push('\n');
+    }
+    if hunks.is_empty() {
+        return Err("The diff has no `@@` hunks".to_string());
+    }
+    Ok(hunks)
+}
+
+// `content` with the hunk applied where its old lines are, preferring the
+// spot nearest its line number, since models often get those slightly wrong
+fn apply(content: &[String], hunk: &Hunk) -> Result<Vec<String>, String> {
+    let at = if hunk.old.is_empty() {
+        // Pure additions go after line `old_start`
+        hunk.old_start.min(content.len())
+    } else {
+        let expected = hunk.old_start.saturating_sub(1);
+        (0..=content.len().saturating_sub(hunk.old.len()))
+            .filter(|&i| {
+                content.len() >= i + hunk.old.len()
+                    && content[i..i + hunk.old.len()]
+                        .iter()
+                        .zip(&hunk.old)
+                        .all(|(a, b)| a.trim_end() == b.trim_end())
+            })
+            .min_by_key(|&i| i.abs_diff(expected))
+            .ok_or("its context and removed lines aren't in the file as written")?
+    };
+
+    let mut edited = content[..at].to_vec();
+    edited.extend(hunk.new.iter().cloned());
+    edited.extend_from_slice(&content[at + hunk.old.len()..]);
+    Ok(edited)
+}
+
+/// The plugin tools together with `edit_file`
+pub struct Toolbox {
+    plugins: Arc<Plugins>,
+    edits: Arc<FileEdits>,
+}
+
+impl Toolbox {
+    pub fn new(plugins: Arc<Plugins>, edits: Arc<FileEdits>) -> Self {
+        Self { plugins, edits }
+    }
+}
+
+#[async_trait]
+impl ToolRunner for Toolbox {
+    fn definitions(&self) -> Vec<ToolDefinition> {
+        let mut definitions = self.plugins.definitions();
+        definitions.push(FileEdits::definition());
+        definitions
+    }
+
+    async fn call(&self, name: &str, input: Value) -> Result<String, String> {
+        if name == TOOL_NAME {
+            self.edits.propose(input)
+        } else {
+            self.plugins.call(name, input).await
+        }
+    }
+}
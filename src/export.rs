@@ -0,0 +1,124 @@
+use rye::conversation::Conversation;
+use rye::providers::{LLMProvider, complete};
+use rye::secrets;
+use rye::spinner::Spinner;
+use rye::symbols::plain;
+use rye::usage;
+use serde::Deserialize;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+// `rye export --format anki <id>`: the model turns a conversation into
+// question and answer pairs, written as a tab-separated file that Anki
+// imports (File > Import) as Basic notes tagged `rye` and with the
+// conversation's tags.
+
+/// Formats `rye export` can write
+pub const FORMATS: [&str; 1] = ["anki"];
+
+/// Instructions for turning a conversation into flashcards
+pub const ANKI_SYSTEM_PROMPT: &str = "You turn conversations into flashcards for spaced repetition. Pick out the facts, concepts, commands and techniques worth remembering from the conversation you are given and write one card for each. Questions must make sense on their own, without the conversation; answers should be short, a sentence or two or a small code snippet. Skip small talk and anything specific to the moment. Respond with ONLY a JSON array of objects with string fields `question` and `answer`, and nothing before or after it.";
+
+#[derive(Deserialize)]
+struct Card {
+    question: String,
+    answer: String,
+}
+
+/// Exports conversation `id` in `format` to `output`, or stdout when there
+/// is none
+pub async fn run(
+    llm_provider: &dyn LLMProvider,
+    id: &str,
+    format: &str,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Only one format so far; clap keeps out the rest
+    debug_assert_eq!(format, "anki");
+    let conversation = Conversation::load(id).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("No conversation '{}'", id),
+        _ => e.to_string(),
+    })?;
+    if conversation.messages.is_empty() {
+        return Err("The conversation has no messages to make cards from".into());
+    }
+
+    let transcript = conversation
+        .messages
+        .iter()
+        .map(|(role, content)| {
+            let role = if role == "user" { "You" } else { "Assistant" };
+            format!("## {}\n\n{}", role, content.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let Some(transcript) = secrets::check(&transcript)? else {
+        return Err("Cancelled, nothing sent".into());
+    };
+
+    let spinner = io::stderr()
+        .is_terminal()
+        .then(|| Spinner::start("Writing flashcards..."));
+    let reply = complete(llm_provider, &[("user".to_string(), transcript)]).await;
+    if let Some(spinner) = spinner {
+        spinner.stop().await;
+    }
+    usage::record(llm_provider, Some(&conversation.id));
+    let cards = parse_cards(&reply?)?;
+
+    let mut tags = vec!["rye".to_string()];
+    // Anki separates tags with spaces
+    tags.extend(conversation.tags.iter().map(|tag| tag.replace(' ', "_")));
+    let mut tsv = String::from("#separator:tab\n#html:true\n#tags column:3\n");
+    for card in &cards {
+        tsv.push_str(&format!(
+            "{}\t{}\t{}\n",
+            anki_field(&card.question),
+            anki_field(&card.answer),
+            tags.join(" ")
+        ));
+    }
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, tsv)
+                .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            eprintln!(
+                "{}",
+                plain(&format!(
+                    "🗂  Wrote {} cards to {}. Import it in Anki with File > Import.",
+                    cards.len(),
+                    path.display()
+                ))
+            );
+        }
+        None => io::stdout().write_all(tsv.as_bytes())?,
+    }
+    Ok(())
+}
+
+// The cards in the model's reply, a JSON array
+fn parse_cards(reply: &str) -> Result<Vec<Card>, Box<dyn std::error::Error>> {
+    let cards: Vec<Card> = serde_json::from_str(reply.trim())
+        .map_err(|e| format!("The model didn't answer with a list of cards: {}", e))?;
+    let cards: Vec<Card> = cards
+        .into_iter()
+        .filter(|card| !card.question.trim().is_empty() && !card.answer.trim().is_empty())
+        .collect();
+    if cards.is_empty() {
+        return Err("The model found nothing to make cards from".into());
+    }
+    Ok(cards)
+}
+
+// A field of an HTML-enabled Anki import: escaped, with line breaks as
+// `<br>` and no tabs
+fn anki_field(text: &str) -> String {
+    text.trim()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\t', "    ")
+        .replace("\r\n", "\n")
+        .replace('\n', "<br>")
+}
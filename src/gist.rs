@@ -0,0 +1,96 @@
+use reqwest::Client;
+use rye::auth;
+use rye::conversation::{Conversation, strip_frontmatter};
+use rye::secrets;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::process::Command;
+
+// `/gist` shares the last response, and `/gist all` the whole conversation,
+// as a secret GitHub gist. The token comes from `GITHUB_TOKEN` or `GH_TOKEN`
+// (it needs the `gist` scope), else from `rye auth login github`, or else
+// from the GitHub CLI's login.
+
+const GISTS_URL: &str = "https://api.github.com/gists";
+
+#[derive(Deserialize)]
+struct Gist {
+    html_url: String,
+}
+
+async fn token() -> Result<String, Box<dyn std::error::Error>> {
+    for var in ["GITHUB_TOKEN", "GH_TOKEN"] {
+        if let Ok(token) = std::env::var(var)
+            && !token.trim().is_empty()
+        {
+            return Ok(token.trim().to_string());
+        }
+    }
+    if let Some(token) = auth::token(auth::service("github")?).await? {
+        return Ok(token);
+    }
+    match Command::new("gh").args(["auth", "token"]).output().await {
+        Ok(output) if output.status.success() => {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        _ => Err(
+            "No GitHub token. Set GITHUB_TOKEN to a token with the gist scope, or log in with `rye auth login` or `gh auth login`."
+                .into(),
+        ),
+    }
+}
+
+/// Posts the last response, or with `whole` the conversation, as a secret
+/// gist and returns its URL. `None` when the user cancels at the secrets
+/// check.
+pub async fn publish(
+    conversation: &Conversation,
+    whole: bool,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let content = if whole {
+        let content = tokio::fs::read_to_string(&conversation.file_path).await?;
+        strip_frontmatter(&content).trim().to_string()
+    } else {
+        conversation
+            .messages
+            .iter()
+            .rev()
+            .find(|(role, _)| role == "assistant")
+            .map(|(_, content)| content.clone())
+            .ok_or("No response to share yet")?
+    };
+    let Some(content) = secrets::check(&content)? else {
+        return Ok(None);
+    };
+
+    let title = conversation.title.as_deref().unwrap_or("Conversation");
+    let file_name = conversation
+        .file_path
+        .file_name()
+        .filter(|_| whole)
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "response.md".to_string());
+    let body = json!({
+        "description": title,
+        "public": false,
+        "files": { file_name: { "content": content } },
+    });
+
+    let response = Client::new()
+        .post(GISTS_URL)
+        .bearer_auth(token().await?)
+        .header("Accept", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", "2022-11-28")
+        .header("User-Agent", "rye")
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Could not reach GitHub: {}", e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!("GitHub answered {}: {}", status, text.trim()).into());
+    }
+    let gist: Gist = response.json().await?;
+    Ok(Some(gist.html_url))
+}
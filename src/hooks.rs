@@ -0,0 +1,107 @@
+use crate::config;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+// Shell commands from the `[hooks]` config section. Each runs with the
+// conversation's file in RYE_CONVERSATION_FILE.
+
+pub(crate) fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+// Runs `command` with `input` on stdin and returns its stdout
+async fn run(
+    command: &str,
+    input: &str,
+    conversation_file: &Path,
+    stdout: Stdio,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut child = shell(command)
+        .env("RYE_CONVERSATION_FILE", conversation_file)
+        .stdin(Stdio::piped())
+        .stdout(stdout)
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Written while the output is read, so a hook echoing a large input
+    // doesn't fill its stdout pipe while rye still waits to finish writing
+    let stdin = child.stdin.take();
+    let write = async move {
+        if let Some(mut stdin) = stdin {
+            // A hook that doesn't read its input closes the pipe early, which is fine
+            let _ = stdin.write_all(input.as_bytes()).await;
+        }
+    };
+    let ((), output) = tokio::join!(write, child.wait_with_output());
+    let output = output?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "`{}` failed ({}): {}",
+            command,
+            output.status,
+            stderr.trim()
+        )
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Passes an outgoing message through the `pre_send` hook, which prints the
+/// message to send. An error means the message shouldn't be sent.
+pub async fn pre_send(
+    message: &str,
+    conversation_file: &Path,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let Some(command) = &config::get().hooks.pre_send else {
+        return Ok(message.to_string());
+    };
+
+    let rewritten = run(command, message, conversation_file, Stdio::piped()).await?;
+    // Only the newline most commands end with, indentation is the message's
+    Ok(rewritten.trim_end_matches(['\n', '\r']).to_string())
+}
+
+/// Feeds a finished response to the `post_response` hook. Its output goes to
+/// the terminal when `show_output` is set, otherwise it's discarded.
+pub async fn post_response(
+    response: &str,
+    conversation_file: &Path,
+    show_output: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(command) = &config::get().hooks.post_response else {
+        return Ok(());
+    };
+
+    let stdout = if show_output {
+        Stdio::inherit()
+    } else {
+        Stdio::null()
+    };
+    run(command, response, conversation_file, stdout).await?;
+    Ok(())
+}
+
+/// Runs the `on_save` hook once an exchange has been written to the
+/// conversation file
+pub async fn on_save(conversation_file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(command) = &config::get().hooks.on_save else {
+        return Ok(());
+    };
+
+    run(command, "", conversation_file, Stdio::null()).await?;
+    Ok(())
+}
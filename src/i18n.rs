@@ -0,0 +1,86 @@
+use crate::config;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::OnceLock;
+use tracing::debug;
+
+// User-facing strings of the chat go through `t()` (or `tf()` when they have
+// `{}` placeholders, filled in order), keyed on their English text. The
+// locale comes from the `locale` setting, else `LC_ALL`, `LC_MESSAGES` or
+// `LANG`, and picks a catalog from `src/locales/<language>.toml` that maps
+// English to the translation. Strings a catalog lacks stay in English, so a
+// translation can be partial. Errors that end rye are looked up whole with
+// `translate()`, so those with fixed text are translated too. `--help`
+// (clap's, generated from the doc comments) and the output of subcommands
+// other than the chat stay English.
+
+// Catalogs shipped with rye, by language code
+const CATALOGS: [(&str, &str); 1] = [("de", include_str!("locales/de.toml"))];
+
+// The language code in a locale like `de_DE.UTF-8` or `pt-BR`
+fn language(locale: &str) -> String {
+    locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// The language user-facing strings are shown in, e.g. `en` or `de`
+pub fn locale() -> String {
+    if let Some(locale) = &config::get().locale {
+        return language(locale);
+    }
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| language(&value))
+        .filter(|language| language != "c" && language != "posix")
+        .unwrap_or_else(|| "en".to_string())
+}
+
+fn catalog() -> &'static HashMap<String, String> {
+    static CATALOG: OnceLock<HashMap<String, String>> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        let locale = locale();
+        let Some((_, source)) = CATALOGS.iter().find(|(code, _)| *code == locale) else {
+            if locale != "en" && config::get().locale.is_some() {
+                eprintln!(
+                    "Warning: No translation for locale '{}', showing English",
+                    locale
+                );
+            }
+            return HashMap::new();
+        };
+        toml::from_str(source).unwrap_or_else(|e| {
+            debug!(locale, error = %e, "Could not parse the translation catalog");
+            HashMap::new()
+        })
+    })
+}
+
+/// `english` in the current locale
+pub fn t(english: &'static str) -> &'static str {
+    catalog().get(english).map_or(english, String::as_str)
+}
+
+/// `text` in the current locale when the catalog has it, for text built at
+/// runtime like error messages
+pub fn translate(text: &str) -> &str {
+    catalog().get(text).map_or(text, String::as_str)
+}
+
+/// `english` in the current locale, with its `{}` placeholders replaced by
+/// `args` in order
+pub fn tf(english: &'static str, args: &[&dyn Display]) -> String {
+    let mut parts = t(english).split("{}");
+    let mut output = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            output.push_str(&arg.to_string());
+        }
+        output.push_str(part);
+    }
+    output
+}
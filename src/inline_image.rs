@@ -0,0 +1,260 @@
+use crate::config;
+use crate::symbols;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use crossterm::{
+    execute,
+    style::{Attribute, Print, SetAttribute},
+    terminal,
+};
+use image::{DynamicImage, ImageFormat, imageops::FilterType};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Approximate cell size used to pick how many columns an image spans
+const CELL_WIDTH_PX: u32 = 10;
+const MAX_SIXEL_HEIGHT_PX: u32 = 600;
+
+#[derive(Clone, Copy)]
+enum Protocol {
+    Kitty,
+    Iterm2,
+    Sixel,
+}
+
+// `images.protocol` in the config wins, otherwise guess from the environment
+fn detect_protocol() -> Option<Protocol> {
+    match config::get().images.protocol.as_str() {
+        "kitty" => return Some(Protocol::Kitty),
+        "iterm2" => return Some(Protocol::Iterm2),
+        "sixel" => return Some(Protocol::Sixel),
+        "none" => return None,
+        _ => {}
+    }
+
+    let term = env::var("TERM").unwrap_or_default();
+    let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+
+    if env::var_os("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || term == "xterm-ghostty"
+    {
+        Some(Protocol::Kitty)
+    } else if term_program == "iTerm.app"
+        || term_program == "WezTerm"
+        || env::var("LC_TERMINAL").is_ok_and(|t| t == "iTerm2")
+    {
+        Some(Protocol::Iterm2)
+    } else if term.contains("sixel") || term.starts_with("foot") || term == "mlterm" {
+        Some(Protocol::Sixel)
+    } else {
+        None
+    }
+}
+
+/// Parses a markdown line that holds nothing but an image: `![alt](source)`
+pub fn parse_image_line(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix("![")?;
+    let (alt, rest) = rest.split_once("](")?;
+    let source = rest.strip_suffix(')')?;
+
+    // Drop an optional title: ![alt](path "title")
+    let source = source.split_once(" \"").map_or(source, |(path, _)| path);
+
+    Some((alt, source.trim()))
+}
+
+// Images the user attached this session. A response can name any path, so
+// only these are read and shown; others, and remote images, get the alt
+// text and source.
+static ATTACHED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Lets responses show the image at `path`, one the user attached
+pub fn allow(path: &Path) {
+    if let Ok(path) = fs::canonicalize(path) {
+        ATTACHED.lock().unwrap().push(path);
+    }
+}
+
+fn is_attached(path: &Path) -> bool {
+    fs::canonicalize(path).is_ok_and(|path| ATTACHED.lock().unwrap().contains(&path))
+}
+
+/// Shows an attached image inline when the terminal supports a graphics
+/// protocol, otherwise prints its alt text and path
+pub fn display_image(alt: &str, source: &str) -> io::Result<()> {
+    let path = resolve_local_path(source).filter(|path| path.is_file() && is_attached(path));
+
+    if let (Some(protocol), Some(path)) = (detect_protocol(), path)
+        && display_with(protocol, &path).is_ok()
+    {
+        println!();
+        return Ok(());
+    }
+
+    execute!(
+        io::stdout(),
+        SetAttribute(Attribute::Dim),
+        Print(symbols::plain(&format!("  🖼  {} ({})\n", alt, source))),
+        SetAttribute(Attribute::Reset),
+    )
+}
+
+fn resolve_local_path(source: &str) -> Option<PathBuf> {
+    let source = source.strip_prefix("file://").unwrap_or(source);
+
+    if source.contains("://") {
+        return None;
+    }
+
+    if let Some(rest) = source.strip_prefix("~/") {
+        return dirs::home_dir().map(|home| home.join(rest));
+    }
+
+    Some(PathBuf::from(source))
+}
+
+fn max_columns() -> u32 {
+    let width = terminal::size().map(|(w, _)| w as u32).unwrap_or(80);
+    // Stay inside the renderer's margins
+    width.saturating_sub(4).max(10)
+}
+
+fn display_with(protocol: Protocol, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = fs::read(path)?;
+    let image = image::load_from_memory(&bytes)?;
+    let columns = (image.width() / CELL_WIDTH_PX).clamp(1, max_columns());
+
+    let mut stdout = io::stdout();
+    match protocol {
+        Protocol::Kitty => {
+            // Kitty only takes PNG data directly, other formats are re-encoded
+            let png = if image::guess_format(&bytes)? == ImageFormat::Png {
+                bytes
+            } else {
+                let mut png = Vec::new();
+                image.write_to(&mut io::Cursor::new(&mut png), ImageFormat::Png)?;
+                png
+            };
+            write_kitty(&mut stdout, &png, columns)?;
+        }
+        Protocol::Iterm2 => {
+            write!(
+                stdout,
+                "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07",
+                bytes.len(),
+                columns,
+                STANDARD.encode(&bytes)
+            )?;
+        }
+        Protocol::Sixel => {
+            let image = image.resize(
+                columns * CELL_WIDTH_PX,
+                MAX_SIXEL_HEIGHT_PX,
+                FilterType::Triangle,
+            );
+            write!(stdout, "{}", encode_sixel(&image))?;
+        }
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+fn write_kitty(stdout: &mut impl Write, png: &[u8], columns: u32) -> io::Result<()> {
+    let encoded = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+
+    // The payload goes out in chunks of at most 4096 bytes, `m=1` means more follow
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            write!(
+                stdout,
+                "\x1b_Gf=100,a=T,c={},m={};{}\x1b\\",
+                columns, more, chunk
+            )?;
+        } else {
+            write!(stdout, "\x1b_Gm={};{}\x1b\\", more, chunk)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Encodes the image as sixel graphics using a fixed 6x6x6 color cube
+fn encode_sixel(image: &DynamicImage) -> String {
+    let image = image.to_rgba8();
+    let (width, height) = image.dimensions();
+
+    let palette_index = |x: u32, y: u32| -> Option<usize> {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        if a < 128 {
+            return None;
+        }
+        let level = |c: u8| (c as usize * 5 + 127) / 255;
+        Some(level(r) * 36 + level(g) * 6 + level(b))
+    };
+
+    let mut output = format!("\x1bPq\"1;1;{};{}", width, height);
+
+    // Palette entries take RGB percentages
+    for i in 0..216 {
+        let percent = |level: usize| level * 100 / 5;
+        output.push_str(&format!(
+            "#{};2;{};{};{}",
+            i,
+            percent(i / 36),
+            percent(i / 6 % 6),
+            percent(i % 6)
+        ));
+    }
+
+    // Each band covers six pixel rows, drawn once per color it contains
+    for band_top in (0..height).step_by(6) {
+        let band_height = (height - band_top).min(6);
+        let mut band = vec![[0u8; 216]; width as usize];
+        let mut used = [false; 216];
+
+        for x in 0..width {
+            for dy in 0..band_height {
+                if let Some(color) = palette_index(x, band_top + dy) {
+                    band[x as usize][color] |= 1 << dy;
+                    used[color] = true;
+                }
+            }
+        }
+
+        for color in (0..216).filter(|&c| used[c]) {
+            output.push_str(&format!("#{}", color));
+            let sixels: Vec<u8> = band.iter().map(|column| column[color]).collect();
+            push_run_length_encoded(&mut output, &sixels);
+            // Return to the start of the band for the next color
+            output.push('$');
+        }
+
+        output.push('-');
+    }
+
+    output.push_str("\x1b\\");
+    output
+}
+
+fn push_run_length_encoded(output: &mut String, sixels: &[u8]) {
+    let mut i = 0;
+
+    while i < sixels.len() {
+        let value = sixels[i];
+        let run = sixels[i..].iter().take_while(|&&s| s == value).count();
+        let ch = (b'?' + value) as char;
+
+        if run > 3 {
+            output.push_str(&format!("!{}{}", run, ch));
+        } else {
+            output.extend(std::iter::repeat_n(ch, run));
+        }
+
+        i += run;
+    }
+}
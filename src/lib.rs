@@ -0,0 +1,95 @@
+//! The library behind the `rye` CLI: the markdown conversation store, the
+//! LLM provider abstraction, and the terminal markdown renderer.
+//!
+//! - [`conversation`] - conversations stored as markdown files in
+//!   `$XDG_DATA_HOME/rye` (or `RYE_CONVERSATIONS`), with titles and tags
+//! - [`providers`] - the [`providers::LLMProvider`] trait and its Anthropic
+//!   implementation, streaming responses as text chunks
+//! - [`streaming`] - renders a response stream to the terminal as it arrives
+//! - [`render`] - markdown to ANSI text using the configured theme
+//! - [`spinner`] - the waiting line [`streaming`] clears on the first chunk
+//!
+//! Those are the API to build on. The other modules are the rest of the CLI,
+//! public only so the `rye` binary can use them; they're hidden from the docs
+//! and change with the CLI.
+//!
+//! ```no_run
+//! use rye::conversation::Conversation;
+//! use rye::providers::{LLMProvider, anthropic::AnthropicProvider};
+//! use futures::StreamExt;
+//!
+//! # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+//! let provider = AnthropicProvider::new()?;
+//! let mut conversation = Conversation::new()?;
+//! conversation.add_message("user", "Hello!").await?;
+//!
+//! let mut stream = provider
+//!     .generate_response_stream(&conversation.messages)
+//!     .await?;
+//! let mut response = String::new();
+//! while let Some(Ok(chunk)) = stream.next().await {
+//!     response.push_str(&chunk);
+//! }
+//! conversation.add_message("assistant", &response).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[doc(hidden)]
+pub mod attachments;
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod auth;
+#[doc(hidden)]
+pub mod background;
+mod bidi;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod config;
+pub mod conversation;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod i18n;
+#[doc(hidden)]
+pub mod inline_image;
+mod links;
+mod math;
+#[doc(hidden)]
+pub mod memory;
+#[doc(hidden)]
+pub mod paths;
+#[doc(hidden)]
+pub mod persona;
+#[doc(hidden)]
+pub mod plugins;
+#[doc(hidden)]
+pub mod project;
+#[doc(hidden)]
+pub mod prompt;
+#[doc(hidden)]
+pub mod protect;
+pub mod providers;
+pub mod render;
+#[doc(hidden)]
+pub mod router;
+#[doc(hidden)]
+pub mod secrets;
+#[doc(hidden)]
+pub mod speech;
+pub mod spinner;
+pub mod streaming;
+#[doc(hidden)]
+pub mod symbols;
+#[doc(hidden)]
+pub mod sync;
+#[doc(hidden)]
+pub mod tee;
+#[doc(hidden)]
+pub mod templates;
+#[doc(hidden)]
+pub mod usage;
+#[doc(hidden)]
+pub mod voice;
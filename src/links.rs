@@ -0,0 +1,155 @@
+// Markdown links rendered as OSC 8 hyperlinks with a dim "(url)" suffix for
+// terminals that don't support them. termimad prints links literally, and
+// would style `*` or `_` inside URLs, so links are swapped for private-use
+// placeholders before rendering and filled back in afterwards.
+
+const LABEL_START: char = '\u{E000}';
+const LABEL_END: char = '\u{E001}';
+// One per character of the "(url)" suffix so wrapping sees the real width
+const SUFFIX: char = '\u{E002}';
+
+pub struct Link {
+    url: String,
+    show_url: bool,
+}
+
+/// Replaces `[label](url)` and `<url>` outside code with placeholders
+pub fn mark_links(text: &str) -> (String, Vec<Link>) {
+    let mut output = String::new();
+    let mut links = Vec::new();
+    let mut in_code_block = false;
+
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+
+        if in_code_block {
+            output.push_str(line);
+        } else {
+            mark_line(line, &mut output, &mut links);
+        }
+    }
+
+    (output, links)
+}
+
+fn mark_line(line: &str, output: &mut String, links: &mut Vec<Link>) {
+    let mut rest = line;
+    let mut in_code_span = false;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '`' {
+            in_code_span = !in_code_span;
+        } else if !in_code_span
+            && !output.ends_with('!')
+            && let Some((label, url, consumed)) = parse_link(rest)
+        {
+            let show_url = label != url;
+            output.push(LABEL_START);
+            output.push_str(label);
+            output.push(LABEL_END);
+            if show_url {
+                output.push(' ');
+                output.extend(std::iter::repeat_n(SUFFIX, url.chars().count() + 2));
+            }
+            links.push(Link {
+                url: url.to_string(),
+                show_url,
+            });
+            rest = &rest[consumed..];
+            continue;
+        }
+
+        output.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+}
+
+// Returns (label, url, bytes consumed) for a link at the start of `text`
+fn parse_link(text: &str) -> Option<(&str, &str, usize)> {
+    if let Some(inner) = text.strip_prefix('<') {
+        let end = inner.find('>')?;
+        let url = &inner[..end];
+        let is_url = (url.starts_with("http://") || url.starts_with("https://"))
+            && !url.contains(char::is_whitespace);
+        return is_url.then_some((url, url, end + 2));
+    }
+
+    let inner = text.strip_prefix('[')?;
+    let label_end = inner.find("](")?;
+    let label = &inner[..label_end];
+    let after_label = &inner[label_end + 2..];
+    let url_end = after_label.find(')')?;
+    let url = &after_label[..url_end];
+
+    if label.is_empty() || label.contains(['[', ']']) || url.is_empty() {
+        return None;
+    }
+    if url.contains(char::is_whitespace) {
+        return None;
+    }
+
+    Some((label, url, 1 + label_end + 2 + url_end + 1))
+}
+
+/// Fills the placeholders in rendered output back in. Hyperlinks are closed
+/// at line ends and reopened so each line stands on its own in pagers.
+pub fn apply_links(rendered: &str, links: &[Link], hyperlinks: bool) -> String {
+    let mut output = String::new();
+    let mut links = links.iter();
+    let mut current: Option<&Link> = None;
+    let mut in_label = false;
+    let mut suffix: Vec<char> = Vec::new();
+    let mut suffix_pos = 0;
+
+    for c in rendered.chars() {
+        match c {
+            LABEL_START => {
+                current = links.next();
+                if let Some(link) = current {
+                    if hyperlinks {
+                        output.push_str(&open_hyperlink(&link.url));
+                    }
+                    in_label = true;
+                    suffix = format!("({})", link.url).chars().collect();
+                    suffix_pos = 0;
+                }
+            }
+            LABEL_END => {
+                if in_label && hyperlinks {
+                    output.push_str(CLOSE_HYPERLINK);
+                }
+                in_label = false;
+            }
+            SUFFIX => {
+                if current.is_some_and(|link| link.show_url) && suffix_pos < suffix.len() {
+                    if suffix_pos == 0 {
+                        output.push_str("\x1b[2m");
+                    }
+                    output.push(suffix[suffix_pos]);
+                    suffix_pos += 1;
+                    if suffix_pos == suffix.len() {
+                        output.push_str("\x1b[22m");
+                    }
+                }
+            }
+            '\n' if in_label && hyperlinks => {
+                output.push_str(CLOSE_HYPERLINK);
+                output.push('\n');
+                if let Some(link) = current {
+                    output.push_str(&open_hyperlink(&link.url));
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+const CLOSE_HYPERLINK: &str = "\x1b]8;;\x1b\\";
+
+fn open_hyperlink(url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\", url)
+}
@@ -0,0 +1,41 @@
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::level_filters::LevelFilter;
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Whether `-v` or more was given, which also shows response latency
+pub fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+/// Sets up tracing for `-v` (info), `-vv` (debug), and `-vvv` (trace). Logs go
+/// to stderr, or to `log_file` when given since stderr shares the terminal with
+/// the chat.
+pub fn init(verbosity: u8, log_file: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    VERBOSE.store(verbosity > 0, Ordering::Relaxed);
+    let level = match verbosity {
+        0 if log_file.is_some() => LevelFilter::INFO,
+        0 => return Ok(()),
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+
+    let builder = tracing_subscriber::fmt().with_max_level(level);
+
+    let result = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            builder
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .try_init()
+        }
+        None => builder.with_writer(std::io::stderr).try_init(),
+    };
+
+    result.map_err(|e| -> Box<dyn std::error::Error> { e })
+}
@@ -1,158 +1,935 @@
-mod conversation;
-mod providers;
-mod render;
-mod streaming;
+mod alias;
+mod ask;
+mod batch;
+mod browse;
+mod commit_msg;
+mod compare;
+mod confirm;
+mod context;
+#[cfg(unix)]
+mod daemon;
+mod digest;
+mod draft;
+mod edit;
+mod editor;
+mod edits;
+mod export;
+mod gist;
+mod logging;
+mod notify;
+mod passphrase;
+mod picker;
+mod pipeline;
+mod prune;
+mod queue;
+mod replay;
+mod review;
+mod rotate;
+mod self_update;
+mod serve;
+mod site;
+mod status;
+mod tree;
+mod tui;
+mod usage_report;
+mod viewport;
 
-use clap::Parser;
-use conversation::{Conversation, list_conversations};
+use clap::{Parser, Subcommand};
+use context::Attachments;
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyModifiers},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
     execute,
-    style::{Color, ResetColor, SetForegroundColor},
+    style::{Color, ResetColor, SetForegroundColor, Stylize},
     terminal,
 };
-use providers::{LLMProvider, anthropic::AnthropicProvider};
-use render::render_markdown;
-use skim::prelude::*;
-use std::io::{self, Write};
+use edits::{FileEdits, Toolbox};
+use notify::notify_response_complete;
+use rye::auth;
+use rye::background;
+use rye::cache;
+use rye::config;
+use rye::conversation::{
+    self, Conversation, ConversationInfo, list_conversations, strip_frontmatter,
+};
+use rye::hooks;
+use rye::i18n::{t, tf, translate};
+use rye::paths;
+use rye::persona;
+use rye::plugins::Plugins;
+use rye::project;
+use rye::providers::{
+    LLMProvider, ToolRunner, anthropic::AnthropicProvider, is_unavailable, models,
+};
+use rye::render::render_markdown;
+use rye::secrets;
+use rye::speech;
+use rye::spinner::Spinner;
+use rye::streaming::stream_and_render_stoppable;
+use rye::symbols::{self, plain};
+use rye::tee;
+use rye::usage;
+use rye::voice;
+use status::{SessionStatus, print_response_footer};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
-use streaming::stream_and_render_response;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 
 #[derive(Parser)]
-#[command(name = "rye")]
+#[command(name = "rye", version)]
 #[command(about = "A CLI tool to chat with LLM's and store conversations in markdown")]
 struct Args {
     /// Continue a conversation (opens interactive selector if no ID provided)
     #[arg(short, long)]
     r#continue: Option<Option<String>>,
 
-    /// LLM provider to use (currently only "anthropic" is supported)
-    #[arg(short, long, default_value = "anthropic")]
-    provider: String,
+    /// LLM provider to use: "anthropic", or "local" with a GGUF `--model` (built with `--features local`)
+    #[arg(short, long, global = true)]
+    provider: Option<String>,
+
+    /// Model to use, overriding `RYE_MODEL` and the config file (repeat with `compare`). A
+    /// `local:` or `anthropic:` prefix, or a `.gguf` path, picks the provider too
+    #[arg(short, long, global = true)]
+    model: Vec<String>,
+
+    /// Send this file as the system prompt instead of the built-in one (system_prompt_file)
+    #[arg(long, value_name = "PATH", global = true)]
+    system_file: Option<String>,
+
+    /// Override a setting for this run, e.g. `--set theme.name=solarized` (repeatable)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_setting, global = true)]
+    set: Vec<(String, String)>,
+
+    /// List every project's conversations, not only the current repository's (`project.scope`)
+    #[arg(long, global = true)]
+    all_projects: bool,
+
+    /// Send requests even when a spending budget is used up (`budget.daily`, `budget.monthly`)
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// Append every response to FILE as it streams in, next to showing it
+    #[arg(long, value_name = "FILE", global = true)]
+    tee: Option<PathBuf>,
+
+    /// Plain ASCII output: no emoji, box drawing or arrows (`output.ascii`)
+    #[arg(long, global = true)]
+    ascii: bool,
+
+    /// Linear plain-text output for screen readers: no spinners or redraws,
+    /// turns announced in words (`output.screen_reader`)
+    #[arg(long, global = true)]
+    screen_reader: bool,
+
+    /// Print the request that would be sent for each message instead of calling the API
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Log requests, SSE events, and file operations (-v info, -vv debug, -vvv trace)
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Same as -vv
+    #[arg(long, global = true)]
+    debug: bool,
+
+    /// Write logs to this file instead of stderr
+    #[arg(long, value_name = "PATH", global = true)]
+    log_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn select_command() -> Result<Option<String>, Box<dyn std::error::Error>> {
-    let commands = vec!["/new-conversation - Start a new conversation"];
+#[derive(Subcommand)]
+enum Command {
+    /// Full-screen interface with a transcript view, input box, and conversation sidebar
+    Tui,
+    /// Browse conversations in a split view to continue, rename, tag, archive, or delete them
+    Browse,
+    /// Show the fork tree of a conversation, or of every conversation with forks
+    Tree {
+        /// Conversation ID or title
+        id: Option<String>,
+    },
+    /// Name a conversation so the name works wherever its ID does, or show its IDs
+    Alias {
+        /// Conversation ID, short ID, or alias
+        id: String,
+        /// New alias, a single word
+        name: Option<String>,
+        /// Remove the alias instead
+        #[arg(long, conflicts_with = "name")]
+        remove: bool,
+    },
+    /// Show a conversation's messages, asking for the passphrase if it's protected
+    Show {
+        /// Conversation ID, short ID, alias, or title
+        id: String,
+    },
+    /// Encrypt a conversation's messages under a passphrase, or store them as plain markdown again
+    Protect {
+        /// Conversation ID, short ID, or alias
+        id: String,
+        /// Remove the protection instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Open a conversation file in `$EDITOR`, then check that it still reads as it looks
+    Edit {
+        /// Conversation ID, short ID, or alias
+        id: String,
+    },
+    /// Re-stream a stored conversation at typing speed
+    Replay {
+        /// Conversation ID or title
+        id: String,
+        /// Typing speed in characters per second
+        #[arg(long, default_value_t = 400)]
+        speed: u32,
+    },
+    /// Serve conversations over HTTP, streaming replies as server-sent events
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+    },
+    /// Keep a provider and the conversation list warm for other rye processes
+    #[cfg(unix)]
+    Daemon,
+    /// Review uncommitted changes, staged changes, or files, and save the review as a conversation
+    Review {
+        /// Files or directories to review instead of the whole diff
+        paths: Vec<PathBuf>,
+        /// Review the staged changes (`git diff --staged`)
+        #[arg(long)]
+        staged: bool,
+    },
+    /// Write a conventional commit message for the staged changes, then edit, regenerate, or commit it
+    CommitMsg {
+        /// Commit with the generated message without asking
+        #[arg(long)]
+        commit: bool,
+    },
+    /// Answer one prompt (from the arguments or stdin) and exit
+    Ask {
+        /// The prompt; read from stdin when left out
+        prompt: Vec<String>,
+        /// Make the answer JSON matching this JSON Schema and print only the JSON
+        #[arg(long, value_name = "FILE")]
+        schema: Option<PathBuf>,
+        /// Fill the prompt into a template from the `templates` directory and apply its constraints
+        #[arg(long, short = 't', value_name = "NAME")]
+        template: Option<String>,
+    },
+    /// Export a conversation, e.g. as Anki flashcards the model writes from it
+    Export {
+        /// Conversation ID
+        id: String,
+        /// `anki`: question and answer cards as a tab-separated file for Anki's File > Import
+        #[arg(long, value_parser = export::FORMATS)]
+        format: String,
+        /// File to write; stdout when left out
+        #[arg(long, short = 'o', value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Write every conversation as a static HTML site with a date and tag index and search
+    ExportSite {
+        /// Directory to write the site to
+        dir: PathBuf,
+    },
+    /// Summarize the conversations of a period into one markdown digest, e.g. for a work journal
+    Digest {
+        /// How far back to go, e.g. `7d`, `12h`, `2w` or a date like `2025-01-31`
+        #[arg(long, default_value = "7d")]
+        since: String,
+        /// File to write the digest to instead of showing it
+        #[arg(long, short = 'o', value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Send one prompt to several models (`-m` for each) at once and show the answers one after another
+    Compare {
+        /// The prompt; read from stdin when left out
+        prompt: Vec<String>,
+    },
+    /// Run multi-step prompt pipelines
+    Pipeline {
+        #[command(subcommand)]
+        action: PipelineAction,
+    },
+    /// Summarize logged spend and tokens per day and model
+    Usage {
+        /// How far back to go, e.g. `7d`, `12h`, `2w` or a date like `2025-01-31`
+        #[arg(long, default_value = "7d")]
+        since: String,
+    },
+    /// Send prompts as a Message Batch, answered within a day at half the price
+    Batch {
+        #[command(subcommand)]
+        action: BatchAction,
+    },
+    /// Send the messages queued while the API was unavailable (`queue.enabled`)
+    Flush,
+    /// Archive (or delete) empty, single-message and long-untouched conversations
+    Prune {
+        /// Also prune conversations unchanged for this long, e.g. `90d`, or since a date
+        #[arg(long, default_value = "180d")]
+        older_than: String,
+        /// Delete instead of archiving
+        #[arg(long)]
+        delete: bool,
+        /// Prune without asking about each conversation
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Show or change settings
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Log in to services like GitHub with the OAuth device flow instead of a static token
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+    /// List the known models with their context window, capabilities and prices
+    Models,
+    /// Replace this binary with the latest release's, after verifying its checksum
+    SelfUpdate {
+        /// Only check whether there's a newer release
+        #[arg(long)]
+        check: bool,
+    },
+}
 
-    let options = SkimOptionsBuilder::default()
-        .height("50%".to_string())
-        .prompt("Select a command: ".to_string())
-        .layout("reverse".to_string()) // Display results below the prompt
-        .build()
-        .unwrap();
+#[derive(Subcommand)]
+enum PipelineAction {
+    /// Run the steps in a pipeline YAML file, each fed the earlier steps' output
+    Run {
+        file: PathBuf,
+        /// Text for `{input}`; read from stdin when left out and piped
+        input: Vec<String>,
+    },
+}
 
-    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+#[derive(Subcommand)]
+enum BatchAction {
+    /// Submit the prompts in a JSONL file (`{"prompt": ..., "title": ...}` per line)
+    Submit {
+        file: PathBuf,
+        /// Return once submitted instead of waiting for the answers
+        #[arg(long = "async")]
+        detach: bool,
+    },
+    /// Show how far a batch, or every batch not fetched yet, has come
+    Status { id: Option<String> },
+    /// Save the answers of a batch, or of every finished batch, as conversations
+    Fetch { id: Option<String> },
+}
 
-    for cmd in commands {
-        tx.send(Arc::new(cmd.to_string())).unwrap();
-    }
-    drop(tx);
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// List every setting with its effective value and where it comes from
+    List,
+    /// Print the effective value of a setting
+    Get { key: String },
+    /// Write a setting to the config file
+    Set { key: String, value: String },
+}
 
-    let output = Skim::run_with(&options, Some(rx));
+#[derive(Subcommand)]
+enum AuthAction {
+    /// Show a code to approve the login with in the browser, and keep the token
+    Login {
+        #[arg(default_value = "github")]
+        service: String,
+    },
+    /// Forget the token of a login
+    Logout {
+        #[arg(default_value = "github")]
+        service: String,
+    },
+    /// List the services logged in to
+    Status,
+}
 
-    // Don't clear the screen, just move down
-    println!();
+fn parse_setting(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{}'", value))
+}
 
-    match output {
-        Some(out) if !out.is_abort => {
-            if let Some(selected) = out.selected_items.first() {
-                let selected_text = selected.output().to_string();
-                // Extract command (everything before " - ")
-                let cmd = if let Some(pos) = selected_text.find(" - ") {
-                    selected_text[..pos].to_string()
-                } else {
-                    selected_text
-                };
-                Ok(Some(cmd))
+async fn run_auth_command(action: AuthAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        AuthAction::Login { service } => {
+            let service = auth::service(&service)?;
+            auth::login(service).await?;
+            println!("Logged in to {}", service.name);
+        }
+        AuthAction::Logout { service } => {
+            let service = auth::service(&service)?;
+            if auth::logout(service)? {
+                println!("Logged out of {}", service.name);
             } else {
-                Ok(None)
+                println!("Not logged in to {}", service.name);
             }
         }
-        _ => Ok(None),
+        AuthAction::Status => {
+            let logins = auth::logins();
+            if logins.is_empty() {
+                println!("Not logged in to any service");
+            }
+            for (name, token) in logins {
+                let expiry = match token.expires_at {
+                    Some(at) if token.refresh_token.is_some() => {
+                        format!("refreshed after {}", at.format("%Y-%m-%d %H:%M"))
+                    }
+                    Some(at) => format!("expires {}", at.format("%Y-%m-%d %H:%M")),
+                    None => "doesn't expire".to_string(),
+                };
+                println!("{}: scope '{}', {}", name, token.scope, expiry);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn print_models() {
+    let yes_no = |supported: bool| if supported { "yes" } else { "no" };
+    println!(
+        "{:<20} {:>8}  {:<6}  {:<5}  {:>10}  {:>10}",
+        "Model", "Context", "Vision", "Tools", "Input $/M", "Output $/M"
+    );
+    for model in &models::MODELS {
+        println!(
+            "{:<20} {:>8}  {:<6}  {:<5}  {:>10.2}  {:>10.2}",
+            model.id,
+            status::format_tokens(model.context_window.into()),
+            yes_no(model.vision),
+            yes_no(model.tools),
+            model.input_price,
+            model.output_price
+        );
+    }
+    if config::get().provider.eq_ignore_ascii_case("anthropic")
+        && let Some(current) = &config::get().model
+    {
+        match models::find(current) {
+            Some(model) => println!("\nThe model setting, {}, is a {}", current, model.id),
+            None => println!(
+                "\nThe model setting, {}, isn't in the table; it's treated as a {}",
+                current,
+                models::capabilities(current).id
+            ),
+        }
+    }
+}
+
+fn run_config_command(action: ConfigAction) -> Result<(), Box<dyn std::error::Error>> {
+    match action {
+        ConfigAction::List => {
+            for setting in config::settings() {
+                let value = match setting.value {
+                    None => "(unset)".to_string(),
+                    Some(_) if setting.key == "store.password" => "(hidden)".to_string(),
+                    Some(value) => value.to_string(),
+                };
+                let source = match setting.source {
+                    config::Source::Default => "default".to_string(),
+                    config::Source::File => "config file".to_string(),
+                    config::Source::Env(var) => format!("env {}", var),
+                    config::Source::Cli => "command line".to_string(),
+                };
+                println!("{} = {}  # {}", setting.key, value, source);
+            }
+            if let Some(path) = config::config_path() {
+                println!("\nConfig file: {}", path.display());
+            }
+        }
+        ConfigAction::Get { key } => {
+            let setting = config::settings()
+                .into_iter()
+                .find(|setting| setting.key == key)
+                .ok_or_else(|| format!("Unknown setting '{}'", key))?;
+            match setting.value {
+                Some(_) if setting.key == "store.password" => println!("(hidden)"),
+                Some(toml::Value::String(value)) => println!("{}", value),
+                Some(value) => println!("{}", value),
+                None => {}
+            }
+        }
+        ConfigAction::Set { key, value } => {
+            let path = config::set_in_file(&key, &value)?;
+            println!("Set {} in {}", key, path.display());
+
+            // Say so when the new value won't take effect
+            let overridden_by = config::settings()
+                .into_iter()
+                .find(|setting| setting.key == key)
+                .and_then(|setting| match setting.source {
+                    config::Source::Env(var) => Some(var),
+                    config::Source::Cli => Some("the command line".to_string()),
+                    _ => None,
+                });
+            if let Some(overridden_by) = overridden_by {
+                eprintln!("Warning: {} is overridden by {}", key, overridden_by);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn select_command(plugins: &Plugins) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let commands = vec![
+        t("/new-conversation - Start a new conversation"),
+        t("/expand - Show the last response in full"),
+        t("/context - List what the next request will include, with token estimates"),
+        t("/fork - Continue in a copy of this conversation, linked to it as a fork"),
+        t("/retitle - Generate a new title from the whole conversation"),
+        t("/open - Edit the conversation file in $EDITOR and continue with your changes"),
+        t("/persona - Answer as a persona from now on, or `/persona off` to stop"),
+        t(
+            "/gist - Share the last response as a secret GitHub gist, or `/gist all` for the conversation",
+        ),
+        t("/remember - Add a fact or preference to the memory sent with every message"),
+        t("/memory - Show the memory file, or `/memory edit` to open it in $EDITOR"),
+        t("/voice - Ask by speaking, press Enter to stop recording"),
+        t("/image - Attach an image to the next message, for models that can see images"),
+        t("/audio - Transcribe an audio file and attach the transcript to the next message"),
+        t("/speak - Turn reading responses aloud on or off"),
+        t("/tee - Append responses to a file as they stream, or `/tee off` to stop"),
+        t("/paste - Attach the clipboard to the next message"),
+        t("/gitdiff - Attach uncommitted changes, or the diff against a ref, to the next message"),
+        t("/gitlog - Attach recent commits, or a range like v1.0..HEAD, to the next message"),
+    ];
+
+    let items = commands
+        .into_iter()
+        .map(String::from)
+        .chain(
+            plugins
+                .commands()
+                .map(|command| format!("/{} - {}", command.name, command.description)),
+        )
+        .collect::<Vec<String>>();
+    if symbols::screen_reader() {
+        return read_command(&items);
+    }
+    let selected = picker::pick(items, t("Select a command: "), true)?;
+
+    // Don't clear the screen, just move down
+    println!();
+
+    // Extract command (everything before " - ")
+    Ok(selected.map(|text| match text.find(" - ") {
+        Some(pos) => text[..pos].to_string(),
+        None => text,
+    }))
+}
+
+// The command picker for screen readers: the commands listed one per line,
+// then one chosen by number or name
+fn read_command(items: &[String]) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    println!("{}", t("Commands:"));
+    for (i, item) in items.iter().enumerate() {
+        println!("{}. {}", i + 1, item);
     }
+    print!("{}", t("Command number or name (Enter to cancel): "));
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(None);
+    }
+    let command = match answer.parse::<usize>() {
+        Ok(n) if (1..=items.len()).contains(&n) => items[n - 1]
+            .split(" - ")
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+        _ => format!("/{}", answer.trim_start_matches('/')),
+    };
+    Ok(Some(command))
+}
+
+// Asks for the argument of a picked command that takes one
+fn with_argument(command: String) -> io::Result<String> {
+    let prompt = match command.as_str() {
+        "/remember" => t("Fact to remember: "),
+        "/tee" => t("File to write responses to (Enter to stop): "),
+        "/persona" => t("Persona, e.g. security-auditor (Enter to list them): "),
+        "/gitdiff" => t("Diff against (Enter for HEAD): "),
+        "/gitlog" => t("Range (Enter for the last 30 commits): "),
+        "/image" => t("Image file: "),
+        "/audio" => t("Audio file: "),
+        _ => return Ok(command),
+    };
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut argument = String::new();
+    io::stdin().read_line(&mut argument)?;
+    Ok(format!("{} {}", command, argument.trim())
+        .trim_end()
+        .to_string())
 }
 
 fn select_conversation() -> Result<Option<String>, Box<dyn std::error::Error>> {
-    let conversations = list_conversations()?;
+    // A running daemon already has the list in memory
+    #[cfg(unix)]
+    let from_daemon = daemon::list_conversations_from_daemon();
+    #[cfg(not(unix))]
+    let from_daemon = None;
+    let conversations: Vec<_> = match from_daemon {
+        Some(conversations) => conversations,
+        None => list_conversations()?,
+    }
+    .into_iter()
+    .filter(|info| project::in_scope(info.project.as_deref()))
+    .collect();
 
     if conversations.is_empty() {
-        println!("No previous conversations found.");
+        match project::scope() {
+            Some(scope) if !project::showing_all_projects() => println!(
+                "No previous conversations in {}. Use --all-projects to see every project's.",
+                scope
+            ),
+            _ => println!("No previous conversations found."),
+        }
         return Ok(None);
     }
 
-    // Prepare items for skim
+    pick_conversation(&conversations, "Select a conversation: ")
+}
+
+// The conversation `query` refers to for `--continue <query>`, asking which
+// one when several match equally well
+fn find_conversation(query: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut matches = conversation::search(query)?;
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.pop().map(|info| info.id)),
+        n => pick_conversation(
+            &matches,
+            &format!("{} conversations match '{}': ", n, query),
+        ),
+    }
+}
+
+fn print_banner() {
+    println!(
+        "{}",
+        plain(&format!(
+            "🥃 {}",
+            t("Welcome to Rye - Your LLM conversation tool")
+        ))
+    );
+    println!(
+        "{}",
+        t("Conversations are stored in markdown files for easy searching")
+    );
+    println!("{}\n", t("Type 'exit' to quit, 'help' for commands"));
+    if let Some(path) = rye::project::context_file() {
+        println!(
+            "{}",
+            plain(&format!(
+                "📄 {}\n",
+                tf("Using project context from {}", &[&path.display()])
+            ))
+        );
+    }
+}
+
+fn print_started(conversation: &Conversation) {
+    println!(
+        "{}",
+        tf(
+            "Started new conversation: {} (short ID {})",
+            &[&conversation.id, &conversation::short_id(&conversation.id)]
+        )
+    );
+}
+
+fn pick_conversation(
+    conversations: &[ConversationInfo],
+    prompt: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    // Prepare items for the picker
     let items: Vec<String> = conversations
         .iter()
-        .map(|conv| {
-            if let Some(ref title) = conv.title {
-                format!("{} - {}", title, conv.id)
-            } else {
-                conv.id.clone()
-            }
+        .map(|conv| match (&conv.title, &conv.alias) {
+            (Some(title), Some(alias)) => format!("{} [{}] - {}", title, alias, conv.id),
+            (Some(title), None) => format!("{} - {}", title, conv.id),
+            (None, Some(alias)) => format!("[{}] - {}", alias, conv.id),
+            (None, None) => conv.id.clone(),
         })
         .collect();
 
-    let options = SkimOptionsBuilder::default()
-        .height("50%".to_string())
-        .prompt("Select a conversation: ".to_string())
-        .build()
-        .unwrap();
+    let selected = picker::pick(items, prompt, false)?;
 
-    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
-
-    for item in items {
-        tx.send(Arc::new(item)).unwrap();
-    }
-    drop(tx);
-
-    let output = Skim::run_with(&options, Some(rx));
-
-    // Clear the terminal after skim exits to remove the skim UI
+    // Clear the terminal after the picker exits to remove its UI
     execute!(io::stdout(), terminal::Clear(terminal::ClearType::All))?;
     execute!(io::stdout(), cursor::MoveTo(0, 0))?;
 
     // Re-print the welcome message after clearing
-    println!("🥃 Welcome to Rye - Your LLM conversation tool");
-    println!("Conversations are stored in markdown files for easy searching");
-    println!("Type 'exit' to quit, 'help' for commands\n");
-
-    match output {
-        Some(out) if !out.is_abort => {
-            if let Some(selected) = out.selected_items.first() {
-                let selected_text = selected.output().to_string();
-                // Extract ID from the end (after the last " - ")
-                let id = if let Some(pos) = selected_text.rfind(" - ") {
-                    selected_text[pos + 3..].to_string()
-                } else {
-                    selected_text
-                };
-                Ok(Some(id))
-            } else {
-                Ok(None)
-            }
-        }
-        _ => Ok(None),
-    }
+    print_banner();
+
+    // Extract ID from the end (after the last " - ")
+    Ok(selected.map(|text| match text.rfind(" - ") {
+        Some(pos) => text[pos + 3..].to_string(),
+        None => text,
+    }))
 }
 
 fn render_conversation_history(
     conversation: &Conversation,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Read and render the entire markdown file
-    let content = std::fs::read_to_string(&conversation.file_path)?;
+    // Read and render the entire markdown file, through a pager if it doesn't fit
+    let content = conversation.markdown()?;
+    let content = strip_frontmatter(&content);
+
+    println!("\n{}", plain(&"═".repeat(60)));
+    println!("{}", plain("📜 Conversation History"));
+    println!("{}\n", plain(&"═".repeat(60)));
+
+    viewport::page_markdown(content)?;
 
-    println!("\n{}", "═".repeat(60));
-    println!("📜 Conversation History");
-    println!("{}\n", "═".repeat(60));
+    println!("\n{}", plain(&"═".repeat(60)));
 
-    render_markdown(&content)?;
+    Ok(())
+}
+
+// Shows the request body for --dry-run. Nothing is sent or saved.
+fn print_request(
+    llm_provider: &dyn LLMProvider,
+    messages: &[(String, String)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let body = serde_json::to_string_pretty(&llm_provider.request_body(messages))?;
 
-    println!("\n{}", "═".repeat(60));
+    println!("\n{}", plain(&"═".repeat(60)));
+    println!("{}", plain("🔍 Dry run, request not sent:"));
+    println!("{}\n", plain(&"═".repeat(60)));
+    println!("{}\n", body);
 
     Ok(())
 }
 
+// Records until Enter (Esc cancels) and returns the transcription, or `None`
+// if there's nothing to send
+async fn ask_by_voice() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let audio_file = voice::audio_file();
+    println!(
+        "{}",
+        plain("🎤 Recording... press Enter to stop, Esc to cancel")
+    );
+
+    terminal::enable_raw_mode()?;
+    let mut cancelled = false;
+    let recorded = voice::record(&audio_file, async {
+        // Polled without blocking so nothing is left reading keys if the
+        // recorder fails
+        loop {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            while let Ok(true) = event::poll(Duration::ZERO) {
+                if let Ok(Event::Key(key)) = event::read()
+                    && key.kind != KeyEventKind::Release
+                {
+                    match key.code {
+                        KeyCode::Enter => return,
+                        KeyCode::Esc => {
+                            cancelled = true;
+                            return;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    })
+    .await;
+    terminal::disable_raw_mode()?;
+
+    let text = match (recorded, cancelled) {
+        (Err(e), _) => Err(e),
+        (Ok(()), true) => {
+            println!("Recording discarded.");
+            Ok(None)
+        }
+        (Ok(()), false) => {
+            let spinner = Spinner::start("Transcribing...");
+            let text = voice::transcribe(&audio_file).await;
+            spinner.stop().await;
+            text.map(Some)
+        }
+    };
+    let _ = std::fs::remove_file(&audio_file);
+
+    match text? {
+        Some(text) if !text.is_empty() => {
+            println!("{}", plain(&format!("🎤 {}", text)));
+            Ok(Some(text))
+        }
+        Some(_) => {
+            println!("Nothing was transcribed.");
+            Ok(None)
+        }
+        None => Ok(None),
+    }
+}
+
+// The provider `model` names, if any, and the model itself: `local:` and
+// `anthropic:` prefixes name one and a `.gguf` path is a local model, other
+// IDs are for the `provider` setting's provider
+fn split_provider(model: &str) -> (Option<&str>, &str) {
+    match model.split_once(':') {
+        Some((provider, model))
+            if ["anthropic", "local"].contains(&provider.to_lowercase().as_str()) =>
+        {
+            (Some(provider), model)
+        }
+        _ if model.ends_with(".gguf") => (Some("local"), model),
+        _ => (None, model),
+    }
+}
+
+// The provider from the `provider` setting, offering the plugins' tools if
+// there are any. `model` overrides the `model` setting, and names its own
+// provider with a `provider:` prefix, so `compare` can put an Anthropic model
+// next to a local one. `edits` adds the `edit_file` tool for the chat loop,
+// which reviews what it proposes.
+fn build_provider(
+    plugins: &Arc<Plugins>,
+    system_prompt: Option<&str>,
+    model: Option<&str>,
+    edits: Option<&Arc<FileEdits>>,
+) -> Result<Box<dyn LLMProvider>, Box<dyn std::error::Error>> {
+    let (named, model) = match model.map(split_provider) {
+        Some((named, model)) => (named, Some(model)),
+        None => (None, None),
+    };
+    let provider = named.unwrap_or(&config::get().provider);
+    match provider.to_lowercase().as_str() {
+        "anthropic" => {
+            let llm_provider = build_anthropic(plugins, system_prompt, model, edits)?;
+            // Commands naming their own model, like `compare`, aren't routed
+            let router = &config::get().router;
+            if !router.enabled || model.is_some() {
+                return Ok(llm_provider);
+            }
+            let fast = build_anthropic(plugins, system_prompt, Some(&router.fast_model), edits)?;
+            let classifier: Option<Box<dyn LLMProvider>> = if router.classify {
+                Some(Box::new(
+                    AnthropicProvider::new()?
+                        .with_model(&router.fast_model)
+                        .with_system_prompt(rye::router::CLASSIFY_PROMPT)
+                        .without_tools()
+                        .with_max_tokens(rye::router::CLASSIFY_MAX_TOKENS),
+                ))
+            } else {
+                None
+            };
+            Ok(rye::router::wrap(llm_provider, fast, classifier))
+        }
+        #[cfg(feature = "local")]
+        "local" => {
+            // Tools need a model trained to call them, so local models go without
+            let path = model
+                .map(str::to_string)
+                .or_else(|| config::get().model.clone())
+                .ok_or(
+                    "The local provider needs a GGUF model, e.g. `--model path/to/model.gguf`",
+                )?;
+            let mut provider = rye::providers::local::LocalProvider::new(&path)?;
+            if let Some(system_prompt) = system_prompt {
+                provider = provider.with_system_prompt(system_prompt);
+            }
+            Ok(Box::new(provider))
+        }
+        #[cfg(not(feature = "local"))]
+        "local" => Err(
+            "This rye was built without local models, rebuild it with `--features local`".into(),
+        ),
+        _ => {
+            eprintln!(
+                "Error: Unknown provider '{}'. Use 'anthropic' or 'local'.",
+                provider
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// An Anthropic provider for `model`, or the `model` setting, with the plugin
+// tools and `edits`
+fn build_anthropic(
+    plugins: &Arc<Plugins>,
+    system_prompt: Option<&str>,
+    model: Option<&str>,
+    edits: Option<&Arc<FileEdits>>,
+) -> Result<Box<dyn LLMProvider>, Box<dyn std::error::Error>> {
+    let mut provider = AnthropicProvider::new()?;
+    if let Some(model) = model {
+        provider = provider.with_model(model);
+    }
+    if let Some(system_prompt) = system_prompt {
+        provider = provider.with_system_prompt(system_prompt);
+    }
+    // Shown with -v only, `rye models` says so too
+    if models::find(provider.model()).is_none() {
+        warn!(
+            model = provider.model(),
+            assumed = models::capabilities(provider.model()).id,
+            "Unknown model, estimating its context window and cost as the assumed one's"
+        );
+    }
+    let capabilities = models::capabilities(provider.model());
+    let offers_tools =
+        edits.is_some() || !plugins.definitions().is_empty() || config::get().web_search.enabled;
+    if offers_tools && !capabilities.tools {
+        eprintln!(
+            "Warning: {} can't call tools, so plugin tools, file edits and web search are off",
+            provider.model()
+        );
+        return Ok(Box::new(provider));
+    }
+    if let Some(edits) = edits {
+        let toolbox = Toolbox::new(plugins.clone(), edits.clone());
+        Ok(Box::new(provider.with_tools(Arc::new(toolbox))))
+    } else if plugins.definitions().is_empty() {
+        Ok(Box::new(provider))
+    } else {
+        Ok(Box::new(provider.with_tools(plugins.clone())))
+    }
+}
+
+fn report_attached(source: &str, kept: usize, total: usize) {
+    if kept < total {
+        println!(
+            "{}",
+            plain(&format!(
+                "📎 Attached the first {} of {} characters from {} to the next message (context.max_chars).",
+                kept, total, source
+            ))
+        );
+    } else {
+        println!(
+            "{}",
+            plain(&format!(
+                "📎 Attached {} characters from {} to the next message.",
+                kept, source
+            ))
+        );
+    }
+}
+
 fn cleanup_and_exit(conversation: &Conversation) {
     // Delete conversation file if no messages were added
     if conversation.messages.is_empty() {
@@ -161,47 +938,123 @@ fn cleanup_and_exit(conversation: &Conversation) {
         }
     } else {
         println!(
-            "Conversation saved to: {}",
-            conversation.file_path.display()
+            "{}",
+            tf(
+                "Conversation saved to: {}",
+                &[&conversation.file_path.display()]
+            )
         );
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args = Args::parse();
+// The configured provider for a one-off command, with the plugins' tools
+async fn command_provider(
+    system_prompt: Option<&str>,
+) -> Result<Box<dyn LLMProvider>, Box<dyn std::error::Error>> {
+    // Query the terminal before anything else reads from it
+    background::init();
+    let plugins = Arc::new(Plugins::load().await);
+    build_provider(&plugins, system_prompt, None, None)
+}
 
-    println!("🥃 Welcome to Rye - Your LLM conversation tool");
-    println!("Conversations are stored in markdown files for easy searching");
-    println!("Type 'exit' to quit, 'help' for commands\n");
+// Requests go through a running daemon when there is one, unless they may
+// propose edits, which have to be reviewed in this process, or are routed
+// between models per message
+#[cfg(unix)]
+fn through_daemon(llm_provider: Box<dyn LLMProvider>, edits: bool) -> Box<dyn LLMProvider> {
+    if edits || config::get().router.enabled {
+        llm_provider
+    } else {
+        Box::new(daemon::DaemonClient::new(llm_provider))
+    }
+}
 
-    // Initialize LLM provider based on configuration
-    let llm_provider: Box<dyn LLMProvider> = match args.provider.to_lowercase().as_str() {
-        "anthropic" => Box::new(AnthropicProvider::new()?),
-        _ => {
-            eprintln!(
-                "Error: Unknown provider '{}'. Currently only 'anthropic' is supported.",
-                args.provider
-            );
-            std::process::exit(1);
-        }
-    };
+#[cfg(not(unix))]
+fn through_daemon(llm_provider: Box<dyn LLMProvider>, _edits: bool) -> Box<dyn LLMProvider> {
+    llm_provider
+}
+
+async fn run_show(id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    background::init();
+    let id = find_conversation(id)?.ok_or_else(|| format!("No conversation '{}'", id))?;
+    render_conversation_history(&passphrase::load(&id)?)
+}
+
+async fn run_compare(
+    models: &[String],
+    prompt: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if models.len() < 2 {
+        return Err("Give at least two models to compare, e.g. `-m <model> -m <model>`".into());
+    }
+    background::init();
+    let plugins = Arc::new(Plugins::load().await);
+    let llm_providers = models
+        .iter()
+        .map(|model| build_provider(&plugins, None, Some(model), None).map(cache::wrap))
+        .collect::<Result<Vec<_>, _>>()?;
+    compare::run(&llm_providers, prompt).await
+}
+
+async fn run_batch(action: BatchAction) -> Result<(), Box<dyn std::error::Error>> {
+    let llm_provider = AnthropicProvider::new()?;
+    match action {
+        BatchAction::Submit { file, detach } => batch::submit(&llm_provider, &file, detach).await,
+        BatchAction::Status { id } => batch::status(&llm_provider, id.as_deref()).await,
+        BatchAction::Fetch { id } => batch::fetch(&llm_provider, id.as_deref()).await,
+    }
+}
+
+// `rye browse`, which continues the conversation picked in it
+async fn run_browse(dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    background::init();
+    match browse::run().await? {
+        Some(id) => run_chat(Some(Some(id)), None, dry_run).await,
+        None => Ok(()),
+    }
+}
+
+// The chat session: `continue_arg` is `--continue` and `piped` the text piped
+// to rye, sent with the first message
+async fn run_chat(
+    continue_arg: Option<Option<String>>,
+    piped: Option<String>,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Query the terminal before anything else reads from it
+    background::init();
+
+    let plugins = Arc::new(Plugins::load().await);
 
-    let mut conversation = if let Some(continue_arg) = args.r#continue {
+    // Only the chat loop is around to review proposed edits
+    let file_edits = config::get()
+        .edits
+        .enabled
+        .then(|| Arc::new(FileEdits::default()));
+    let llm_provider = build_provider(&plugins, None, None, file_edits.as_ref())?;
+    let llm_provider = through_daemon(llm_provider, file_edits.is_some());
+
+    print_banner();
+
+    let mut conversation = if let Some(continue_arg) = continue_arg {
         // --continue flag was provided
         match continue_arg {
-            Some(id) => {
-                // ID was explicitly provided
-                match Conversation::load(&id) {
-                    Ok(conv) => {
-                        println!("Continuing conversation: {}", id);
+            Some(query) => {
+                // An ID, or words from the title or content
+                match find_conversation(&query)?.map(|id| passphrase::load(&id)) {
+                    Some(Ok(conv)) => {
+                        println!("{}", tf("Continuing conversation: {}", &[&conv.id]));
                         render_conversation_history(&conv)?;
                         conv
                     }
-                    Err(_) => {
+                    Some(Err(e)) if passphrase::is_locked(e.as_ref()) => return Err(e),
+                    _ => {
                         println!(
-                            "Could not find conversation {}. Starting new conversation.",
-                            id
+                            "{}",
+                            tf(
+                                "Could not find conversation {}. Starting new conversation.",
+                                &[&query]
+                            )
                         );
                         Conversation::new()?
                     }
@@ -210,24 +1063,31 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             None => {
                 // No ID provided, show interactive selector
                 match select_conversation()? {
-                    Some(id) => match Conversation::load(&id) {
+                    Some(id) => match passphrase::load(&id) {
                         Ok(conv) => {
-                            println!("Continuing conversation: {}", id);
+                            println!("{}", tf("Continuing conversation: {}", &[&id]));
                             render_conversation_history(&conv)?;
                             conv
                         }
+                        Err(e) if passphrase::is_locked(e.as_ref()) => return Err(e),
                         Err(_) => {
                             println!(
-                                "Could not find conversation {}. Starting new conversation.",
-                                id
+                                "{}",
+                                tf(
+                                    "Could not find conversation {}. Starting new conversation.",
+                                    &[&id]
+                                )
                             );
                             Conversation::new()?
                         }
                     },
                     None => {
-                        println!("No conversation selected. Starting new conversation.");
+                        println!(
+                            "{}",
+                            t("No conversation selected. Starting new conversation.")
+                        );
                         let conv = Conversation::new()?;
-                        println!("Started new conversation: {}", conv.id);
+                        print_started(&conv);
                         conv
                     }
                 }
@@ -235,85 +1095,166 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     } else {
         let conv = Conversation::new()?;
-        println!("Started new conversation: {}", conv.id);
+        print_started(&conv);
         conv
     };
 
-    let mut running = true;
-    while running {
-        // Print a visually appealing separator before input
-        println!("\n{}", "─".repeat(60));
+    // Everything shown this session, so PgUp can scroll back without relying
+    // on terminal scrollback (which the raw-mode toggling and clears corrupt)
+    let mut transcript = if conversation.messages.is_empty() {
+        String::new()
+    } else {
+        strip_frontmatter(&conversation.markdown()?).to_string()
+    };
 
-        // Check first character to see if it's a command
-        terminal::enable_raw_mode()?;
+    let mut status = SessionStatus::default();
+    // Long responses are collapsed while streaming, /expand pages the full text
+    let mut last_response: Option<String> = None;
+    // Questions suggested after the last response, sent by typing their number
+    let mut followups: Vec<String> = Vec::new();
+    // Context from /paste and similar commands, sent with the next message
+    let mut attachments = Attachments::default();
+    if let Some(text) = piped.filter(|text| !text.trim().is_empty()) {
+        let kept = attachments.add("Piped input", &text, "");
+        report_attached("stdin", kept, text.chars().count());
+    }
 
-        print!("➤ ");
-        io::stdout().flush()?;
+    // Sent in place of typed input: a draft restored at startup, or the
+    // correction typed after stopping a response
+    let mut next_input = draft::offer()?;
 
-        let Event::Key(key_event) = event::read()? else {
-            terminal::disable_raw_mode()?;
-            continue;
-        };
+    let mut running = true;
+    while running {
+        // Print the session status and a visually appealing separator before input
+        println!();
+        status.print(llm_provider.as_ref(), &conversation)?;
+        if !symbols::screen_reader() {
+            println!("{}", plain(&"─".repeat(60)));
+        }
 
-        let input = match key_event.code {
-            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                terminal::disable_raw_mode()?;
-                println!("\nExiting...");
+        let input = if let Some(input) = next_input.take() {
+            println!("{}{}", symbols::prompt(), input);
+            input
+        } else if symbols::screen_reader() {
+            // A plain line read, without raw mode or redrawing the prompt
+            print!("{}", symbols::prompt());
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line)? == 0 {
                 cleanup_and_exit(&conversation);
                 running = false;
-                String::new()
             }
-            KeyCode::Char('/') => {
-                // Switch to command mode immediately
-                // Clear current line and redraw with cyan
-                execute!(io::stdout(), cursor::MoveToColumn(0))?;
-                execute!(
-                    io::stdout(),
-                    terminal::Clear(terminal::ClearType::CurrentLine)
-                )?;
-                execute!(io::stdout(), cursor::MoveUp(1))?;
-                execute!(
-                    io::stdout(),
-                    terminal::Clear(terminal::ClearType::CurrentLine)
-                )?;
+            match line.trim() {
+                "/" => select_command(&plugins)?
+                    .map(with_argument)
+                    .transpose()?
+                    .unwrap_or_default(),
+                line => line.to_string(),
+            }
+        } else {
+            // Check first character to see if it's a command
+            terminal::enable_raw_mode()?;
+
+            print!("{}", plain("➤ "));
+            io::stdout().flush()?;
 
-                execute!(io::stdout(), SetForegroundColor(Color::Cyan))?;
-                println!("{}", "─".repeat(60));
-                print!("➤ /");
-                execute!(io::stdout(), ResetColor)?;
-                io::stdout().flush()?;
+            // Windows reports key releases too, skip them so the Enter that
+            // submitted the last line isn't read as the next key
+            let key_event = loop {
+                if let Event::Key(key_event) = event::read()?
+                    && key_event.kind != KeyEventKind::Release
+                {
+                    break key_event;
+                }
+            };
+
+            match key_event.code {
+                KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    terminal::disable_raw_mode()?;
+                    println!("\n{}", t("Exiting..."));
+                    cleanup_and_exit(&conversation);
+                    running = false;
+                    String::new()
+                }
+                KeyCode::Char('/') => {
+                    // Switch to command mode immediately
+                    // Clear current line and redraw with cyan
+                    execute!(io::stdout(), cursor::MoveToColumn(0))?;
+                    execute!(
+                        io::stdout(),
+                        terminal::Clear(terminal::ClearType::CurrentLine)
+                    )?;
+                    execute!(io::stdout(), cursor::MoveUp(1))?;
+                    execute!(
+                        io::stdout(),
+                        terminal::Clear(terminal::ClearType::CurrentLine)
+                    )?;
 
-                terminal::disable_raw_mode()?;
-                println!();
+                    execute!(io::stdout(), SetForegroundColor(Color::Cyan))?;
+                    println!("{}", plain(&"─".repeat(60)));
+                    print!("{}", plain("➤ /"));
+                    execute!(io::stdout(), ResetColor)?;
+                    io::stdout().flush()?;
 
-                // Show command selector
-                match select_command()? {
-                    Some(cmd) => cmd,
-                    None => {
-                        println!("No command selected.");
-                        String::new()
+                    terminal::disable_raw_mode()?;
+                    println!();
+
+                    // Show command selector
+                    match select_command(&plugins)? {
+                        Some(cmd) => with_argument(cmd)?,
+                        None => {
+                            println!("{}", t("No command selected."));
+                            String::new()
+                        }
                     }
                 }
-            }
-            KeyCode::Char(c) => {
-                // Not a command, use normal input
-                print!("{}", c);
-                io::stdout().flush()?;
-                terminal::disable_raw_mode()?;
-
-                // Read the rest of the line normally
-                let mut rest = String::new();
-                io::stdin().read_line(&mut rest)?;
-                format!("{}{}", c, rest.trim())
-            }
-            KeyCode::Enter => {
-                terminal::disable_raw_mode()?;
-                println!();
-                String::new()
-            }
-            _ => {
-                terminal::disable_raw_mode()?;
-                String::new()
+                KeyCode::Char(c) => {
+                    // Not a command, read the rest of the line, saving the
+                    // draft as it's typed
+                    print!("{}", c);
+                    io::stdout().flush()?;
+                    let line = draft::read_line(c, !conversation.protected);
+                    terminal::disable_raw_mode()?;
+                    println!();
+                    match line? {
+                        Some(line) => line.trim().to_string(),
+                        None => {
+                            println!("{}", t("Exiting..."));
+                            cleanup_and_exit(&conversation);
+                            running = false;
+                            String::new()
+                        }
+                    }
+                }
+                KeyCode::Enter => {
+                    terminal::disable_raw_mode()?;
+                    println!();
+                    String::new()
+                }
+                KeyCode::PageUp => {
+                    terminal::disable_raw_mode()?;
+                    viewport::show_transcript(&transcript)?;
+
+                    // Remove the prompt, separator, and status line, the loop redraws them
+                    execute!(io::stdout(), cursor::MoveToColumn(0))?;
+                    for _ in 0..2 {
+                        execute!(
+                            io::stdout(),
+                            terminal::Clear(terminal::ClearType::CurrentLine)
+                        )?;
+                        execute!(io::stdout(), cursor::MoveUp(1))?;
+                    }
+                    execute!(
+                        io::stdout(),
+                        terminal::Clear(terminal::ClearType::CurrentLine)
+                    )?;
+                    execute!(io::stdout(), cursor::MoveUp(1))?;
+                    String::new()
+                }
+                _ => {
+                    terminal::disable_raw_mode()?;
+                    String::new()
+                }
             }
         };
 
@@ -323,6 +1264,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             continue;
         }
 
+        // A number picks a follow-up only as the answer to the list just
+        // shown; after anything else it's sent as it is
+        let offered = std::mem::take(&mut followups);
+        let input = match input.parse::<usize>() {
+            Ok(n) if (1..=offered.len()).contains(&n) => {
+                let followup = offered[n - 1].clone();
+                println!("{}{}", symbols::prompt(), followup);
+                followup
+            }
+            _ => input,
+        };
+
         let input_lower = input.to_lowercase();
 
         if input_lower == "exit" || input_lower == "quit" {
@@ -332,17 +1285,133 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
 
         if input_lower == "help" {
-            println!("\nCommands:");
-            println!("  exit/quit - Quit the program (case insensitive)");
-            println!("  help - Show this help");
-            println!("\nSlash Commands:");
-            println!("  / - Open command selector (fuzzy search)");
-            println!("  /new-conversation - Start a new conversation");
-            println!("\nCurrent Conversation:");
-            println!("  ID: {}", conversation.id);
-            println!("  File: {}\n", conversation.file_path.display());
+            println!("\n{}", t("Commands:"));
+            println!("  {}", t("exit/quit - Quit the program (case insensitive)"));
+            println!("  {}", t("help - Show this help"));
+            println!(
+                "  {}",
+                t("PgUp - Scroll back through this session's transcript")
+            );
+            println!(
+                "  {}",
+                t("Esc - Stop a response while it streams, then type a correction to ask again")
+            );
+            println!(
+                "  {}",
+                t("1-3 - Ask a suggested follow-up (with followups.enabled)")
+            );
+            println!("\n{}", t("Slash Commands:"));
+            println!("  {}", t("/ - Open command selector (fuzzy search)"));
+            println!("  {}", t("/new-conversation - Start a new conversation"));
+            println!(
+                "  {}",
+                t("/expand - Show the last response in full, including collapsed parts")
+            );
+            println!(
+                "  {}",
+                t(
+                    "/context - List the system prompt, memory, tools, messages and attachments the next request includes, with token estimates"
+                )
+            );
+            println!(
+                "  {}",
+                t(
+                    "/remember <fact> - Add a fact or preference to the memory sent with every message"
+                )
+            );
+            println!(
+                "  {}",
+                t("/memory [edit] - Show the memory file, or open it in $EDITOR")
+            );
+            println!(
+                "  {}",
+                t("/fork - Continue in a copy of this conversation; `rye tree` shows the forks")
+            );
+            println!(
+                "  {}",
+                t("/retitle - Generate a new title from the whole conversation")
+            );
+            println!(
+                "  {}",
+                t(
+                    "/open - Open the conversation file in $EDITOR (or the desktop's app) and reload it when you're done"
+                )
+            );
+            println!(
+                "  {}",
+                t(
+                    "/gist [all] - Share the last response (or the conversation) as a secret gist and copy its URL"
+                )
+            );
+            println!(
+                "  {}",
+                t(
+                    "/persona [name|off] - Answer as a persona (personas/<name>.md) from the next message on"
+                )
+            );
+            println!(
+                "  {}",
+                t("/voice - Record a question and send its transcription")
+            );
+            println!(
+                "  {}",
+                t("/image <file> - Attach a PNG, JPEG, GIF or WebP image to the next message")
+            );
+            println!(
+                "  {}",
+                t(
+                    "/audio <file> - Transcribe an audio file with voice.transcribe and attach the transcript, linked to the file"
+                )
+            );
+            println!("  {}", t("/speak - Turn reading responses aloud on or off"));
+            println!(
+                "  {}",
+                t("/tee [file|off] - Append each response to a file as it streams, or stop")
+            );
+            println!(
+                "  {}",
+                t("/paste - Attach the clipboard's contents to the next message")
+            );
+            println!(
+                "  {}",
+                t(
+                    "/gitdiff [ref] - Attach `git diff` (against HEAD by default) to the next message"
+                )
+            );
+            println!(
+                "  {}",
+                t(
+                    "/gitlog [range] - Attach the last 30 commits, or those in a range, to the next message"
+                )
+            );
+            for command in plugins.commands() {
+                println!(
+                    "  {}",
+                    tf("/{} - {} (plugin)", &[&command.name, &command.description])
+                );
+            }
+            println!("\n{}", t("Current Conversation:"));
+            println!("  {}", tf("ID: {}", &[&conversation.id]));
+            println!(
+                "  {}\n",
+                tf("File: {}", &[&conversation.file_path.display()])
+            );
         }
 
+        // A spoken question is sent like a typed one
+        let input = if input_lower == "/voice" {
+            match ask_by_voice().await {
+                Ok(Some(text)) => text,
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("{}", tf("Error: /voice failed: {}", &[&e]));
+                    continue;
+                }
+            }
+        } else {
+            input
+        };
+
         // Handle slash commands (for direct typing like /new-conversation)
         if input.starts_with('/') {
             match input_lower.as_str() {
@@ -350,83 +1419,678 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     // Check if current conversation is empty and delete if so
                     if conversation.messages.is_empty() {
                         if let Err(e) = std::fs::remove_file(&conversation.file_path) {
-                            eprintln!("Warning: Could not delete empty conversation file: {}", e);
+                            eprintln!(
+                                "{}",
+                                tf(
+                                    "Warning: Could not delete empty conversation file: {}",
+                                    &[&e]
+                                )
+                            );
                         } else {
-                            println!("Empty conversation deleted.");
+                            println!("{}", t("Empty conversation deleted."));
                         }
                     } else {
                         println!(
-                            "Current conversation saved to: {}",
-                            conversation.file_path.display()
+                            "{}",
+                            tf(
+                                "Current conversation saved to: {}",
+                                &[&conversation.file_path.display()]
+                            )
                         );
                     }
                     conversation = Conversation::new()?;
-                    println!("Started new conversation: {}", conversation.id);
+                    print_started(&conversation);
                     continue;
                 }
-                _ => {
+                command if command.split(' ').next() == Some("/persona") => {
+                    match input["/persona".len()..].trim() {
+                        "" => {
+                            println!(
+                                "{}",
+                                tf(
+                                    "Current persona: {}",
+                                    &[&conversation.persona.as_deref().unwrap_or("default")]
+                                )
+                            );
+                            let names = persona::list();
+                            if names.is_empty() {
+                                println!(
+                                    "{}",
+                                    t(
+                                        "No persona files yet. Describe one in personas/<name>.md next to the config file, or use any name."
+                                    )
+                                );
+                            } else {
+                                println!("{}", tf("Personas: {}", &[&names.join(", ")]));
+                            }
+                        }
+                        "off" | "default" => {
+                            if conversation.persona.is_some() {
+                                conversation.set_persona(None).await?;
+                                transcript.push_str("## Persona: default\n\n");
+                            }
+                            println!("{}", t("Back to the default persona."));
+                        }
+                        name => {
+                            conversation.set_persona(Some(name.to_string())).await?;
+                            transcript.push_str(&format!("## Persona: {}\n\n", name));
+                            println!(
+                                "{}",
+                                plain(&tf(
+                                    "🎭 Answering as {} from the next message on.",
+                                    &[&name]
+                                ))
+                            );
+                        }
+                    }
+                    continue;
+                }
+                "/retitle" => {
+                    if conversation.messages.is_empty() {
+                        println!("{}", t("Nothing to title yet."));
+                        continue;
+                    }
+                    match llm_provider
+                        .generate_conversation_title(&conversation.messages)
+                        .await
+                    {
+                        Ok(title) => match conversation.set_title(title).await {
+                            Ok(()) => println!(
+                                "{}",
+                                tf(
+                                    "Retitled: {}",
+                                    &[&conversation.title.as_deref().unwrap_or_default()]
+                                )
+                            ),
+                            Err(e) => eprintln!(
+                                "{}",
+                                tf("Error: Could not set conversation title: {}", &[&e])
+                            ),
+                        },
+                        Err(e) => eprintln!("{}", tf("Error: Could not generate title: {}", &[&e])),
+                    }
+                    continue;
+                }
+                "/open" => {
+                    match edit::open_in_chat(&conversation).await {
+                        Ok(Some(edited)) => {
+                            conversation = edited;
+                            println!(
+                                "{}",
+                                plain(&tf(
+                                    "✏️  Reloaded {} ({} messages).",
+                                    &[
+                                        &conversation.file_path.display(),
+                                        &conversation.messages.len()
+                                    ]
+                                ))
+                            );
+                        }
+                        Ok(None) => {}
+                        Err(e) => eprintln!("{}", tf("Error: /open failed: {}", &[&e])),
+                    }
+                    continue;
+                }
+                "/fork" => {
+                    if conversation.messages.is_empty() {
+                        println!("{}", t("Nothing to fork yet."));
+                        continue;
+                    }
+                    conversation = conversation.fork().await?;
                     println!(
-                        "Unknown command: {}. Type 'help' for available commands.",
-                        input_lower
+                        "{}",
+                        tf(
+                            "Continuing in the fork {}. `rye tree` shows the forks.",
+                            &[&conversation.file_path.display()]
+                        )
+                    );
+                    continue;
+                }
+                command if command.split(' ').next() == Some("/remember") => {
+                    let fact = input["/remember".len()..].trim();
+                    if fact.is_empty() {
+                        println!("{}", t("Usage: /remember <fact>"));
+                    } else {
+                        match rye::memory::remember(fact) {
+                            Ok(path) => println!(
+                                "{}",
+                                plain(&tf("🧠 Remembered, in {}", &[&path.display()]))
+                            ),
+                            Err(e) => eprintln!("{}", tf("Error: /remember failed: {}", &[&e])),
+                        }
+                    }
+                    continue;
+                }
+                "/memory" => {
+                    match rye::memory::path().map(std::fs::read_to_string) {
+                        Some(Ok(memory)) if !memory.trim().is_empty() => render_markdown(&memory)?,
+                        _ => println!(
+                            "{}",
+                            t("Nothing remembered yet. Add facts with /remember <fact>.")
+                        ),
+                    }
+                    if !config::get().memory.enabled {
+                        println!("{}", t("Memory is turned off (memory.enabled = false)."));
+                    }
+                    continue;
+                }
+                "/memory edit" => {
+                    match rye::memory::path() {
+                        Some(path) => {
+                            if let Some(parent) = path.parent() {
+                                std::fs::create_dir_all(parent)?;
+                            }
+                            if let Err(e) = editor::open(&path) {
+                                eprintln!("{}", tf("Error: /memory edit failed: {}", &[&e]));
+                            }
+                        }
+                        None => {
+                            eprintln!("{}", t("Error: Could not determine the config directory"))
+                        }
+                    }
+                    continue;
+                }
+                "/paste" => {
+                    match context::clipboard().await {
+                        Ok(text) if text.trim().is_empty() => {
+                            println!("{}", t("The clipboard is empty."))
+                        }
+                        Ok(text) => {
+                            let kept = attachments.add("Clipboard", &text, "");
+                            report_attached("the clipboard", kept, text.chars().count());
+                        }
+                        Err(e) => eprintln!("{}", tf("Error: /paste failed: {}", &[&e])),
+                    }
+                    continue;
+                }
+                command if command.split(' ').next() == Some("/gitdiff") => {
+                    let reference = input["/gitdiff".len()..].trim();
+                    let reference = if reference.is_empty() {
+                        "HEAD"
+                    } else {
+                        reference
+                    };
+                    match context::git(&["diff", reference]).await {
+                        Ok(diff) if diff.trim().is_empty() => {
+                            println!("{}", tf("No differences from {}.", &[&reference]))
+                        }
+                        Ok(diff) => {
+                            let label = format!("Output of `git diff {}`", reference);
+                            let kept = attachments.add(&label, &diff, "diff");
+                            report_attached("the diff", kept, diff.chars().count());
+                        }
+                        Err(e) => eprintln!("{}", tf("Error: /gitdiff failed: {}", &[&e])),
+                    }
+                    continue;
+                }
+                command if command.split(' ').next() == Some("/gitlog") => {
+                    let range = input["/gitlog".len()..].trim();
+                    let mut git_args =
+                        vec!["log", "--date=short", "--format=%h %ad %an%n%n%w(0,4,4)%B"];
+                    if range.is_empty() {
+                        git_args.push("-n30");
+                    } else {
+                        git_args.push(range);
+                    }
+                    match context::git(&git_args).await {
+                        Ok(log) if log.trim().is_empty() => println!("{}", t("No commits found.")),
+                        Ok(log) => {
+                            let label = match range {
+                                "" => "Recent commits (`git log`)".to_string(),
+                                range => format!("Commits in {} (`git log`)", range),
+                            };
+                            let kept = attachments.add(&label, &log, "");
+                            report_attached("the log", kept, log.chars().count());
+                        }
+                        Err(e) => eprintln!("{}", tf("Error: /gitlog failed: {}", &[&e])),
+                    }
+                    continue;
+                }
+                command if command.split(' ').next() == Some("/image") => {
+                    let path = input["/image".len()..].trim();
+                    if path.is_empty() {
+                        println!(
+                            "{}",
+                            t("Usage: /image <file> to attach an image to the next message.")
+                        );
+                    } else if !models::capabilities(llm_provider.model()).vision {
+                        eprintln!(
+                            "{}",
+                            tf(
+                                "Error: {} can't see images, pick a model that can (see `rye models`)",
+                                &[&llm_provider.model()]
+                            )
+                        );
+                    } else {
+                        match attachments.add_image(Path::new(path)) {
+                            Ok(()) => println!(
+                                "{}",
+                                plain(&tf("📎 Attached {} to the next message.", &[&path]))
+                            ),
+                            Err(e) => eprintln!("{}", tf("Error: /image failed: {}", &[&e])),
+                        }
+                    }
+                    continue;
+                }
+                command if command.split(' ').next() == Some("/audio") => {
+                    let path = input["/audio".len()..].trim();
+                    if path.is_empty() {
+                        println!("{}", t("Usage: /audio <file> to attach its transcript."));
+                        continue;
+                    }
+                    let spinner = Spinner::start("Transcribing...");
+                    let transcript = voice::transcribe_file(path).await;
+                    spinner.stop().await;
+                    match transcript {
+                        Ok((label, text)) => {
+                            let kept = attachments.add(&label, &text, "");
+                            report_attached(path, kept, text.chars().count());
+                        }
+                        Err(e) => eprintln!("{}", tf("Error: /audio failed: {}", &[&e])),
+                    }
+                    continue;
+                }
+                command if command.split(' ').next() == Some("/tee") => {
+                    match input["/tee".len()..].trim() {
+                        "" | "off" => match tee::stop() {
+                            Some(path) => {
+                                println!(
+                                    "{}",
+                                    tf("Stopped writing responses to {}.", &[&path.display()])
+                                )
+                            }
+                            None => println!(
+                                "{}",
+                                t("Usage: /tee <file> to write responses to a file.")
+                            ),
+                        },
+                        path => match tee::start(Path::new(path)) {
+                            Ok(()) => {
+                                println!("{}", tf("Responses will be appended to {}.", &[&path]))
+                            }
+                            Err(e) => {
+                                eprintln!("{}", tf("Error: Could not open {}: {}", &[&path, &e]))
+                            }
+                        },
+                    }
+                    continue;
+                }
+                "/speak" => {
+                    let enabled = !speech::enabled();
+                    speech::set_enabled(enabled);
+                    if enabled {
+                        println!("{}", t("Responses will be read aloud."));
+                    } else {
+                        println!("{}", t("Responses won't be read aloud."));
+                    }
+                    continue;
+                }
+                "/gist" | "/gist all" => {
+                    match gist::publish(&conversation, input_lower == "/gist all").await {
+                        Ok(Some(url)) => match context::copy_to_clipboard(&url).await {
+                            Ok(()) => println!(
+                                "{}",
+                                plain(&tf("🔗 {} (copied to the clipboard)", &[&url]))
+                            ),
+                            Err(_) => println!("{}", plain(&format!("🔗 {}", url))),
+                        },
+                        Ok(None) => println!("{}", t("Nothing shared.")),
+                        Err(e) => eprintln!("{}", tf("Error: /gist failed: {}", &[&e])),
+                    }
+                    continue;
+                }
+                "/context" => {
+                    confirm::show_context(
+                        llm_provider.as_ref(),
+                        &conversation.messages,
+                        conversation.persona.as_deref(),
+                        &attachments,
                     );
                     continue;
                 }
+                "/expand" => {
+                    match &last_response {
+                        Some(response) => viewport::page_markdown(response)?,
+                        None => println!("{}", t("No response to expand yet.")),
+                    }
+                    continue;
+                }
+                _ => {
+                    let command = input.trim_start_matches('/');
+                    let (name, command_args) = command.split_once(' ').unwrap_or((command, ""));
+                    match plugins
+                        .run_command(name, command_args.trim(), &conversation.file_path)
+                        .await
+                    {
+                        Some(Ok(output)) => render_markdown(&output)?,
+                        Some(Err(e)) => eprintln!("{}", tf("Error: /{} failed: {}", &[&name, &e])),
+                        None => println!(
+                            "{}",
+                            tf(
+                                "Unknown command: {}. Type 'help' for available commands.",
+                                &[&input_lower]
+                            )
+                        ),
+                    }
+                    continue;
+                }
             }
         }
 
+        let attached = attachments.len();
+        let input = attachments.take_into(&input);
+        let Some(input) = secrets::check(&input)? else {
+            println!("{}", t("Message not sent."));
+            continue;
+        };
+        // Kept until the message is saved, in case sending is cancelled or
+        // rye goes down before then, as it passed the secrets check
+        if !conversation.protected {
+            draft::save(&input);
+        }
+
         // Add user message to conversation
-        conversation.add_message("user", &input)?;
+        let input = match hooks::pre_send(&input, &conversation.file_path).await {
+            Ok(hooked) if hooked.is_empty() => {
+                println!(
+                    "{}",
+                    t("The pre_send hook returned an empty message, nothing sent.")
+                );
+                continue;
+            }
+            // What the hook added is checked too
+            Ok(hooked) if hooked != input => match secrets::check_added(&input, &hooked)? {
+                Some(hooked) => hooked,
+                None => {
+                    println!("{}", t("Message not sent."));
+                    continue;
+                }
+            },
+            Ok(hooked) => hooked,
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    tf("Warning: pre_send hook: {}. Message not sent.", &[&e])
+                );
+                continue;
+            }
+        };
 
-        // Prepare messages for API call
-        let mut api_messages = Vec::new();
-        for (role, content) in &conversation.messages {
-            api_messages.push((role.clone(), content.clone()));
+        if dry_run {
+            let mut api_messages = conversation.messages.clone();
+            api_messages.push(("user".to_string(), input));
+            let api_messages = persona::apply(conversation.persona.as_deref(), &api_messages);
+            print_request(llm_provider.as_ref(), &api_messages)?;
+            draft::clear();
+            continue;
+        }
+
+        // Edited elsewhere, like in Obsidian or on a synced machine
+        match conversation.reload_if_changed() {
+            Ok(true) => println!(
+                "{}",
+                plain(&tf(
+                    "🔄 {} changed on disk, continuing from it as it is now ({} messages).",
+                    &[
+                        &conversation.file_path.display(),
+                        &conversation.messages.len()
+                    ]
+                ))
+            ),
+            Ok(false) => {}
+            Err(e) => eprintln!(
+                "{}",
+                tf("Warning: Could not reload the conversation: {}", &[&e])
+            ),
         }
 
+        let Some(input) =
+            confirm::preflight(llm_provider.as_ref(), &conversation.messages, &input).await?
+        else {
+            println!("{}", t("Message not sent."));
+            continue;
+        };
+
+        let input = if config::get().context.confirm {
+            match confirm::review(
+                llm_provider.as_ref(),
+                &conversation.messages,
+                &input,
+                attached,
+            )? {
+                Some(edited) if edited != input => match secrets::check_added(&input, &edited)? {
+                    Some(edited) => edited,
+                    None => {
+                        println!("{}", t("Message not sent."));
+                        continue;
+                    }
+                },
+                Some(input) => input,
+                None => {
+                    println!("{}", t("Message not sent."));
+                    continue;
+                }
+            }
+        } else {
+            input
+        };
+
+        conversation
+            .add_message_with_attachments("user", &input, attachments.sent())
+            .await?;
+        transcript.push_str(&format!("## You\n\n{}\n\n", input));
+        draft::clear();
+
+        // Prepare messages for API call
+        let api_messages = persona::apply(conversation.persona.as_deref(), &conversation.messages);
+
         // Print a visually appealing separator before assistant response
-        println!("\n{}", "═".repeat(60));
-        println!("🤖 Assistant Response:");
-        println!("{}", "═".repeat(60));
-        println!();
+        symbols::print_assistant_header();
+
+        let mut request_started = Instant::now();
+        let mut spinner = Some(Spinner::start("Waiting for response..."));
+
+        let result = match llm_provider.generate_response_stream(&api_messages).await {
+            Err(e) if config::get().queue.enabled && is_unavailable(e.as_ref()) => {
+                if let Some(spinner) = spinner.take() {
+                    spinner.stop().await;
+                }
+                let retried = queue::retry(llm_provider.as_ref(), &mut conversation, e.as_ref());
+                match retried.await {
+                    Ok(Some(stream)) => {
+                        request_started = Instant::now();
+                        Ok(stream)
+                    }
+                    Ok(None) => {
+                        if let Some(file_edits) = &file_edits {
+                            file_edits.discard();
+                        }
+                        println!();
+                        continue;
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            result => result,
+        };
 
-        match llm_provider.generate_response_stream(&api_messages).await {
+        match result {
             Ok(stream) => {
-                match stream_and_render_response(stream).await {
-                    Ok(full_response) => {
+                match stream_and_render_stoppable(stream, spinner).await {
+                    Ok((full_response, stopped)) => {
+                        let elapsed = request_started.elapsed();
+                        let usage = llm_provider.last_usage();
+                        info!(
+                            elapsed_ms = elapsed.as_millis() as u64,
+                            input_tokens = usage.input_tokens,
+                            output_tokens = usage.output_tokens,
+                            tokens_per_second = usage.output_tokens as f64 / elapsed.as_secs_f64(),
+                            "Response complete"
+                        );
+                        print_response_footer(elapsed, llm_provider.as_ref())?;
                         println!();
+                        status.record(llm_provider.as_ref());
+                        usage::record(llm_provider.as_ref(), Some(&conversation.id));
+                        if let Some(warning) = usage::budget_warning() {
+                            eprintln!("{}", tf("Warning: {}", &[&warning]));
+                        }
+
+                        // A stopped response is kept as far as it got, so the
+                        // model sees what a correction refers to
+                        let full_response = if stopped {
+                            format!("{}\n\n*[Stopped]*", full_response.trim_end())
+                                .trim_start()
+                                .to_string()
+                        } else {
+                            full_response
+                        };
+
+                        // Edits proposed during the response are reviewed now, and
+                        // what came of them is saved with it
+                        let full_response = match &file_edits {
+                            Some(file_edits) => {
+                                let record = file_edits.review()?;
+                                if record.is_empty() {
+                                    full_response
+                                } else {
+                                    format!("{}\n\n{}", full_response.trim_end(), record)
+                                }
+                            }
+                            None => full_response,
+                        };
 
                         // Save the complete response to conversation
                         if !full_response.is_empty() {
-                            conversation.add_message("assistant", &full_response)?;
+                            if let Some(model) = llm_provider.routed_model() {
+                                conversation.note_model(model).await?;
+                            }
+                            conversation
+                                .add_message("assistant", &full_response)
+                                .await?;
+                            transcript.push_str(&format!("## Assistant\n\n{}\n\n", full_response));
+                            if let Err(e) =
+                                hooks::post_response(&full_response, &conversation.file_path, true)
+                                    .await
+                            {
+                                eprintln!("{}", tf("Warning: post_response hook: {}", &[&e]));
+                            }
+                            last_response = Some(full_response);
+                        }
+
+                        if stopped {
+                            print!(
+                                "{}",
+                                plain(t(
+                                    "⏹ Stopped. Type a correction to ask again with it, or press Enter to leave it there: "
+                                ))
+                            );
+                            io::stdout().flush()?;
+                            let mut correction = String::new();
+                            io::stdin().read_line(&mut correction)?;
+                            let correction = correction.trim();
+                            if !correction.is_empty() {
+                                next_input = Some(correction.to_string());
+                            }
                         }
 
-                        // Generate title after first exchange if conversation doesn't have one
-                        if conversation.title.is_none()
-                            && conversation.messages.len() == 2
-                            && let Some((_, first_user_message)) = conversation.messages.first()
-                        {
-                            match llm_provider.generate_title(first_user_message).await {
+                        // Generate a title after the first exchange, or after the next
+                        // one for continued conversations that never got one
+                        if conversation.title.is_none() {
+                            match llm_provider
+                                .generate_conversation_title(&conversation.messages)
+                                .await
+                            {
                                 Ok(title) => {
-                                    if let Err(e) = conversation.set_title(title) {
+                                    if let Err(e) = conversation.set_title(title).await {
                                         eprintln!(
-                                            "Warning: Could not set conversation title: {}",
-                                            e
+                                            "{}",
+                                            tf(
+                                                "Warning: Could not set conversation title: {}",
+                                                &[&e]
+                                            )
                                         );
                                     }
                                 }
                                 Err(e) => {
-                                    eprintln!("Warning: Could not generate title: {}", e);
+                                    eprintln!(
+                                        "{}",
+                                        tf("Warning: Could not generate title: {}", &[&e])
+                                    );
                                 }
                             }
                         }
+
+                        if let Err(e) = hooks::on_save(&conversation.file_path).await {
+                            eprintln!("{}", tf("Warning: on_save hook: {}", &[&e]));
+                        }
+
+                        // Focus can't be observed from the line-based loop, so
+                        // only the time threshold applies here
+                        let title = conversation.title.as_deref().unwrap_or(&conversation.id);
+                        notify_response_complete(title, elapsed, None);
+
+                        let answered = conversation
+                            .messages
+                            .last()
+                            .is_some_and(|(role, _)| role == "assistant");
+                        if config::get().followups.enabled && answered && !stopped {
+                            match llm_provider
+                                .generate_followups(&conversation.messages)
+                                .await
+                            {
+                                Ok(suggested) => {
+                                    println!("{}", t("Follow-ups, type a number to ask:").dim());
+                                    for (i, followup) in suggested.iter().enumerate() {
+                                        println!("  {}", format!("{}. {}", i + 1, followup).dim());
+                                    }
+                                    followups = suggested;
+                                }
+                                Err(e) => eprintln!(
+                                    "{}",
+                                    tf("Warning: Could not suggest follow-ups: {}", &[&e])
+                                ),
+                            }
+                        }
+
+                        if rotate::due(&conversation) {
+                            let spinner = Spinner::start("Starting a new part...");
+                            let rotated =
+                                rotate::rotate(llm_provider.as_ref(), &conversation).await;
+                            spinner.stop().await;
+                            match rotated {
+                                Ok(next) => {
+                                    println!(
+                                        "{}",
+                                        plain(&tf(
+                                            "📚 This conversation got long, it continues from a summary in {}",
+                                            &[&next.file_path.display()]
+                                        ))
+                                    );
+                                    conversation = next;
+                                }
+                                Err(e) => eprintln!(
+                                    "{}",
+                                    tf("Warning: Could not start a new part: {}", &[&e])
+                                ),
+                            }
+                        }
                     }
                     Err(e) => {
-                        eprintln!("Streaming error: {}", e);
+                        if let Some(file_edits) = &file_edits {
+                            file_edits.discard();
+                        }
+                        eprintln!("{}", tf("Streaming error: {}", &[&e]));
                     }
                 }
             }
             Err(e) => {
-                println!("Error: {}", e);
+                if let Some(spinner) = spinner {
+                    spinner.stop().await;
+                }
+                if let Some(file_edits) = &file_edits {
+                    file_edits.discard();
+                }
+                println!("{}", tf("Error: {}", &[&e]));
             }
         }
 
@@ -435,3 +2099,170 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Errors with fixed text can have a translation, others keep the
+            // translated prefix
+            eprintln!("{}", tf("Error: {}", &[&translate(&e.to_string())]));
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let verbosity = if args.debug {
+        args.verbose.max(2)
+    } else {
+        args.verbose
+    };
+    logging::init(verbosity, args.log_file.as_deref())?;
+
+    // Command line settings win over the environment and the config file
+    let mut overrides = args.set.clone();
+    if let Some(provider) = &args.provider {
+        overrides.push(("provider".to_string(), provider.clone()));
+    }
+    if let Some(path) = &args.system_file {
+        overrides.push(("system_prompt_file".to_string(), path.clone()));
+    }
+    // `compare` sends to each model given, everything else uses one
+    let comparing = matches!(args.command, Some(Command::Compare { .. }));
+    match args.model.as_slice() {
+        [model] if !comparing => {
+            let (provider, model) = split_provider(model);
+            if let Some(provider) = provider {
+                overrides.push(("provider".to_string(), provider.to_string()));
+            }
+            overrides.push(("model".to_string(), model.to_string()));
+        }
+        [_, _, ..] if !comparing => {
+            return Err("--model can only be given more than once with `compare`".into());
+        }
+        _ => {}
+    }
+    if args.ascii {
+        overrides.push(("output.ascii".to_string(), "true".to_string()));
+    }
+    if args.screen_reader {
+        overrides.push(("output.screen_reader".to_string(), "true".to_string()));
+    }
+    config::set_overrides(&overrides)?;
+
+    paths::migrate_legacy_dir();
+    if args.all_projects {
+        project::show_all_projects();
+    }
+    if args.force {
+        usage::ignore_budget();
+    }
+    if let Some(path) = &args.tee {
+        tee::start(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    }
+
+    // Settings, usage, sign-in and updates don't read conversations, the
+    // rest sees the remote store's newer copies first
+    if !matches!(
+        args.command,
+        Some(
+            Command::Config { .. }
+                | Command::Usage { .. }
+                | Command::Auth { .. }
+                | Command::Models
+                | Command::SelfUpdate { .. }
+        )
+    ) {
+        rye::conversation::pull_remote().await?;
+    }
+
+    match args.command {
+        Some(Command::Config { action }) => run_config_command(action),
+        Some(Command::Usage { since }) => usage_report::run(&since),
+        Some(Command::Auth { action }) => run_auth_command(action).await,
+        Some(Command::Models) => {
+            print_models();
+            Ok(())
+        }
+        Some(Command::SelfUpdate { check }) => self_update::run(check).await,
+        Some(Command::Tree { id }) => tree::run(id.as_deref()),
+        Some(Command::ExportSite { dir }) => site::run(&dir),
+        Some(Command::Prune {
+            older_than,
+            delete,
+            yes,
+        }) => prune::run(&older_than, delete, yes).await,
+        Some(Command::Alias { id, name, remove }) => alias::run(&id, name.as_deref(), remove).await,
+        Some(Command::Protect { id, remove }) => passphrase::run(&id, remove).await,
+        Some(Command::Show { id }) => run_show(&id).await,
+        Some(Command::Edit { id }) => edit::run(&id).await,
+        Some(Command::Replay { id, speed }) => {
+            background::init();
+            replay::run(&id, speed).await
+        }
+        Some(Command::Review { paths, staged }) => {
+            let llm_provider = command_provider(Some(review::SYSTEM_PROMPT)).await?;
+            review::run(llm_provider.as_ref(), &paths, staged).await
+        }
+        Some(Command::CommitMsg { commit }) => {
+            let llm_provider = command_provider(Some(commit_msg::SYSTEM_PROMPT)).await?;
+            commit_msg::run(llm_provider.as_ref(), commit).await
+        }
+        Some(Command::Digest { since, output }) => {
+            let llm_provider = command_provider(Some(digest::SYSTEM_PROMPT)).await?;
+            digest::run(llm_provider.as_ref(), &since, output.as_deref()).await
+        }
+        Some(Command::Export { id, format, output }) => {
+            let llm_provider = command_provider(Some(export::ANKI_SYSTEM_PROMPT)).await?;
+            export::run(llm_provider.as_ref(), &id, &format, output.as_deref()).await
+        }
+        Some(Command::Ask {
+            prompt,
+            schema,
+            template,
+        }) => {
+            let system_prompt = schema.as_ref().map(|_| ask::SCHEMA_SYSTEM_PROMPT);
+            let llm_provider = cache::wrap(command_provider(system_prompt).await?);
+            ask::run(
+                llm_provider.as_ref(),
+                &prompt,
+                schema.as_deref(),
+                template.as_deref(),
+            )
+            .await
+        }
+        Some(Command::Compare { prompt }) => run_compare(&args.model, &prompt).await,
+        Some(Command::Pipeline {
+            action: PipelineAction::Run { file, input },
+        }) => {
+            let llm_provider = cache::wrap(command_provider(None).await?);
+            pipeline::run(llm_provider.as_ref(), &file, &input).await
+        }
+        Some(Command::Batch { action }) => run_batch(action).await,
+        Some(Command::Flush) => queue::flush(command_provider(None).await?.as_ref()).await,
+        Some(Command::Serve { host, port }) => {
+            serve::run(command_provider(None).await?.into(), &host, port).await
+        }
+        #[cfg(unix)]
+        Some(Command::Daemon) => daemon::run(command_provider(None).await?.into()).await,
+        Some(Command::Tui) => {
+            let llm_provider = through_daemon(command_provider(None).await?, false);
+            tui::run(llm_provider.as_ref()).await
+        }
+        Some(Command::Browse) => run_browse(args.dry_run).await,
+        None => {
+            // `git diff | rye`: the piped text goes with the first message,
+            // and the terminal takes over stdin for the session
+            let piped = if io::stdin().is_terminal() {
+                None
+            } else {
+                context::take_piped_stdin().map_err(|e| e.to_string())?
+            };
+            run_chat(args.r#continue, piped, args.dry_run).await
+        }
+    }
+}
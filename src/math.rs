@@ -0,0 +1,502 @@
+// Best-effort LaTeX to Unicode pass run before markdown rendering. Inline math
+// (`$...$`, `\(...\)`) becomes an inline code span and display math (`$$...$$`,
+// `\[...\]`) a code block, so it stands out and termimad leaves it alone.
+
+const SYMBOLS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ε"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "φ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("times", "×"),
+    ("cdot", "·"),
+    ("div", "÷"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("sim", "∼"),
+    ("equiv", "≡"),
+    ("propto", "∝"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("infty", "∞"),
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("int", "∫"),
+    ("oint", "∮"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⇒"),
+    ("iff", "⇔"),
+    ("mapsto", "↦"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("supseteq", "⊇"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("land", "∧"),
+    ("wedge", "∧"),
+    ("lor", "∨"),
+    ("vee", "∨"),
+    ("oplus", "⊕"),
+    ("otimes", "⊗"),
+    ("circ", "∘"),
+    ("star", "⋆"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("mid", "|"),
+    ("vert", "|"),
+    ("Vert", "‖"),
+    ("hbar", "ℏ"),
+    ("ell", "ℓ"),
+    ("degree", "°"),
+    ("prime", "′"),
+    ("quad", "  "),
+    ("qquad", "    "),
+    ("sin", "sin"),
+    ("cos", "cos"),
+    ("tan", "tan"),
+    ("log", "log"),
+    ("ln", "ln"),
+    ("exp", "exp"),
+    ("lim", "lim"),
+    ("max", "max"),
+    ("min", "min"),
+    ("det", "det"),
+];
+
+const SUPERSCRIPTS: &[(char, char)] = &[
+    ('0', '⁰'),
+    ('1', '¹'),
+    ('2', '²'),
+    ('3', '³'),
+    ('4', '⁴'),
+    ('5', '⁵'),
+    ('6', '⁶'),
+    ('7', '⁷'),
+    ('8', '⁸'),
+    ('9', '⁹'),
+    ('+', '⁺'),
+    ('-', '⁻'),
+    ('=', '⁼'),
+    ('(', '⁽'),
+    (')', '⁾'),
+    ('a', 'ᵃ'),
+    ('b', 'ᵇ'),
+    ('c', 'ᶜ'),
+    ('d', 'ᵈ'),
+    ('e', 'ᵉ'),
+    ('f', 'ᶠ'),
+    ('g', 'ᵍ'),
+    ('h', 'ʰ'),
+    ('i', 'ⁱ'),
+    ('j', 'ʲ'),
+    ('k', 'ᵏ'),
+    ('l', 'ˡ'),
+    ('m', 'ᵐ'),
+    ('n', 'ⁿ'),
+    ('o', 'ᵒ'),
+    ('p', 'ᵖ'),
+    ('r', 'ʳ'),
+    ('s', 'ˢ'),
+    ('t', 'ᵗ'),
+    ('u', 'ᵘ'),
+    ('v', 'ᵛ'),
+    ('w', 'ʷ'),
+    ('x', 'ˣ'),
+    ('y', 'ʸ'),
+    ('z', 'ᶻ'),
+    ('T', 'ᵀ'),
+    ('′', '′'),
+];
+
+const SUBSCRIPTS: &[(char, char)] = &[
+    ('0', '₀'),
+    ('1', '₁'),
+    ('2', '₂'),
+    ('3', '₃'),
+    ('4', '₄'),
+    ('5', '₅'),
+    ('6', '₆'),
+    ('7', '₇'),
+    ('8', '₈'),
+    ('9', '₉'),
+    ('+', '₊'),
+    ('-', '₋'),
+    ('=', '₌'),
+    ('(', '₍'),
+    (')', '₎'),
+    ('a', 'ₐ'),
+    ('e', 'ₑ'),
+    ('h', 'ₕ'),
+    ('i', 'ᵢ'),
+    ('j', 'ⱼ'),
+    ('k', 'ₖ'),
+    ('l', 'ₗ'),
+    ('m', 'ₘ'),
+    ('n', 'ₙ'),
+    ('o', 'ₒ'),
+    ('p', 'ₚ'),
+    ('r', 'ᵣ'),
+    ('s', 'ₛ'),
+    ('t', 'ₜ'),
+    ('u', 'ᵤ'),
+    ('v', 'ᵥ'),
+    ('x', 'ₓ'),
+];
+
+/// Replaces math outside code with its Unicode rendering
+pub fn render_math(text: &str) -> String {
+    let mut output = String::new();
+    let mut in_code_block = false;
+    // Closing delimiter and body of an unfinished display block
+    let mut display: Option<(&'static str, String)> = None;
+
+    for line in text.split_inclusive('\n') {
+        if display.is_none() && line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+
+        if in_code_block {
+            output.push_str(line);
+        } else {
+            render_line(line, &mut display, &mut output);
+        }
+    }
+
+    // Unterminated display math is left as written
+    if let Some((close, body)) = display {
+        output.push_str(if close == "$$" { "$$" } else { "\\[" });
+        output.push_str(&body);
+    }
+
+    output
+}
+
+fn render_line(line: &str, display: &mut Option<(&'static str, String)>, output: &mut String) {
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some((close, body)) = display {
+            if let Some(after) = rest.strip_prefix(*close) {
+                if !output.is_empty() && !output.ends_with('\n') {
+                    output.push('\n');
+                }
+                output.push_str(&math_block(body));
+                *display = None;
+                // The block already ends its line
+                rest = after.strip_prefix('\n').unwrap_or(after);
+            } else {
+                body.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+            continue;
+        }
+
+        // Code spans, escaped dollars, and the math delimiters
+        if c == '`' {
+            let end = rest[1..].find('`').map_or(rest.len(), |end| end + 2);
+            output.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix("\\$") {
+            output.push_str("\\$");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("$$") {
+            *display = Some(("$$", String::new()));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\[") {
+            *display = Some(("\\]", String::new()));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("\\(")
+            && let Some(end) = after.find("\\)")
+        {
+            output.push_str(&inline_math(&after[..end]));
+            rest = &after[end + 2..];
+        } else if c == '$'
+            && let Some(end) = inline_dollar_end(rest)
+        {
+            output.push_str(&inline_math(&rest[1..end]));
+            rest = &rest[end + 1..];
+        } else {
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+}
+
+// Finds the closing `$` the way pandoc does, so prices like "$5 and $10"
+// stay text: no space just inside either dollar, no digit right after the close
+fn inline_dollar_end(text: &str) -> Option<usize> {
+    let body = &text[1..];
+    if body.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let end = body.find('$')? + 1;
+    let inner = &text[1..end];
+    let followed_by_digit = text[end + 1..].starts_with(|c: char| c.is_ascii_digit());
+
+    if inner.is_empty() || inner.ends_with(char::is_whitespace) || followed_by_digit {
+        return None;
+    }
+
+    Some(end)
+}
+
+fn inline_math(tex: &str) -> String {
+    let rendered = tex_to_unicode(tex).replace(['\n', '`'], " ");
+    format!("`{}`", rendered.trim())
+}
+
+fn math_block(tex: &str) -> String {
+    let rendered = tex_to_unicode(tex.trim());
+    let lines: Vec<&str> = rendered.lines().map(str::trim).collect();
+    format!("```math\n{}\n```\n", lines.join("\n"))
+}
+
+fn tex_to_unicode(tex: &str) -> String {
+    let mut parser = Parser {
+        chars: tex.chars().collect(),
+        pos: 0,
+    };
+    parser.expression()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied();
+        self.pos += 1;
+        c
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn expression(&mut self) -> String {
+        let mut output = String::new();
+
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => output.push_str(&self.command()),
+                '^' => output.push_str(&script(&self.argument(), SUPERSCRIPTS, '^')),
+                '_' => output.push_str(&script(&self.argument(), SUBSCRIPTS, '_')),
+                '{' | '}' | '&' => {}
+                '~' => output.push(' '),
+                c => output.push(c),
+            }
+        }
+
+        output
+    }
+
+    // A braced group, a command, or a single character
+    fn argument(&mut self) -> String {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+
+        match self.next() {
+            Some('{') => tex_to_unicode(&self.raw_group()),
+            Some('\\') => self.command(),
+            Some(c) => c.to_string(),
+            None => String::new(),
+        }
+    }
+
+    // The text up to the `}` matching an already consumed `{`
+    fn raw_group(&mut self) -> String {
+        let mut depth = 1;
+        let mut group = String::new();
+
+        while let Some(c) = self.next() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            group.push(c);
+        }
+
+        group
+    }
+
+    fn command(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+            name.push(c);
+            self.pos += 1;
+        }
+
+        if name.is_empty() {
+            return match self.next() {
+                Some(',' | ';' | ':' | ' ') => " ".to_string(),
+                Some('!') | None => String::new(),
+                Some('\\') => "\n".to_string(),
+                Some(c) => c.to_string(),
+            };
+        }
+
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.argument();
+                let denominator = self.argument();
+                format!(
+                    "{}/{}",
+                    parenthesize(&numerator),
+                    parenthesize(&denominator)
+                )
+            }
+            "sqrt" => {
+                let index = if self.peek() == Some('[') {
+                    self.pos += 1;
+                    let mut index = String::new();
+                    while let Some(c) = self.next().filter(|&c| c != ']') {
+                        index.push(c);
+                    }
+                    script(&index, SUPERSCRIPTS, '^')
+                } else {
+                    String::new()
+                };
+                format!("{}√{}", index, parenthesize(&self.argument()))
+            }
+            "text" | "textrm" | "textit" | "textbf" | "mbox" => {
+                if self.peek() == Some('{') {
+                    self.pos += 1;
+                    self.raw_group()
+                } else {
+                    String::new()
+                }
+            }
+            "mathbb" => self.argument().chars().map(double_struck).collect(),
+            "mathrm" | "mathbf" | "mathit" | "mathsf" | "mathtt" | "mathcal" | "boldsymbol"
+            | "operatorname" => self.argument(),
+            "begin" | "end" => {
+                self.argument();
+                String::new()
+            }
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" => {
+                // `\left.` is an invisible delimiter
+                if self.peek() == Some('.') {
+                    self.pos += 1;
+                }
+                String::new()
+            }
+            "displaystyle" | "textstyle" | "limits" | "nolimits" => String::new(),
+            _ => SYMBOLS
+                .iter()
+                .find(|(symbol, _)| *symbol == name)
+                .map_or_else(|| format!("\\{}", name), |(_, unicode)| unicode.to_string()),
+        }
+    }
+}
+
+// Maps every character or falls back to ^x / ^(...)
+fn script(text: &str, table: &[(char, char)], marker: char) -> String {
+    let mapped: Option<String> = text
+        .chars()
+        .map(|c| table.iter().find(|(from, _)| *from == c).map(|(_, to)| *to))
+        .collect();
+
+    match mapped {
+        Some(mapped) if !mapped.is_empty() => mapped,
+        _ if text.chars().count() <= 1 => format!("{}{}", marker, text),
+        _ => format!("{}({})", marker, text),
+    }
+}
+
+fn parenthesize(text: &str) -> String {
+    if text.chars().count() <= 1 || text.chars().all(|c| c.is_alphanumeric() || c == '.') {
+        text.to_string()
+    } else {
+        format!("({})", text)
+    }
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'N' => 'ℕ',
+        'Z' => 'ℤ',
+        'Q' => 'ℚ',
+        'R' => 'ℝ',
+        'C' => 'ℂ',
+        'P' => 'ℙ',
+        'H' => 'ℍ',
+        c => c,
+    }
+}
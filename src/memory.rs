@@ -0,0 +1,50 @@
+use crate::config;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+// Facts and preferences the model should always know, kept in `memory.md`
+// next to the config file. The file is read for every request, so `/remember`
+// and edits take effect on the next message. Turned off with
+// `memory.enabled = false`.
+
+/// `memory.md` next to the config file
+pub fn path() -> Option<PathBuf> {
+    config::config_path().and_then(|path| Some(path.parent()?.join("memory.md")))
+}
+
+/// The memory file's content, if memory is enabled and the file isn't empty
+pub fn read() -> Option<String> {
+    if !config::get().memory.enabled {
+        return None;
+    }
+    let content = std::fs::read_to_string(path()?).ok()?;
+    let content = content.trim();
+    (!content.is_empty()).then(|| content.to_string())
+}
+
+/// Appends `fact` to the memory file as a list item
+pub fn remember(fact: &str) -> io::Result<PathBuf> {
+    let path = path().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            "Could not determine the config directory",
+        )
+    })?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // Keep the list item on its own line when the file doesn't end with one
+    let needs_newline = std::fs::read_to_string(&path)
+        .map(|content| !content.is_empty() && !content.ends_with('\n'))
+        .unwrap_or(false);
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    if needs_newline {
+        writeln!(file)?;
+    }
+    writeln!(file, "- {}", fact.trim())?;
+    Ok(path)
+}
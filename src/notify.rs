@@ -0,0 +1,35 @@
+use notify_rust::Notification;
+use rye::config;
+use std::time::Duration;
+
+// Responses slower than the `notify_after` setting (in seconds) trigger a
+// desktop notification, 0 disables them
+fn notify_threshold() -> Option<Duration> {
+    let secs = config::get().notify_after;
+
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Fires a desktop notification if the response took longer than the threshold
+/// and the terminal isn't focused. `focused` is `None` when focus can't be tracked,
+/// in which case only the threshold applies.
+pub fn notify_response_complete(
+    conversation_title: &str,
+    elapsed: Duration,
+    focused: Option<bool>,
+) {
+    let Some(threshold) = notify_threshold() else {
+        return;
+    };
+
+    if elapsed < threshold || focused == Some(true) {
+        return;
+    }
+
+    // A missing notification daemon shouldn't interrupt the conversation
+    let _ = Notification::new()
+        .summary("🥃 Rye - Response ready")
+        .body(&format!("{} ({}s)", conversation_title, elapsed.as_secs()))
+        .appname("rye")
+        .show();
+}
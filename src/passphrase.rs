@@ -0,0 +1,85 @@
+use rye::conversation::Conversation;
+use rye::protect::ask_passphrase;
+use std::io;
+
+// `rye protect <id>` encrypts a conversation's messages under a passphrase,
+// `rye protect <id> --remove` stores them as plain markdown again. Opening a
+// protected conversation, with `rye show` or `--continue`, asks for it.
+
+// Wrong passphrases allowed before giving up
+const ATTEMPTS: usize = 3;
+
+/// Loads conversation `id` with its messages, asking for the passphrase if
+/// it's protected
+pub fn load(id: &str) -> Result<Conversation, Box<dyn std::error::Error>> {
+    let mut conversation = Conversation::load_metadata(id)?;
+    if !conversation.protected {
+        conversation.load_messages()?;
+        return Ok(conversation);
+    }
+    unlock(&mut conversation)?;
+    Ok(conversation)
+}
+
+/// Whether `error` is a protected conversation left locked, by a wrong
+/// passphrase or none at all
+pub fn is_locked(error: &(dyn std::error::Error + 'static)) -> bool {
+    error
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied)
+}
+
+fn unlock(conversation: &mut Conversation) -> Result<(), Box<dyn std::error::Error>> {
+    let label = conversation
+        .title
+        .clone()
+        .unwrap_or_else(|| conversation.id.clone());
+    for attempt in 1..=ATTEMPTS {
+        let passphrase = ask_passphrase(&format!("Passphrase for {}: ", label))?;
+        match conversation.unlock(&passphrase) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied && attempt < ATTEMPTS => {
+                eprintln!("{}", e);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!("the last attempt returns")
+}
+
+/// Protects conversation `id` with a passphrase, or with `remove` stores it
+/// as plain markdown again
+pub async fn run(id: &str, remove: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut conversation =
+        Conversation::load_metadata(id).map_err(|_| format!("No conversation '{}'", id))?;
+    let label = conversation
+        .title
+        .clone()
+        .unwrap_or_else(|| conversation.id.clone());
+
+    if remove {
+        if !conversation.protected {
+            println!("{} isn't protected", label);
+            return Ok(());
+        }
+        unlock(&mut conversation)?;
+        conversation.unprotect().await?;
+        println!("{} is stored as plain markdown again", label);
+        return Ok(());
+    }
+
+    if conversation.protected {
+        println!("{} is already protected", label);
+        return Ok(());
+    }
+    let passphrase = ask_passphrase("New passphrase: ")?;
+    if ask_passphrase("Repeat it: ")? != passphrase {
+        return Err("The passphrases don't match".into());
+    }
+    conversation.protect(&passphrase).await?;
+    println!(
+        "{}'s messages are now encrypted; `rye show` and `--continue` ask for the passphrase. There's no way to recover it.",
+        label
+    );
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use crate::config;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+// Where rye keeps its files, following the XDG base directories:
+// conversations in $XDG_DATA_HOME/rye, config in $XDG_CONFIG_HOME/rye and
+// caches in $XDG_CACHE_HOME/rye. Older versions kept everything in ~/.rye,
+// which is still used until `migrate_legacy_dir()` has moved it. The data
+// directory is only resolved after the move was tried, so nothing holds on
+// to a path under ~/.rye that's about to move. The config directory can't
+// wait for it, since the move reads the config.
+
+fn legacy_dir() -> Option<PathBuf> {
+    dirs::home_dir()
+        .map(|home| home.join(".rye"))
+        .filter(|dir| dir.is_dir())
+}
+
+/// Directory for conversation files
+pub fn data_dir() -> Option<PathBuf> {
+    migrate_legacy_dir();
+    legacy_dir().or_else(|| dirs::data_dir().map(|dir| dir.join("rye")))
+}
+
+/// Directory holding `config.toml`
+pub fn config_dir() -> Option<PathBuf> {
+    legacy_dir()
+        .filter(|dir| dir.join("config.toml").exists())
+        .or_else(|| dirs::config_dir().map(|dir| dir.join("rye")))
+}
+
+/// Directory for files that can be rebuilt, such as the conversation list cache
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("rye"))
+}
+
+/// Unix socket `rye daemon` listens on, in `$XDG_RUNTIME_DIR` when set
+pub fn socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .map(|dir| dir.join("rye.sock"))
+        .or_else(|| cache_dir().map(|dir| dir.join("daemon.sock")))
+}
+
+/// Moves `~/.rye` to the data directory and its `config.toml` to the config
+/// directory. Skipped when `conversations_dir` or `RYE_CONFIG` is set, since
+/// those may point into `~/.rye`. Only tried once per run.
+pub fn migrate_legacy_dir() {
+    static MIGRATED: Once = Once::new();
+    MIGRATED.call_once(migrate);
+}
+
+fn migrate() {
+    if config::get().conversations_dir.is_some() || env::var_os("RYE_CONFIG").is_some() {
+        return;
+    }
+
+    let (Some(legacy), Some(data_dir), Some(config_dir)) = (
+        legacy_dir(),
+        dirs::data_dir().map(|dir| dir.join("rye")),
+        dirs::config_dir().map(|dir| dir.join("rye")),
+    ) else {
+        return;
+    };
+
+    if data_dir.exists() {
+        return;
+    }
+
+    match move_legacy_files(&legacy, &data_dir, &config_dir) {
+        Ok(()) => println!(
+            "Moved conversations from {} to {}",
+            legacy.display(),
+            data_dir.display()
+        ),
+        Err(e) => eprintln!(
+            "Warning: Could not move {} to {}: {}",
+            legacy.display(),
+            data_dir.display(),
+            e
+        ),
+    }
+}
+
+fn move_legacy_files(legacy: &Path, data_dir: &Path, config_dir: &Path) -> io::Result<()> {
+    let legacy_config = legacy.join("config.toml");
+    let config_path = config_dir.join("config.toml");
+    if legacy_config.exists() && !config_path.exists() {
+        fs::create_dir_all(config_dir)?;
+        fs::rename(&legacy_config, &config_path)?;
+    }
+
+    // The list cache now lives in the cache directory
+    let _ = fs::remove_file(legacy.join(".list-cache.json"));
+
+    if let Some(parent) = data_dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(legacy, data_dir)
+}
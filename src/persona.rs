@@ -0,0 +1,50 @@
+use crate::config;
+use std::path::PathBuf;
+
+// `/persona <name>` has the model answer as someone else from the next
+// message on, e.g. a security auditor. A persona is described by
+// `personas/<name>.md` next to the config file; without one the name itself
+// is the description. The switch is recorded in the conversation file as a
+// `## Persona: <name>` line, which message parsing skips.
+
+/// The `personas` directory next to the config file
+pub fn dir() -> Option<PathBuf> {
+    config::config_path().and_then(|path| Some(path.parent()?.join("personas")))
+}
+
+/// Names of the personas with a file, sorted
+pub fn list() -> Vec<String> {
+    let Some(entries) = dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "md"))
+        .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .collect();
+    names.sort();
+    names
+}
+
+/// What the model is told with every message while `name` is active
+pub fn instruction(name: &str) -> String {
+    let description = dir()
+        .and_then(|dir| std::fs::read_to_string(dir.join(format!("{}.md", name))).ok())
+        .map(|content| content.trim().to_string())
+        .filter(|content| !content.is_empty())
+        .unwrap_or_else(|| format!("Answer as {}.", name));
+    format!("Persona for this answer: {}", description)
+}
+
+/// `messages` with the instruction for `persona` added to the newest one, so
+/// earlier answers keep the persona they were given under
+pub fn apply(persona: Option<&str>, messages: &[(String, String)]) -> Vec<(String, String)> {
+    let mut messages = messages.to_vec();
+    if let Some(persona) = persona
+        && let Some((_, content)) = messages.last_mut()
+    {
+        content.push_str(&format!("\n\n{}", instruction(persona)));
+    }
+    messages
+}
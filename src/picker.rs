@@ -0,0 +1,57 @@
+use std::io;
+
+// Interactive single-choice list. skim only builds on unix, so other
+// platforms get a numbered list read from stdin instead.
+
+/// Shows `items` and returns the chosen one, or `None` if the user aborted.
+/// `reverse` lists the items below the prompt.
+#[cfg(unix)]
+pub fn pick(items: Vec<String>, prompt: &str, reverse: bool) -> io::Result<Option<String>> {
+    use skim::prelude::*;
+    use std::sync::Arc;
+
+    let mut builder = SkimOptionsBuilder::default();
+    builder.height("50%".to_string()).prompt(prompt.to_string());
+    if reverse {
+        builder.layout("reverse".to_string());
+    }
+    let options = builder.build().map_err(io::Error::other)?;
+
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+
+    for item in items {
+        tx.send(Arc::new(item)).map_err(io::Error::other)?;
+    }
+    drop(tx);
+
+    let output = Skim::run_with(&options, Some(rx));
+
+    Ok(match output {
+        Some(out) if !out.is_abort => out
+            .selected_items
+            .first()
+            .map(|selected| selected.output().to_string()),
+        _ => None,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn pick(items: Vec<String>, prompt: &str, _reverse: bool) -> io::Result<Option<String>> {
+    use std::io::Write;
+
+    for (i, item) in items.iter().enumerate() {
+        println!("{:>3}. {}", i + 1, item);
+    }
+    print!("{}", prompt);
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+
+    Ok(line
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| items.get(n.wrapping_sub(1)))
+        .cloned())
+}
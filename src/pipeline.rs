@@ -0,0 +1,222 @@
+use crate::status::print_response_footer;
+use rye::conversation::Conversation;
+use rye::hooks;
+use rye::providers::{LLMProvider, collect_with};
+use rye::secrets;
+use rye::streaming::stream_and_render_response;
+use rye::symbols::plain;
+use rye::tee;
+use rye::usage;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Read};
+use std::path::Path;
+use std::time::Instant;
+
+// `rye pipeline run file.yaml`: prompts run one after another, each able to
+// use the output of earlier ones:
+//
+//   name: Summarize then translate
+//   steps:
+//     - name: summary
+//       prompt: "Summarize in three bullet points:\n\n{input}"
+//     - name: french
+//       prompt: "Translate into French:\n\n{previous}"
+//
+// `{input}` is the text given on the command line (or stdin), `{previous}`
+// the previous step's output and `{steps.<name>}` a named step's output. Each
+// step is sent on its own, and every step is saved, in order, as one
+// conversation tagged `pipeline`.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Pipeline {
+    name: Option<String>,
+    steps: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    name: Option<String>,
+    prompt: String,
+}
+
+fn load(path: &Path) -> Result<Pipeline, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let pipeline: Pipeline =
+        serde_yaml_ng::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if pipeline.steps.is_empty() {
+        return Err(format!("{} has no steps", path.display()).into());
+    }
+
+    // Catch references to steps that don't run first before anything is sent
+    let mut earlier: Vec<&str> = Vec::new();
+    for (i, step) in pipeline.steps.iter().enumerate() {
+        let mut rest = step.prompt.as_str();
+        while let Some(start) = rest.find("{steps.") {
+            rest = &rest[start + "{steps.".len()..];
+            let name = rest.split('}').next().unwrap_or_default();
+            if !earlier.contains(&name) {
+                return Err(format!(
+                    "{}: step {} uses {{steps.{}}}, which isn't an earlier step",
+                    path.display(),
+                    i + 1,
+                    name
+                )
+                .into());
+            }
+        }
+        if let Some(name) = &step.name {
+            earlier.push(name);
+        }
+    }
+    Ok(pipeline)
+}
+
+// Replaces the placeholders in one pass over the prompt, so braces in an
+// output aren't read as placeholders of their own. Unknown ones stay as they are.
+fn fill(prompt: &str, input: &str, previous: &str, outputs: &HashMap<String, String>) -> String {
+    let mut filled = String::with_capacity(prompt.len());
+    let mut rest = prompt;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest.find('}').and_then(|end| {
+            let value = match &rest[1..end] {
+                "input" => input,
+                "previous" => previous,
+                name => outputs.get(name.strip_prefix("steps.")?)?,
+            };
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                filled.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Runs the pipeline in `path` on `words` (or stdin). In a terminal every
+/// step is shown as it streams; otherwise only the last step's output is
+/// printed.
+pub async fn run(
+    llm_provider: &dyn LLMProvider,
+    path: &Path,
+    words: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pipeline = load(path)?;
+    let input = if !words.is_empty() {
+        words.join(" ")
+    } else if !io::stdin().is_terminal() {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        input
+    } else {
+        String::new()
+    };
+    let Some(input) = secrets::check(&input)? else {
+        return Err("Cancelled, nothing sent".into());
+    };
+
+    let interactive = io::stdout().is_terminal();
+    let mut conversation = Conversation::new()?;
+    let mut outputs = HashMap::new();
+    let mut previous = String::new();
+
+    for (i, step) in pipeline.steps.iter().enumerate() {
+        let prompt = fill(&step.prompt, &input, &previous, &outputs);
+        let label = step.name.as_deref().unwrap_or("unnamed");
+        let messages = [("user".to_string(), prompt.clone())];
+
+        let result = async {
+            let stream = llm_provider.generate_response_stream(&messages).await?;
+            if interactive {
+                println!(
+                    "{}",
+                    plain(&format!(
+                        "▶ Step {} of {}: {}\n",
+                        i + 1,
+                        pipeline.steps.len(),
+                        label
+                    ))
+                );
+                let request_started = Instant::now();
+                let output = stream_and_render_response(stream, None).await?;
+                print_response_footer(request_started.elapsed(), llm_provider)?;
+                println!();
+                Ok(output)
+            } else {
+                let output = collect_with(stream, tee::write).await?;
+                tee::finish();
+                Ok::<_, Box<dyn std::error::Error>>(output)
+            }
+        }
+        .await;
+        usage::record(llm_provider, Some(&conversation.id));
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                finish(&mut conversation, &pipeline, path).await?;
+                return Err(format!("Step {} ({}) failed: {}", i + 1, label, e).into());
+            }
+        };
+
+        conversation.add_message("user", &prompt).await?;
+        if let Some(model) = llm_provider.routed_model() {
+            conversation.note_model(model).await?;
+        }
+        conversation.add_message("assistant", &output).await?;
+        if let Err(e) = hooks::post_response(&output, &conversation.file_path, false).await {
+            eprintln!("Warning: post_response hook: {}", e);
+        }
+
+        if let Some(name) = &step.name {
+            outputs.insert(name.clone(), output.clone());
+        }
+        previous = output;
+    }
+
+    if !interactive {
+        println!("{}", previous.trim_end());
+    }
+    finish(&mut conversation, &pipeline, path).await?;
+    if interactive {
+        println!("Pipeline saved to: {}", conversation.file_path.display());
+    }
+    Ok(())
+}
+
+// Titles and tags the conversation, or removes it when no step finished
+async fn finish(
+    conversation: &mut Conversation,
+    pipeline: &Pipeline,
+    path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if conversation.messages.is_empty() {
+        std::fs::remove_file(&conversation.file_path)?;
+        return Ok(());
+    }
+
+    let name = pipeline.name.clone().unwrap_or_else(|| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Pipeline".to_string())
+    });
+    let title = format!("{} {}", name, chrono::Local::now().format("%Y-%m-%d %H:%M"));
+    conversation.set_tags(vec!["pipeline".to_string()]).await?;
+    conversation.set_title(title).await?;
+    if let Err(e) = hooks::on_save(&conversation.file_path).await {
+        eprintln!("Warning: on_save hook: {}", e);
+    }
+    Ok(())
+}
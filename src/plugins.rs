@@ -0,0 +1,246 @@
+use crate::config;
+use crate::providers::{ToolDefinition, ToolRunner};
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::debug;
+
+// Plugins are executables in the `plugins` directory next to the config file.
+// Each call starts the plugin, writes one JSON request to its stdin and reads
+// one JSON reply from its stdout:
+//
+//   {"type": "describe"}
+//     -> {"commands": [{"name", "description"}], "tools": [{"name", "description", "input_schema"}]}
+//   {"type": "command", "name": "...", "args": "...", "conversation_file": "..."}
+//     -> {"output": "markdown"} or {"error": "..."}
+//   {"type": "tool", "name": "...", "input": {...}}
+//     -> {"output": "text"} or {"error": "..."}
+
+/// A slash command provided by a plugin, without the leading `/`
+#[derive(Deserialize, Clone)]
+pub struct PluginCommand {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Deserialize, Default)]
+struct Description {
+    #[serde(default)]
+    commands: Vec<PluginCommand>,
+    #[serde(default)]
+    tools: Vec<ToolDefinition>,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    output: Option<String>,
+    error: Option<String>,
+}
+
+struct Plugin {
+    path: PathBuf,
+    commands: Vec<PluginCommand>,
+    tools: Vec<ToolDefinition>,
+}
+
+/// The plugins found at startup and what they provide
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+/// Directory plugins are loaded from, next to the config file
+pub fn plugins_dir() -> Option<PathBuf> {
+    config::config_path().and_then(|path| Some(path.parent()?.join("plugins")))
+}
+
+// Runs the plugin at `path` with `request`, killing it once `plugins.timeout`
+// has passed
+async fn call(path: &Path, request: &impl Serialize) -> Result<Vec<u8>, String> {
+    let timeout = config::get().plugins.timeout;
+    tokio::time::timeout(Duration::from_secs(timeout), call_once(path, request))
+        .await
+        .unwrap_or_else(|_| Err(format!("no answer within {} seconds", timeout)))
+}
+
+async fn call_once(path: &Path, request: &impl Serialize) -> Result<Vec<u8>, String> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| e.to_string())?;
+
+    let mut request = serde_json::to_vec(request).map_err(|e| e.to_string())?;
+    request.push(b'\n');
+    let stdin = child.stdin.take();
+    let write = async move {
+        if let Some(mut stdin) = stdin {
+            let _ = stdin.write_all(&request).await;
+        }
+    };
+    let ((), output) = tokio::join!(write, child.wait_with_output());
+    let output = output.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("exited with {}: {}", output.status, stderr.trim()));
+    }
+
+    Ok(output.stdout)
+}
+
+async fn call_for_output(path: &Path, request: Value) -> Result<String, String> {
+    let stdout = call(path, &request).await?;
+    let reply: Reply = serde_json::from_slice(&stdout).map_err(|e| e.to_string())?;
+
+    match (reply.output, reply.error) {
+        (_, Some(error)) => Err(error),
+        (Some(output), None) => Ok(output),
+        (None, None) => Ok(String::new()),
+    }
+}
+
+impl Plugins {
+    /// Asks every executable in the plugins directory what it provides.
+    /// Plugins that fail to answer are skipped with a warning.
+    pub async fn load() -> Self {
+        let Some(dir) = plugins_dir() else {
+            return Self::default();
+        };
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return Self::default();
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+
+        // All asked at once, so a slow plugin doesn't hold up the others
+        let request = json!({ "type": "describe" });
+        let descriptions = join_all(paths.iter().map(|path| async {
+            match call(path, &request).await {
+                Ok(stdout) => serde_json::from_slice::<Description>(&stdout)
+                    .map_err(|e| format!("invalid reply: {}", e)),
+                Err(e) => Err(e),
+            }
+        }))
+        .await;
+
+        let mut plugins = Self::default();
+        for (path, description) in paths.into_iter().zip(descriptions) {
+            match description {
+                Ok(description) => plugins.add(path, description),
+                Err(e) => eprintln!("Warning: Skipping plugin {}: {}", path.display(), e),
+            }
+        }
+
+        plugins
+    }
+
+    // Names already taken by an earlier plugin are dropped
+    fn add(&mut self, path: PathBuf, description: Description) {
+        let mut commands = Vec::new();
+        for command in description.commands {
+            if self.find_command(&command.name).is_some() {
+                eprintln!(
+                    "Warning: Plugin {} redefines /{}, ignoring it",
+                    path.display(),
+                    command.name
+                );
+            } else {
+                commands.push(command);
+            }
+        }
+
+        let mut tools = Vec::new();
+        for tool in description.tools {
+            if self.find_tool(&tool.name).is_some() {
+                eprintln!(
+                    "Warning: Plugin {} redefines tool {}, ignoring it",
+                    path.display(),
+                    tool.name
+                );
+            } else {
+                tools.push(tool);
+            }
+        }
+
+        debug!(
+            path = %path.display(),
+            commands = commands.len(),
+            tools = tools.len(),
+            "Loaded plugin"
+        );
+        self.plugins.push(Plugin {
+            path,
+            commands,
+            tools,
+        });
+    }
+
+    /// Every plugin command, in load order
+    pub fn commands(&self) -> impl Iterator<Item = &PluginCommand> {
+        self.plugins.iter().flat_map(|plugin| &plugin.commands)
+    }
+
+    fn find_command(&self, name: &str) -> Option<&Plugin> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.commands.iter().any(|c| c.name == name))
+    }
+
+    fn find_tool(&self, name: &str) -> Option<&Plugin> {
+        self.plugins
+            .iter()
+            .find(|plugin| plugin.tools.iter().any(|t| t.name == name))
+    }
+
+    /// Runs `/name args` if a plugin provides it. Returns `None` for unknown
+    /// commands, otherwise the markdown the plugin printed.
+    pub async fn run_command(
+        &self,
+        name: &str,
+        args: &str,
+        conversation_file: &Path,
+    ) -> Option<Result<String, String>> {
+        let plugin = self.find_command(name)?;
+        let request = json!({
+            "type": "command",
+            "name": name,
+            "args": args,
+            "conversation_file": conversation_file,
+        });
+
+        Some(call_for_output(&plugin.path, request).await)
+    }
+}
+
+#[async_trait]
+impl ToolRunner for Plugins {
+    fn definitions(&self) -> Vec<ToolDefinition> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.tools.iter().cloned())
+            .collect()
+    }
+
+    async fn call(&self, name: &str, input: Value) -> Result<String, String> {
+        let plugin = self
+            .find_tool(name)
+            .ok_or_else(|| format!("Unknown tool '{}'", name))?;
+        let request = json!({ "type": "tool", "name": name, "input": input });
+
+        call_for_output(&plugin.path, request).await
+    }
+}
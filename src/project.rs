@@ -0,0 +1,134 @@
+use crate::config;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Per-project instructions: the nearest `.rye/context.md` or `RYE.md` in the
+// working directory or its parents, up to the repository root, is sent as
+// part of the system prompt. Turned off with `project.context = false`.
+//
+// With `project.scope` on, conversations started inside a git repository
+// record it as their `project`, and conversation lists only show the current
+// project's conversations unless `--all-projects` is given. A repository is
+// known by its `origin` remote, as `host/owner/name` so SSH and HTTPS clones
+// match, or by its root's canonical path when it has none. Conversations
+// from before, which recorded only the directory name, still match it.
+
+const CONTEXT_FILES: [&str; 2] = [".rye/context.md", "RYE.md"];
+
+static CONTEXT_FILE: OnceLock<Option<PathBuf>> = OnceLock::new();
+static ROOT: OnceLock<Option<PathBuf>> = OnceLock::new();
+static SCOPE: OnceLock<Option<String>> = OnceLock::new();
+static ALL_PROJECTS: AtomicBool = AtomicBool::new(false);
+
+/// The git repository the working directory is in, looked up once per run
+pub fn root() -> Option<&'static Path> {
+    ROOT.get_or_init(|| {
+        let cwd = std::env::current_dir().ok()?;
+        let home = dirs::home_dir();
+        cwd.ancestors()
+            .take_while(|dir| home.as_deref() != Some(*dir))
+            .find(|dir| dir.join(".git").exists())
+            .map(Path::to_path_buf)
+    })
+    .as_deref()
+}
+
+/// The project new conversations belong to: the repository's remote or
+/// path, when `project.scope` is on
+pub fn scope() -> Option<String> {
+    if !config::get().project.scope {
+        return None;
+    }
+    SCOPE
+        .get_or_init(|| {
+            let root = root()?;
+            remote(root).or_else(|| {
+                let root = std::fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+                Some(root.to_string_lossy().into_owned())
+            })
+        })
+        .clone()
+}
+
+// The `origin` remote of the repository at `root`, as `host/owner/name`
+fn remote(root: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["config", "--get", "remote.origin.url"])
+        .current_dir(root)
+        .output()
+        .ok()?;
+    let url = String::from_utf8(output.stdout).ok()?;
+    let url = url.trim();
+    // `https://host/owner/name.git`, `ssh://git@host/...` or `git@host:owner/name`
+    let url = match url.split_once("://") {
+        Some((_, rest)) => rest.to_string(),
+        None => url.replacen(':', "/", 1),
+    };
+    let url = url.rsplit_once('@').map_or(url.as_str(), |(_, host)| host);
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+    (!url.is_empty()).then(|| url.to_lowercase())
+}
+
+// The repository's directory name, what conversations recorded before
+fn legacy_scope() -> Option<String> {
+    Some(root()?.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Lists show every project's conversations, for `--all-projects`
+pub fn show_all_projects() {
+    ALL_PROJECTS.store(true, Ordering::Relaxed);
+}
+
+pub fn showing_all_projects() -> bool {
+    ALL_PROJECTS.load(Ordering::Relaxed)
+}
+
+/// Whether a conversation of `project` belongs in conversation lists
+pub fn in_scope(project: Option<&str>) -> bool {
+    if showing_all_projects() {
+        return true;
+    }
+    match scope() {
+        Some(scope) => project == Some(scope.as_str()) || project == legacy_scope().as_deref(),
+        None => true,
+    }
+}
+
+/// The project's context file, looked up once per run
+pub fn context_file() -> Option<&'static Path> {
+    CONTEXT_FILE
+        .get_or_init(|| {
+            if !config::get().project.context {
+                return None;
+            }
+            let cwd = std::env::current_dir().ok()?;
+            let home = dirs::home_dir();
+            for dir in cwd.ancestors() {
+                // `~/.rye` is where older versions kept conversations
+                if home.as_deref() == Some(dir) {
+                    break;
+                }
+                if let Some(path) = CONTEXT_FILES
+                    .iter()
+                    .map(|name| dir.join(name))
+                    .find(|path| path.is_file())
+                {
+                    return Some(path);
+                }
+                if dir.join(".git").exists() {
+                    break;
+                }
+            }
+            None
+        })
+        .as_deref()
+}
+
+/// The context file's content, re-read for every request so edits apply
+/// straight away
+pub fn context() -> Option<String> {
+    let content = std::fs::read_to_string(context_file()?).ok()?;
+    let content = content.trim();
+    (!content.is_empty()).then(|| content.to_string())
+}
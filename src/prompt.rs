@@ -0,0 +1,46 @@
+use std::io::{self, Write};
+
+// The one-letter questions asked before acting, like `[S]end, [e]dit, or
+// [c]ancel?`. An answer is a choice's first letter or the whole word, in any
+// case; anything else asks again.
+
+/// Asks `question` until one of `choices` is picked and returns its first
+/// letter. An empty answer picks `default` when there is one; `None` is the
+/// end of input.
+pub fn choose(question: &str, choices: &[&str], default: Option<char>) -> io::Result<Option<char>> {
+    loop {
+        print!("{} ", question);
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        let answer = answer.trim().to_lowercase();
+        if answer.is_empty()
+            && let Some(default) = default
+        {
+            return Ok(Some(default));
+        }
+        let picked = choices.iter().find(|choice| {
+            answer == **choice || (answer.chars().count() == 1 && choice.starts_with(&answer))
+        });
+        match picked.and_then(|choice| choice.chars().next()) {
+            Some(letter) => return Ok(Some(letter)),
+            None => println!("Type {}.", letters(choices)),
+        }
+    }
+}
+
+// `e, k, or r`
+fn letters(choices: &[&str]) -> String {
+    let letters: Vec<String> = choices
+        .iter()
+        .filter_map(|choice| choice.chars().next())
+        .map(String::from)
+        .collect();
+    match letters.split_last() {
+        Some((last, rest)) if rest.len() > 1 => format!("{}, or {}", rest.join(", "), last),
+        Some((last, [first])) => format!("{} or {}", first, last),
+        _ => letters.concat(),
+    }
+}
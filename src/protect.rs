@@ -0,0 +1,130 @@
+use argon2::Argon2;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::io;
+
+// A protected conversation keeps its frontmatter and title readable, so it
+// still shows up in listings and can be found by title, but its messages are
+// sealed in a single `## Protected` section:
+//
+//   ## Protected
+//
+//   <!-- rye:sealed v1 -->
+//   <base64 of salt, nonce and ciphertext, in lines of 76>
+//
+// The key is derived from the passphrase and the salt with Argon2id, and the
+// messages are encrypted with XChaCha20-Poly1305, so a wrong passphrase or a
+// changed file fails to open rather than giving garbage. Every write seals
+// the messages again under a fresh nonce.
+
+// The section that holds a protected conversation's messages
+const SECTION_HEADER: &str = "## Protected";
+const MARKER: &str = "<!-- rye:sealed v1 -->";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// A key derived from a conversation's passphrase
+#[derive(Clone)]
+pub struct Key {
+    key: [u8; 32],
+    salt: [u8; SALT_LEN],
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn random<const N: usize>() -> io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(bytes)
+}
+
+fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> io::Result<Key> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(Key { key, salt })
+}
+
+/// A key for `passphrase` with a fresh salt, to protect a conversation with
+pub fn new_key(passphrase: &str) -> io::Result<Key> {
+    if passphrase.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "The passphrase can't be empty",
+        ));
+    }
+    derive(passphrase, random()?)
+}
+
+/// `messages`, the markdown after a conversation's title, as a sealed section
+pub fn seal(key: &Key, messages: &str) -> io::Result<String> {
+    let nonce: [u8; NONCE_LEN] = random()?;
+    let ciphertext = XChaCha20Poly1305::new((&key.key).into())
+        .encrypt(XNonce::from_slice(&nonce), messages.as_bytes())
+        .map_err(|_| invalid("Could not encrypt the conversation"))?;
+
+    let mut sealed = key.salt.to_vec();
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    let encoded = STANDARD.encode(sealed);
+
+    let mut section = format!("\n{}\n\n{}\n", SECTION_HEADER, MARKER);
+    for line in encoded.as_bytes().chunks(76) {
+        section.push_str(&String::from_utf8_lossy(line));
+        section.push('\n');
+    }
+    Ok(section)
+}
+
+// Salt, nonce and ciphertext of a sealed section
+fn decode(body: &str) -> io::Result<Vec<u8>> {
+    let (_, encoded) = body
+        .split_once(MARKER)
+        .ok_or_else(|| invalid("The protected section is missing its marker"))?;
+    let encoded: String = encoded.split_whitespace().collect();
+    let sealed = STANDARD
+        .decode(encoded)
+        .map_err(|_| invalid("The protected section isn't valid base64"))?;
+    if sealed.len() < SALT_LEN + NONCE_LEN {
+        return Err(invalid("The protected section is cut short"));
+    }
+    Ok(sealed)
+}
+
+fn decrypt(key: &Key, sealed: &[u8]) -> io::Result<String> {
+    let nonce = &sealed[SALT_LEN..SALT_LEN + NONCE_LEN];
+    let plaintext = XChaCha20Poly1305::new((&key.key).into())
+        .decrypt(XNonce::from_slice(nonce), &sealed[SALT_LEN + NONCE_LEN..])
+        .map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Wrong passphrase, or the file was changed",
+            )
+        })?;
+    String::from_utf8(plaintext).map_err(|_| invalid("The protected messages aren't UTF-8"))
+}
+
+/// Opens a sealed section with `passphrase`, returning the key for writing
+/// to it again and the messages
+pub fn open(passphrase: &str, body: &str) -> io::Result<(Key, String)> {
+    let sealed = decode(body)?;
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&sealed[..SALT_LEN]);
+    let key = derive(passphrase, salt)?;
+    let messages = decrypt(&key, &sealed)?;
+    Ok((key, messages))
+}
+
+/// Opens a sealed section with a key from an earlier `open`
+pub fn open_with(key: &Key, body: &str) -> io::Result<String> {
+    decrypt(key, &decode(body)?)
+}
+
+/// Reads a passphrase from the terminal without echoing it
+pub fn ask_passphrase(prompt: &str) -> io::Result<String> {
+    rpassword::prompt_password(prompt)
+}
@@ -1,28 +1,54 @@
-use super::LLMProvider;
+use super::keys::{self, KeyPool};
+use super::models;
+use super::{
+    LLMProvider, Latency, ResponseStream, Timing, ToolDefinition, ToolRunner, Unavailable, Usage,
+    followup_prompt, history_window, image_media_type, parse_followups, timed,
+};
+use crate::inline_image::parse_image_line;
+use crate::{audit, config, memory, project, usage};
 use async_trait::async_trait;
-use futures::stream::{Stream, StreamExt};
-use reqwest::Client;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use futures::channel::mpsc::UnboundedSender;
+use futures::stream::StreamExt;
+use reqwest::{Client, RequestBuilder, Response};
 use serde::{Deserialize, Serialize};
-use std::env;
-use std::pin::Pin;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{debug, info, trace, warn};
 
-#[derive(Serialize)]
+const API_URL: &str = "https://api.anthropic.com/v1/messages";
+const BATCHES_URL: &str = "https://api.anthropic.com/v1/messages/batches";
+
+/// Batched requests cost this share of the usual price
+pub const BATCH_PRICE_FACTOR: f64 = 0.5;
+
+#[derive(Serialize, Clone)]
 struct AnthropicMessage {
     role: String,
-    content: String,
+    // A string, or content blocks once tools are involved
+    content: Value,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 struct AnthropicRequest {
     model: String,
     max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
     messages: Vec<AnthropicMessage>,
     stream: bool,
+    // `ToolDefinition`s, and server tools like web search
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Value>,
 }
 
 #[derive(Deserialize)]
 struct AnthropicResponse {
     content: Vec<AnthropicContent>,
+    usage: Option<StreamUsage>,
 }
 
 #[derive(Deserialize)]
@@ -34,109 +60,261 @@ struct AnthropicContent {
 struct StreamEvent {
     #[serde(rename = "type")]
     event_type: String,
+    index: Option<usize>,
+    content_block: Option<Value>,
     delta: Option<Delta>,
+    message: Option<StreamMessage>,
+    usage: Option<StreamUsage>,
+    error: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamMessage {
+    usage: StreamUsage,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamUsage {
+    input_tokens: Option<u32>,
+    output_tokens: Option<u32>,
 }
 
 #[derive(Deserialize, Debug)]
 struct Delta {
     text: Option<String>,
+    partial_json: Option<String>,
+    citation: Option<Value>,
+    stop_reason: Option<String>,
 }
 
+type ChunkSender = UnboundedSender<Result<String, Box<dyn std::error::Error + Send>>>;
+
+fn stream_error(message: String) -> Box<dyn std::error::Error + Send> {
+    Box::<dyn std::error::Error + Send + Sync>::from(message)
+}
+
+// Appended to every user message unless `system_prompt_file` is set
+const DEFAULT_INSTRUCTION: &str = "You are a helpful assistant. Always respond in markdown format. When referring to information you've previously provided in this conversation, reference the relevant sections instead of repeating the information. Be concise and avoid unnecessary repetition.";
+
+// The contents of `system_prompt_file`, if set
+fn read_system_prompt_file() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let Some(path) = &config::get().system_prompt_file else {
+        return Ok(None);
+    };
+    let expanded = match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => path.into(),
+    };
+    let prompt = std::fs::read_to_string(&expanded)
+        .map_err(|e| format!("Could not read system_prompt_file {}: {}", path, e))?;
+    Ok(Some(prompt.trim().to_string()))
+}
+
+/// Claude through the Anthropic Messages API
 pub struct AnthropicProvider {
     client: Client,
-    api_key: String,
+    keys: &'static KeyPool,
     model: String,
+    usage: Arc<Mutex<Usage>>,
+    timing: Arc<Mutex<Timing>>,
+    tools: Option<Arc<dyn ToolRunner>>,
+    // Off for requests that only want a word or two back, like the router's
+    // classifier
+    web_search: bool,
+    max_tokens: u32,
+    system_prompt: Option<String>,
+    // From `system_prompt_file`, replacing `DEFAULT_INSTRUCTION`
+    system_file_prompt: Option<String>,
 }
 
 impl AnthropicProvider {
+    /// Reads `ANTHROPIC_API_KEY`, and `ANTHROPIC_API_KEYS` to rotate between
+    /// several, from the environment, or the variable `workspace.key_env`
+    /// names. The model comes from the `model` setting.
     pub fn new() -> Result<Self, Box<dyn std::error::Error>> {
-        let api_key = env::var("ANTHROPIC_API_KEY")
-            .map_err(|_| "ANTHROPIC_API_KEY environment variable not set")?;
+        let keys = keys::anthropic();
+        if keys.is_empty() {
+            return Err(format!("{} environment variable not set", keys.vars()).into());
+        }
 
-        let model = env::var("ANTHROPIC_MODEL")
-            .unwrap_or_else(|_| "claude-sonnet-4-5-20250929".to_string());
+        let model = config::get()
+            .model
+            .clone()
+            .unwrap_or_else(|| "claude-sonnet-4-5-20250929".to_string());
 
         Ok(Self {
             client: Client::new(),
-            api_key,
+            keys,
             model,
+            usage: Arc::new(Mutex::new(Usage::default())),
+            timing: Arc::default(),
+            tools: None,
+            web_search: true,
+            max_tokens: 4096,
+            system_prompt: None,
+            system_file_prompt: read_system_prompt_file()?,
         })
     }
-}
 
-#[async_trait]
-impl LLMProvider for AnthropicProvider {
-    async fn generate_response_stream(
-        &self,
-        messages: &[(String, String)],
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<String, Box<dyn std::error::Error + Send>>> + Send>>,
-        Box<dyn std::error::Error>,
-    > {
-        let mut api_messages = Vec::new();
+    /// Uses `model` instead of the `model` setting
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    /// Sends `prompt` as the system prompt of response requests
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    /// Offers `tools` to the model. Tool calls are run as they come in and
+    /// the response continues after them in the same stream.
+    pub fn with_tools(mut self, tools: Arc<dyn ToolRunner>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Offers no tools at all, not even web search with `web_search.enabled`
+    pub fn without_tools(mut self) -> Self {
+        self.tools = None;
+        self.web_search = false;
+        self
+    }
+
+    /// Caps response requests at `max_tokens` instead of 4096
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
 
-        let system_message = "You are a helpful assistant. Always respond in markdown format. When referring to information you've previously provided in this conversation, reference the relevant sections instead of repeating the information. Be concise and avoid unnecessary repetition.";
+    fn build_request(&self, messages: &[(String, String)]) -> AnthropicRequest {
+        // The whole conversation stays on disk, only its newest part is sent
+        let sent = history_window(messages);
+        if sent.len() < messages.len() {
+            debug!(
+                sent = sent.len(),
+                total = messages.len(),
+                "Sending only the newest messages"
+            );
+        }
+        let messages = sent;
+        let mut api_messages = Vec::new();
+        let vision = models::capabilities(&self.model).vision;
 
         for (role, content) in messages {
+            // Any system prompt replaces the markdown instruction, so callers
+            // asking for JSON or plain text get exactly that
+            let text = if role == "user"
+                && self.system_file_prompt.is_none()
+                && self.system_prompt.is_none()
+            {
+                format!("{}\n\nSystem instruction: {}", content, DEFAULT_INSTRUCTION)
+            } else {
+                content.clone()
+            };
             api_messages.push(AnthropicMessage {
                 role: role.clone(),
-                content: if role == "user" && !messages.is_empty() {
-                    format!("{}\n\nSystem instruction: {}", content, system_message)
+                content: if role == "user" {
+                    user_content(text, vision)
                 } else {
-                    content.clone()
+                    Value::String(text)
                 },
             });
         }
 
-        let request = AnthropicRequest {
+        // The memory file and project context go ahead of any system prompt
+        let mut system = Vec::new();
+        if let Some(memory) = memory::read() {
+            system.push(format!(
+                "Things the user has asked you to remember about them and their preferences:\n\n{}",
+                memory
+            ));
+        }
+        if let Some(context) = project::context() {
+            system.push(format!(
+                "Instructions for the project being worked on:\n\n{}",
+                context
+            ));
+        }
+        // An empty file turns the built-in instruction off without a replacement
+        system.extend(
+            self.system_file_prompt
+                .clone()
+                .filter(|prompt| !prompt.is_empty()),
+        );
+        system.extend(self.system_prompt.clone());
+        let system = (!system.is_empty()).then(|| system.join("\n\n"));
+
+        AnthropicRequest {
             model: self.model.clone(),
-            max_tokens: 4096,
+            max_tokens: self.max_tokens,
+            system,
             messages: api_messages,
             stream: true,
-        };
+            // Models that can't call tools get none, web search included
+            tools: if models::capabilities(&self.model).tools {
+                tool_definitions(self.tools.as_deref(), self.web_search)
+            } else {
+                Vec::new()
+            },
+        }
+    }
 
-        let response = self
-            .client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+    // Titles and follow-ups are billed like any request, but don't replace
+    // the usage of the response they're about
+    fn record_side_usage(&self, response: &AnthropicResponse) {
+        if let Some(reported) = &response.usage {
+            let side = Usage {
+                input_tokens: reported.input_tokens.unwrap_or(0),
+                output_tokens: reported.output_tokens.unwrap_or(0),
+            };
+            usage::record_usage(self, side);
+        }
+    }
+}
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("API Error: {}", error_text).into());
+#[async_trait]
+impl LLMProvider for AnthropicProvider {
+    async fn generate_response_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<ResponseStream, Box<dyn std::error::Error>> {
+        if let Some(reason) = usage::budget_exceeded() {
+            return Err(reason.into());
         }
 
-        let stream = response.bytes_stream().map(|chunk| {
-            let bytes = chunk.map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?;
-            let text = String::from_utf8_lossy(&bytes);
+        let request = self.build_request(messages);
+        info!(
+            model = %self.model,
+            messages = messages.len(),
+            tools = request.tools.len(),
+            "Sending streaming request"
+        );
 
-            // Parse SSE events
-            for line in text.lines() {
-                if let Some(data) = line.strip_prefix("data: ") {
-                    if data == "[DONE]" {
-                        continue;
-                    }
+        let started = Instant::now();
+        let (response, audit_id, retries) = post(&self.client, self.keys, &request)
+            .await
+            .map_err(|e| e as Box<dyn std::error::Error>)?;
 
-                    if let Ok(event) = serde_json::from_str::<StreamEvent>(data) {
-                        if event.event_type == "content_block_delta" {
-                            if let Some(delta) = event.delta {
-                                if let Some(text) = delta.text {
-                                    return Ok(text);
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        *self.usage.lock().unwrap() = Usage::default();
+        *self.timing.lock().unwrap() = Timing {
+            retries,
+            ..Timing::new(started)
+        };
 
-            Ok(String::new())
-        });
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let turn = Turn {
+            client: self.client.clone(),
+            keys: self.keys,
+            usage: Arc::clone(&self.usage),
+            timing: Arc::clone(&self.timing),
+            tools: self.tools.clone(),
+            request,
+        };
+        tokio::spawn(turn.run(response, audit_id, tx));
 
-        Ok(Box::pin(stream))
+        Ok(timed(Box::pin(rx), Arc::clone(&self.timing)))
     }
 
     async fn generate_title(
@@ -148,31 +326,30 @@ impl LLMProvider for AnthropicProvider {
             user_message
         );
 
+        if let Some(reason) = usage::budget_exceeded() {
+            return Err(reason.into());
+        }
+        info!(model = %self.model, "Requesting conversation title");
         let request = AnthropicRequest {
             model: self.model.clone(),
             max_tokens: 100,
+            system: None,
             messages: vec![AnthropicMessage {
                 role: "user".to_string(),
-                content: title_prompt,
+                content: Value::String(title_prompt),
             }],
             stream: false,
+            tools: Vec::new(),
         };
 
-        let response = self
-            .client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+        let (response, audit_id, _) =
+            post(&self.client, self.keys, &request).await.map_err(|e| {
+                warn!(error = %e, "Title request failed");
+                "Failed to generate title"
+            })?;
 
-        if !response.status().is_success() {
-            return Err("Failed to generate title".into());
-        }
-
-        let api_response: AnthropicResponse = response.json().await?;
+        let api_response = read_response(response, audit_id).await?;
+        self.record_side_usage(&api_response);
 
         if let Some(content) = api_response.content.first() {
             Ok(content.text.trim().to_string())
@@ -180,4 +357,731 @@ impl LLMProvider for AnthropicProvider {
             Err("No title generated".into())
         }
     }
+
+    async fn generate_followups(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        if let Some(reason) = usage::budget_exceeded() {
+            return Err(reason.into());
+        }
+        info!(model = %self.model, "Requesting follow-up questions");
+        let request = AnthropicRequest {
+            model: self.model.clone(),
+            max_tokens: 200,
+            system: None,
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: Value::String(followup_prompt(messages)),
+            }],
+            stream: false,
+            tools: Vec::new(),
+        };
+
+        let (response, audit_id, _) =
+            post(&self.client, self.keys, &request).await.map_err(|e| {
+                warn!(error = %e, "Follow-up request failed");
+                "Failed to suggest follow-ups"
+            })?;
+
+        let api_response = read_response(response, audit_id).await?;
+        self.record_side_usage(&api_response);
+        let followups = api_response
+            .content
+            .first()
+            .map(|content| parse_followups(&content.text))
+            .unwrap_or_default();
+        if followups.is_empty() {
+            return Err("No follow-ups suggested".into());
+        }
+        Ok(followups)
+    }
+
+    fn request_body(&self, messages: &[(String, String)]) -> serde_json::Value {
+        serde_json::to_value(self.build_request(messages)).unwrap_or_default()
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn last_usage(&self) -> Usage {
+        *self.usage.lock().unwrap()
+    }
+
+    fn last_latency(&self) -> Option<Latency> {
+        self.timing.lock().unwrap().latency()
+    }
+
+    fn context_window(&self) -> u32 {
+        models::capabilities(&self.model).context_window
+    }
+
+    fn cost(&self, usage: &Usage) -> f64 {
+        models::capabilities(&self.model).cost(usage.input_tokens, usage.output_tokens)
+    }
+}
+
+// A user message, with the images `/image` attached to it as image blocks
+// ahead of the text when the model can see them. Those are `![alt](path)`
+// lines with an absolute path, outside code blocks.
+fn user_content(text: String, vision: bool) -> Value {
+    if !vision {
+        return Value::String(text);
+    }
+    let mut images = Vec::new();
+    let mut rest = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        let image = (!in_code)
+            .then(|| parse_image_line(line.trim()))
+            .flatten()
+            .and_then(|(_, source)| image_block(Path::new(source)));
+        match image {
+            Some(image) => images.push(image),
+            None => rest.push(line),
+        }
+    }
+    if images.is_empty() {
+        return Value::String(text);
+    }
+    images.push(json!({"type": "text", "text": rest.join("\n").trim_start()}));
+    Value::Array(images)
+}
+
+fn image_block(path: &Path) -> Option<Value> {
+    if !path.is_absolute() {
+        return None;
+    }
+    let media_type = image_media_type(path)?;
+    let data = std::fs::read(path)
+        .inspect_err(|e| warn!(path = %path.display(), error = %e, "Could not read image"))
+        .ok()?;
+    Some(json!({
+        "type": "image",
+        "source": {"type": "base64", "media_type": media_type, "data": STANDARD.encode(data)},
+    }))
+}
+
+// The plugin tools, plus web search with `web_search.enabled` unless
+// `web_search` is off for the request
+fn tool_definitions(tools: Option<&dyn ToolRunner>, web_search: bool) -> Vec<Value> {
+    let mut definitions: Vec<Value> = tools
+        .map(|tools| tools.definitions())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|definition: &ToolDefinition| serde_json::to_value(definition).ok())
+        .collect();
+    let config = &config::get().web_search;
+    if web_search && config.enabled {
+        definitions.push(json!({
+            "type": "web_search_20250305",
+            "name": "web_search",
+            "max_uses": config.max_uses,
+        }));
+    }
+    definitions
+}
+
+// Sends a Messages request and returns the response with the ID the audit
+// log has the request under, and how many times it was sent again
+async fn post(
+    client: &Client,
+    keys: &KeyPool,
+    request: &AnthropicRequest,
+) -> Result<(Response, Option<String>, u32), Box<dyn std::error::Error + Send + Sync>> {
+    let audit_id = audit::request(API_URL, request);
+    let builder = client
+        .post(API_URL)
+        .header("content-type", "application/json")
+        .json(request);
+    let (response, retries, _) = send(builder, keys, None, audit_id.as_deref()).await?;
+    Ok((response, audit_id, retries))
+}
+
+// Sends `request` with the next key, or only with key `pinned`, turning error
+// statuses into errors. A rate-limited request is sent again with the next
+// key until each was tried; how many times is returned with the response and
+// the index of the key that got it. Failures are logged for the audit under
+// `audit_id`.
+async fn send(
+    request: RequestBuilder,
+    keys: &KeyPool,
+    pinned: Option<usize>,
+    audit_id: Option<&str>,
+) -> Result<(Response, u32, usize), Box<dyn std::error::Error + Send + Sync>> {
+    let attempts = if pinned.is_some() { 1 } else { keys.len() };
+    for attempt in 1..=attempts {
+        let (index, api_key) = match pinned {
+            Some(index) => (
+                index,
+                keys.get(index)
+                    .ok_or_else(|| format!("There's no API key {} anymore", index + 1))?,
+            ),
+            None => keys.next(),
+        };
+        // Not getting through at all is worth retrying later
+        let response = request
+            .try_clone()
+            .ok_or("Could not resend the request")?
+            .header("x-api-key", api_key)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await
+            .map_err(|e| {
+                audit::response(audit_id, None, json!({ "error": e.to_string() }));
+                Unavailable(e.to_string())
+            })?;
+        debug!(status = %response.status(), headers = ?response.headers(), "Response received");
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok((response, attempt as u32 - 1, index));
+        }
+        let error_text = response.text().await.map_err(|e| e.to_string())?;
+        warn!(error = %error_text, key = index + 1, "Request failed");
+        audit::response(
+            audit_id,
+            Some(status.as_u16()),
+            serde_json::from_str(&error_text).unwrap_or_else(|_| json!(error_text)),
+        );
+        if status.as_u16() == 429 && attempt < attempts {
+            keys.rate_limited(index);
+            info!(key = index + 1, "Rate limited, trying the next API key");
+            continue;
+        }
+        // Rate limits, server errors and 529 Overloaded
+        if status.as_u16() == 429 || status.is_server_error() {
+            return Err(Unavailable(format!("{} {}", status, error_text)).into());
+        }
+        return Err(format!("API Error: {}", error_text).into());
+    }
+    unreachable!("the last key's response returns")
+}
+
+// The body of a non-streamed response, logged for the audit
+async fn read_response(
+    response: Response,
+    audit_id: Option<String>,
+) -> Result<AnthropicResponse, Box<dyn std::error::Error>> {
+    let status = response.status().as_u16();
+    let body: Value = response.json().await?;
+    if audit_id.is_some() {
+        audit::response(audit_id.as_deref(), Some(status), body.clone());
+    }
+    Ok(serde_json::from_value(body)?)
+}
+
+/// A Message Batch as the API reports it
+#[derive(Deserialize)]
+pub struct Batch {
+    pub id: String,
+    /// `in_progress`, `canceling` or `ended`
+    pub processing_status: String,
+    pub request_counts: BatchCounts,
+    /// Where the results are once the batch has ended
+    pub results_url: Option<String>,
+    /// Index of the API key that submitted it, which requests about it use
+    #[serde(skip)]
+    pub key: usize,
+}
+
+#[derive(Deserialize)]
+pub struct BatchCounts {
+    pub processing: u32,
+    pub succeeded: u32,
+    pub errored: u32,
+    pub canceled: u32,
+    pub expired: u32,
+}
+
+/// What one request of an ended batch produced: the response and its usage,
+/// or why there is none
+pub struct BatchResult {
+    pub custom_id: String,
+    pub outcome: Result<(String, Usage), String>,
+}
+
+// Message Batches are answered within 24 hours at `BATCH_PRICE_FACTOR` of the
+// price. They can't call tools, since nothing is around to run them.
+impl AnthropicProvider {
+    /// Submits one request per `(custom_id, messages)` as a batch
+    pub async fn create_batch(
+        &self,
+        requests: &[(String, Vec<(String, String)>)],
+    ) -> Result<Batch, Box<dyn std::error::Error>> {
+        let requests: Vec<Value> = requests
+            .iter()
+            .map(|(custom_id, messages)| {
+                let mut params = self.build_request(messages);
+                params.stream = false;
+                params.tools.clear();
+                json!({ "custom_id": custom_id, "params": params })
+            })
+            .collect();
+        info!(model = %self.model, requests = requests.len(), "Creating batch");
+
+        let body = json!({ "requests": requests });
+        let audit_id = audit::request(BATCHES_URL, &body);
+        let request = self.client.post(BATCHES_URL).json(&body);
+        let (response, key) =
+            send_batch_request(request, self.keys, None, audit_id.as_deref()).await?;
+        let batch: Value = response.json().await?;
+        if audit_id.is_some() {
+            audit::response(audit_id.as_deref(), Some(200), batch.clone());
+        }
+        Ok(Batch {
+            key,
+            ..serde_json::from_value(batch)?
+        })
+    }
+
+    /// The current status of batch `id`, submitted with API key `key`
+    pub async fn batch(&self, id: &str, key: usize) -> Result<Batch, Box<dyn std::error::Error>> {
+        let request = self.client.get(format!("{}/{}", BATCHES_URL, id));
+        let (response, key) = send_batch_request(request, self.keys, Some(key), None).await?;
+        Ok(Batch {
+            key,
+            ..response.json().await?
+        })
+    }
+
+    /// The results of an ended batch, in no particular order
+    pub async fn batch_results(
+        &self,
+        batch: &Batch,
+    ) -> Result<Vec<BatchResult>, Box<dyn std::error::Error>> {
+        let url = batch
+            .results_url
+            .as_deref()
+            .ok_or_else(|| format!("Batch {} has no results yet", batch.id))?;
+        let audit_id = audit::request(url, &Value::Null);
+        let (response, _) = send_batch_request(
+            self.client.get(url),
+            self.keys,
+            Some(batch.key),
+            audit_id.as_deref(),
+        )
+        .await?;
+        let text = response.text().await?;
+        let values = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?;
+        if audit_id.is_some() {
+            audit::response(audit_id.as_deref(), Some(200), Value::Array(values.clone()));
+        }
+
+        let mut results = Vec::new();
+        for value in values {
+            let custom_id = value["custom_id"].as_str().unwrap_or_default().to_string();
+            let result = &value["result"];
+            let outcome = match result["type"].as_str() {
+                Some("succeeded") => {
+                    let message = &result["message"];
+                    let text: String = message["content"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter(|block| block["type"] == "text")
+                        .filter_map(|block| block["text"].as_str())
+                        .collect();
+                    let usage = Usage {
+                        input_tokens: message["usage"]["input_tokens"].as_u64().unwrap_or(0) as u32,
+                        output_tokens: message["usage"]["output_tokens"].as_u64().unwrap_or(0)
+                            as u32,
+                    };
+                    Ok((text, usage))
+                }
+                Some("errored") => Err(result["error"]["error"]["message"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| result["error"].to_string())),
+                Some(other) => Err(other.to_string()),
+                None => Err("No result".to_string()),
+            };
+            results.push(BatchResult { custom_id, outcome });
+        }
+        Ok(results)
+    }
+}
+
+// Sends a Message Batches request, turning error statuses into errors like
+// `post()`. A batch belongs to the workspace of the key that submitted it,
+// so requests about one are `pinned` to that key. Returns the key's index
+// with the response.
+async fn send_batch_request(
+    request: RequestBuilder,
+    keys: &KeyPool,
+    pinned: Option<usize>,
+    audit_id: Option<&str>,
+) -> Result<(Response, usize), Box<dyn std::error::Error>> {
+    let (response, _, key) = send(request, keys, pinned, audit_id)
+        .await
+        .map_err(|e| e as Box<dyn std::error::Error>)?;
+    Ok((response, key))
+}
+
+// One response, which may take several requests when the model calls tools
+struct Turn {
+    client: Client,
+    keys: &'static KeyPool,
+    usage: Arc<Mutex<Usage>>,
+    timing: Arc<Mutex<Timing>>,
+    tools: Option<Arc<dyn ToolRunner>>,
+    request: AnthropicRequest,
+}
+
+// The web pages cited in a response as `(url, title)`, numbered from 1 in
+// the order they were first cited
+#[derive(Default)]
+struct Sources(Vec<(String, String)>);
+
+impl Sources {
+    // The number of a citation's source, added if it's new
+    fn number(&mut self, citation: &Value) -> Option<usize> {
+        let url = citation["url"].as_str()?;
+        if let Some(i) = self.0.iter().position(|(known, _)| known == url) {
+            return Some(i + 1);
+        }
+        let title = citation["title"].as_str().unwrap_or(url);
+        self.0.push((url.to_string(), title.to_string()));
+        Some(self.0.len())
+    }
+
+    // Kept in the response, so the saved conversation has them too
+    fn list(&self) -> String {
+        let mut list = String::from("\n\n**Sources**\n\n");
+        for (i, (url, title)) in self.0.iter().enumerate() {
+            let title = title.replace('[', "(").replace(']', ")");
+            list.push_str(&format!("{}. [{}]({})\n", i + 1, title.trim(), url));
+        }
+        list
+    }
+}
+
+// What one request produced: its content blocks and why it stopped
+#[derive(Default)]
+struct Round {
+    blocks: Vec<Value>,
+    stop_reason: Option<String>,
+}
+
+impl Turn {
+    async fn run(self, response: Response, audit_id: Option<String>, tx: ChunkSender) {
+        let mut sources = Sources::default();
+        self.run_rounds(response, audit_id, &tx, &mut sources).await;
+        if !sources.0.is_empty() {
+            let _ = tx.unbounded_send(Ok(sources.list()));
+        }
+    }
+
+    async fn run_rounds(
+        mut self,
+        mut response: Response,
+        mut audit_id: Option<String>,
+        tx: &ChunkSender,
+        sources: &mut Sources,
+    ) {
+        // Tokens of earlier requests in this turn, each billed on its own
+        let mut earlier = Usage::default();
+        // Follow-up requests so far, for tool calls and paused searches
+        // alike, capped by `tools.max_rounds`
+        let mut rounds = 0;
+        let max_rounds = config::get().tools.max_rounds;
+
+        loop {
+            let status = response.status().as_u16();
+            let round = read_round(response, &self.usage, earlier, tx, sources).await;
+            if audit_id.is_some() {
+                let body = match &round {
+                    Ok(round) => {
+                        let usage = *self.usage.lock().unwrap();
+                        json!({
+                            "content": round.blocks,
+                            "stop_reason": round.stop_reason,
+                            "usage": {
+                                "input_tokens": usage.input_tokens - earlier.input_tokens,
+                                "output_tokens": usage.output_tokens - earlier.output_tokens,
+                            },
+                        })
+                    }
+                    Err(e) => json!({ "error": e.to_string() }),
+                };
+                audit::response(audit_id.as_deref(), Some(status), body);
+            }
+            let round = match round {
+                Ok(round) => round,
+                Err(e) => {
+                    let _ = tx.unbounded_send(Err(e));
+                    return;
+                }
+            };
+
+            // A long run of server-side searches can pause partway; sending
+            // what came so far back as is lets it go on
+            if round.stop_reason.as_deref() == Some("pause_turn") {
+                rounds += 1;
+                if rounds > max_rounds {
+                    let _ = tx.unbounded_send(Err(stream_error(too_many_rounds(max_rounds))));
+                    return;
+                }
+                self.request.messages.push(AnthropicMessage {
+                    role: "assistant".to_string(),
+                    content: Value::Array(without_empty_text(round.blocks)),
+                });
+                earlier = *self.usage.lock().unwrap();
+                (response, audit_id) = match post(&self.client, self.keys, &self.request).await {
+                    Ok((sent, id, retries)) => {
+                        self.timing.lock().unwrap().retries += retries;
+                        (sent, id)
+                    }
+                    Err(e) => {
+                        let _ = tx.unbounded_send(Err(stream_error(e.to_string())));
+                        return;
+                    }
+                };
+                continue;
+            }
+
+            let tool_calls: Vec<Value> = round
+                .blocks
+                .iter()
+                .filter(|block| block["type"] == "tool_use")
+                .cloned()
+                .collect();
+            let Some(tools) = self.tools.clone() else {
+                return;
+            };
+            if round.stop_reason.as_deref() != Some("tool_use") || tool_calls.is_empty() {
+                return;
+            }
+            rounds += 1;
+            if rounds > max_rounds {
+                let _ = tx.unbounded_send(Err(stream_error(too_many_rounds(max_rounds))));
+                return;
+            }
+
+            let mut results = Vec::new();
+            for call in tool_calls {
+                let name = call["name"].as_str().unwrap_or_default();
+                let _ = tx.unbounded_send(Ok(format!("\n\n> 🔧 {}\n\n", name)));
+                info!(tool = name, "Calling tool");
+
+                let (content, is_error) = match tools.call(name, call["input"].clone()).await {
+                    Ok(output) => (output, false),
+                    Err(e) => (e, true),
+                };
+                results.push(json!({
+                    "type": "tool_result",
+                    "tool_use_id": call["id"],
+                    "content": content,
+                    "is_error": is_error,
+                }));
+            }
+
+            self.request.messages.push(AnthropicMessage {
+                role: "assistant".to_string(),
+                content: Value::Array(without_empty_text(round.blocks)),
+            });
+            self.request.messages.push(AnthropicMessage {
+                role: "user".to_string(),
+                content: Value::Array(results),
+            });
+
+            earlier = *self.usage.lock().unwrap();
+            (response, audit_id) = match post(&self.client, self.keys, &self.request).await {
+                Ok((sent, id, retries)) => {
+                    self.timing.lock().unwrap().retries += retries;
+                    (sent, id)
+                }
+                Err(e) => {
+                    let _ = tx.unbounded_send(Err(stream_error(e.to_string())));
+                    return;
+                }
+            };
+        }
+    }
+}
+
+fn too_many_rounds(max_rounds: u64) -> String {
+    format!(
+        "Stopped after {} rounds of tool calls (tools.max_rounds)",
+        max_rounds
+    )
+}
+
+// The API rejects empty text blocks
+fn without_empty_text(blocks: Vec<Value>) -> Vec<Value> {
+    blocks
+        .into_iter()
+        .filter(|block| block["type"] != "text" || block["text"] != "")
+        .collect()
+}
+
+// Reads one SSE response, sending text as it arrives. Text citing web
+// pages is followed by the `[n]` numbers of its sources.
+async fn read_round(
+    response: Response,
+    usage: &Mutex<Usage>,
+    earlier: Usage,
+    tx: &ChunkSender,
+    sources: &mut Sources,
+) -> Result<Round, Box<dyn std::error::Error + Send>> {
+    let mut bytes = response.bytes_stream();
+    let mut pending = Vec::new();
+    let mut round = Round::default();
+    // Tool input arrives as JSON fragments, keyed by block index
+    let mut tool_inputs: HashMap<usize, String> = HashMap::new();
+    // Source numbers cited by each text block, keyed by block index
+    let mut cited: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    while let Some(chunk) = bytes.next().await {
+        // The response was stopped and its stream dropped. Dropping the body
+        // closes the connection, which ends the generation too.
+        if tx.is_closed() {
+            return Err(stream_error("Response stopped".to_string()));
+        }
+        let chunk = chunk.map_err(|e| -> Box<dyn std::error::Error + Send> { Box::new(e) })?;
+        pending.extend_from_slice(&chunk);
+
+        // Events can be split across chunks, only complete lines are parsed
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim_end().strip_prefix("data: ") else {
+                continue;
+            };
+            if data == "[DONE]" {
+                continue;
+            }
+
+            let Ok(event) = serde_json::from_str::<StreamEvent>(data) else {
+                warn!(data, "Could not parse SSE event");
+                continue;
+            };
+            trace!(event = %event.event_type, data, "SSE event");
+
+            let index = event.index.unwrap_or(0);
+            match event.event_type.as_str() {
+                "content_block_start" => {
+                    if let Some(block) = event.content_block {
+                        if round.blocks.len() <= index {
+                            round
+                                .blocks
+                                .resize(index + 1, json!({ "type": "text", "text": "" }));
+                        }
+                        if block["type"] == "web_search_tool_result"
+                            && block["content"]["type"] == "web_search_tool_result_error"
+                        {
+                            let code = block["content"]["error_code"].as_str().unwrap_or("error");
+                            let _ = tx.unbounded_send(Ok(format!(
+                                "\n\n> ⚠️ Web search failed: {}\n\n",
+                                code
+                            )));
+                        }
+                        round.blocks[index] = block;
+                    }
+                }
+                "content_block_delta" => {
+                    let Some(delta) = event.delta else {
+                        continue;
+                    };
+                    if let Some(text) = delta.text {
+                        if round.blocks.len() <= index {
+                            round
+                                .blocks
+                                .resize(index + 1, json!({ "type": "text", "text": "" }));
+                        }
+                        let block_text = round.blocks[index]["text"].as_str().unwrap_or_default();
+                        round.blocks[index]["text"] =
+                            Value::String(format!("{}{}", block_text, text));
+                        let _ = tx.unbounded_send(Ok(text));
+                    }
+                    if let Some(json) = delta.partial_json {
+                        tool_inputs.entry(index).or_default().push_str(&json);
+                    }
+                    if let Some(citation) = delta.citation
+                        && let Some(block) = round.blocks.get_mut(index)
+                    {
+                        if let Some(number) = sources.number(&citation) {
+                            let numbers = cited.entry(index).or_default();
+                            if !numbers.contains(&number) {
+                                numbers.push(number);
+                            }
+                        }
+                        // Kept so the block goes back with its citations
+                        // when the response continues after a tool call
+                        match block["citations"].as_array_mut() {
+                            Some(citations) => citations.push(citation),
+                            None => block["citations"] = json!([citation]),
+                        }
+                    }
+                }
+                "content_block_stop" => {
+                    if let Some(input) = tool_inputs.remove(&index)
+                        && let Some(block) = round.blocks.get_mut(index)
+                    {
+                        block["input"] = serde_json::from_str(&input).unwrap_or_else(|_| json!({}));
+                    }
+                    let Some(block) = round.blocks.get(index) else {
+                        continue;
+                    };
+                    if block["type"] == "server_tool_use" && block["name"] == "web_search" {
+                        let query = block["input"]["query"].as_str().unwrap_or_default();
+                        info!(query, "Searching the web");
+                        let _ = tx.unbounded_send(Ok(format!("\n\n> 🔎 {}\n\n", query)));
+                    }
+                    if let Some(mut numbers) = cited.remove(&index) {
+                        numbers.sort_unstable();
+                        let marks: String = numbers.iter().map(|n| format!("[{}]", n)).collect();
+                        let _ = tx.unbounded_send(Ok(format!(" {}", marks)));
+                    }
+                }
+                // Input tokens arrive with the start of the message,
+                // the final output token count with the last delta
+                "message_start" => {
+                    if let Some(message) = event.message {
+                        record_usage(usage, &message.usage, earlier);
+                    }
+                }
+                "message_delta" => {
+                    if let Some(ref stream_usage) = event.usage {
+                        record_usage(usage, stream_usage, earlier);
+                    }
+                    if let Some(stop_reason) = event.delta.and_then(|delta| delta.stop_reason) {
+                        round.stop_reason = Some(stop_reason);
+                    }
+                }
+                "error" => {
+                    let error = event.error.unwrap_or_default();
+                    warn!(%error, "Stream error event");
+                    return Err(stream_error(format!("API Error: {}", error)));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(round)
+}
+
+// Adds a round's usage to that of the turn's earlier rounds
+fn record_usage(usage: &Mutex<Usage>, stream_usage: &StreamUsage, earlier: Usage) {
+    debug!(
+        input_tokens = ?stream_usage.input_tokens,
+        output_tokens = ?stream_usage.output_tokens,
+        "Usage reported"
+    );
+    let mut usage = usage.lock().unwrap();
+    if let Some(input_tokens) = stream_usage.input_tokens {
+        usage.input_tokens = earlier.input_tokens + input_tokens;
+    }
+    if let Some(output_tokens) = stream_usage.output_tokens {
+        usage.output_tokens = earlier.output_tokens + output_tokens;
+    }
 }
@@ -0,0 +1,124 @@
+use crate::config;
+use std::env;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Teams sharing rate-limited keys can give a provider several of them:
+// `ANTHROPIC_API_KEYS` holds a comma-separated list, used after
+// `ANTHROPIC_API_KEY` when both are set. With `api_keys.rotation` set to
+// "on-429" (the default) requests use one key until it's rate limited, then
+// move on to the next; "round-robin" takes the next key for every request.
+// Either way a request that gets a 429 is sent again with the next key,
+// trying each key once. Keys are only ever logged by their number.
+//
+// Anthropic bills usage to the workspace a key belongs to, so teams pick
+// where usage goes by key: `workspace.key_env` names the variable holding
+// the workspace's key (or comma-separated keys), used instead of both.
+
+/// The API keys of a provider and which one is next
+pub struct KeyPool {
+    keys: Vec<String>,
+    // The variables the keys were read from
+    vars: Vec<String>,
+    round_robin: bool,
+    current: AtomicUsize,
+}
+
+impl KeyPool {
+    // The comma-separated keys in each of `vars`, in order
+    fn from_env(vars: Vec<String>) -> Self {
+        let mut keys: Vec<String> = Vec::new();
+        let values: Vec<String> = vars.iter().filter_map(|var| env::var(var).ok()).collect();
+        for key in values.iter().flat_map(|value| value.split(',')) {
+            let key = key.trim();
+            if !key.is_empty() && !keys.iter().any(|known| known == key) {
+                keys.push(key.to_string());
+            }
+        }
+        let rotation = &config::get().api_keys.rotation;
+        if rotation != "on-429" && rotation != "round-robin" {
+            eprintln!(
+                "Warning: Unknown api_keys.rotation '{}', using on-429",
+                rotation
+            );
+        }
+        let round_robin = rotation == "round-robin";
+        // Separate runs, like a batch script's, start at different keys
+        let start = if round_robin {
+            std::process::id() as usize
+        } else {
+            0
+        };
+        Self {
+            keys,
+            vars,
+            round_robin,
+            current: AtomicUsize::new(start),
+        }
+    }
+
+    /// The environment variables the keys come from, for error messages
+    pub fn vars(&self) -> String {
+        self.vars.join(" or ")
+    }
+
+    /// How many keys there are
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether there are no keys at all
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// A hash of the keys, for telling whether another process uses the
+    /// same ones without passing them around
+    pub fn fingerprint(&self) -> String {
+        hmac_sha256::Hash::hash(self.keys.join(",").as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// The key for the next request, with its index
+    pub fn next(&self) -> (usize, &str) {
+        let index = if self.round_robin {
+            self.current.fetch_add(1, Ordering::Relaxed) % self.keys.len()
+        } else {
+            self.current.load(Ordering::Relaxed) % self.keys.len()
+        };
+        (index, &self.keys[index])
+    }
+
+    /// Key `index`, if there are that many
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.keys.get(index).map(String::as_str)
+    }
+
+    /// Moves past key `index` after it was rate limited, unless a concurrent
+    /// request already did
+    pub fn rate_limited(&self, index: usize) {
+        if !self.round_robin {
+            let _ = self.current.compare_exchange(
+                index,
+                (index + 1) % self.keys.len(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+    }
+}
+
+/// The Anthropic keys, shared by every provider in the process so rotation
+/// spans all their requests
+pub fn anthropic() -> &'static KeyPool {
+    static POOL: OnceLock<KeyPool> = OnceLock::new();
+    POOL.get_or_init(|| match &config::get().workspace.key_env {
+        Some(var) => KeyPool::from_env(vec![var.clone()]),
+        None => KeyPool::from_env(vec![
+            "ANTHROPIC_API_KEY".to_string(),
+            "ANTHROPIC_API_KEYS".to_string(),
+        ]),
+    })
+}
@@ -0,0 +1,444 @@
+use super::{
+    LLMProvider, Latency, ResponseStream, Timing, Usage, followup_prompt, history_window,
+    parse_followups, timed,
+};
+use crate::config;
+use async_trait::async_trait;
+use candle_core::quantized::gguf_file;
+use candle_core::{Device, Tensor};
+use candle_transformers::generation::LogitsProcessor;
+use candle_transformers::models::{quantized_llama, quantized_qwen2};
+use candle_transformers::utils::apply_repeat_penalty;
+use futures::channel::mpsc::UnboundedSender;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tokenizers::Tokenizer;
+use tracing::{debug, info};
+
+// With the `local` cargo feature, `--provider local --model model.gguf` runs
+// a quantized GGUF model inside rye on the CPU with candle, no server or API
+// key involved. Llama-family (Llama, Mistral and the like) and Qwen2 models
+// are supported. The tokenizer is `tokenizer.json` next to the model file
+// unless `local.tokenizer` points elsewhere, and the chat template is picked
+// from the one the GGUF file names: Llama 3, ChatML or `[INST]`, else plain
+// `User:`/`Assistant:` turns. Responses are generated on a blocking thread
+// and streamed token by token; one response runs at a time.
+
+type ChunkSender = UnboundedSender<Result<String, Box<dyn std::error::Error + Send>>>;
+
+// Penalized for showing up again within the last `REPEAT_LAST_N` tokens
+const REPEAT_PENALTY: f32 = 1.1;
+const REPEAT_LAST_N: usize = 64;
+
+// Token IDs that end a turn in some common templates, besides the model's
+// end-of-sequence token
+const END_TOKENS: [&str; 4] = ["<|eot_id|>", "<|im_end|>", "<|end|>", "</s>"];
+
+enum Weights {
+    Llama(quantized_llama::ModelWeights),
+    Qwen2(quantized_qwen2::ModelWeights),
+}
+
+impl Weights {
+    fn forward(&mut self, input: &Tensor, position: usize) -> candle_core::Result<Tensor> {
+        match self {
+            Weights::Llama(model) => model.forward(input, position),
+            Weights::Qwen2(model) => model.forward(input, position),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Template {
+    Llama3,
+    ChatMl,
+    Inst,
+    Plain,
+}
+
+impl Template {
+    // The template a GGUF file's `tokenizer.chat_template` looks like
+    fn detect(chat_template: &str) -> Self {
+        if chat_template.contains("<|start_header_id|>") {
+            Template::Llama3
+        } else if chat_template.contains("<|im_start|>") {
+            Template::ChatMl
+        } else if chat_template.contains("[INST]") {
+            Template::Inst
+        } else {
+            Template::Plain
+        }
+    }
+
+    // `messages` after `system`, up to where the assistant's answer starts
+    fn prompt(self, system: Option<&str>, messages: &[(String, String)]) -> String {
+        let mut prompt = String::new();
+        match self {
+            Template::Llama3 => {
+                prompt.push_str("<|begin_of_text|>");
+                let turns = system.map(|system| ("system", system)).into_iter().chain(
+                    messages
+                        .iter()
+                        .map(|(role, content)| (role.as_str(), content.as_str())),
+                );
+                for (role, content) in turns {
+                    prompt.push_str(&format!(
+                        "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                        role, content
+                    ));
+                }
+                prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+            }
+            Template::ChatMl => {
+                let turns = system.map(|system| ("system", system)).into_iter().chain(
+                    messages
+                        .iter()
+                        .map(|(role, content)| (role.as_str(), content.as_str())),
+                );
+                for (role, content) in turns {
+                    prompt.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", role, content));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+            }
+            // No system role, so the system prompt leads the first message
+            Template::Inst => {
+                for (i, (role, content)) in messages.iter().enumerate() {
+                    if role == "user" {
+                        let content = match system {
+                            Some(system) if i == 0 => format!("{}\n\n{}", system, content),
+                            _ => content.clone(),
+                        };
+                        prompt.push_str(&format!("<s>[INST] {} [/INST]", content));
+                    } else {
+                        prompt.push_str(&format!(" {}</s>", content));
+                    }
+                }
+            }
+            Template::Plain => {
+                if let Some(system) = system {
+                    prompt.push_str(&format!("{}\n\n", system));
+                }
+                for (role, content) in messages {
+                    let role = if role == "user" { "User" } else { "Assistant" };
+                    prompt.push_str(&format!("{}: {}\n\n", role, content));
+                }
+                prompt.push_str("Assistant:");
+            }
+        }
+        prompt
+    }
+}
+
+// The loaded model, used by one response at a time
+struct Engine {
+    weights: Weights,
+    tokenizer: Tokenizer,
+    template: Template,
+    end_tokens: Vec<u32>,
+    context_length: usize,
+}
+
+impl Engine {
+    // Generates an answer to `prompt`, passing each new piece of text to
+    // `on_text` until it returns false. Returns the answer and its usage.
+    fn generate(
+        &mut self,
+        prompt: &str,
+        mut on_text: impl FnMut(&str) -> bool,
+    ) -> Result<(String, Usage), Box<dyn std::error::Error + Send + Sync>> {
+        let settings = &config::get().local;
+        let prompt_tokens = self
+            .tokenizer
+            .encode(prompt, false)
+            .map_err(|e| e.to_string())?
+            .get_ids()
+            .to_vec();
+        if prompt_tokens.len() + 1 >= self.context_length {
+            return Err(format!(
+                "The conversation is {} tokens, more than the model's {}",
+                prompt_tokens.len(),
+                self.context_length
+            )
+            .into());
+        }
+        let max_tokens = settings
+            .max_tokens
+            .min(self.context_length - prompt_tokens.len());
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        let mut sampler = LogitsProcessor::new(seed, Some(settings.temperature), None);
+
+        let mut tokens = prompt_tokens.clone();
+        let mut generated: Vec<u32> = Vec::new();
+        let mut text = String::new();
+        let mut input = Tensor::new(prompt_tokens.as_slice(), &Device::Cpu)?.unsqueeze(0)?;
+        let mut position = 0;
+        while generated.len() < max_tokens {
+            let logits = self.weights.forward(&input, position)?.squeeze(0)?;
+            position += input.dim(1)?;
+            let start = tokens.len().saturating_sub(REPEAT_LAST_N);
+            let logits = apply_repeat_penalty(&logits, REPEAT_PENALTY, &tokens[start..])?;
+            let next = sampler.sample(&logits)?;
+            if self.end_tokens.contains(&next) {
+                break;
+            }
+            tokens.push(next);
+            generated.push(next);
+
+            // Decoding it all again keeps characters split across tokens whole
+            let decoded = self
+                .tokenizer
+                .decode(&generated, true)
+                .map_err(|e| e.to_string())?;
+            if decoded.len() > text.len()
+                && decoded.is_char_boundary(text.len())
+                && !decoded.ends_with('\u{FFFD}')
+            {
+                let piece = decoded[text.len()..].to_string();
+                text = decoded;
+                if !on_text(&piece) {
+                    break;
+                }
+            }
+            input = Tensor::new(&[next], &Device::Cpu)?.unsqueeze(0)?;
+        }
+
+        let usage = Usage {
+            input_tokens: prompt_tokens.len() as u32,
+            output_tokens: generated.len() as u32,
+        };
+        debug!(
+            input_tokens = usage.input_tokens,
+            output_tokens = usage.output_tokens,
+            "Local generation finished"
+        );
+        Ok((text, usage))
+    }
+}
+
+/// A GGUF model run in process with candle
+pub struct LocalProvider {
+    engine: Arc<Mutex<Engine>>,
+    model: String,
+    context_length: usize,
+    usage: Arc<Mutex<Usage>>,
+    timing: Arc<Mutex<Timing>>,
+    system_prompt: Option<String>,
+}
+
+// `tokenizer.json` next to the model, unless `local.tokenizer` is set
+fn tokenizer_path(model: &Path) -> PathBuf {
+    match &config::get().local.tokenizer {
+        Some(path) => PathBuf::from(path),
+        None => model.with_file_name("tokenizer.json"),
+    }
+}
+
+impl LocalProvider {
+    /// Loads the GGUF model at `path`
+    pub fn new(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let model_path = PathBuf::from(path);
+        let mut file = std::fs::File::open(&model_path)
+            .map_err(|e| format!("Could not open the model {}: {}", path, e))?;
+        let content = gguf_file::Content::read(&mut file)
+            .map_err(|e| format!("{} isn't a GGUF model: {}", path, e))?;
+        let metadata = |key: &str| {
+            content
+                .metadata
+                .get(key)
+                .and_then(|value| value.to_string().ok())
+                .cloned()
+        };
+        let architecture = metadata("general.architecture").unwrap_or_default();
+        let template = Template::detect(&metadata("tokenizer.chat_template").unwrap_or_default());
+        let eos = content
+            .metadata
+            .get("tokenizer.ggml.eos_token_id")
+            .and_then(|value| value.to_u32().ok());
+        let context_length = content
+            .metadata
+            .get(&format!("{}.context_length", architecture))
+            .and_then(|value| value.to_u32().ok())
+            .map_or(quantized_llama::MAX_SEQ_LEN, |length| length as usize);
+
+        let tokenizer_path = tokenizer_path(&model_path);
+        let tokenizer = Tokenizer::from_file(&tokenizer_path).map_err(|e| {
+            format!(
+                "Could not load the tokenizer {} (set local.tokenizer): {}",
+                tokenizer_path.display(),
+                e
+            )
+        })?;
+        let end_tokens = END_TOKENS
+            .iter()
+            .filter_map(|token| tokenizer.token_to_id(token))
+            .chain(eos)
+            .collect();
+
+        info!(model = path, architecture, "Loading local model");
+        let weights = match architecture.as_str() {
+            "llama" | "mistral" => Weights::Llama(quantized_llama::ModelWeights::from_gguf(
+                content,
+                &mut file,
+                &Device::Cpu,
+            )?),
+            "qwen2" => Weights::Qwen2(quantized_qwen2::ModelWeights::from_gguf(
+                content,
+                &mut file,
+                &Device::Cpu,
+            )?),
+            other => {
+                return Err(format!(
+                    "Local models of architecture '{}' aren't supported, only llama and qwen2",
+                    other
+                )
+                .into());
+            }
+        };
+        // The Llama weights only precompute this many positions
+        let context_length = match weights {
+            Weights::Llama(_) => context_length.min(quantized_llama::MAX_SEQ_LEN),
+            Weights::Qwen2(_) => context_length,
+        };
+
+        Ok(Self {
+            engine: Arc::new(Mutex::new(Engine {
+                weights,
+                tokenizer,
+                template,
+                end_tokens,
+                context_length,
+            })),
+            model: model_path
+                .file_stem()
+                .map_or(path.to_string(), |stem| stem.to_string_lossy().into_owned()),
+            context_length,
+            usage: Arc::new(Mutex::new(Usage::default())),
+            timing: Arc::default(),
+            system_prompt: None,
+        })
+    }
+
+    /// Uses `prompt` as the system prompt of responses
+    pub fn with_system_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.system_prompt = Some(prompt.into());
+        self
+    }
+
+    fn prompt(&self, messages: &[(String, String)]) -> String {
+        let template = self.engine.lock().unwrap().template;
+        template.prompt(self.system_prompt.as_deref(), history_window(messages))
+    }
+
+    // A whole answer to a single user message, for titles and follow-ups
+    async fn complete(&self, message: String) -> Result<String, Box<dyn std::error::Error>> {
+        let prompt = self.prompt(&[("user".to_string(), message)]);
+        let engine = Arc::clone(&self.engine);
+        let (text, _) =
+            tokio::task::spawn_blocking(move || engine.lock().unwrap().generate(&prompt, |_| true))
+                .await?
+                .map_err(|e| e.to_string())?;
+        Ok(text)
+    }
+}
+
+#[async_trait]
+impl LLMProvider for LocalProvider {
+    async fn generate_response_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<ResponseStream, Box<dyn std::error::Error>> {
+        let prompt = self.prompt(messages);
+        info!(model = %self.model, messages = messages.len(), "Generating locally");
+        *self.usage.lock().unwrap() = Usage::default();
+        *self.timing.lock().unwrap() = Timing::new(Instant::now());
+
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        let engine = Arc::clone(&self.engine);
+        let usage = Arc::clone(&self.usage);
+        tokio::task::spawn_blocking(move || generate_into(&engine, &prompt, &usage, tx));
+        Ok(timed(Box::pin(rx), Arc::clone(&self.timing)))
+    }
+
+    async fn generate_title(
+        &self,
+        user_message: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let title = self
+            .complete(format!(
+                "Generate a concise, descriptive title (max 50 characters) for a conversation that starts with this user message: \"{}\"\n\nRespond with ONLY the title, no additional text or formatting.",
+                user_message
+            ))
+            .await?;
+        let title = title
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_matches('"');
+        if title.is_empty() {
+            return Err("No title generated".into());
+        }
+        Ok(title.to_string())
+    }
+
+    async fn generate_followups(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let followups = parse_followups(&self.complete(followup_prompt(messages)).await?);
+        if followups.is_empty() {
+            return Err("No follow-ups suggested".into());
+        }
+        Ok(followups)
+    }
+
+    fn request_body(&self, messages: &[(String, String)]) -> serde_json::Value {
+        json!({
+            "model": self.model,
+            "prompt": self.prompt(messages),
+            "max_tokens": config::get().local.max_tokens,
+            "temperature": config::get().local.temperature,
+        })
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn last_usage(&self) -> Usage {
+        *self.usage.lock().unwrap()
+    }
+
+    fn last_latency(&self) -> Option<Latency> {
+        self.timing.lock().unwrap().latency()
+    }
+
+    fn context_window(&self) -> u32 {
+        self.context_length as u32
+    }
+
+    // Running on your own machine costs nothing per token
+    fn cost(&self, _usage: &Usage) -> f64 {
+        0.0
+    }
+}
+
+// Streams the answer to `prompt` into `tx`, stopping once its receiver is
+// dropped
+fn generate_into(engine: &Mutex<Engine>, prompt: &str, usage: &Mutex<Usage>, tx: ChunkSender) {
+    let result = engine.lock().unwrap().generate(prompt, |piece| {
+        tx.unbounded_send(Ok(piece.to_string())).is_ok()
+    });
+    match result {
+        Ok((_, generated)) => *usage.lock().unwrap() = generated,
+        Err(e) => {
+            let error: Box<dyn std::error::Error + Send> =
+                Box::<dyn std::error::Error + Send + Sync>::from(e.to_string());
+            let _ = tx.unbounded_send(Err(error));
+        }
+    }
+}
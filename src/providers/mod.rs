@@ -1,22 +1,353 @@
+use crate::config;
 use async_trait::async_trait;
-use futures::Stream;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 pub mod anthropic;
+pub mod keys;
+#[cfg(feature = "local")]
+pub mod local;
+pub mod models;
 
-// Generic LLM trait
+/// Token counts reported by the provider for a single request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Usage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl Usage {
+    pub fn total(&self) -> u32 {
+        self.input_tokens + self.output_tokens
+    }
+}
+
+/// How quickly a response stream arrived, logged with its usage
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct Latency {
+    /// From sending the request to the first text, `None` without text
+    pub first_token_ms: Option<u64>,
+    /// From sending the request to the last text
+    pub total_ms: u64,
+    /// Standard deviation of the time between text chunks
+    pub jitter_ms: f64,
+    pub chunks: u32,
+    /// Requests sent again, like after a 429 with the next API key
+    pub retries: u32,
+}
+
+/// Records the arrival of a response stream's chunks for `Latency`
+#[derive(Default)]
+pub struct Timing {
+    started: Option<Instant>,
+    first: Option<Instant>,
+    last: Option<Instant>,
+    chunks: u32,
+    // Running mean and sum of squared deviations of the gaps (Welford)
+    mean_gap: f64,
+    squared_deviations: f64,
+    pub retries: u32,
+}
+
+impl Timing {
+    /// Timing of a request sent at `started`
+    pub fn new(started: Instant) -> Self {
+        Self {
+            started: Some(started),
+            ..Self::default()
+        }
+    }
+
+    fn chunk(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last {
+            let gap = now.duration_since(last).as_secs_f64() * 1000.0;
+            let gaps = f64::from(self.chunks);
+            let delta = gap - self.mean_gap;
+            self.mean_gap += delta / gaps;
+            self.squared_deviations += delta * (gap - self.mean_gap);
+        }
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+        self.chunks += 1;
+    }
+
+    /// What was recorded so far, `None` before a request was sent
+    pub fn latency(&self) -> Option<Latency> {
+        let started = self.started?;
+        let millis = |at: Instant| at.duration_since(started).as_millis() as u64;
+        let gaps = self.chunks.saturating_sub(1);
+        Some(Latency {
+            first_token_ms: self.first.map(millis),
+            total_ms: self.last.map_or(0, millis),
+            jitter_ms: if gaps > 1 {
+                (self.squared_deviations / f64::from(gaps)).sqrt()
+            } else {
+                0.0
+            },
+            chunks: self.chunks,
+            retries: self.retries,
+        })
+    }
+}
+
+/// `stream` with the arrival of each text chunk recorded in `timing`
+pub fn timed(stream: ResponseStream, timing: Arc<Mutex<Timing>>) -> ResponseStream {
+    Box::pin(stream.inspect(move |chunk| {
+        if matches!(chunk, Ok(text) if !text.is_empty()) {
+            timing.lock().unwrap().chunk();
+        }
+    }))
+}
+
+/// The whole text of `stream`, with each chunk passed to `on_chunk` as it
+/// arrives
+pub async fn collect_with(
+    mut stream: ResponseStream,
+    mut on_chunk: impl FnMut(&str),
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut text = String::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        on_chunk(&chunk);
+        text.push_str(&chunk);
+    }
+    Ok(text)
+}
+
+/// The whole text of `stream`
+pub async fn collect(stream: ResponseStream) -> Result<String, Box<dyn std::error::Error>> {
+    collect_with(stream, |_| {}).await
+}
+
+/// The whole response of `llm_provider` to `messages`
+pub async fn complete(
+    llm_provider: &dyn LLMProvider,
+    messages: &[(String, String)],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let stream = llm_provider.generate_response_stream(messages).await?;
+    collect(stream).await
+}
+
+/// Largest image file the API takes
+pub const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+/// The media type of an image file the API takes, from its extension
+pub fn image_media_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        _ => None,
+    }
+}
+
+/// Rough token count of text that hasn't been sent yet, at about four
+/// characters a token
+pub fn estimate_tokens(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(4)
+}
+
+/// The newest of `messages` that `context.max_history_messages` and
+/// `context.max_history_tokens` allow sending. The last message is always
+/// kept, and the window starts at a user message as providers expect.
+pub fn history_window(messages: &[(String, String)]) -> &[(String, String)] {
+    let context = &config::get().context;
+    let mut start = match context.max_history_messages {
+        0 => 0,
+        max => messages.len().saturating_sub(max),
+    };
+    if context.max_history_tokens > 0 {
+        let mut tokens = 0;
+        for (i, (_, content)) in messages.iter().enumerate().rev() {
+            tokens += estimate_tokens(content);
+            if tokens > context.max_history_tokens && i + 1 < messages.len() {
+                start = start.max(i + 1);
+                break;
+            }
+        }
+    }
+    while start + 1 < messages.len() && messages[start].0 != "user" {
+        start += 1;
+    }
+    &messages[start..]
+}
+
+// Characters of a long conversation given to the title prompt, shared
+// evenly between its messages
+const TITLE_EXCERPT_CHARS: usize = 4000;
+
+/// `messages` as `User: ...`/`Assistant: ...` paragraphs, `chars` characters
+/// at most shared evenly between them
+pub fn excerpt(messages: &[(String, String)], chars: usize) -> String {
+    let per_message = (chars / messages.len().max(1)).max(100);
+    messages
+        .iter()
+        .map(|(role, content)| {
+            let role = if role == "user" { "User" } else { "Assistant" };
+            let mut text: String = content.chars().take(per_message).collect();
+            if text.len() < content.len() {
+                text.push('…');
+            }
+            format!("{}: {}", role, text.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Characters of the last exchange given to the follow-up prompt
+const FOLLOWUP_EXCERPT_CHARS: usize = 6000;
+
+/// The prompt asking for follow-ups to the latest exchange in `messages`
+pub fn followup_prompt(messages: &[(String, String)]) -> String {
+    let last_exchange = &messages[messages.len().saturating_sub(2)..];
+    format!(
+        "Here is the latest exchange of a conversation:\n\n{}\n\nSuggest 2 or 3 short follow-up questions the user might ask next, each under 80 characters and written as the user would type it. Respond with ONLY the questions, one per line, with no numbering or other text.",
+        excerpt(last_exchange, FOLLOWUP_EXCERPT_CHARS)
+    )
+}
+
+/// The questions in a reply to `followup_prompt`, without numbering or
+/// bullets, three at most
+pub fn parse_followups(reply: &str) -> Vec<String> {
+    reply
+        .lines()
+        .map(|line| {
+            line.trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .trim_start_matches(['.', ')', '-', '*', '•'])
+                .trim()
+                .to_string()
+        })
+        .filter(|question| !question.is_empty())
+        .take(3)
+        .collect()
+}
+
+/// A request that failed because the API couldn't be reached or was
+/// overloaded, and may well work when retried later
+#[derive(Debug)]
+pub struct Unavailable(pub String);
+
+impl std::fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", UNAVAILABLE_PREFIX, self.0)
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+// Errors relayed as text, like the daemon's, keep the prefix
+const UNAVAILABLE_PREFIX: &str = "API unavailable: ";
+
+/// Whether `error` is an `Unavailable`, possibly relayed as text
+pub fn is_unavailable(error: &(dyn std::error::Error + 'static)) -> bool {
+    error.is::<Unavailable>() || error.to_string().starts_with(UNAVAILABLE_PREFIX)
+}
+
+/// Text chunks of a response, in the order they arrive
+pub type ResponseStream =
+    Pin<Box<dyn Stream<Item = Result<String, Box<dyn std::error::Error + Send>>> + Send>>;
+
+/// A tool the model can call while responding. `input_schema` is the JSON
+/// Schema of its input.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ToolDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub input_schema: serde_json::Value,
+}
+
+/// Runs the tool calls a model makes during a response
+#[async_trait]
+pub trait ToolRunner: Send + Sync {
+    /// The tools offered to the model
+    fn definitions(&self) -> Vec<ToolDefinition>;
+
+    /// Runs a tool and returns its output, or an error message for the model
+    async fn call(&self, name: &str, input: serde_json::Value) -> Result<String, String>;
+}
+
+/// A chat model backend. Messages are `(role, content)` pairs with roles
+/// `"user"` and `"assistant"`.
 #[async_trait]
 pub trait LLMProvider: Send + Sync {
+    /// Starts a response to the conversation so far
     async fn generate_response_stream(
         &self,
         messages: &[(String, String)],
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<String, Box<dyn std::error::Error + Send>>> + Send>>,
-        Box<dyn std::error::Error>,
-    >;
+    ) -> Result<ResponseStream, Box<dyn std::error::Error>>;
 
+    /// Starts a response like `generate_response_stream()`, along with the
+    /// model a router picked for it. Unlike `routed_model()`, which only
+    /// knows the most recent response, this stays right when concurrent
+    /// requests share the provider, as under `rye serve`.
+    async fn generate_routed_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<(ResponseStream, Option<String>), Box<dyn std::error::Error>> {
+        Ok((self.generate_response_stream(messages).await?, None))
+    }
+
+    /// Asks the model for a short conversation title
     async fn generate_title(
         &self,
         user_message: &str,
     ) -> Result<String, Box<dyn std::error::Error>>;
+
+    /// A title for the conversation so far: from the first message after
+    /// the first exchange, from an excerpt of all of it later on
+    async fn generate_conversation_title(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        match messages {
+            [] => Err("Nothing to title yet".into()),
+            [(_, first)] | [(_, first), _] => self.generate_title(first).await,
+            _ => {
+                self.generate_title(&excerpt(messages, TITLE_EXCERPT_CHARS))
+                    .await
+            }
+        }
+    }
+
+    /// Asks the model for two or three short questions the user might
+    /// follow the latest exchange with
+    async fn generate_followups(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>>;
+
+    /// The JSON body `generate_response_stream` would send, for `--dry-run`
+    fn request_body(&self, messages: &[(String, String)]) -> serde_json::Value;
+
+    /// Model identifier sent with requests
+    fn model(&self) -> &str;
+
+    /// The model the most recent response went to, when a router picks one
+    /// per message. `None` without a router, or before the first response.
+    fn routed_model(&self) -> Option<&str> {
+        None
+    }
+
+    /// Usage of the most recent response stream, complete once the stream ends
+    fn last_usage(&self) -> Usage;
+
+    /// Timing of the most recent response stream, as far as it has arrived
+    fn last_latency(&self) -> Option<Latency> {
+        None
+    }
+
+    /// Maximum tokens of input plus output the model accepts
+    fn context_window(&self) -> u32;
+
+    /// Estimated cost in USD
+    fn cost(&self, usage: &Usage) -> f64;
 }
@@ -0,0 +1,85 @@
+// What each known model can do and costs, so context warnings, cost
+// estimates and feature checks don't each guess from the model ID. Models
+// are found by the first entry their ID contains, so dated IDs like
+// `claude-sonnet-4-5-20250929` and aliases like `claude-sonnet-4-5` both
+// match; more specific entries come first. `rye models` lists the table.
+
+/// Capabilities and pricing of a model family
+pub struct Model {
+    /// Part of the model IDs the entry applies to
+    pub id: &'static str,
+    /// Maximum tokens of input plus output
+    pub context_window: u32,
+    /// Whether it accepts images
+    pub vision: bool,
+    /// Whether it can call tools, web search included
+    pub tools: bool,
+    /// USD per million input tokens
+    pub input_price: f64,
+    /// USD per million output tokens
+    pub output_price: f64,
+}
+
+const fn model(id: &'static str, vision: bool, input_price: f64, output_price: f64) -> Model {
+    Model {
+        id,
+        context_window: 200_000,
+        vision,
+        tools: true,
+        input_price,
+        output_price,
+    }
+}
+
+// Claude 2 models take text only: no images, no tools
+const fn legacy(
+    id: &'static str,
+    context_window: u32,
+    input_price: f64,
+    output_price: f64,
+) -> Model {
+    Model {
+        id,
+        context_window,
+        vision: false,
+        tools: false,
+        input_price,
+        output_price,
+    }
+}
+
+pub const MODELS: [Model; 12] = [
+    model("claude-opus-4-5", true, 5.0, 25.0),
+    model("claude-opus-4", true, 15.0, 75.0),
+    model("claude-sonnet-4", true, 3.0, 15.0),
+    model("claude-haiku-4", true, 1.0, 5.0),
+    model("claude-3-7-sonnet", true, 3.0, 15.0),
+    model("claude-3-5-sonnet", true, 3.0, 15.0),
+    model("claude-3-5-haiku", false, 0.8, 4.0),
+    model("claude-3-opus", true, 15.0, 75.0),
+    model("claude-3-haiku", true, 0.25, 1.25),
+    legacy("claude-2.1", 200_000, 8.0, 24.0),
+    legacy("claude-2.0", 100_000, 8.0, 24.0),
+    legacy("claude-instant-1", 100_000, 0.8, 2.4),
+];
+
+// Assumed for models missing from the table: the default model's
+const FALLBACK: Model = model("claude-sonnet-4", true, 3.0, 15.0);
+
+/// The table entry for `model`, if it's a known one
+pub fn find(model: &str) -> Option<&'static Model> {
+    MODELS.iter().find(|known| model.contains(known.id))
+}
+
+/// The entry for `model`, or the default model's when it's unknown
+pub fn capabilities(model: &str) -> &'static Model {
+    find(model).unwrap_or(&FALLBACK)
+}
+
+impl Model {
+    /// Estimated USD for the given tokens
+    pub fn cost(&self, input_tokens: u32, output_tokens: u32) -> f64 {
+        (input_tokens as f64 * self.input_price + output_tokens as f64 * self.output_price)
+            / 1_000_000.0
+    }
+}
@@ -0,0 +1,126 @@
+use crate::usage_report::parse_since;
+use chrono::{DateTime, Local};
+use rye::conversation::{Conversation, list_conversations};
+use rye::prompt;
+use std::collections::HashSet;
+use std::io::{self, IsTerminal};
+
+// `rye prune` tidies the conversation store: conversations with no messages,
+// with only one (a question that never got its answer), or untouched since
+// `--older-than` are archived, or deleted with `--delete`. Each one is
+// confirmed unless `--yes` is given. Conversations with a message queued for
+// `rye flush`, and those another conversation was forked from, are kept.
+
+struct Candidate {
+    conversation: Conversation,
+    reason: String,
+}
+
+/// Archives or deletes the conversations that are empty, single-message or
+/// older than `older_than`
+pub async fn run(
+    older_than: &str,
+    delete: bool,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cutoff = parse_since(older_than, "--older-than")?;
+    if !yes && !io::stdin().is_terminal() {
+        return Err("Nothing to confirm with, pass --yes to prune without asking".into());
+    }
+
+    let conversations = list_conversations()?;
+    let parents: HashSet<&str> = conversations
+        .iter()
+        .filter_map(|info| info.parent.as_deref())
+        .collect();
+    let mut candidates = Vec::new();
+    for info in &conversations {
+        if parents.contains(info.id.as_str()) {
+            continue;
+        }
+        let conversation = match Conversation::load(&info.id) {
+            Ok(conversation) => conversation,
+            Err(e) => {
+                eprintln!("Warning: Skipping {}: {}", info.file_path.display(), e);
+                continue;
+            }
+        };
+        if conversation.queued {
+            continue;
+        }
+        let modified: Option<DateTime<Local>> = std::fs::metadata(&info.file_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(DateTime::from);
+        let reason = match conversation.messages.len() {
+            0 => "empty".to_string(),
+            1 => "one message".to_string(),
+            _ => match modified {
+                Some(modified) if modified < cutoff => {
+                    format!("last changed {}", modified.format("%Y-%m-%d"))
+                }
+                _ => continue,
+            },
+        };
+        candidates.push(Candidate {
+            conversation,
+            reason,
+        });
+    }
+
+    if candidates.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+
+    let (verb, done) = if delete {
+        ("Delete", "Deleted")
+    } else {
+        ("Archive", "Archived")
+    };
+    let total = candidates.len();
+    let mut pruned = 0;
+    let mut all = yes;
+    for candidate in candidates {
+        let conversation = candidate.conversation;
+        let label = conversation
+            .title
+            .clone()
+            .unwrap_or_else(|| conversation.id.clone());
+
+        if !all {
+            let question = format!(
+                "{} {} ({})? [y]es, [n]o, [a]ll the rest, [q]uit",
+                verb, label, candidate.reason
+            );
+            match prompt::choose(&question, &["yes", "no", "all", "quit"], None)? {
+                Some('y') => {}
+                Some('n') => continue,
+                Some('a') => all = true,
+                _ => break,
+            }
+        }
+
+        let result = if delete {
+            conversation.delete().await
+        } else {
+            conversation.archive().await.map(|_| ())
+        };
+        match result {
+            Ok(()) => {
+                // Asked-about ones need no second mention
+                if all {
+                    println!("{} {} ({})", done, label, candidate.reason);
+                }
+                pruned += 1;
+            }
+            Err(e) => eprintln!("Warning: Could not prune {}: {}", label, e),
+        }
+    }
+
+    println!("{} {} of {} conversations.", done, pruned, total);
+    if !delete && pruned > 0 {
+        println!("Archived conversations are kept in the archive directory next to the others.");
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use crate::status::print_response_footer;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use rye::conversation::{Conversation, list_conversations};
+use rye::hooks;
+use rye::persona;
+use rye::providers::{LLMProvider, ResponseStream, collect_with, is_unavailable};
+use rye::spinner::Spinner;
+use rye::streaming::stream_and_render_response;
+use rye::symbols::plain;
+use rye::tee;
+use rye::usage;
+use std::io::{self, IsTerminal};
+use std::time::{Duration, Instant};
+
+// With `queue.enabled`, a message that can't be sent because the API is
+// unreachable or overloaded stays in its conversation marked `queued`. The
+// chat loop keeps retrying it until the API answers or Esc is pressed;
+// `rye flush` sends whatever is still queued later.
+
+/// Longest wait between retries
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Queues the conversation's last message and retries it until a response
+/// starts. `None` when the user stops retrying; the message stays queued.
+pub async fn retry(
+    llm_provider: &dyn LLMProvider,
+    conversation: &mut Conversation,
+    error: &dyn std::error::Error,
+) -> Result<Option<ResponseStream>, Box<dyn std::error::Error>> {
+    conversation.set_queued(true).await?;
+    println!("{}", plain(&format!("📮 {}", error)));
+    println!(
+        "The message is queued. Retrying until it goes through; Esc stops, `rye flush` sends it later."
+    );
+
+    let mut delay = Duration::from_secs(5);
+    loop {
+        let spinner = Spinner::start(&format!("Waiting {}s to retry...", delay.as_secs()));
+        let stopped = wait(delay);
+        spinner.stop().await;
+        if stopped? {
+            println!("Stopped retrying. The message is still queued for `rye flush`.");
+            return Ok(None);
+        }
+
+        match llm_provider
+            .generate_response_stream(&persona::apply(
+                conversation.persona.as_deref(),
+                &conversation.messages,
+            ))
+            .await
+        {
+            Ok(stream) => {
+                conversation.set_queued(false).await?;
+                return Ok(Some(stream));
+            }
+            Err(e) if is_unavailable(e.as_ref()) => {
+                println!("{}", plain(&format!("📮 Still unavailable: {}", e)));
+                delay = (delay * 2).min(MAX_DELAY);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Waits `delay`, or until Esc or Ctrl-C is pressed, which returns true
+fn wait(delay: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + delay;
+    terminal::enable_raw_mode()?;
+    let stopped = loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break Ok(false);
+        }
+        match event::poll(remaining.min(Duration::from_millis(100))) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                    KeyCode::Esc => break Ok(true),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        break Ok(true);
+                    }
+                    _ => {}
+                },
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            },
+            Ok(false) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    terminal::disable_raw_mode()?;
+    stopped
+}
+
+/// Sends every queued message and saves the responses. Stops at the first
+/// one that still can't be sent.
+pub async fn flush(llm_provider: &dyn LLMProvider) -> Result<(), Box<dyn std::error::Error>> {
+    let mut queued = Vec::new();
+    for info in list_conversations()? {
+        let conversation = Conversation::load_metadata(&info.id)?;
+        if conversation.queued {
+            queued.push(conversation);
+        }
+    }
+    if queued.is_empty() {
+        println!("Nothing is queued.");
+        return Ok(());
+    }
+
+    // Oldest first, as they were written
+    let interactive = io::stdout().is_terminal();
+    let total = queued.len();
+    for (i, mut conversation) in queued.into_iter().rev().enumerate() {
+        conversation.load_messages()?;
+        let title = conversation
+            .title
+            .clone()
+            .unwrap_or_else(|| conversation.id.clone());
+        if conversation
+            .messages
+            .last()
+            .is_none_or(|(role, _)| role != "user")
+        {
+            // Answered some other way since
+            conversation.set_queued(false).await?;
+            continue;
+        }
+
+        println!(
+            "{}",
+            plain(&format!("▶ Sending {} of {}: {}\n", i + 1, total, title))
+        );
+        let request_started = Instant::now();
+        let stream = match llm_provider
+            .generate_response_stream(&persona::apply(
+                conversation.persona.as_deref(),
+                &conversation.messages,
+            ))
+            .await
+        {
+            Ok(stream) => stream,
+            Err(e) if is_unavailable(e.as_ref()) => {
+                return Err(format!("{}. {} message(s) are still queued.", e, total - i).into());
+            }
+            Err(e) => return Err(format!("{}: {}", title, e).into()),
+        };
+        let response = if interactive {
+            let spinner = Spinner::start("Waiting for response...");
+            let response = stream_and_render_response(stream, Some(spinner)).await?;
+            print_response_footer(request_started.elapsed(), llm_provider)?;
+            response
+        } else {
+            let response = collect_with(stream, tee::write).await?;
+            tee::finish();
+            println!("{}", response.trim_end());
+            response
+        };
+        usage::record(llm_provider, Some(&conversation.id));
+
+        if let Some(model) = llm_provider.routed_model() {
+            conversation.note_model(model).await?;
+        }
+        conversation.add_message("assistant", &response).await?;
+        conversation.set_queued(false).await?;
+        if let Err(e) = hooks::post_response(&response, &conversation.file_path, false).await {
+            eprintln!("Warning: post_response hook: {}", e);
+        }
+        if conversation.title.is_none()
+            && let Some((_, first_user_message)) = conversation.messages.first()
+        {
+            match llm_provider.generate_title(first_user_message).await {
+                Ok(title) => conversation.set_title(title).await?,
+                Err(e) => eprintln!("Warning: Could not generate title: {}", e),
+            }
+        }
+        if let Err(e) = hooks::on_save(&conversation.file_path).await {
+            eprintln!("Warning: on_save hook: {}", e);
+        }
+        println!();
+    }
+    Ok(())
+}
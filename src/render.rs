@@ -1,31 +1,625 @@
+use crate::background::{self, Background};
+use crate::bidi;
+use crate::config::{self, parse_color};
+use crate::links::{Link, apply_links, mark_links};
+use crate::math::render_math;
+use crate::symbols;
+use ansi_to_tui::IntoText;
+use crossterm::style::{Color, Stylize};
+use ratatui::text::Text;
+use regex::Regex;
+use std::sync::OnceLock;
 use termimad::MadSkin;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+struct Palette {
+    headers: Color,
+    bold: Color,
+    italic: Color,
+    inline_code: Color,
+    code_block: Color,
+}
+
+const DEFAULT_PALETTE: Palette = Palette {
+    headers: Color::Cyan,
+    bold: Color::Yellow,
+    italic: Color::Green,
+    inline_code: Color::Magenta,
+    code_block: Color::Blue,
+};
+
+// Darker accents for light terminal backgrounds
+const LIGHT_PALETTE: Palette = Palette {
+    headers: Color::DarkBlue,
+    bold: Color::DarkRed,
+    italic: Color::DarkGreen,
+    inline_code: Color::DarkMagenta,
+    code_block: Color::DarkBlue,
+};
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    Color::Rgb { r, g, b }
+}
+
+// Solarized accents read well on both light and dark backgrounds
+const SOLARIZED_PALETTE: Palette = Palette {
+    headers: rgb(0x26, 0x8b, 0xd2),
+    bold: rgb(0xb5, 0x89, 0x00),
+    italic: rgb(0x2a, 0xa1, 0x98),
+    inline_code: rgb(0xd3, 0x36, 0x82),
+    code_block: rgb(0x85, 0x99, 0x00),
+};
+
+// Terminal default colors only, relying on bold/italic for emphasis
+const MONOCHROME_PALETTE: Palette = Palette {
+    headers: Color::Reset,
+    bold: Color::Reset,
+    italic: Color::Reset,
+    inline_code: Color::Reset,
+    code_block: Color::Reset,
+};
+
+/// The termimad skin for the configured theme and detected background
 pub fn get_markdown_skin() -> MadSkin {
+    let theme = &config::get().theme;
+    let light_background = background::get() == Background::Light;
+    let palette = match theme.name.as_str() {
+        "solarized" => &SOLARIZED_PALETTE,
+        "monochrome" => &MONOCHROME_PALETTE,
+        _ if light_background => &LIGHT_PALETTE,
+        _ => &DEFAULT_PALETTE,
+    };
+    let color = |value: &Option<String>, fallback: Color| {
+        value.as_deref().and_then(parse_color).unwrap_or(fallback)
+    };
+
     let mut skin = MadSkin::default();
 
     // Customize the skin for better readability
-    skin.set_headers_fg(crossterm::style::Color::Cyan);
-    skin.bold.set_fg(crossterm::style::Color::Yellow);
-    skin.italic.set_fg(crossterm::style::Color::Green);
-    skin.inline_code.set_fg(crossterm::style::Color::Magenta);
-    skin.code_block.set_fg(crossterm::style::Color::Blue);
+    skin.set_headers_fg(color(&theme.headers, palette.headers));
+    skin.bold.set_fg(color(&theme.bold, palette.bold));
+    skin.italic.set_fg(color(&theme.italic, palette.italic));
+    skin.inline_code
+        .set_fg(color(&theme.inline_code, palette.inline_code));
+    skin.code_block
+        .set_fg(color(&theme.code_block, palette.code_block));
+
+    if theme.name == "monochrome" || light_background {
+        // The default skin paints a dark grey background behind code
+        skin.inline_code.set_bg(Color::Reset);
+        skin.code_block.set_bg(Color::Reset);
+    }
 
     // Add left padding for better readability
-    skin.paragraph.set_fgbg(crossterm::style::Color::Reset, crossterm::style::Color::Reset);
-    skin.paragraph.left_margin = 2;
-    skin.headers[0].left_margin = 2;
-    skin.headers[1].left_margin = 2;
-    skin.headers[2].left_margin = 2;
-    skin.code_block.left_margin = 4;
+    let margin = theme.margin.unwrap_or(2);
+    skin.paragraph.set_fgbg(Color::Reset, Color::Reset);
+    skin.paragraph.left_margin = margin;
+    skin.headers[0].left_margin = margin;
+    skin.headers[1].left_margin = margin;
+    skin.headers[2].left_margin = margin;
+    skin.code_block.left_margin = theme.code_margin.unwrap_or(4);
+
+    if symbols::ascii() {
+        skin.limit_to_ascii();
+    }
 
     skin
 }
 
-pub fn render_markdown(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Renders markdown wrapped to `width`, with links as OSC 8 hyperlinks
+pub fn format_markdown(text: &str, width: usize) -> String {
+    if symbols::screen_reader() {
+        return linear_markdown(text);
+    }
+    let (prepared, links) = prepare(text);
+    let rendered = apply_links(&layout_markdown(&prepared, width), &links, true);
+    with_bidi(rendered, width)
+}
+
+// `rendered` in visual order for right-to-left text, with `output.bidi`
+fn with_bidi(rendered: String, width: usize) -> String {
+    if config::get().output.bidi {
+        bidi::reorder(&rendered, width)
+    } else {
+        rendered
+    }
+}
+
+// Text passes that have to run before termimad sees the markdown. With
+// `output.ascii`, math keeps its LaTeX source.
+fn prepare(text: &str) -> (String, Vec<Link>) {
+    if symbols::ascii() {
+        mark_links(&plain_outside_code(text))
+    } else {
+        mark_links(&render_math(text))
+    }
+}
+
+// `text` through `symbols::plain()`, except in code blocks, whose contents
+// stay exactly as they'd be copied
+fn plain_outside_code(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut in_code_block = false;
+    for line in text.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            output.push_str(line);
+        } else if in_code_block {
+            output.push_str(line);
+        } else {
+            output.push_str(&symbols::plain(line));
+        }
+    }
+    output
+}
+
+// termimad wraps list items back to the margin, so each item is rendered on
+// its own and continuation lines are indented under the item's text instead.
+fn layout_markdown(text: &str, width: usize) -> String {
     let skin = get_markdown_skin();
+    let mut item_skin = skin.clone();
+    item_skin.paragraph.left_margin = 0;
+
+    let lines: Vec<&str> = text.lines().collect();
+    let mut output = String::new();
+    let mut pending = String::new();
+    // Code blocks are numbered per message
+    let mut code_blocks = 0;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+
+        if let Some(language) = line.trim_start().strip_prefix("```") {
+            // Gather the whole block, up to its closing fence
+            let mut block = format!("{}\n", line);
+            while i < lines.len() {
+                block.push_str(lines[i]);
+                block.push('\n');
+                i += 1;
+                if lines[i - 1].trim_start().starts_with("```") {
+                    break;
+                }
+            }
+
+            if !pending.is_empty() {
+                output.push_str(&skin.text(&pending, Some(width)).to_string());
+                pending.clear();
+            }
 
-    // Print the text with proper formatting
-    println!("{}", skin.term_text(text));
+            // Display math from math.rs is a code block too, but not one to number
+            if language.trim() == "math" {
+                output.push_str(&skin.text(&block, Some(width)).to_string());
+            } else {
+                code_blocks += 1;
+                output.push_str(&format_code_block(&skin, &block, code_blocks, width, false));
+            }
+            continue;
+        }
+
+        if line.starts_with("## ") {
+            code_blocks = 0;
+        }
+
+        let Some((indent, marker, content)) = split_list_item(line) else {
+            pending.push_str(line);
+            pending.push('\n');
+            continue;
+        };
+
+        if !pending.is_empty() {
+            output.push_str(&skin.text(&pending, Some(width)).to_string());
+            pending.clear();
+        }
+
+        // Lazy continuation lines belong to the item
+        let mut content = content.to_string();
+        while i < lines.len()
+            && !lines[i].trim().is_empty()
+            && split_list_item(lines[i]).is_none()
+            && indent_width(lines[i]) > indent
+        {
+            content.push(' ');
+            content.push_str(lines[i].trim());
+            i += 1;
+        }
+
+        let prefix_width = skin.paragraph.left_margin + indent + marker.width() + 1;
+        let body = item_skin
+            .text(&content, Some(width.saturating_sub(prefix_width).max(10)))
+            .to_string();
+
+        for (n, body_line) in body.lines().enumerate() {
+            if n == 0 {
+                let lead = " ".repeat(skin.paragraph.left_margin + indent);
+                output.push_str(&format!("{}{} {}\n", lead, marker, body_line));
+            } else {
+                output.push_str(&format!("{}{}\n", " ".repeat(prefix_width), body_line));
+            }
+        }
+    }
+
+    if !pending.is_empty() {
+        output.push_str(&skin.text(&pending, Some(width)).to_string());
+    }
+
+    output
+}
+
+// Lines kept around the marker when a long block or response is collapsed
+pub const COLLAPSED_HEAD_LINES: usize = 20;
+pub const COLLAPSED_TAIL_LINES: usize = 10;
+
+pub fn collapsed_marker(hidden: usize) -> String {
+    symbols::plain(&format!("  … {} lines hidden (/expand to show)", hidden))
+        .into_owned()
+        .dim()
+        .to_string()
+}
+
+// A dim "language · #n" header over the block, plus line numbers if enabled.
+// With `collapse`, blocks over the configured length keep only their ends.
+fn format_code_block(
+    skin: &MadSkin,
+    block: &str,
+    index: usize,
+    width: usize,
+    collapse: bool,
+) -> String {
+    if symbols::screen_reader() {
+        return linear_markdown(block);
+    }
+    let mut lines = block.lines();
+    let fence = lines.next().unwrap_or("```");
+    let language = fence.trim_start().trim_start_matches('`').trim();
+    let mut body: Vec<&str> = lines.collect();
+    let closing = body
+        .last()
+        .filter(|line| line.trim_start().starts_with("```"))
+        .copied();
+    if closing.is_some() {
+        body.pop();
+    }
+
+    let label = if language.is_empty() {
+        format!("#{}", index)
+    } else {
+        format!("{} {} #{}", language, symbols::pick("·", "-"), index)
+    };
+    let mut output = format!(
+        "{}{}\n",
+        " ".repeat(skin.code_block.left_margin),
+        label.dim()
+    );
+
+    let number_width = body.len().to_string().len();
+    let numbered = |n: usize, line: &str| {
+        if config::get().code.line_numbers {
+            format!(
+                "{:>w$} {} {}",
+                n + 1,
+                symbols::pick("│", "|"),
+                line,
+                w = number_width
+            )
+        } else {
+            line.to_string()
+        }
+    };
+
+    let limit = config::get().output.collapse_lines;
+    let collapsed_length = limit.max(COLLAPSED_HEAD_LINES + COLLAPSED_TAIL_LINES);
+    let segments = if collapse && limit > 0 && body.len() > collapsed_length {
+        vec![
+            (0, COLLAPSED_HEAD_LINES),
+            (body.len() - COLLAPSED_TAIL_LINES, body.len()),
+        ]
+    } else {
+        vec![(0, body.len())]
+    };
+
+    for (i, &(start, end)) in segments.iter().enumerate() {
+        if i > 0 {
+            let hidden = start - segments[i - 1].1;
+            output.push_str(&format!("{}\n", collapsed_marker(hidden)));
+        }
+
+        if matches!(language, "diff" | "patch") {
+            for (n, line) in body.iter().enumerate().take(end).skip(start) {
+                output.push_str(&" ".repeat(skin.code_block.left_margin));
+                output.push_str(&diff_line(&numbered(n, line), line));
+                output.push('\n');
+            }
+            continue;
+        }
+
+        let mut code = format!("{}\n", fence);
+        for (n, line) in body.iter().enumerate().take(end).skip(start) {
+            code.push_str(&numbered(n, line));
+            code.push('\n');
+        }
+        if let Some(closing) = closing {
+            code.push_str(closing);
+            code.push('\n');
+        }
+        output.push_str(&skin.text(&code, Some(width)).to_string());
+    }
+
+    output
+}
+
+// Colors a diff line by its first character: additions green, deletions red, hunks cyan
+fn diff_line(text: &str, line: &str) -> String {
+    let theme = &config::get().theme;
+    if theme.name == "monochrome" {
+        return text.to_string();
+    }
+
+    let light_background = background::get() == Background::Light;
+    let (added, removed, hunk) = if light_background {
+        (Color::DarkGreen, Color::DarkRed, Color::DarkCyan)
+    } else {
+        (Color::Green, Color::Red, Color::Cyan)
+    };
+
+    if line.starts_with("+++") || line.starts_with("---") {
+        text.bold().to_string()
+    } else if line.starts_with('+') {
+        text.with(added).to_string()
+    } else if line.starts_with('-') {
+        text.with(removed).to_string()
+    } else if line.starts_with("@@") {
+        text.with(hunk).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+// Returns (indent, marker, content) for `- item`, `* item`, `+ item`, and `1. item`
+fn split_list_item(line: &str) -> Option<(usize, &str, &str)> {
+    let trimmed = line.trim_start();
+    let (marker, content) = trimmed.split_once(' ')?;
+
+    let is_bullet = matches!(marker, "-" | "*" | "+");
+    let is_numbered = marker.len() > 1
+        && marker.ends_with(['.', ')'])
+        && marker[..marker.len() - 1]
+            .chars()
+            .all(|c| c.is_ascii_digit());
+
+    (is_bullet || is_numbered).then(|| (indent_width(line), marker, content.trim_start()))
+}
+
+fn indent_width(line: &str) -> usize {
+    line.chars()
+        .take_while(|c| c.is_whitespace())
+        .map(|c| if c == '\t' { 4 } else { 1 })
+        .sum()
+}
+
+/// Markdown turned into plain lines for screen readers, one source line at
+/// a time: markup that would be read out symbol by symbol is dropped, and
+/// headings, quotes and code blocks are said in words
+#[derive(Default)]
+pub struct Linear {
+    in_code_block: bool,
+}
+
+impl Linear {
+    /// What to print for `line`, `None` for lines with nothing to read, like
+    /// a rule or a table's delimiter row
+    pub fn line(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let trimmed = line.trim();
+
+        if let Some(language) = trimmed.strip_prefix("```") {
+            self.in_code_block = !self.in_code_block;
+            let language = language.trim_start_matches('`').trim();
+            return Some(if !self.in_code_block {
+                "End of code block.".to_string()
+            } else if language.is_empty() {
+                "Code block:".to_string()
+            } else {
+                format!("Code block, {}:", language)
+            });
+        }
+        if self.in_code_block {
+            return Some(line.to_string());
+        }
+
+        if is_rule(trimmed) || is_table_delimiter(trimmed) {
+            return None;
+        }
+        let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+        let text = if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+            format!("Heading: {}", linear_inline(trimmed[hashes..].trim()))
+        } else if let Some(quoted) = trimmed.strip_prefix('>') {
+            format!(
+                "Quote: {}",
+                linear_inline(quoted.trim_start_matches(['>', ' ']))
+            )
+        } else if trimmed.starts_with('|') {
+            trimmed
+                .split('|')
+                .map(str::trim)
+                .filter(|cell| !cell.is_empty())
+                .map(linear_inline)
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            let item = trimmed
+                .strip_prefix("* ")
+                .or_else(|| trimmed.strip_prefix("+ "))
+                .map(|rest| format!("- {}", rest));
+            format!(
+                "{}{}",
+                indent,
+                linear_inline(item.as_deref().unwrap_or(trimmed))
+            )
+        };
+        Some(symbols::plain(&text).into_owned())
+    }
+}
+
+/// `text` as plain lines for screen readers, see [`Linear`]
+pub fn linear_markdown(text: &str) -> String {
+    let mut linear = Linear::default();
+    text.lines()
+        .filter_map(|line| linear.line(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// `---`, `***` or `___`
+fn is_rule(trimmed: &str) -> bool {
+    let mut marks = trimmed.chars().filter(|&c| c != ' ');
+    let first = marks.next();
+    matches!(first, Some('-' | '*' | '_'))
+        && trimmed.chars().filter(|&c| Some(c) == first).count() >= 3
+        && marks.all(|c| Some(c) == first)
+}
+
+// The `---|:---:` row under a table's header
+fn is_table_delimiter(trimmed: &str) -> bool {
+    trimmed.contains('|')
+        && trimmed.contains('-')
+        && trimmed
+            .chars()
+            .all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+// Inline markup in words: images and links by their text, emphasis and
+// code spans without their markers
+fn linear_inline(text: &str) -> String {
+    static PATTERNS: OnceLock<[(Regex, &str); 3]> = OnceLock::new();
+    let patterns = PATTERNS.get_or_init(|| {
+        [
+            (
+                Regex::new(r"!\[([^\]]*)\]\([^)]*\)").expect("valid image pattern"),
+                "Image: $1",
+            ),
+            (
+                Regex::new(r"\[([^\]]+)\]\(<?([^)>]*)>?\)").expect("valid link pattern"),
+                "$1 ($2)",
+            ),
+            (
+                Regex::new(r"\*([^*\s][^*]*)\*").expect("valid emphasis pattern"),
+                "$1",
+            ),
+        ]
+    });
+    let mut text = text.replace("**", "").replace("__", "").replace("~~", "");
+    for (pattern, replacement) in patterns {
+        text = pattern.replace_all(&text, *replacement).into_owned();
+    }
+    text.replace('`', "")
+}
+
+/// `line` without its ANSI escape sequences
+pub fn strip_ansi(line: &str) -> String {
+    let mut output = String::new();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            if chars.next() == Some(']') {
+                // Skip an OSC sequence (hyperlinks) up to its ST terminator
+                while let Some(c) = chars.next() {
+                    if c == '\x1b' {
+                        chars.next();
+                        break;
+                    }
+                }
+            } else {
+                // Skip a CSI sequence up to its final byte
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            output.push(c);
+        }
+    }
+
+    output
+}
+
+/// Columns `text` takes up in the terminal, escape sequences aside. CJK and
+/// most emoji take two.
+pub fn display_width(text: &str) -> usize {
+    strip_ansi(text).width()
+}
+
+/// Rows `line` takes up in a terminal `width` columns wide once it wraps
+pub fn display_rows(line: &str, width: usize) -> usize {
+    display_width(line).div_ceil(width.max(1)).max(1)
+}
+
+/// Plain `text` cut to at most `width` columns, without splitting a wide
+/// character
+pub fn truncate_to_width(text: &str, width: usize) -> String {
+    let mut used = 0;
+    text.chars()
+        .take_while(|c| {
+            used += c.width().unwrap_or(0);
+            used <= width
+        })
+        .collect()
+}
+
+pub fn terminal_width() -> usize {
+    crossterm::terminal::size()
+        .map(|(width, _)| width as usize)
+        .unwrap_or(80)
+}
+
+/// Prints markdown wrapped to the current terminal width
+pub fn render_markdown(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // Measured on every call so output after a resize wraps to the new width
+    println!("{}", format_markdown(text, terminal_width()));
 
     Ok(())
 }
+
+/// Prints a complete fenced block as code block number `index` of its
+/// message. Long blocks are collapsed, the full text stays available to /expand.
+pub fn render_code_block(block: &str, index: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let skin = get_markdown_skin();
+    println!(
+        "{}",
+        format_code_block(&skin, block, index, terminal_width(), true)
+    );
+
+    Ok(())
+}
+
+/// Formats a fenced block like [`render_code_block`], without collapsing
+pub fn format_code(block: &str, index: usize, width: usize) -> String {
+    format_code_block(&get_markdown_skin(), block, index, width, false)
+}
+
+/// Rendered markdown split into lines, for pagers
+pub fn render_markdown_lines(text: &str, width: usize) -> Vec<String> {
+    format_markdown(text, width)
+        .lines()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Same skin, converted for ratatui widgets in the full-screen modes. ratatui
+/// can't pass OSC 8 through, so links keep only their URL suffix.
+pub fn render_markdown_text(text: &str, width: usize) -> Text<'static> {
+    let (prepared, links) = prepare(text);
+    let rendered = with_bidi(
+        apply_links(&layout_markdown(&prepared, width), &links, false),
+        width,
+    );
+
+    rendered
+        .into_text()
+        .unwrap_or_else(|_| Text::raw(rendered.clone()))
+}
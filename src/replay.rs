@@ -0,0 +1,73 @@
+use crossterm::{
+    execute,
+    style::{Attribute, Print, SetAttribute},
+};
+use futures::StreamExt;
+use rye::conversation::Conversation;
+use rye::providers::ResponseStream;
+use rye::streaming::stream_and_render_response;
+use rye::symbols::{self, plain};
+use std::io::{self, Write};
+use std::time::Duration;
+
+// Characters are sent in small groups on a fixed tick, like a fast typist
+const TICK: Duration = Duration::from_millis(20);
+
+fn chunks_per_tick(text: &str, chars_per_second: u32) -> Vec<String> {
+    let per_tick = (chars_per_second as usize * TICK.as_millis() as usize / 1000).max(1);
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(per_tick)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+fn typed_stream(text: &str, chars_per_second: u32) -> ResponseStream {
+    let chunks = chunks_per_tick(text, chars_per_second);
+    Box::pin(futures::stream::iter(chunks).then(|chunk| async move {
+        tokio::time::sleep(TICK).await;
+        Ok(chunk)
+    }))
+}
+
+async fn type_out(text: &str, chars_per_second: u32) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    for chunk in chunks_per_tick(text, chars_per_second) {
+        write!(stdout, "{}", chunk)?;
+        stdout.flush()?;
+        tokio::time::sleep(TICK).await;
+    }
+    writeln!(stdout)
+}
+
+/// Re-streams a stored conversation through the renderer, typing out each
+/// message at `chars_per_second`
+pub async fn run(id: &str, chars_per_second: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let conversation = Conversation::load(id)?;
+
+    let title = conversation.title.as_deref().unwrap_or(&conversation.id);
+    execute!(
+        io::stdout(),
+        SetAttribute(Attribute::Bold),
+        Print(plain(&format!("▶ Replaying {}\n", title))),
+        SetAttribute(Attribute::Reset),
+    )?;
+
+    for (role, content) in &conversation.messages {
+        if role == "user" {
+            if symbols::screen_reader() {
+                println!();
+            } else {
+                println!("\n{}", plain(&"─".repeat(60)));
+            }
+            print!("{}", symbols::prompt());
+            type_out(content, chars_per_second).await?;
+        } else {
+            symbols::print_assistant_header();
+            stream_and_render_response(typed_stream(content, chars_per_second), None).await?;
+        }
+    }
+
+    println!();
+    Ok(())
+}
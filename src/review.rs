@@ -0,0 +1,131 @@
+use crate::context::{self, Attachments};
+use crate::status::print_response_footer;
+use rye::conversation::Conversation;
+use rye::hooks;
+use rye::providers::LLMProvider;
+use rye::secrets;
+use rye::spinner::Spinner;
+use rye::streaming::stream_and_render_response;
+use rye::symbols::plain;
+use rye::usage;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+// `rye review`: sends a diff (or whole files) with a reviewer system prompt
+// and saves the exchange as a conversation tagged `review`.
+
+/// Persona for review requests. Asks for one heading per file so findings
+/// render grouped by file.
+pub const SYSTEM_PROMPT: &str = "You are a meticulous senior code reviewer. Review the code you are given for bugs, security issues, performance problems, and unclear or unidiomatic code. Group findings by file: one `## <path>` heading per file that has findings, then one bullet per finding that starts with its severity (**high**, **medium** or **low**) and the line or hunk it concerns, followed by a short explanation and a suggested fix. Skip files without findings and don't restate the changes. End with a `## Summary` section giving an overall assessment in one paragraph.";
+
+fn language(path: &Path) -> &str {
+    path.extension().and_then(|ext| ext.to_str()).unwrap_or("")
+}
+
+// Files to review in full: the given files, and the tracked files inside the
+// given directories
+async fn files_in(paths: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let listed = context::git(&["ls-files", "--", &path.to_string_lossy()]).await?;
+            files.extend(listed.lines().map(PathBuf::from));
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Reviews the staged or uncommitted changes to `paths` (everything when
+/// empty). Paths without changes are reviewed in full.
+pub async fn run(
+    llm_provider: &dyn LLMProvider,
+    paths: &[PathBuf],
+    staged: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut git_args = vec!["diff".to_string()];
+    git_args.push(if staged { "--staged" } else { "HEAD" }.to_string());
+    git_args.push("--".to_string());
+    git_args.extend(paths.iter().map(|path| path.to_string_lossy().into_owned()));
+    let git_args: Vec<&str> = git_args.iter().map(String::as_str).collect();
+    let diff = context::git(&git_args).await?;
+
+    let mut what = if staged {
+        "staged changes".to_string()
+    } else {
+        "uncommitted changes".to_string()
+    };
+    let mut attachments = Attachments::default();
+
+    if !diff.trim().is_empty() {
+        attachments.add("Changes", &diff, "diff");
+    } else if !paths.is_empty() {
+        what = paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        for file in files_in(paths).await? {
+            match std::fs::read_to_string(&file) {
+                Ok(content) => {
+                    attachments.add(&file.display().to_string(), &content, language(&file));
+                }
+                Err(e) => eprintln!("Warning: Skipping {}: {}", file.display(), e),
+            }
+        }
+    }
+
+    if attachments.is_empty() {
+        println!("Nothing to review.");
+        return Ok(());
+    }
+
+    let message = attachments.take_into(&format!("Review the {}.", what));
+    let Some(message) = secrets::check(&message)? else {
+        return Err("Cancelled, nothing sent".into());
+    };
+    let mut conversation = Conversation::new()?;
+    conversation
+        .add_message_with_attachments("user", &message, attachments.sent())
+        .await?;
+
+    println!("{}", plain(&format!("🔍 Reviewing {}...\n", what)));
+    let request_started = Instant::now();
+    let spinner = Spinner::start("Waiting for review...");
+    let stream = match llm_provider
+        .generate_response_stream(&conversation.messages)
+        .await
+    {
+        Ok(stream) => stream,
+        Err(e) => {
+            spinner.stop().await;
+            std::fs::remove_file(&conversation.file_path)?;
+            return Err(e);
+        }
+    };
+    let review = stream_and_render_response(stream, Some(spinner)).await?;
+    print_response_footer(request_started.elapsed(), llm_provider)?;
+    usage::record(llm_provider, Some(&conversation.id));
+
+    if !review.is_empty() {
+        conversation.add_message("assistant", &review).await?;
+        if let Err(e) = hooks::post_response(&review, &conversation.file_path, true).await {
+            eprintln!("Warning: post_response hook: {}", e);
+        }
+    }
+
+    let title = format!(
+        "Review of {} {}",
+        what,
+        chrono::Local::now().format("%Y-%m-%d %H:%M")
+    );
+    conversation.set_tags(vec!["review".to_string()]).await?;
+    conversation.set_title(title).await?;
+    if let Err(e) = hooks::on_save(&conversation.file_path).await {
+        eprintln!("Warning: on_save hook: {}", e);
+    }
+
+    println!("\nReview saved to: {}", conversation.file_path.display());
+    Ok(())
+}
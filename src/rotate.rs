@@ -0,0 +1,120 @@
+use rye::config;
+use rye::conversation::{Conversation, unique_title};
+use rye::providers::{LLMProvider, complete};
+use rye::usage;
+
+// A conversation whose file outgrows `rotation.max_kb`, or that passes
+// `rotation.max_turns` exchanges, is rolled over once the exchange that
+// crossed the limit is saved: the model sums it up and a new part, titled
+// `<title> (part N)`, starts from that summary. The old file ends with a link
+// to the new part and the new part's first message links back, so neither
+// an editor nor the parser ever has to deal with the whole history.
+
+/// Characters of the most recent messages given to the summary prompt
+const SUMMARY_CHARS: usize = 80_000;
+
+/// Whether `conversation` has grown past `[rotation]`'s limits
+pub fn due(conversation: &Conversation) -> bool {
+    let rotation = &config::get().rotation;
+    let turns = conversation
+        .messages
+        .iter()
+        .filter(|(role, _)| role == "assistant")
+        .count();
+    if rotation.max_turns > 0 && turns >= rotation.max_turns {
+        return true;
+    }
+    rotation.max_kb > 0
+        && std::fs::metadata(&conversation.file_path)
+            .is_ok_and(|metadata| metadata.len() >= rotation.max_kb * 1024)
+}
+
+// `title` without its ` (part N)` suffix, and N, 1 when there is none
+fn split_part(title: &str) -> (&str, usize) {
+    title
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" (part "))
+        .and_then(|(base, n)| Some((base, n.parse().ok()?)))
+        .unwrap_or((title, 1))
+}
+
+// The model's summary of the conversation, from as many of the latest
+// messages as fit in `SUMMARY_CHARS`
+async fn summarize(
+    llm_provider: &dyn LLMProvider,
+    conversation: &Conversation,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let messages = &conversation.messages;
+    let mut start = messages.len();
+    let mut chars = 0;
+    while start > 0 {
+        chars += messages[start - 1].1.chars().count();
+        if chars > SUMMARY_CHARS && start < messages.len() {
+            break;
+        }
+        start -= 1;
+    }
+    let transcript = messages[start..]
+        .iter()
+        .map(|(role, content)| {
+            let role = if role == "user" { "User" } else { "Assistant" };
+            format!("{}: {}", role, content.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let earlier = if start > 0 {
+        " (its earliest messages are left out)"
+    } else {
+        ""
+    };
+    let prompt = format!(
+        "Here is a long conversation between a user and an assistant{}. It continues in a new thread that starts from your summary alone. Summarize it so the assistant can carry on: the user's goals, what was decided or found out, code, names and numbers that matter, open questions, and where it left off. Use markdown and respond with ONLY the summary.\n\n{}",
+        earlier, transcript
+    );
+
+    let summary = complete(llm_provider, &[("user".to_string(), prompt)]).await?;
+    usage::record(llm_provider, Some(&conversation.id));
+    if summary.trim().is_empty() {
+        return Err("The summary came back empty".into());
+    }
+    Ok(summary.trim().to_string())
+}
+
+/// Rolls `conversation` over into a new part starting from a summary, and
+/// returns the new part
+pub async fn rotate(
+    llm_provider: &dyn LLMProvider,
+    conversation: &Conversation,
+) -> Result<Conversation, Box<dyn std::error::Error>> {
+    let summary = summarize(llm_provider, conversation).await?;
+
+    let title = conversation
+        .title
+        .clone()
+        .unwrap_or_else(|| conversation.id.clone());
+    let (base, part) = split_part(&title);
+    let next_title = format!("{} (part {})", base, part + 1);
+    let next_title = unique_title(&next_title, |n| format!("{} ({})", next_title, n))?;
+    let file_name = conversation
+        .file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut next = conversation.successor()?;
+    next.add_message(
+        "user",
+        &format!(
+            "This conversation continues [{}](<{}>). Summarize it so far.",
+            title, file_name
+        ),
+    )
+    .await?;
+    next.add_message("assistant", &summary).await?;
+    next.set_title(next_title).await?;
+    if conversation.persona.is_some() {
+        next.set_persona(conversation.persona.clone()).await?;
+    }
+    conversation.link_continuation(&next).await?;
+    Ok(next)
+}
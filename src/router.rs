@@ -0,0 +1,197 @@
+use crate::config;
+use crate::providers::{LLMProvider, Latency, ResponseStream, Usage, complete};
+use crate::usage;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::{debug, info};
+
+// With `router.enabled`, conversations on the default model send each
+// message to one of two models: `router.fast_model` for short, simple ones
+// and the default (strong) model for the rest. By default a message is simple
+// when it's at most `router.max_chars` characters and has no code; with
+// `router.classify` the fast model is asked first, and the length check is
+// only the fallback when that fails. Titles and follow-up questions always
+// go to the fast model. The model each answer came from is noted in the
+// conversation file, see `Conversation::note_model()`. The model, usage and
+// cost read back from the router are those of its most recent response;
+// `generate_routed_stream()` names the model per request for callers that
+// share it.
+//
+// `--dry-run` and the response cache can't wait for a classifier, so their
+// request body is picked by the length check alone.
+
+/// System prompt of the classifier request
+pub const CLASSIFY_PROMPT: &str = "Decide whether a small, fast model can answer the user's message as well as a strong one. Reply with only SIMPLE for greetings, quick facts, definitions and short rewrites, or COMPLEX for anything that needs careful reasoning, code, analysis or a long answer.";
+
+/// The classifier's answer is one word
+pub const CLASSIFY_MAX_TOKENS: u32 = 5;
+
+// Characters of a message the classifier sees
+const CLASSIFY_CHARS: usize = 4_000;
+
+/// Routes between `strong` and `fast` per message. `classifier`, a fast
+/// model with `CLASSIFY_PROMPT`, judges messages when given.
+pub fn wrap(
+    strong: Box<dyn LLMProvider>,
+    fast: Box<dyn LLMProvider>,
+    classifier: Option<Box<dyn LLMProvider>>,
+) -> Box<dyn LLMProvider> {
+    Box::new(Router {
+        strong,
+        fast,
+        classifier,
+        fast_chosen: AtomicBool::new(false),
+        routed: AtomicBool::new(false),
+    })
+}
+
+struct Router {
+    strong: Box<dyn LLMProvider>,
+    fast: Box<dyn LLMProvider>,
+    classifier: Option<Box<dyn LLMProvider>>,
+    // Which model the last response went to
+    fast_chosen: AtomicBool,
+    // Whether there was a response yet
+    routed: AtomicBool,
+}
+
+// The newest user message, which the route is picked for
+fn last_message(messages: &[(String, String)]) -> &str {
+    messages
+        .iter()
+        .rev()
+        .find(|(role, _)| role == "user")
+        .map_or("", |(_, content)| content)
+}
+
+// Fenced code, or a few lines that are indented or end like code
+fn has_code(message: &str) -> bool {
+    message.contains("```")
+        || message
+            .lines()
+            .filter(|line| {
+                line.starts_with("    ")
+                    || line.starts_with('\t')
+                    || line.trim_end().ends_with([';', '{', '}'])
+            })
+            .count()
+            >= 2
+}
+
+fn looks_simple(message: &str) -> bool {
+    message.chars().count() <= config::get().router.max_chars && !has_code(message)
+}
+
+impl Router {
+    fn chosen(&self) -> &dyn LLMProvider {
+        if self.fast_chosen.load(Ordering::Relaxed) {
+            self.fast.as_ref()
+        } else {
+            self.strong.as_ref()
+        }
+    }
+
+    // Whether the classifier judges `message` simple
+    async fn classify(
+        &self,
+        classifier: &dyn LLMProvider,
+        message: &str,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let excerpt: String = message.chars().take(CLASSIFY_CHARS).collect();
+        let answer = complete(classifier, &[("user".to_string(), excerpt)]).await?;
+        usage::record(classifier, None);
+        debug!(answer = answer.trim(), "Classified message");
+        Ok(answer.trim().eq_ignore_ascii_case("SIMPLE"))
+    }
+
+    // Whether `message` goes to the fast model
+    async fn route(&self, message: &str) -> bool {
+        if let Some(classifier) = &self.classifier {
+            match self.classify(classifier.as_ref(), message).await {
+                Ok(simple) => return simple,
+                Err(e) => debug!(error = %e, "Classifier failed, routing by length"),
+            }
+        }
+        looks_simple(message)
+    }
+}
+
+#[async_trait]
+impl LLMProvider for Router {
+    async fn generate_response_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<ResponseStream, Box<dyn std::error::Error>> {
+        let (stream, _) = self.generate_routed_stream(messages).await?;
+        Ok(stream)
+    }
+
+    async fn generate_routed_stream(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<(ResponseStream, Option<String>), Box<dyn std::error::Error>> {
+        let fast = self.route(last_message(messages)).await;
+        self.fast_chosen.store(fast, Ordering::Relaxed);
+        self.routed.store(true, Ordering::Relaxed);
+        // From `fast` rather than `chosen()`, which a concurrent request may
+        // have changed by now
+        let chosen = if fast {
+            self.fast.as_ref()
+        } else {
+            self.strong.as_ref()
+        };
+        info!(model = chosen.model(), "Routed message");
+        let stream = chosen.generate_response_stream(messages).await?;
+        Ok((stream, Some(chosen.model().to_string())))
+    }
+
+    async fn generate_title(
+        &self,
+        user_message: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        self.fast.generate_title(user_message).await
+    }
+
+    async fn generate_followups(
+        &self,
+        messages: &[(String, String)],
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        self.fast.generate_followups(messages).await
+    }
+
+    fn request_body(&self, messages: &[(String, String)]) -> Value {
+        if looks_simple(last_message(messages)) {
+            self.fast.request_body(messages)
+        } else {
+            self.strong.request_body(messages)
+        }
+    }
+
+    fn model(&self) -> &str {
+        self.chosen().model()
+    }
+
+    fn routed_model(&self) -> Option<&str> {
+        self.routed
+            .load(Ordering::Relaxed)
+            .then(|| self.chosen().model())
+    }
+
+    fn last_usage(&self) -> Usage {
+        self.chosen().last_usage()
+    }
+
+    fn last_latency(&self) -> Option<Latency> {
+        self.chosen().last_latency()
+    }
+
+    // Checked before the message is routed, so it has to fit either model
+    fn context_window(&self) -> u32 {
+        self.strong.context_window().min(self.fast.context_window())
+    }
+
+    fn cost(&self, usage: &Usage) -> f64 {
+        self.chosen().cost(usage)
+    }
+}
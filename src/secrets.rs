@@ -0,0 +1,154 @@
+use crate::config;
+use crate::prompt;
+use crate::symbols::plain;
+use regex::Regex;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+use std::sync::OnceLock;
+
+// Outgoing messages are scanned for things that shouldn't reach a cloud API:
+// API keys and tokens, AWS credentials, private keys, `.env`-style secret
+// assignments and email addresses. The user is asked before anything found
+// is sent; when nobody can be asked, it is redacted. `secrets.check = false`
+// turns this off.
+
+// Patterns with a capture group redact only the group, keeping e.g. the
+// variable name of `PASSWORD=...`
+const PATTERNS: [(&str, &str); 10] = [
+    (
+        "private key",
+        r"(?s)-----BEGIN [A-Z ]*PRIVATE KEY-----.*?(?:-----END [A-Z ]*PRIVATE KEY-----|\z)",
+    ),
+    ("Anthropic API key", r"\bsk-ant-[A-Za-z0-9_-]{20,}"),
+    ("OpenAI API key", r"\bsk-(?:proj-)?[A-Za-z0-9_-]{20,}"),
+    (
+        "GitHub token",
+        r"\b(?:gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{22,})",
+    ),
+    ("Slack token", r"\bxox[abprs]-[A-Za-z0-9-]{10,}"),
+    ("Google API key", r"\bAIza[0-9A-Za-z_-]{35}"),
+    ("AWS access key ID", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "AWS secret access key",
+        r#"(?i)aws_?secret_?access_?key["']?\s*[=:]\s*["']?([A-Za-z0-9/+=]{40,})"#,
+    ),
+    (
+        "secret value",
+        r#"(?im)^\s*(?:export\s+)?[A-Z0-9_]*(?:SECRET|TOKEN|PASSWORD|PASSWD|API_?KEY|PRIVATE_KEY)[A-Z0-9_]*\s*[=:]\s*["']?([^\s"']{8,})"#,
+    ),
+    (
+        "email address",
+        r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b",
+    ),
+];
+
+/// Something in a message that looks like a secret
+pub struct Finding {
+    pub kind: &'static str,
+    pub range: Range<usize>,
+}
+
+fn patterns() -> &'static [(&'static str, Regex)] {
+    static PATTERNS_COMPILED: OnceLock<Vec<(&str, Regex)>> = OnceLock::new();
+    PATTERNS_COMPILED.get_or_init(|| {
+        PATTERNS
+            .iter()
+            .map(|(kind, pattern)| (*kind, Regex::new(pattern).expect("valid secret pattern")))
+            .collect()
+    })
+}
+
+/// Everything in `text` that looks like a secret, in order and without
+/// overlaps
+pub fn scan(text: &str) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = Vec::new();
+    for (kind, regex) in patterns() {
+        for captures in regex.captures_iter(text) {
+            let Some(found) = captures.get(1).or_else(|| captures.get(0)) else {
+                continue;
+            };
+            // Earlier patterns are more specific and win
+            let range = found.range();
+            if findings
+                .iter()
+                .all(|f| f.range.end <= range.start || range.end <= f.range.start)
+            {
+                findings.push(Finding { kind, range });
+            }
+        }
+    }
+    findings.sort_by_key(|finding| finding.range.start);
+    findings
+}
+
+/// `text` with every finding replaced by `[REDACTED <kind>]`
+pub fn redact(text: &str, findings: &[Finding]) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut last = 0;
+    for finding in findings {
+        redacted.push_str(&text[last..finding.range.start]);
+        redacted.push_str(&format!("[REDACTED {}]", finding.kind));
+        last = finding.range.end;
+    }
+    redacted.push_str(&text[last..]);
+    redacted
+}
+
+/// A finding as shown to the user, with most of the secret hidden
+pub fn describe(text: &str, finding: &Finding) -> String {
+    let found = &text[finding.range.clone()];
+    let line = text[..finding.range.start].matches('\n').count() + 1;
+    let shown: String = found.chars().take(4).collect();
+    plain(&format!("{} on line {}: {}…", finding.kind, line, shown)).into_owned()
+}
+
+/// The message to send after checking it for secrets: as is, redacted, or
+/// `None` when the user cancels. Asks on the terminal when stdin is one and
+/// redacts without asking otherwise.
+pub fn check(text: &str) -> io::Result<Option<String>> {
+    check_added("", text)
+}
+
+/// The findings in `text` that aren't already in `checked`, the message as
+/// it was when it last went through the check
+pub fn added(checked: &str, text: &str) -> Vec<Finding> {
+    scan(text)
+        .into_iter()
+        .filter(|finding| !checked.contains(&text[finding.range.clone()]))
+        .collect()
+}
+
+/// `check()` for a message changed after it was checked, by a hook or an
+/// edit. Only what the change added is asked about; the rest was answered.
+pub fn check_added(checked: &str, text: &str) -> io::Result<Option<String>> {
+    if !config::get().secrets.check {
+        return Ok(Some(text.to_string()));
+    }
+    let findings = added(checked, text);
+    if findings.is_empty() {
+        return Ok(Some(text.to_string()));
+    }
+
+    eprintln!(
+        "{}",
+        plain("⚠️  This message looks like it contains secrets:")
+    );
+    for finding in &findings {
+        eprintln!("  - {}", describe(text, finding));
+    }
+    if !io::stdin().is_terminal() {
+        eprintln!("Warning: Redacted them, since there's no terminal to ask on.");
+        return Ok(Some(redact(text, &findings)));
+    }
+
+    let choice = prompt::choose(
+        "[R]edact them, [s]end as is, or [c]ancel?",
+        &["redact", "send", "cancel"],
+        Some('r'),
+    )?;
+    Ok(match choice {
+        Some('r') => Some(redact(text, &findings)),
+        Some('s') => Some(text.to_string()),
+        _ => None,
+    })
+}
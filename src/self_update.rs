@@ -0,0 +1,183 @@
+use reqwest::Client;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+// `rye self-update` replaces the running binary with the one from the latest
+// GitHub release, for installs of the prebuilt binaries. Each release has a
+// binary per platform, named `rye-<arch>-<os>` (`.exe` on Windows), and a
+// `SHA256SUMS` file listing their checksums; a binary whose checksum is
+// missing or doesn't match is never installed. The new binary is written
+// next to the old one and renamed over it, so an interrupted update leaves
+// the old binary in place.
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/SamuelBurac/rye/releases/latest";
+const CHECKSUMS: &str = "SHA256SUMS";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Result<&Asset, String> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| format!("Release {} has no {}", self.tag_name, name))
+    }
+}
+
+// The release asset built for this platform
+fn asset_name() -> String {
+    format!(
+        "rye-{}-{}{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+// The numeric parts of a version like `v1.2.3`, for comparing
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+fn client() -> Result<Client, reqwest::Error> {
+    Client::builder().user_agent("rye").build()
+}
+
+async fn download(client: &Client, url: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    debug!(url, "Downloading");
+    let response = client.get(url).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("Downloading {} failed: {}", url, status).into());
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+// The checksum of `name` in a `SHA256SUMS` file, lines of `<hex>  <name>`
+fn expected_checksum(sums: &str, name: &str) -> Option<String> {
+    sums.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        // `sha256sum --binary` marks names with `*`
+        let file = file.trim().trim_start_matches('*');
+        (file == name).then(|| hash.to_lowercase())
+    })
+}
+
+fn checksum(data: &[u8]) -> String {
+    hmac_sha256::Hash::hash(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+// Puts `binary` in place of `current`: written next to it first, then
+// renamed over it. Windows can't replace a running binary, but it can rename
+// it, so there the old one is moved aside first.
+fn replace(current: &Path, binary: &[u8]) -> std::io::Result<()> {
+    let staged = current.with_file_name(format!(".{}", asset_name()));
+    std::fs::write(&staged, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o755))?;
+    }
+    #[cfg(windows)]
+    let old = current.with_extension("old.exe");
+    #[cfg(windows)]
+    {
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(current, &old)?;
+    }
+    std::fs::rename(&staged, current).inspect_err(|_| {
+        let _ = std::fs::remove_file(&staged);
+        #[cfg(windows)]
+        let _ = std::fs::rename(&old, current);
+    })
+}
+
+fn current_exe() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let path = std::env::current_exe()?;
+    // Through symlinks to the binary itself, so the link stays
+    Ok(std::fs::canonicalize(&path).unwrap_or(path))
+}
+
+/// Installs the latest release if it's newer than this binary, or with
+/// `check` only says whether there is one
+pub async fn run(check: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let current_version = env!("CARGO_PKG_VERSION");
+    let client = client()?;
+    let response = client.get(LATEST_RELEASE_URL).send().await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("Could not check for releases: {}", status).into());
+    }
+    let release: Release = response.json().await?;
+    let latest_version = release.tag_name.trim_start_matches('v');
+
+    if version_parts(latest_version) <= version_parts(current_version) {
+        println!("rye {} is the latest version", current_version);
+        return Ok(());
+    }
+    if check {
+        println!(
+            "rye {} is available (this is {}): {}\nInstall it with `rye self-update`",
+            latest_version, current_version, release.html_url
+        );
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let asset = release.asset(&name).map_err(|e| {
+        format!(
+            "{}, there's no prebuilt binary for this platform. See {}",
+            e, release.html_url
+        )
+    })?;
+    let sums = download(&client, &release.asset(CHECKSUMS)?.browser_download_url).await?;
+    let expected = expected_checksum(&String::from_utf8_lossy(&sums), &name)
+        .ok_or_else(|| format!("{} has no checksum for {}", CHECKSUMS, name))?;
+
+    println!("Downloading rye {} ({})...", latest_version, name);
+    let binary = download(&client, &asset.browser_download_url).await?;
+    let actual = checksum(&binary);
+    if actual != expected {
+        return Err(format!(
+            "The checksum of {} doesn't match {} ({} instead of {}), not installing it",
+            name, CHECKSUMS, actual, expected
+        )
+        .into());
+    }
+    debug!(bytes = binary.len(), checksum = %actual, "Checksum verified");
+
+    let current = current_exe()?;
+    replace(&current, &binary).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::PermissionDenied {
+            format!(
+                "Could not replace {}: {}. Run it with permission to write there, or update through the package manager that installed it",
+                current.display(),
+                e
+            )
+        } else {
+            format!("Could not replace {}: {}", current.display(), e)
+        }
+    })?;
+    info!(from = current_version, to = latest_version, path = %current.display(), "Updated");
+    println!("Updated rye from {} to {}", current_version, latest_version);
+    Ok(())
+}
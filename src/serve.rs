@@ -0,0 +1,297 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::get,
+};
+use futures::StreamExt;
+use futures::channel::mpsc;
+use rye::conversation::{Conversation, list_conversations};
+use rye::hooks;
+use rye::persona;
+use rye::providers::LLMProvider;
+use rye::symbols::plain;
+use rye::usage;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+
+// `rye serve`: a small JSON API over the conversation store so other
+// front-ends share the same history.
+//
+//   GET  /conversations                 -> [{"id", "title", "tags"}]
+//   POST /conversations                 -> {"id"}
+//   GET  /conversations/{id}            -> {"id", "title", "tags", "messages": [{"role", "content"}]}
+//   POST /conversations/{id}/messages   {"content": "..."} -> server-sent events
+//
+// A reply streams as `chunk` events holding text, then ends with a `done`
+// event ({"id", "title"}, the ID changes once the conversation is titled)
+// or an `error` event. The response is saved even if the
+// client disconnects early.
+
+#[derive(Clone)]
+struct AppState {
+    llm_provider: Arc<dyn LLMProvider>,
+    // Conversations with a response in flight
+    busy: Arc<Mutex<HashSet<String>>>,
+}
+
+#[derive(Deserialize)]
+struct NewMessage {
+    content: String,
+}
+
+struct ApiError(StatusCode, String);
+
+impl From<io::Error> for ApiError {
+    fn from(e: io::Error) -> Self {
+        let status = match e.kind() {
+            io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// Serves the API on `host:port` until interrupted
+pub async fn run(
+    llm_provider: Arc<dyn LLMProvider>,
+    host: &str,
+    port: u16,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let state = AppState {
+        llm_provider,
+        busy: Arc::default(),
+    };
+
+    let app = Router::new()
+        .route("/conversations", get(list_handler).post(create_handler))
+        .route("/conversations/{id}", get(show_handler))
+        .route(
+            "/conversations/{id}/messages",
+            axum::routing::post(message_handler),
+        )
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind((host, port)).await?;
+    println!(
+        "{}",
+        plain(&format!(
+            "🥃 Serving conversations on http://{}",
+            listener.local_addr()?
+        ))
+    );
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+
+    Ok(())
+}
+
+async fn list_handler() -> Result<Json<Value>, ApiError> {
+    let conversations: Vec<Value> = list_conversations()?
+        .into_iter()
+        .map(|info| json!({ "id": info.id, "title": info.title, "tags": info.tags }))
+        .collect();
+
+    Ok(Json(Value::Array(conversations)))
+}
+
+async fn create_handler() -> Result<(StatusCode, Json<Value>), ApiError> {
+    let conversation = Conversation::new()?;
+    Ok((StatusCode::CREATED, Json(json!({ "id": conversation.id }))))
+}
+
+// Loads the conversation named in the URL, which is never a path
+fn load(id: &str) -> Result<Conversation, ApiError> {
+    if id.contains(['/', '\\']) || id.contains("..") {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid conversation ID: {}", id),
+        ));
+    }
+    Ok(Conversation::load(id)?)
+}
+
+async fn show_handler(Path(id): Path<String>) -> Result<Json<Value>, ApiError> {
+    let conversation = load(&id)?;
+    let messages: Vec<Value> = conversation
+        .messages
+        .iter()
+        .map(|(role, content)| json!({ "role": role, "content": content }))
+        .collect();
+
+    Ok(Json(json!({
+        "id": conversation.id,
+        "title": conversation.title,
+        "tags": conversation.tags,
+        "messages": messages,
+    })))
+}
+
+async fn message_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(message): Json<NewMessage>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut conversation = load(&id)?;
+
+    if !state.busy.lock().unwrap().insert(conversation.id.clone()) {
+        return Err(ApiError(
+            StatusCode::CONFLICT,
+            "A response is already streaming for this conversation".to_string(),
+        ));
+    }
+
+    match start_response(&state, &mut conversation, &message.content).await {
+        Ok((stream, model)) => {
+            let (tx, rx) = mpsc::unbounded();
+            tokio::spawn(finish_response(state, conversation, stream, model, tx));
+            Ok(Sse::new(rx).keep_alive(KeepAlive::default()))
+        }
+        Err(e) => {
+            state.busy.lock().unwrap().remove(&conversation.id);
+            Err(e)
+        }
+    }
+}
+
+async fn start_response(
+    state: &AppState,
+    conversation: &mut Conversation,
+    content: &str,
+) -> Result<(rye::providers::ResponseStream, Option<String>), ApiError> {
+    let content = hooks::pre_send(content, &conversation.file_path)
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, format!("pre_send hook: {}", e)))?;
+    if content.is_empty() {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Message is empty".to_string(),
+        ));
+    }
+
+    conversation.add_message("user", &content).await?;
+    info!(id = %conversation.id, "Serving response");
+
+    // The routed model comes with the stream, other requests share the
+    // provider
+    state
+        .llm_provider
+        .generate_routed_stream(&persona::apply(
+            conversation.persona.as_deref(),
+            &conversation.messages,
+        ))
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_GATEWAY, e.to_string()))
+}
+
+// Forwards the stream to the client, then saves the response and runs the
+// same title and hook steps as the chat loop
+async fn finish_response(
+    state: AppState,
+    mut conversation: Conversation,
+    mut stream: rye::providers::ResponseStream,
+    model: Option<String>,
+    tx: mpsc::UnboundedSender<Result<Event, Infallible>>,
+) {
+    // Titling renames the file, which changes the ID
+    let busy_id = conversation.id.clone();
+    let mut full_response = String::new();
+    let mut error = None;
+
+    while let Some(result) = stream.next().await {
+        match result {
+            Ok(chunk) => {
+                let _ = tx.unbounded_send(Ok(Event::default().event("chunk").data(&chunk)));
+                full_response.push_str(&chunk);
+            }
+            Err(e) => {
+                error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    usage::record(state.llm_provider.as_ref(), Some(&busy_id));
+
+    if let Err(e) = save_response(&state, &mut conversation, &full_response, model).await {
+        error.get_or_insert(e.to_string());
+    }
+
+    let event = match error {
+        Some(e) => {
+            eprintln!("Warning: Response for {} failed: {}", conversation.id, e);
+            Event::default()
+                .event("error")
+                .data(json!({ "error": e }).to_string())
+        }
+        None => Event::default().event("done").data(
+            json!({ "id": current_id(&conversation), "title": conversation.title }).to_string(),
+        ),
+    };
+    let _ = tx.unbounded_send(Ok(event));
+
+    state.busy.lock().unwrap().remove(&busy_id);
+}
+
+fn current_id(conversation: &Conversation) -> String {
+    conversation.file_path.file_stem().map_or_else(
+        || conversation.id.clone(),
+        |stem| stem.to_string_lossy().into_owned(),
+    )
+}
+
+async fn save_response(
+    state: &AppState,
+    conversation: &mut Conversation,
+    full_response: &str,
+    model: Option<String>,
+) -> io::Result<()> {
+    if !full_response.is_empty() {
+        if let Some(model) = model {
+            conversation.note_model(&model).await?;
+        }
+        conversation.add_message("assistant", full_response).await?;
+        if let Err(e) = hooks::post_response(full_response, &conversation.file_path, false).await {
+            eprintln!("Warning: post_response hook: {}", e);
+        }
+    }
+
+    // Generate a title after the first exchange, or after the next one for
+    // continued conversations that never got one
+    if conversation.title.is_none() {
+        // The provider's error isn't Send, so it can't be held across the rename
+        let title = state
+            .llm_provider
+            .generate_conversation_title(&conversation.messages)
+            .await
+            .map_err(|e| e.to_string());
+        match title {
+            Ok(title) => conversation.set_title(title).await?,
+            Err(e) => eprintln!("Warning: Could not generate title: {}", e),
+        }
+    }
+
+    if let Err(e) = hooks::on_save(&conversation.file_path).await {
+        eprintln!("Warning: on_save hook: {}", e);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,470 @@
+use chrono::{DateTime, Local};
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+use rye::conversation::{Conversation, list_conversations};
+use rye::symbols::plain;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// `rye export-site <dir>` writes the conversation store as a static site
+// that works straight from disk, no server needed:
+//
+//   index.html                 conversations by month, newest first, with search
+//   tags.html                  conversations by tag
+//   conversations/<slug>.html  one page per conversation
+//   search.js                  the search index and the code that reads it
+//   style.css
+//
+// The search index is a script rather than JSON because browsers don't let
+// pages opened from `file://` fetch other files.
+
+/// Marks pages written by `rye export-site`, so a rerun knows it may clear
+/// out the old ones
+const GENERATOR: &str = r#"<meta name="generator" content="rye export-site">"#;
+
+/// Characters of each conversation's text kept in the search index
+const SEARCH_TEXT_CHARS: usize = 20_000;
+
+struct Page {
+    conversation: Conversation,
+    slug: String,
+    date: DateTime<Local>,
+}
+
+/// Writes the site for every stored conversation into `dir`
+pub fn run(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let pages_dir = dir.join("conversations");
+    if pages_dir.exists() {
+        // Rather than overwrite a directory of someone else's
+        let generated =
+            fs::read_to_string(dir.join("index.html")).is_ok_and(|index| index.contains(GENERATOR));
+        if !generated {
+            return Err(format!(
+                "{} already has a conversations directory rye didn't write, pick another directory",
+                dir.display()
+            )
+            .into());
+        }
+        // Pages of conversations renamed or removed since the last export
+        fs::remove_dir_all(&pages_dir)?;
+    }
+    fs::create_dir_all(&pages_dir)
+        .map_err(|e| format!("Could not create {}: {}", pages_dir.display(), e))?;
+
+    let mut pages = Vec::new();
+    let mut slugs = HashSet::new();
+    for info in list_conversations()? {
+        let conversation = match Conversation::load(&info.id) {
+            Ok(conversation) => conversation,
+            Err(e) => {
+                eprintln!("Warning: Skipping {}: {}", info.file_path.display(), e);
+                continue;
+            }
+        };
+        let date = fs::metadata(&info.file_path)
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::from)
+            .unwrap_or_else(|_| Local::now());
+        let base = slugify(&conversation.id);
+        let mut slug = base.clone();
+        let mut n = 2;
+        while !slugs.insert(slug.clone()) {
+            slug = format!("{}-{}", base, n);
+            n += 1;
+        }
+        pages.push(Page {
+            conversation,
+            slug,
+            date,
+        });
+    }
+    pages.sort_by_key(|page| std::cmp::Reverse(page.date));
+
+    let slugs: HashMap<&str, &str> = pages
+        .iter()
+        .map(|page| (page.conversation.id.as_str(), page.slug.as_str()))
+        .collect();
+    for page in &pages {
+        let path = pages_dir.join(format!("{}.html", page.slug));
+        fs::write(&path, conversation_page(page, &slugs))
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    }
+    fs::write(dir.join("index.html"), index_page(&pages))?;
+    fs::write(dir.join("tags.html"), tags_page(&pages))?;
+    fs::write(dir.join("search.js"), search_script(&pages))?;
+    fs::write(dir.join("style.css"), STYLE)?;
+
+    println!(
+        "{}",
+        plain(&format!(
+            "🌐 Wrote {} conversations to {}. Open {} in a browser.",
+            pages.len(),
+            dir.display(),
+            dir.join("index.html").display()
+        ))
+    );
+    Ok(())
+}
+
+// A file name for a conversation ID: lowercase letters, digits and dashes
+fn slugify(id: &str) -> String {
+    let mut slug = String::new();
+    for c in id.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "conversation".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Whether a link may point at `url`: the web, mail, or a relative path.
+// Schemes like `javascript:` could script the page.
+fn safe_url(url: &str) -> bool {
+    let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme)
+        .filter(|scheme| !scheme.contains(['/', '?', '#']));
+    match scheme {
+        Some(scheme) => ["http", "https", "mailto"]
+            .iter()
+            .any(|allowed| scheme.eq_ignore_ascii_case(allowed)),
+        None => true,
+    }
+}
+
+fn safe_link(url: CowStr) -> CowStr {
+    if safe_url(&url) { url } else { "#".into() }
+}
+
+// Markdown as HTML. HTML written in a message is shown as text, and links
+// and images only keep safe URLs, so a conversation about HTML can't break
+// or script the page.
+fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let parser = Parser::new_ext(markdown, options).map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: safe_link(dest_url),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: safe_link(dest_url),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser);
+    html
+}
+
+fn title(conversation: &Conversation) -> &str {
+    conversation.title.as_deref().unwrap_or(&conversation.id)
+}
+
+// The page around `body`; `root` leads from the page to the site's top
+fn layout(page_title: &str, root: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+{generator}
+<title>{title}</title>
+<link rel="stylesheet" href="{root}style.css">
+</head>
+<body>
+<nav><a href="{root}index.html">Conversations</a> <a href="{root}tags.html">Tags</a></nav>
+<main>
+{body}
+</main>
+</body>
+</html>
+"#,
+        generator = GENERATOR,
+        title = escape(page_title),
+        root = root,
+        body = body
+    )
+}
+
+fn tag_links(tags: &[String], root: &str) -> String {
+    tags.iter()
+        .map(|tag| {
+            format!(
+                r#"<a class="tag" href="{}tags.html#{}">{}</a>"#,
+                root,
+                slugify(tag),
+                escape(tag)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// A line of a conversation list
+fn list_item(page: &Page, root: &str) -> String {
+    format!(
+        r#"<li><time>{}</time> <a href="{}conversations/{}.html">{}</a> {}</li>"#,
+        page.date.format("%Y-%m-%d"),
+        root,
+        page.slug,
+        escape(title(&page.conversation)),
+        tag_links(&page.conversation.tags, root)
+    )
+}
+
+fn conversation_page(page: &Page, slugs: &HashMap<&str, &str>) -> String {
+    let conversation = &page.conversation;
+    let mut body = format!("<h1>{}</h1>\n", escape(title(conversation)));
+    body.push_str(&format!(
+        r#"<p class="meta"><time>{}</time> {}"#,
+        page.date.format("%Y-%m-%d %H:%M"),
+        tag_links(&conversation.tags, "../")
+    ));
+    if let Some(parent) = &conversation.parent {
+        match slugs.get(parent.as_str()) {
+            Some(slug) => body.push_str(&format!(
+                r#" · forked from <a href="{}.html">{}</a>"#,
+                slug,
+                escape(parent)
+            )),
+            None => body.push_str(&format!(" · forked from {}", escape(parent))),
+        }
+    }
+    body.push_str("</p>\n");
+
+    for (role, content) in &conversation.messages {
+        let (class, label) = if role == "user" {
+            ("user", "You")
+        } else {
+            ("assistant", "Assistant")
+        };
+        body.push_str(&format!(
+            "<section class=\"message {}\">\n<h2>{}</h2>\n{}</section>\n",
+            class,
+            label,
+            markdown_to_html(content)
+        ));
+    }
+    layout(title(conversation), "../", &body)
+}
+
+fn index_page(pages: &[Page]) -> String {
+    let mut body = String::from(
+        r#"<h1>Conversations</h1>
+<input id="search" type="search" placeholder="Search titles, tags and messages" autofocus>
+<ul id="results" class="conversations" hidden></ul>
+<div id="by-date">
+"#,
+    );
+    if pages.is_empty() {
+        body.push_str("<p>No conversations yet.</p>\n");
+    }
+    let mut month = String::new();
+    for page in pages {
+        let page_month = page.date.format("%B %Y").to_string();
+        if page_month != month {
+            if !month.is_empty() {
+                body.push_str("</ul>\n");
+            }
+            body.push_str(&format!(
+                "<h2>{}</h2>\n<ul class=\"conversations\">\n",
+                page_month
+            ));
+            month = page_month;
+        }
+        body.push_str(&list_item(page, ""));
+        body.push('\n');
+    }
+    if !month.is_empty() {
+        body.push_str("</ul>\n");
+    }
+    body.push_str("</div>\n<script src=\"search.js\"></script>\n");
+    layout("Conversations", "", &body)
+}
+
+fn tags_page(pages: &[Page]) -> String {
+    let mut tags: BTreeMap<String, Vec<&Page>> = BTreeMap::new();
+    let mut untagged = Vec::new();
+    for page in pages {
+        if page.conversation.tags.is_empty() {
+            untagged.push(page);
+        }
+        for tag in &page.conversation.tags {
+            tags.entry(tag.to_lowercase()).or_default().push(page);
+        }
+    }
+
+    let mut body = String::from("<h1>Tags</h1>\n");
+    if tags.is_empty() {
+        body.push_str("<p>No conversations are tagged yet.</p>\n");
+    } else {
+        let contents = tags
+            .iter()
+            .map(|(tag, pages)| {
+                format!(
+                    r##"<a class="tag" href="#{}">{}</a> {}"##,
+                    slugify(tag),
+                    escape(tag),
+                    pages.len()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        body.push_str(&format!("<p>{}</p>\n", contents));
+    }
+    let sections = tags
+        .iter()
+        .map(|(tag, pages)| (slugify(tag), escape(tag), pages))
+        .chain(
+            (!untagged.is_empty())
+                .then(|| ("untagged".to_string(), "Untagged".to_string(), &untagged)),
+        );
+    for (id, heading, pages) in sections {
+        body.push_str(&format!(
+            "<h2 id=\"{}\">{}</h2>\n<ul class=\"conversations\">\n",
+            id, heading
+        ));
+        for page in pages {
+            body.push_str(&list_item(page, ""));
+            body.push('\n');
+        }
+        body.push_str("</ul>\n");
+    }
+    layout("Tags", "", &body)
+}
+
+fn search_script(pages: &[Page]) -> String {
+    let entries: Vec<_> = pages
+        .iter()
+        .map(|page| {
+            let text: String = page
+                .conversation
+                .messages
+                .iter()
+                .map(|(_, content)| content.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")
+                .chars()
+                .take(SEARCH_TEXT_CHARS)
+                .collect();
+            json!({
+                "title": title(&page.conversation),
+                "url": format!("conversations/{}.html", page.slug),
+                "date": page.date.format("%Y-%m-%d").to_string(),
+                "tags": page.conversation.tags,
+                "text": text,
+            })
+        })
+        .collect();
+    // `</` can't appear in the script, in case it's ever inlined
+    let index = serde_json::to_string(&entries)
+        .unwrap_or_else(|_| "[]".to_string())
+        .replace("</", "<\\/");
+    format!("const RYE_INDEX = {};\n{}", index, SEARCH)
+}
+
+const SEARCH: &str = r#"
+(function () {
+  const input = document.getElementById("search");
+  const results = document.getElementById("results");
+  const byDate = document.getElementById("by-date");
+  const haystacks = RYE_INDEX.map(function (entry) {
+    return [entry.title, entry.tags.join(" "), entry.text].join("\n").toLowerCase();
+  });
+
+  function snippet(entry, word) {
+    const at = entry.text.toLowerCase().indexOf(word);
+    if (at < 0) return "";
+    const start = Math.max(0, at - 60);
+    return (start > 0 ? "…" : "") + entry.text.slice(start, at + 100).replace(/\s+/g, " ") + "…";
+  }
+
+  function search() {
+    const words = input.value.toLowerCase().split(/\s+/).filter(Boolean);
+    results.replaceChildren();
+    results.hidden = words.length === 0;
+    byDate.hidden = words.length > 0;
+    if (words.length === 0) return;
+    let found = 0;
+    RYE_INDEX.forEach(function (entry, i) {
+      if (found >= 100 || !words.every(function (word) { return haystacks[i].includes(word); })) return;
+      found++;
+      const item = document.createElement("li");
+      const time = document.createElement("time");
+      time.textContent = entry.date;
+      const link = document.createElement("a");
+      link.href = entry.url;
+      link.textContent = entry.title;
+      const context = document.createElement("p");
+      context.className = "snippet";
+      context.textContent = snippet(entry, words[0]);
+      item.append(time, " ", link, context);
+      results.append(item);
+    });
+    if (found === 0) {
+      const item = document.createElement("li");
+      item.textContent = "Nothing matches.";
+      results.append(item);
+    }
+  }
+
+  input.addEventListener("input", search);
+  search();
+})();
+"#;
+
+const STYLE: &str = r#"body { margin: 0; font: 16px/1.55 system-ui, sans-serif; color: #222; background: #fdfdfb; }
+nav { padding: 0.6em 1.2em; background: #6b3e26; }
+nav a { color: #fff; margin-right: 1em; text-decoration: none; font-weight: 600; }
+main { max-width: 52em; margin: 0 auto; padding: 1em 1.2em 3em; }
+a { color: #8a4b2a; }
+time, .meta, .snippet { color: #777; font-size: 0.9em; }
+.snippet { margin: 0.2em 0 0.6em; }
+ul.conversations { list-style: none; padding: 0; }
+ul.conversations li { margin: 0.3em 0; }
+.tag { display: inline-block; padding: 0 0.5em; border-radius: 0.8em; background: #efe4dc; color: #6b3e26; font-size: 0.8em; text-decoration: none; }
+#search { width: 100%; box-sizing: border-box; padding: 0.5em; font-size: 1em; }
+.message { border-top: 1px solid #e4ddd6; padding-top: 0.4em; }
+.message h2 { font-size: 0.95em; text-transform: uppercase; letter-spacing: 0.05em; color: #8a4b2a; }
+.message.user h2 { color: #2a5d8a; }
+pre { overflow-x: auto; padding: 0.8em; background: #f3f0ec; border-radius: 4px; }
+code { font-family: ui-monospace, monospace; font-size: 0.9em; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #d8d0c8; padding: 0.3em 0.6em; }
+blockquote { margin-left: 0; padding-left: 1em; border-left: 3px solid #e4ddd6; color: #555; }
+"#;
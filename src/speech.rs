@@ -0,0 +1,180 @@
+use crate::{config, hooks};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+// Reads responses aloud through the `speech.command` TTS engine. A `Speaker`
+// splits streamed text into sentences as they complete, and one background
+// task speaks them in order, so speech keeps up with the stream instead of
+// waiting for the whole response. Code blocks are skipped.
+
+static ENABLED: OnceLock<AtomicBool> = OnceLock::new();
+static QUEUE: OnceLock<mpsc::UnboundedSender<String>> = OnceLock::new();
+// While the TUI owns the screen, warnings wait here for its status line
+// instead of drawing over it
+static HOLD_WARNINGS: AtomicBool = AtomicBool::new(false);
+static WARNING: Mutex<Option<String>> = Mutex::new(None);
+
+fn enabled_flag() -> &'static AtomicBool {
+    ENABLED.get_or_init(|| AtomicBool::new(config::get().speech.enabled))
+}
+
+/// Whether responses are spoken, `speech.enabled` until toggled
+pub fn enabled() -> bool {
+    enabled_flag().load(Ordering::Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    enabled_flag().store(enabled, Ordering::Relaxed);
+}
+
+fn queue() -> &'static mpsc::UnboundedSender<String> {
+    QUEUE.get_or_init(|| {
+        let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+        tokio::spawn(async move {
+            while let Some(sentence) = rx.recv().await {
+                // Sentences queued before speech was turned off are dropped
+                if !enabled() {
+                    continue;
+                }
+                if let Err(e) = speak(&sentence).await {
+                    warn(format!(
+                        "Warning: Speech turned off, `speech.command` failed: {}",
+                        e
+                    ));
+                    set_enabled(false);
+                }
+            }
+        });
+        tx
+    })
+}
+
+/// Keeps warnings for `take_warning()` rather than printing them, for
+/// callers that draw the whole screen
+pub fn hold_warnings(hold: bool) {
+    HOLD_WARNINGS.store(hold, Ordering::Relaxed);
+}
+
+/// The latest warning held since the last call
+pub fn take_warning() -> Option<String> {
+    WARNING.lock().unwrap().take()
+}
+
+fn warn(warning: String) {
+    if HOLD_WARNINGS.load(Ordering::Relaxed) {
+        *WARNING.lock().unwrap() = Some(warning);
+    } else {
+        eprintln!("{}", warning);
+    }
+}
+
+async fn speak(text: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let command = &config::get().speech.command;
+    let mut child = hooks::shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        // Exiting rye stops the sentence being spoken
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{}: {}", output.status, stderr.trim()).into());
+    }
+    debug!(chars = text.len(), "Spoke sentence");
+    Ok(())
+}
+
+/// Collects a streaming response and queues each sentence once complete
+#[derive(Default)]
+pub struct Speaker {
+    line: String,
+    in_code_block: bool,
+}
+
+impl Speaker {
+    /// A speaker if speech is on, `None` otherwise
+    pub fn new() -> Option<Self> {
+        enabled().then(Self::default)
+    }
+
+    pub fn push(&mut self, chunk: &str) {
+        for ch in chunk.chars() {
+            if ch == '\n' {
+                let line = std::mem::take(&mut self.line);
+                if line.trim_start().starts_with("```") {
+                    self.in_code_block = !self.in_code_block;
+                } else if !self.in_code_block {
+                    say(&line);
+                }
+                continue;
+            }
+
+            // A list number like "1." isn't a sentence
+            let sentence_ended = ch.is_whitespace()
+                && self.line.ends_with(['.', '!', '?', ':'])
+                && self.line.chars().any(char::is_alphabetic)
+                && !self.in_code_block
+                && !self.line.trim_start().starts_with('`');
+            if sentence_ended {
+                say(&std::mem::take(&mut self.line));
+            } else {
+                self.line.push(ch);
+            }
+        }
+    }
+
+    /// Queues whatever is left once the response has ended
+    pub fn finish(mut self) {
+        if !self.in_code_block {
+            say(&std::mem::take(&mut self.line));
+        }
+    }
+}
+
+fn say(markdown: &str) {
+    let text = plain_text(markdown);
+    if text.chars().any(char::is_alphanumeric) {
+        let _ = queue().send(text);
+    }
+}
+
+// Drops markdown syntax that would otherwise be read out
+fn plain_text(markdown: &str) -> String {
+    let line = markdown
+        .trim()
+        .trim_start_matches(['#', '>', '-', '*', '+'])
+        .trim_start();
+
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' | '`' | '~' | '_' => {}
+            '|' => text.push_str(", "),
+            // [label](url) keeps only the label
+            ']' if chars.peek() == Some(&'(') => {
+                for ch in chars.by_ref() {
+                    if ch == ')' {
+                        break;
+                    }
+                }
+            }
+            '[' => {}
+            '!' if chars.peek() == Some(&'[') => {}
+            _ => text.push(ch),
+        }
+    }
+
+    text.trim().to_string()
+}
@@ -0,0 +1,92 @@
+use crate::symbols;
+use crossterm::{
+    cursor, execute,
+    style::{Attribute, Print, SetAttribute},
+    terminal,
+};
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+const FRAMES: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const ASCII_FRAMES: [&str; 4] = ["|", "/", "-", "\\"];
+
+/// Animated "waiting" line shown until the first token arrives
+pub struct Spinner {
+    started: Instant,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Spinner {
+    pub fn start(message: &str) -> Self {
+        let started = Instant::now();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        // Screen readers get the message once, as a line of its own
+        if symbols::screen_reader() {
+            println!("{}", message);
+            return Self {
+                started,
+                stop,
+                handle: tokio::spawn(async {}),
+            };
+        }
+
+        let message = message.to_string();
+        let stop_flag = Arc::clone(&stop);
+        let handle = tokio::spawn(async move {
+            let frames: &[&str] = if symbols::ascii() {
+                &ASCII_FRAMES
+            } else {
+                &FRAMES
+            };
+            let mut frame = 0;
+            while !stop_flag.load(Ordering::Relaxed) {
+                let mut stdout = io::stdout();
+                let _ = execute!(
+                    stdout,
+                    cursor::MoveToColumn(0),
+                    SetAttribute(Attribute::Dim),
+                    Print(format!(
+                        "{} {} {:.1}s",
+                        frames[frame % frames.len()],
+                        message,
+                        started.elapsed().as_secs_f64()
+                    )),
+                    SetAttribute(Attribute::Reset),
+                );
+                let _ = stdout.flush();
+
+                frame += 1;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        });
+
+        Self {
+            started,
+            stop,
+            handle,
+        }
+    }
+
+    /// Stops the animation, clears its line, and returns how long it ran
+    pub async fn stop(self) -> Duration {
+        let elapsed = self.started.elapsed();
+
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.await;
+        if symbols::screen_reader() {
+            return elapsed;
+        }
+        let _ = execute!(
+            io::stdout(),
+            cursor::MoveToColumn(0),
+            terminal::Clear(terminal::ClearType::CurrentLine)
+        );
+
+        elapsed
+    }
+}
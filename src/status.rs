@@ -0,0 +1,141 @@
+use crate::logging;
+use crossterm::{
+    execute,
+    style::{Attribute, Print, SetAttribute},
+    terminal,
+};
+use rye::conversation::Conversation;
+use rye::project;
+use rye::providers::{LLMProvider, Latency, Usage};
+use rye::render::truncate_to_width;
+use rye::symbols::plain;
+use std::io;
+use std::time::Duration;
+
+// Running totals for the status line shown above the prompt
+#[derive(Default)]
+pub struct SessionStatus {
+    last_usage: Usage,
+    total_cost: f64,
+}
+
+impl SessionStatus {
+    // Call after each completed response
+    pub fn record(&mut self, llm_provider: &dyn LLMProvider) {
+        let usage = llm_provider.last_usage();
+        self.total_cost += llm_provider.cost(&usage);
+        self.last_usage = usage;
+    }
+
+    pub fn line(&self, llm_provider: &dyn LLMProvider, conversation: &Conversation) -> String {
+        let title = conversation.title.as_deref().unwrap_or(&conversation.id);
+
+        // The last response's input plus output is what the next turn builds
+        // on; a turn with tool rounds counts each round's input
+        let mut line = format!(
+            "{} · {} · {}/{} tokens · ${:.4}",
+            llm_provider.model(),
+            title,
+            format_tokens(self.last_usage.total().into()),
+            format_tokens(llm_provider.context_window().into()),
+            self.total_cost
+        );
+        if let Some(name) = project_context_name() {
+            line.push_str(&format!(" · {}", name));
+        }
+        plain(&line).into_owned()
+    }
+
+    pub fn print(
+        &self,
+        llm_provider: &dyn LLMProvider,
+        conversation: &Conversation,
+    ) -> io::Result<()> {
+        // Keep it to a single row so the prompt redraw logic can count lines
+        let width = terminal::size().map(|(w, _)| w as usize).unwrap_or(80);
+        let line = truncate_to_width(
+            &self.line(llm_provider, conversation),
+            width.saturating_sub(1),
+        );
+
+        execute!(
+            io::stdout(),
+            SetAttribute(Attribute::Dim),
+            Print(line),
+            SetAttribute(Attribute::Reset),
+            Print("\n"),
+        )
+    }
+}
+
+/// The project context file in use, as shown to the user, e.g. `RYE.md`
+pub fn project_context_name() -> Option<String> {
+    let path = project::context_file()?;
+    let name = path.file_name()?.to_string_lossy();
+    match path.parent().and_then(|dir| dir.file_name()) {
+        Some(dir) if dir == ".rye" => Some(format!(".rye/{}", name)),
+        _ => Some(name.into_owned()),
+    }
+}
+
+// Dim "4.2s · 512 tokens · 121 tok/s" footer printed under each response
+pub fn print_response_footer(elapsed: Duration, llm_provider: &dyn LLMProvider) -> io::Result<()> {
+    execute!(
+        io::stdout(),
+        SetAttribute(Attribute::Dim),
+        Print(format!("  {}", response_footer(elapsed, llm_provider))),
+        SetAttribute(Attribute::Reset),
+        Print("\n"),
+    )
+}
+
+/// The footer of the provider's last response, with its latency in verbose
+/// mode
+pub fn response_footer(elapsed: Duration, llm_provider: &dyn LLMProvider) -> String {
+    let usage = llm_provider.last_usage();
+    let seconds = elapsed.as_secs_f64();
+    let throughput = if seconds > 0.0 {
+        usage.output_tokens as f64 / seconds
+    } else {
+        0.0
+    };
+
+    let mut footer = format!(
+        "{:.1}s · {} tokens · {:.0} tok/s",
+        seconds,
+        format_tokens(usage.output_tokens.into()),
+        throughput
+    );
+    if logging::verbose()
+        && let Some(latency) = llm_provider.last_latency()
+    {
+        footer.push_str(&format!(" · {}", format_latency(&latency)));
+    }
+    plain(&footer).into_owned()
+}
+
+/// Time to first token, jitter and retries, e.g. `first token 0.8s · jitter
+/// 12ms · 1 retry`
+pub fn format_latency(latency: &Latency) -> String {
+    let mut parts = Vec::new();
+    if let Some(first) = latency.first_token_ms {
+        parts.push(format!("first token {:.2}s", first as f64 / 1000.0));
+    }
+    parts.push(format!("jitter {:.0}ms", latency.jitter_ms));
+    match latency.retries {
+        0 => {}
+        1 => parts.push("1 retry".to_string()),
+        retries => parts.push(format!("{} retries", retries)),
+    }
+    parts.join(" · ")
+}
+
+/// A token count as shown to the user, e.g. `12.3k`
+pub fn format_tokens(tokens: u64) -> String {
+    if tokens >= 1000 {
+        let formatted = format!("{:.1}", tokens as f64 / 1000.0);
+        format!("{}k", formatted.trim_end_matches(".0"))
+    } else {
+        tokens.to_string()
+    }
+}
@@ -1,43 +1,381 @@
-use crate::render::render_markdown;
+use crate::config;
+use crate::inline_image::{display_image, parse_image_line};
+use crate::providers::ResponseStream;
+use crate::render::{
+    COLLAPSED_TAIL_LINES, Linear, collapsed_marker, display_rows, display_width, format_code,
+    format_markdown, render_code_block, render_markdown, terminal_width,
+};
+use crate::speech::Speaker;
+use crate::spinner::Spinner;
+use crate::symbols;
+use crate::tee;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute, Stylize},
+    terminal,
+};
 use futures::StreamExt;
-use std::io::{self, Write};
-use std::pin::Pin;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::oneshot;
 
+// The block still being streamed, drawn in place and redrawn as text arrives
+// so it shows up immediately but ends up rendered like the final block
+struct LiveBlock {
+    // Terminal rows the preview takes up, more than its lines when a wide
+    // line wraps
+    rows: usize,
+    enabled: bool,
+}
+
+impl LiveBlock {
+    fn new() -> Self {
+        Self {
+            rows: 0,
+            enabled: io::stdout().is_terminal() && !symbols::screen_reader(),
+        }
+    }
+
+    fn draw(&mut self, text: &str, code_block: Option<usize>) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if text.is_empty() {
+            // Between blocks only the typing indicator shows
+            return self.show("  ");
+        }
+
+        // Lines that scrolled off can't be rewritten, so a block taller than
+        // the screen keeps its last preview until the final render
+        let height = terminal::size().map(|(_, h)| h as usize).unwrap_or(24);
+        if text.lines().count() + 1 >= height {
+            return Ok(());
+        }
+
+        // One column short so the typing indicator never wraps the last line
+        let width = terminal_width().saturating_sub(1);
+        let rendered = match code_block {
+            Some(index) => format_code(text, index, width),
+            None => format_markdown(text, width),
+        };
+        let rows: usize = rendered
+            .lines()
+            .map(|line| display_rows(line, terminal_width()))
+            .sum();
+        if rows + 1 >= height {
+            return Ok(());
+        }
+
+        self.show(&rendered)
+    }
+
+    fn show(&mut self, rendered: &str) -> io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let lines: Vec<&str> = rendered.lines().collect();
+        self.clear()?;
+        let mut stdout = io::stdout();
+        for (i, line) in lines.iter().enumerate() {
+            queue!(stdout, Print(line))?;
+            // A blinking block after the last line means the model is still generating
+            if i + 1 == lines.len() {
+                queue!(stdout, Print(symbols::pick("▍", "_").slow_blink()))?;
+            }
+            queue!(stdout, Print("\n"))?;
+        }
+        stdout.flush()?;
+        // The typing indicator takes a column after the last line
+        let width = terminal_width().max(1);
+        self.rows = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let indicator = usize::from(i + 1 == lines.len());
+                (display_width(line) + indicator).div_ceil(width).max(1)
+            })
+            .sum();
+
+        Ok(())
+    }
+
+    // Erases the preview so the final render can take its place
+    fn clear(&mut self) -> io::Result<()> {
+        if self.rows > 0 {
+            execute!(
+                io::stdout(),
+                cursor::MoveUp(self.rows as u16),
+                cursor::MoveToColumn(0),
+                terminal::Clear(terminal::ClearType::FromCursorDown)
+            )?;
+            self.rows = 0;
+        }
+
+        Ok(())
+    }
+}
+
+// Watches for Esc on its own thread while a response streams. Meanwhile the
+// terminal neither echoes nor waits for whole lines, but unlike raw mode it
+// still starts output lines at the left edge and turns Ctrl-C into SIGINT.
+struct StopKey {
+    pressed: oneshot::Receiver<()>,
+    was_pressed: bool,
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    #[cfg(unix)]
+    saved: libc::termios,
+}
+
+impl StopKey {
+    fn start() -> Option<Self> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+        #[cfg(unix)]
+        let saved = cbreak()?;
+        #[cfg(not(unix))]
+        terminal::enable_raw_mode().ok()?;
+
+        let (tx, pressed) = oneshot::channel();
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let done = Arc::clone(&done);
+            move || {
+                while !done.load(Ordering::Relaxed) {
+                    match event::poll(Duration::from_millis(50)) {
+                        Ok(true) => {
+                            // Other keys are dropped rather than typed ahead
+                            if let Ok(Event::Key(key)) = event::read()
+                                && key.kind == KeyEventKind::Press
+                                && (key.code == KeyCode::Esc
+                                    || (key.code == KeyCode::Char('c')
+                                        && key.modifiers.contains(KeyModifiers::CONTROL)))
+                            {
+                                let _ = tx.send(());
+                                return;
+                            }
+                        }
+                        Ok(false) => {}
+                        Err(_) => return,
+                    }
+                }
+            }
+        });
+
+        Some(Self {
+            pressed,
+            was_pressed: false,
+            done,
+            thread: Some(thread),
+            #[cfg(unix)]
+            saved,
+        })
+    }
+
+    // Completes when Esc is pressed, and never if the watcher gave up
+    async fn pressed(&mut self) {
+        if (&mut self.pressed).await.is_ok() {
+            self.was_pressed = true;
+        } else {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl Drop for StopKey {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        // SAFETY: restores the settings tcgetattr filled in
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved);
+        }
+        #[cfg(not(unix))]
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// Turns off line buffering and echo on the terminal, returning the settings
+// to restore
+#[cfg(unix)]
+fn cbreak() -> Option<libc::termios> {
+    // SAFETY: termios is plain data, filled in by tcgetattr before it's used
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+        return None;
+    }
+    let saved = termios;
+    termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+    termios.c_cc[libc::VMIN] = 1;
+    termios.c_cc[libc::VTIME] = 0;
+    // SAFETY: termios is a valid, initialized termios
+    (unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } == 0).then_some(saved)
+}
+
+// The next chunk, or `None` once the stream ends or the stop key is pressed
+async fn next_chunk(
+    stream: &mut ResponseStream,
+    stop: Option<&mut StopKey>,
+) -> Option<Result<String, Box<dyn std::error::Error + Send>>> {
+    match stop {
+        Some(stop) => tokio::select! {
+            chunk = stream.next() => chunk,
+            _ = stop.pressed() => None,
+        },
+        None => stream.next().await,
+    }
+}
+
+/// Renders a response stream to stdout as it arrives and returns the full text.
+/// `spinner`, if given, keeps running until the first chunk.
 pub async fn stream_and_render_response(
-    mut stream: Pin<Box<dyn futures::Stream<Item = Result<String, Box<dyn std::error::Error + Send>>> + Send>>,
+    stream: ResponseStream,
+    spinner: Option<Spinner>,
 ) -> Result<String, Box<dyn std::error::Error>> {
+    let (full_response, _) = render_stream(stream, spinner, None).await?;
+    Ok(full_response)
+}
+
+/// Like `stream_and_render_response`, but pressing Esc stops the response
+/// where it is. Returns the text so far and whether it was stopped.
+pub async fn stream_and_render_stoppable(
+    stream: ResponseStream,
+    spinner: Option<Spinner>,
+) -> Result<(String, bool), Box<dyn std::error::Error>> {
+    render_stream(stream, spinner, StopKey::start()).await
+}
+
+async fn render_stream(
+    stream: ResponseStream,
+    mut spinner: Option<Spinner>,
+    stop: Option<StopKey>,
+) -> Result<(String, bool), Box<dyn std::error::Error>> {
+    let rendered = render_chunks(stream, &mut spinner, stop).await;
+    // Still running when rendering failed before the first chunk
+    if let Some(spinner) = spinner {
+        spinner.stop().await;
+    }
+    rendered
+}
+
+async fn render_chunks(
+    mut stream: ResponseStream,
+    spinner: &mut Option<Spinner>,
+    mut stop: Option<StopKey>,
+) -> Result<(String, bool), Box<dyn std::error::Error>> {
     let mut full_response = String::new();
     let mut current_line = String::new();
     let mut buffer = String::new();
     let mut in_code_block = false;
+    let mut in_table = false;
+    // In a list or blockquote, which blank lines may only pause; they're
+    // held back until the next line shows whether it goes on
+    let mut in_container = false;
+    let mut blank_lines = 0;
+    let mut code_blocks = 0;
+    let mut live = LiveBlock::new();
+    // Screen readers get each line once it's complete, and all of them
+    let mut linear = symbols::screen_reader().then(Linear::default);
+    let collapse_lines = if linear.is_some() {
+        0
+    } else {
+        config::get().output.collapse_lines
+    };
+    let mut lines_streamed = 0;
+    // Text past the collapse limit, shown only by its last lines at the end
+    let mut held: Option<String> = None;
+    let mut speaker = Speaker::new();
 
     // Stream and render with proper buffering for markdown elements
-    while let Some(result) = stream.next().await {
+    while let Some(result) = next_chunk(&mut stream, stop.as_mut()).await {
         match result {
             Ok(chunk) => {
                 if !chunk.is_empty() {
+                    if let Some(spinner) = spinner.take() {
+                        let time_to_first_token = spinner.stop().await;
+                        execute!(
+                            io::stdout(),
+                            SetAttribute(Attribute::Dim),
+                            Print(symbols::plain(&format!(
+                                "  ⏱ {:.1}s to first token\n\n",
+                                time_to_first_token.as_secs_f64()
+                            ))),
+                            SetAttribute(Attribute::Reset),
+                        )?;
+                    }
+
                     full_response.push_str(&chunk);
+                    tee::write(&chunk);
+                    if let Some(speaker) = speaker.as_mut() {
+                        speaker.push(&chunk);
+                    }
 
                     for ch in chunk.chars() {
+                        if let Some(held) = held.as_mut() {
+                            held.push(ch);
+                            continue;
+                        }
+
                         current_line.push(ch);
 
                         if ch == '\n' {
+                            if let Some(linear) = linear.as_mut() {
+                                if let Some(text) = linear.line(&current_line) {
+                                    println!("{}", text);
+                                }
+                                current_line.clear();
+                                continue;
+                            }
+                            live.clear()?;
                             let trimmed = current_line.trim();
 
+                            // A table ends at the first line that isn't a row - render it whole
+                            // so termimad can align the columns
+                            if in_table && !trimmed.contains('|') {
+                                render_markdown(&table_with_pipes(&buffer))?;
+                                buffer.clear();
+                                in_table = false;
+                            }
+
+                            // After a blank line, a list goes on with another item or an
+                            // indented line, and a blockquote with another quoted line;
+                            // anything else ends it
+                            if blank_lines > 0 && !trimmed.is_empty() && !in_code_block {
+                                let continues = is_list_item(trimmed)
+                                    || current_line.starts_with([' ', '\t'])
+                                    || trimmed.starts_with('>');
+                                if continues {
+                                    buffer.push_str(&"\n".repeat(blank_lines));
+                                    blank_lines = 0;
+                                } else {
+                                    flush_block(&mut buffer, &mut blank_lines)?;
+                                    in_container = false;
+                                }
+                            }
+
                             // Check if we're entering or exiting a code block
                             if trimmed.starts_with("```") {
                                 if in_code_block {
                                     // End of code block - render it
                                     buffer.push_str(&current_line);
-                                    render_markdown(&buffer)?;
+                                    code_blocks += 1;
+                                    render_code_block(&buffer, code_blocks)?;
                                     buffer.clear();
                                     in_code_block = false;
                                 } else {
                                     // Flush any pending buffer before code block
-                                    if !buffer.is_empty() {
-                                        render_markdown(&buffer)?;
-                                        buffer.clear();
-                                    }
+                                    flush_block(&mut buffer, &mut blank_lines)?;
+                                    in_container = false;
                                     // Start of code block
                                     buffer.push_str(&current_line);
                                     in_code_block = true;
@@ -45,23 +383,50 @@ pub async fn stream_and_render_response(
                             } else if in_code_block {
                                 // Inside code block - accumulate
                                 buffer.push_str(&current_line);
+                            } else if in_table {
+                                // Table row - accumulate
+                                buffer.push_str(&current_line);
+                            } else if is_table_row(trimmed)
+                                || (is_table_delimiter(trimmed) && last_line(&buffer).contains('|'))
+                            {
+                                // Table start - flush preceding text, then accumulate rows. A
+                                // table without outer pipes is only known by its delimiter
+                                // row, so its header row is taken back from the buffer.
+                                let header = if is_table_row(trimmed) {
+                                    String::new()
+                                } else {
+                                    take_last_line(&mut buffer)
+                                };
+                                flush_block(&mut buffer, &mut blank_lines)?;
+                                in_container = false;
+                                buffer.push_str(&header);
+                                buffer.push_str(&current_line);
+                                in_table = true;
+                            } else if let Some((alt, source)) = parse_image_line(trimmed) {
+                                // Image on its own line - flush buffer, then show it inline
+                                flush_block(&mut buffer, &mut blank_lines)?;
+                                in_container = false;
+                                display_image(alt, source)?;
                             } else if trimmed.is_empty() {
-                                // Empty line - flush buffer and render
-                                if !buffer.is_empty() {
-                                    render_markdown(&buffer)?;
-                                    buffer.clear();
+                                if in_container {
+                                    // Maybe a pause in a list or blockquote - wait and see
+                                    blank_lines += 1;
+                                } else {
+                                    // Empty line - flush buffer and render
+                                    flush_block(&mut buffer, &mut blank_lines)?;
+                                    println!();
                                 }
-                                println!();
-                            } else if trimmed.starts_with('#') {
+                            } else if trimmed.starts_with('#')
+                                && !(in_container && current_line.starts_with([' ', '\t']))
+                            {
                                 // Header - flush buffer, then render header alone
-                                if !buffer.is_empty() {
-                                    render_markdown(&buffer)?;
-                                    buffer.clear();
-                                }
+                                flush_block(&mut buffer, &mut blank_lines)?;
+                                in_container = false;
                                 render_markdown(&current_line)?;
-                            } else if is_list_item(trimmed) {
-                                // List item - accumulate
+                            } else if is_list_item(trimmed) || trimmed.starts_with('>') {
+                                // List item or quoted line - accumulate the whole list or quote
                                 buffer.push_str(&current_line);
+                                in_container = true;
                             } else {
                                 // Regular text - accumulate
                                 buffer.push_str(&current_line);
@@ -69,33 +434,177 @@ pub async fn stream_and_render_response(
 
                             current_line.clear();
                             io::stdout().flush()?;
+
+                            // Past the limit, hold the rest back at the next block boundary
+                            lines_streamed += 1;
+                            if collapse_lines > 0
+                                && lines_streamed >= collapse_lines
+                                && buffer.is_empty()
+                                && !in_code_block
+                                && !in_table
+                            {
+                                held = Some(String::new());
+                            }
                         }
                     }
+
+                    if let Some(held) = &held {
+                        let progress = symbols::plain(&format!(
+                            "  … long response collapsed, {} more lines so far\n",
+                            held.lines().count()
+                        ))
+                        .into_owned();
+                        live.show(&progress.dim().to_string())?;
+                        continue;
+                    }
+
+                    // A half-streamed closing fence would show up as code
+                    let partial = if in_code_block && current_line.trim_start().starts_with('`') {
+                        ""
+                    } else {
+                        current_line.as_str()
+                    };
+                    let pending = if in_table {
+                        table_with_pipes(&format!("{}{}", buffer, partial))
+                    } else {
+                        format!("{}{}", buffer, partial)
+                    };
+                    live.draw(&pending, in_code_block.then_some(code_blocks + 1))?;
                 }
             }
             Err(e) => {
+                if let Some(spinner) = spinner.take() {
+                    spinner.stop().await;
+                }
+                live.clear()?;
                 eprintln!("\nStream error: {}", e);
                 break;
             }
         }
     }
 
+    // Dropping the watcher gives the terminal back its line editing
+    let stopped = stop.take().is_some_and(|stop| stop.was_pressed);
+    if let Some(spinner) = spinner.take() {
+        spinner.stop().await;
+    }
+
     // Render any remaining content
+    live.clear()?;
     if !current_line.is_empty() {
-        buffer.push_str(&current_line);
+        match linear.as_mut() {
+            Some(linear) => {
+                if let Some(text) = linear.line(&current_line) {
+                    println!("{}", text);
+                }
+            }
+            None => buffer.push_str(&current_line),
+        }
     }
-    if !buffer.is_empty() {
+    if in_code_block {
+        render_code_block(&buffer, code_blocks + 1)?;
+    } else if in_table {
+        render_markdown(&table_with_pipes(&buffer))?;
+    } else if !buffer.is_empty() {
         render_markdown(&buffer)?;
     }
+    if let Some(held) = held {
+        render_held_tail(&held)?;
+    }
+    if let Some(speaker) = speaker {
+        speaker.finish();
+    }
+    tee::finish();
 
-    Ok(full_response)
+    Ok((full_response, stopped))
+}
+
+// Shows only the end of the text held back past the collapse limit
+fn render_held_tail(held: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let rendered = format_markdown(held, terminal_width());
+    let lines: Vec<&str> = rendered.lines().collect();
+
+    let hidden = lines.len().saturating_sub(COLLAPSED_TAIL_LINES);
+    if hidden > COLLAPSED_TAIL_LINES {
+        println!("{}", collapsed_marker(hidden));
+        for line in &lines[hidden..] {
+            println!("{}", line);
+        }
+    } else {
+        println!("{}", rendered);
+    }
+
+    Ok(())
+}
+
+// Renders the buffered block, then the blank lines held back after it
+fn flush_block(
+    buffer: &mut String,
+    blank_lines: &mut usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !buffer.is_empty() {
+        render_markdown(buffer)?;
+        buffer.clear();
+    }
+    for _ in 0..std::mem::take(blank_lines) {
+        println!();
+    }
+    Ok(())
+}
+
+fn last_line(buffer: &str) -> &str {
+    buffer
+        .trim_end_matches('\n')
+        .lines()
+        .last()
+        .unwrap_or_default()
+}
+
+// Removes and returns the buffer's last line
+fn take_last_line(buffer: &mut String) -> String {
+    let start = buffer
+        .trim_end_matches('\n')
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    buffer.split_off(start)
+}
+
+fn is_table_row(trimmed: &str) -> bool {
+    trimmed.starts_with('|')
+}
+
+// The `---|:---:` row under a table's header
+fn is_table_delimiter(trimmed: &str) -> bool {
+    trimmed.contains('|')
+        && trimmed.contains('-')
+        && trimmed
+            .chars()
+            .all(|c| matches!(c, '|' | '-' | ':' | ' ' | '\t'))
+}
+
+// termimad only knows rows that start with a pipe, which GFM tables may leave out
+fn table_with_pipes(table: &str) -> String {
+    table
+        .lines()
+        .map(|line| {
+            let row = line.trim();
+            match (row.starts_with('|'), row.ends_with('|')) {
+                (true, true) => format!("{}\n", row),
+                (true, false) => format!("{}|\n", row),
+                (false, true) => format!("|{}\n", row),
+                (false, false) => format!("|{}|\n", row),
+            }
+        })
+        .collect()
 }
 
+// `- `, `* `, `+ `, `1. ` or `1) ` and not, say, `**bold**` or `---`
 fn is_list_item(trimmed: &str) -> bool {
-    trimmed.starts_with('-')
-        || trimmed.starts_with('*')
-        || trimmed.starts_with('+')
-        || (trimmed.len() > 2
-            && trimmed.chars().next().unwrap().is_numeric()
-            && trimmed.chars().nth(1) == Some('.'))
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    let marker = if digits > 0 {
+        trimmed[digits..].strip_prefix(['.', ')'])
+    } else {
+        trimmed.strip_prefix(['-', '*', '+'])
+    };
+    marker.is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
 }
@@ -0,0 +1,135 @@
+use crate::config;
+use crate::i18n::t;
+use ratatui::symbols::border;
+use std::borrow::Cow;
+
+// With `output.ascii` (or `--ascii`), rye's own output sticks to plain ASCII
+// for limited fonts, serial consoles and screen readers: `plain()` drops
+// emoji and swaps box drawing, arrows and typographic punctuation for ASCII
+// look-alikes, and the renderer, spinner and full-screen views use their
+// ASCII variants. Letters outside ASCII, like accents or CJK, are kept.
+//
+// `output.screen_reader` (or `--screen-reader`) goes further for VoiceOver,
+// Orca and the like: on top of ASCII, output is only ever appended, never
+// redrawn. Spinners and in-place previews are off, responses come line by
+// line as plain text (see `render::Linear`), and each turn is announced as
+// "You say:" or "Assistant says:" instead of separator lines.
+
+/// Whether output sticks to ASCII
+pub fn ascii() -> bool {
+    let output = &config::get().output;
+    output.ascii || output.screen_reader
+}
+
+/// Whether output is linear plain text for screen readers
+pub fn screen_reader() -> bool {
+    config::get().output.screen_reader
+}
+
+/// The prompt in front of the user's input
+pub fn prompt() -> Cow<'static, str> {
+    if screen_reader() {
+        Cow::Owned(format!("{} ", t("You say:")))
+    } else {
+        plain("➤ ")
+    }
+}
+
+/// Prints the banner that starts an assistant response
+pub fn print_assistant_header() {
+    if screen_reader() {
+        println!("\n{}", t("Assistant says:"));
+        return;
+    }
+    println!("\n{}", plain(&"═".repeat(60)));
+    println!("{}", plain(&format!("🤖 {}", t("Assistant Response:"))));
+    println!("{}", plain(&"═".repeat(60)));
+    println!();
+}
+
+// Full-screen view borders drawn with `+`, `-` and `|`
+const ASCII_BORDER: border::Set = border::Set {
+    top_left: "+",
+    top_right: "+",
+    bottom_left: "+",
+    bottom_right: "+",
+    vertical_left: "|",
+    vertical_right: "|",
+    horizontal_top: "-",
+    horizontal_bottom: "-",
+};
+
+/// Borders for the full-screen views
+pub fn border() -> border::Set<'static> {
+    if ascii() { ASCII_BORDER } else { border::PLAIN }
+}
+
+/// `fancy`, or `ascii` when output sticks to ASCII
+pub fn pick<'a>(fancy: &'a str, ascii: &'a str) -> &'a str {
+    if self::ascii() { ascii } else { fancy }
+}
+
+// The ASCII stand-in for a decorative character
+fn replacement(c: char) -> Option<&'static str> {
+    Some(match c {
+        '─' | '━' | '―' | '–' => "-",
+        '═' => "=",
+        '│' | '┃' | '▍' | '▌' | '▐' => "|",
+        '├' | '└' | '┌' | '┐' | '┘' | '┤' | '┬' | '┴' | '┼' | '╭' | '╮' | '╯' | '╰' => {
+            "+"
+        }
+        '➤' | '▶' | '►' | '›' => ">",
+        '◀' | '◄' | '‹' => "<",
+        '→' => "->",
+        '←' => "<-",
+        '↔' => "<->",
+        '⇒' => "=>",
+        '⇐' => "<=",
+        '↑' => "Up",
+        '↓' => "Down",
+        '·' => "-",
+        '•' => "-",
+        '…' => "...",
+        '—' => "--",
+        '‘' | '’' => "'",
+        '“' | '”' => "\"",
+        '≤' => "<=",
+        '≥' => ">=",
+        '≠' => "!=",
+        '≈' => "~",
+        '×' => "x",
+        '±' => "+/-",
+        _ => return None,
+    })
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(c,
+        '\u{1F000}'..='\u{1FAFF}'
+        | '\u{2600}'..='\u{27BF}'
+        | '\u{2B00}'..='\u{2BFF}'
+        | '\u{23E9}'..='\u{23FA}'
+        | '\u{FE0F}'
+        | '\u{200D}')
+}
+
+/// `text` as ASCII decoration when output sticks to ASCII: emoji are dropped
+/// along with the spaces after them, and box drawing, arrows and typographic
+/// punctuation are replaced
+pub fn plain(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() || !ascii() {
+        return Cow::Borrowed(text);
+    }
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(replacement) = replacement(c) {
+            output.push_str(replacement);
+        } else if is_emoji(c) {
+            while chars.next_if(|&c| c == ' ' || is_emoji(c)).is_some() {}
+        } else {
+            output.push(c);
+        }
+    }
+    Cow::Owned(output)
+}
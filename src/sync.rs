@@ -0,0 +1,572 @@
+use crate::config;
+use async_trait::async_trait;
+use futures::StreamExt;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode, Url};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+use std::sync::OnceLock;
+use std::time::SystemTime;
+use tracing::debug;
+
+// Syncs the conversations directory with a remote store. Conversations are
+// still read and written on disk; the store holds a copy: `pull()` fetches
+// conversations that are missing or changed remotely at startup, and every
+// write is pushed as it happens, so a machine without local state (such as a
+// container) sees and extends the same history. `.synced.json` in the
+// directory notes when each file was last pushed, so a file isn't pulled
+// straight back at the next startup for being newer remotely.
+
+/// A remote place conversation files are copied to. Names are paths relative to the
+/// conversations directory with `/` separators, such as `archive/Title.md`.
+#[async_trait]
+pub trait RemoteStore: Send + Sync {
+    /// Top-level conversation files with their modification times
+    async fn list(&self) -> io::Result<Vec<(String, Option<SystemTime>)>>;
+
+    async fn read(&self, name: &str) -> io::Result<String>;
+
+    async fn write(&self, name: &str, content: &str) -> io::Result<()>;
+
+    /// Removing a file that doesn't exist succeeds
+    async fn remove(&self, name: &str) -> io::Result<()>;
+}
+
+static REMOTE: OnceLock<Option<Box<dyn RemoteStore>>> = OnceLock::new();
+
+/// The store configured in the `[store]` section, if any
+pub fn remote() -> Option<&'static dyn RemoteStore> {
+    REMOTE
+        .get_or_init(|| match open() {
+            Ok(store) => store,
+            Err(e) => {
+                eprintln!("Warning: Remote store disabled: {}", e);
+                None
+            }
+        })
+        .as_deref()
+}
+
+fn open() -> Result<Option<Box<dyn RemoteStore>>, String> {
+    let store = &config::get().store;
+    let Some(backend) = &store.backend else {
+        return Ok(None);
+    };
+
+    let url = store.url.as_deref().ok_or("store.url is not set")?;
+    // A trailing slash makes relative names resolve inside the base path
+    let url = Url::parse(&format!("{}/", url.trim_end_matches('/')))
+        .map_err(|e| format!("Invalid store.url: {}", e))?;
+
+    match backend.as_str() {
+        "webdav" => Ok(Some(Box::new(WebDavStore {
+            client: Client::new(),
+            url,
+            username: store.username.clone(),
+            password: store.password.clone(),
+        }))),
+        "s3" => {
+            let (Some(access_key), Some(secret_key)) = (&store.username, &store.password) else {
+                return Err("S3 needs store.username and store.password".to_string());
+            };
+            Ok(Some(Box::new(S3Store::new(
+                url,
+                access_key.clone(),
+                secret_key.clone(),
+                store.region.clone(),
+            )?)))
+        }
+        other => Err(format!(
+            "Unknown store.backend '{}', expected webdav or s3",
+            other
+        )),
+    }
+}
+
+// The store name of a file inside the conversations directory
+fn name(dir: &Path, path: &Path) -> Option<String> {
+    let parts: Option<Vec<&str>> = path
+        .strip_prefix(dir)
+        .ok()?
+        .components()
+        .map(|component| match component {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+    Some(parts?.join("/"))
+}
+
+const SYNCED_FILE: &str = ".synced.json";
+
+// When each file was last pushed, by store name
+fn synced(dir: &Path) -> HashMap<String, SystemTime> {
+    fs::read_to_string(dir.join(SYNCED_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// Notes that `name` was pushed just now, or forgets it with `None`
+fn mark_synced(dir: &Path, name: &str, time: Option<SystemTime>) {
+    let mut synced = synced(dir);
+    match time {
+        Some(time) => synced.insert(name.to_string(), time),
+        None => synced.remove(name),
+    };
+    let result = serde_json::to_string(&synced)
+        .map_err(io::Error::other)
+        .and_then(|content| fs::write(dir.join(SYNCED_FILE), content));
+    if let Err(e) = result {
+        debug!(error = %e, "Could not note synced file");
+    }
+}
+
+/// Downloads remote conversations that are missing locally or changed since
+/// the local copy was written
+pub async fn pull(dir: &Path) {
+    let Some(store) = remote() else {
+        return;
+    };
+
+    let entries = match store.list().await {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Warning: Could not list remote conversations: {}", e);
+            return;
+        }
+    };
+
+    let synced = synced(dir);
+    let stale = entries.into_iter().filter(|(name, remote_modified)| {
+        let local_modified = fs::metadata(dir.join(name)).and_then(|m| m.modified());
+        match (local_modified, remote_modified) {
+            (Err(_), _) => true,
+            // Newer than this machine's own last push too, so someone else's
+            (Ok(local), Some(remote)) => {
+                *remote > synced.get(name).map_or(local, |pushed| local.max(*pushed))
+            }
+            (Ok(_), None) => false,
+        }
+    });
+
+    futures::stream::iter(stale)
+        .for_each_concurrent(8, |(name, _)| async move {
+            let result = match store.read(&name).await {
+                Ok(content) => tokio::fs::write(dir.join(&name), content).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => debug!(name, "Pulled conversation"),
+                Err(e) => eprintln!("Warning: Could not fetch {}: {}", name, e),
+            }
+        })
+        .await;
+}
+
+/// Uploads the file at `path` to the remote store
+pub async fn push(dir: &Path, path: &Path) {
+    let (Some(store), Some(name)) = (remote(), name(dir, path)) else {
+        return;
+    };
+
+    let result = match tokio::fs::read_to_string(path).await {
+        Ok(content) => store.write(&name, &content).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => {
+            mark_synced(dir, &name, Some(SystemTime::now()));
+            debug!(name, "Pushed conversation");
+        }
+        Err(e) => eprintln!("Warning: Could not upload {}: {}", name, e),
+    }
+}
+
+/// Removes the remote copy of the file at `path`
+pub async fn remove(dir: &Path, path: &Path) {
+    let (Some(store), Some(name)) = (remote(), name(dir, path)) else {
+        return;
+    };
+
+    match store.remove(&name).await {
+        Ok(()) => mark_synced(dir, &name, None),
+        Err(e) => eprintln!("Warning: Could not remove remote {}: {}", name, e),
+    }
+}
+
+// Turns a failed response into an error carrying the status and body
+async fn check(response: reqwest::Result<Response>) -> io::Result<Response> {
+    let response = response.map_err(io::Error::other)?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let kind = if status == StatusCode::NOT_FOUND {
+        io::ErrorKind::NotFound
+    } else {
+        io::ErrorKind::Other
+    };
+    let body = response.text().await.unwrap_or_default();
+    Err(io::Error::new(kind, format!("{}: {}", status, body.trim())))
+}
+
+// Inner text of every element called `name`, whatever its namespace prefix
+fn xml_elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut elements = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..end];
+        let local = tag.split_whitespace().next().unwrap_or("");
+        let local = local.rsplit(':').next().unwrap_or(local);
+        rest = &rest[end + 1..];
+        if local != name || tag.starts_with('/') || tag.ends_with('/') {
+            continue;
+        }
+
+        // The matching close tag ends with `name>`, with or without a prefix
+        let mut search = 0;
+        while let Some(close) = rest[search..].find("</") {
+            let close = search + close;
+            let Some(close_end) = rest[close..].find('>') else {
+                break;
+            };
+            let close_tag = &rest[close + 2..close + close_end];
+            if close_tag.rsplit(':').next() == Some(name) {
+                elements.push(&rest[..close]);
+                break;
+            }
+            search = close + close_end;
+        }
+    }
+
+    elements
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+struct WebDavStore {
+    client: Client,
+    url: Url,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+// Characters left as they are in URL paths, as SigV4 expects
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+fn encode_name(name: &str) -> String {
+    name.split('/')
+        .map(|part| utf8_percent_encode(part, UNRESERVED).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl WebDavStore {
+    fn request(&self, method: Method, name: &str) -> io::Result<RequestBuilder> {
+        let url = self
+            .url
+            .join(&encode_name(name))
+            .map_err(io::Error::other)?;
+        let request = self.client.request(method, url);
+        Ok(match &self.username {
+            Some(username) => request.basic_auth(username, self.password.as_deref()),
+            None => request,
+        })
+    }
+}
+
+#[async_trait]
+impl RemoteStore for WebDavStore {
+    async fn list(&self) -> io::Result<Vec<(String, Option<SystemTime>)>> {
+        let propfind = Method::from_bytes(b"PROPFIND").map_err(io::Error::other)?;
+        let body = r#"<?xml version="1.0"?><propfind xmlns="DAV:"><prop><getlastmodified/></prop></propfind>"#;
+        let response = check(
+            self.request(propfind, "")?
+                .header("Depth", "1")
+                .header("Content-Type", "application/xml")
+                .body(body)
+                .send()
+                .await,
+        )
+        .await?;
+        let xml = response.text().await.map_err(io::Error::other)?;
+
+        let mut entries = Vec::new();
+        for entry in xml_elements(&xml, "response") {
+            let Some(href) = xml_elements(entry, "href").first().copied() else {
+                continue;
+            };
+            let href = unescape(&percent_decode_str(href.trim()).decode_utf8_lossy());
+            let Some(name) = href.trim_end_matches('/').rsplit('/').next() else {
+                continue;
+            };
+            if !name.ends_with(".md") || href.ends_with('/') {
+                continue;
+            }
+
+            let modified = xml_elements(entry, "getlastmodified")
+                .first()
+                .and_then(|date| chrono::DateTime::parse_from_rfc2822(date.trim()).ok())
+                .map(SystemTime::from);
+            entries.push((name.to_string(), modified));
+        }
+
+        Ok(entries)
+    }
+
+    async fn read(&self, name: &str) -> io::Result<String> {
+        let response = check(self.request(Method::GET, name)?.send().await).await?;
+        response.text().await.map_err(io::Error::other)
+    }
+
+    async fn write(&self, name: &str, content: &str) -> io::Result<()> {
+        let put =
+            || -> io::Result<_> { Ok(self.request(Method::PUT, name)?.body(content.to_string())) };
+        let response = put()?.send().await.map_err(io::Error::other)?;
+
+        // A missing parent collection (such as `archive/`) is created once
+        if response.status() == StatusCode::CONFLICT
+            && let Some((parent, _)) = name.rsplit_once('/')
+        {
+            let mkcol = Method::from_bytes(b"MKCOL").map_err(io::Error::other)?;
+            check(self.request(mkcol, &format!("{}/", parent))?.send().await).await?;
+            check(put()?.send().await).await?;
+            return Ok(());
+        }
+
+        check(Ok(response)).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, name: &str) -> io::Result<()> {
+        match check(self.request(Method::DELETE, name)?.send().await).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+// Objects addressed path-style, `https://<endpoint>/<bucket>/<prefix>/<name>`,
+// and signed with AWS Signature Version 4
+struct S3Store {
+    client: Client,
+    url: Url,
+    bucket: String,
+    // Ends with `/` unless empty
+    prefix: String,
+    access_key: String,
+    secret_key: String,
+    region: String,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(&hmac_sha256::Hash::hash(data))
+}
+
+impl S3Store {
+    fn new(
+        url: Url,
+        access_key: String,
+        secret_key: String,
+        region: String,
+    ) -> Result<Self, String> {
+        let path = url.path().trim_matches('/');
+        let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+        if bucket.is_empty() {
+            return Err(
+                "store.url has no bucket, expected https://<endpoint>/<bucket>".to_string(),
+            );
+        }
+        let prefix = match prefix {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+
+        Ok(Self {
+            client: Client::new(),
+            bucket: bucket.to_string(),
+            prefix,
+            url,
+            access_key,
+            secret_key,
+            region,
+        })
+    }
+
+    // Builds a signed request for `key` (an object, or the bucket when empty)
+    fn request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> io::Result<RequestBuilder> {
+        let path = if key.is_empty() {
+            format!("/{}", self.bucket)
+        } else {
+            format!("/{}/{}", self.bucket, encode_name(key))
+        };
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| {
+                (
+                    utf8_percent_encode(k, UNRESERVED).to_string(),
+                    utf8_percent_encode(v, UNRESERVED).to_string(),
+                )
+            })
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let host = match self.url.port() {
+            Some(port) => format!("{}:{}", self.url.host_str().unwrap_or_default(), port),
+            None => self.url.host_str().unwrap_or_default().to_string(),
+        };
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = sha256_hex(&body);
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\n{}",
+            method, path, query, host, payload_hash, amz_date, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            sha256_hex(canonical_request.as_bytes())
+        );
+
+        let mut key = hmac_sha256::HMAC::mac(date, format!("AWS4{}", self.secret_key));
+        for part in [self.region.as_str(), "s3", "aws4_request"] {
+            key = hmac_sha256::HMAC::mac(part, key);
+        }
+        let signature = hex(&hmac_sha256::HMAC::mac(string_to_sign, key));
+
+        let mut url = format!("{}://{}{}", self.url.scheme(), host, path);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+
+        Ok(self
+            .client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header(
+                "Authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+                    self.access_key, scope, signature
+                ),
+            )
+            .body(body))
+    }
+}
+
+#[async_trait]
+impl RemoteStore for S3Store {
+    async fn list(&self) -> io::Result<Vec<(String, Option<SystemTime>)>> {
+        let mut entries = Vec::new();
+        let mut continuation: Option<String> = None;
+
+        loop {
+            let mut query = vec![
+                ("list-type", "2"),
+                ("prefix", self.prefix.as_str()),
+                ("delimiter", "/"),
+            ];
+            if let Some(token) = &continuation {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let response = check(
+                self.request(Method::GET, "", &query, Vec::new())?
+                    .send()
+                    .await,
+            )
+            .await?;
+            let xml = response.text().await.map_err(io::Error::other)?;
+
+            for object in xml_elements(&xml, "Contents") {
+                let Some(key) = xml_elements(object, "Key").first().copied() else {
+                    continue;
+                };
+                let Some(name) = key.strip_prefix(self.prefix.as_str()) else {
+                    continue;
+                };
+                if !name.ends_with(".md") {
+                    continue;
+                }
+                let modified = xml_elements(object, "LastModified")
+                    .first()
+                    .and_then(|date| chrono::DateTime::parse_from_rfc3339(date).ok())
+                    .map(SystemTime::from);
+                entries.push((unescape(name), modified));
+            }
+
+            continuation = xml_elements(&xml, "NextContinuationToken")
+                .first()
+                .map(|token| token.to_string());
+            if continuation.is_none() {
+                return Ok(entries);
+            }
+        }
+    }
+
+    async fn read(&self, name: &str) -> io::Result<String> {
+        let key = format!("{}{}", self.prefix, name);
+        let response = check(
+            self.request(Method::GET, &key, &[], Vec::new())?
+                .send()
+                .await,
+        )
+        .await?;
+        response.text().await.map_err(io::Error::other)
+    }
+
+    async fn write(&self, name: &str, content: &str) -> io::Result<()> {
+        let key = format!("{}{}", self.prefix, name);
+        let request = self.request(Method::PUT, &key, &[], content.as_bytes().to_vec())?;
+        check(request.send().await).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, name: &str) -> io::Result<()> {
+        // S3 reports success for keys that don't exist
+        let key = format!("{}{}", self.prefix, name);
+        let request = self.request(Method::DELETE, &key, &[], Vec::new())?;
+        check(request.send().await).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// With `--tee <file>` or `/tee <file>`, every response's raw markdown is
+// appended to the file chunk by chunk as it streams in, next to whatever the
+// terminal shows, so a long generation is captured even if rendering or the
+// session goes wrong halfway.
+
+static TEE: Mutex<Option<(PathBuf, File)>> = Mutex::new(None);
+
+/// Appends responses to `path` from now on
+pub fn start(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *TEE.lock().unwrap() = Some((path.to_path_buf(), file));
+    Ok(())
+}
+
+/// Stops writing responses to the file, returning its path
+pub fn stop() -> Option<PathBuf> {
+    TEE.lock().unwrap().take().map(|(path, _)| path)
+}
+
+/// The file responses are written to, if any
+pub fn path() -> Option<PathBuf> {
+    TEE.lock().unwrap().as_ref().map(|(path, _)| path.clone())
+}
+
+/// Writes a chunk of a response to the file, if there is one. A failed
+/// write stops the tee rather than failing the response.
+pub fn write(chunk: &str) {
+    let mut tee = TEE.lock().unwrap();
+    if let Some((path, file)) = tee.as_mut()
+        && let Err(e) = file.write_all(chunk.as_bytes()).and_then(|_| file.flush())
+    {
+        eprintln!("Warning: Stopped writing to {}: {}", path.display(), e);
+        *tee = None;
+    }
+}
+
+/// Ends a response in the file, keeping the next one apart
+pub fn finish() {
+    write("\n\n");
+}
@@ -0,0 +1,111 @@
+use crate::config;
+use regex::Regex;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+// Prompt templates are TOML files in the `templates` directory next to the
+// config file, used with `rye ask --template <name>`:
+//
+//   prompt = "Summarize in one sentence:\n\n{input}"
+//
+//   [constraints]
+//   must_match = "^[A-Z].*\\.$"
+//   max_chars = 200
+//   forbidden = ["As an AI"]
+//
+// `{input}` is replaced by the prompt given to `rye ask`. Answers that break
+// a constraint are sent back with the violations until one passes.
+
+/// Rules an answer has to follow, all optional
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Constraints {
+    /// Regex the whole answer must match somewhere
+    pub must_match: Option<String>,
+    /// Most characters the answer may have
+    pub max_chars: Option<usize>,
+    /// Text the answer must not contain, compared case-insensitively
+    #[serde(default)]
+    pub forbidden: Vec<String>,
+}
+
+impl Constraints {
+    pub fn is_empty(&self) -> bool {
+        self.must_match.is_none() && self.max_chars.is_none() && self.forbidden.is_empty()
+    }
+
+    /// What's wrong with `answer`, empty when it follows every rule
+    pub fn violations(&self, answer: &str) -> Result<Vec<String>, regex::Error> {
+        let mut violations = Vec::new();
+
+        if let Some(pattern) = &self.must_match
+            && !Regex::new(pattern)?.is_match(answer)
+        {
+            violations.push(format!("It must match the regex `{}`", pattern));
+        }
+
+        let chars = answer.chars().count();
+        if let Some(max_chars) = self.max_chars
+            && chars > max_chars
+        {
+            violations.push(format!(
+                "It is {} characters long, the limit is {}",
+                chars, max_chars
+            ));
+        }
+
+        let lowercase = answer.to_lowercase();
+        for forbidden in &self.forbidden {
+            if lowercase.contains(&forbidden.to_lowercase()) {
+                violations.push(format!("It must not contain \"{}\"", forbidden));
+            }
+        }
+
+        Ok(violations)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Template {
+    pub prompt: String,
+    #[serde(default)]
+    pub constraints: Constraints,
+}
+
+impl Template {
+    /// Loads `<templates dir>/<name>.toml`
+    pub fn load(name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = templates_dir()
+            .ok_or("Could not determine the templates directory")?
+            .join(format!("{}.toml", name));
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read template {}: {}", path.display(), e))?;
+        let template: Template =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        // Catch a bad regex before anything is sent
+        if let Some(pattern) = &template.constraints.must_match {
+            Regex::new(pattern)
+                .map_err(|e| format!("{}: invalid must_match: {}", path.display(), e))?;
+        }
+        Ok(template)
+    }
+
+    /// The prompt with `{input}` replaced, or followed by `input` when the
+    /// template has no placeholder
+    pub fn fill(&self, input: &str) -> String {
+        if self.prompt.contains("{input}") {
+            self.prompt.replace("{input}", input)
+        } else if input.is_empty() {
+            self.prompt.clone()
+        } else {
+            format!("{}\n\n{}", self.prompt, input)
+        }
+    }
+}
+
+/// Directory templates are loaded from, next to the config file
+pub fn templates_dir() -> Option<PathBuf> {
+    config::config_path().and_then(|path| Some(path.parent()?.join("templates")))
+}
@@ -0,0 +1,126 @@
+use crossterm::style::Stylize;
+use rye::conversation::{Conversation, ConversationInfo, list_conversations};
+use rye::symbols::plain;
+use std::collections::{HashMap, HashSet};
+
+// `rye tree [id]`: the fork graph of conversations, built from the `parent`
+// each fork records in its frontmatter. With an ID, shows the whole tree that
+// conversation belongs to and marks it; without, every tree with a fork.
+
+struct Tree<'a> {
+    children: HashMap<&'a str, Vec<&'a ConversationInfo>>,
+    current: Option<&'a str>,
+}
+
+impl Tree<'_> {
+    fn print(
+        &self,
+        info: &ConversationInfo,
+        prefix: &str,
+        branch: &str,
+        seen: &mut HashSet<String>,
+    ) {
+        // Hand-edited frontmatter could link a conversation to itself
+        if !seen.insert(info.id.clone()) {
+            return;
+        }
+
+        let mut line = format!("{}{}", prefix, branch);
+        match &info.title {
+            Some(title) if *title != info.id => {
+                line.push_str(&format!("{} {}", title, format!("({})", info.id).dim()))
+            }
+            Some(title) => line.push_str(title),
+            None => line.push_str(&info.id),
+        }
+        if self.current == Some(info.id.as_str()) {
+            line = format!("{}  {}", line.bold(), "◀".cyan());
+        }
+        println!("{}", plain(&line));
+
+        let child_prefix = match branch {
+            "" => prefix.to_string(),
+            "└── " => format!("{}    ", prefix),
+            _ => format!("{}│   ", prefix),
+        };
+        let children = self
+            .children
+            .get(info.id.as_str())
+            .map_or(&[][..], Vec::as_slice);
+        for (i, child) in children.iter().enumerate() {
+            let branch = if i + 1 == children.len() {
+                "└── "
+            } else {
+                "├── "
+            };
+            self.print(child, &child_prefix, branch, seen);
+        }
+    }
+}
+
+/// Prints the fork tree containing `id`, or every fork tree
+pub fn run(id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    // Oldest first, so forks are listed in the order they were made
+    let mut conversations = list_conversations()?;
+    conversations.reverse();
+
+    let by_id: HashMap<&str, &ConversationInfo> = conversations
+        .iter()
+        .map(|info| (info.id.as_str(), info))
+        .collect();
+    let mut children: HashMap<&str, Vec<&ConversationInfo>> = HashMap::new();
+    for info in &conversations {
+        if let Some(parent) = &info.parent
+            && by_id.contains_key(parent.as_str())
+        {
+            children.entry(parent).or_default().push(info);
+        }
+    }
+
+    let mut current = None;
+    let roots: Vec<&ConversationInfo> = match id {
+        Some(id) => {
+            let conversation = Conversation::load_metadata(id)?;
+            let start = by_id
+                .get(conversation.id.as_str())
+                .ok_or_else(|| format!("{} is archived or not listed", conversation.id))?;
+
+            // Walk up to the first conversation whose parent is gone
+            let mut root = *start;
+            let mut seen = HashSet::new();
+            while let Some(parent) = root.parent.as_deref().and_then(|parent| by_id.get(parent))
+                && seen.insert(root.id.as_str())
+            {
+                root = parent;
+            }
+            current = Some(conversation.id);
+            vec![root]
+        }
+        None => conversations
+            .iter()
+            .filter(|info| children.contains_key(info.id.as_str()))
+            .filter(|info| {
+                info.parent
+                    .as_deref()
+                    .is_none_or(|parent| !by_id.contains_key(parent))
+            })
+            .collect(),
+    };
+
+    if roots.is_empty() {
+        println!("No forked conversations yet. Use /fork in a conversation to branch it.");
+        return Ok(());
+    }
+
+    let tree = Tree {
+        children,
+        current: current.as_deref(),
+    };
+    for (i, root) in roots.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        tree.print(root, "", "", &mut HashSet::new());
+    }
+    Ok(())
+}
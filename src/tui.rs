@@ -0,0 +1,648 @@
+use crate::draft;
+use crate::notify::notify_response_complete;
+use crate::rotate;
+use crate::status::{project_context_name, response_footer};
+use crossterm::{
+    event::{
+        self, DisableFocusChange, EnableFocusChange, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers,
+    },
+    execute,
+};
+use futures::StreamExt;
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+};
+use rye::config;
+use rye::conversation::{Conversation, ConversationInfo, list_conversations};
+use rye::hooks;
+use rye::persona;
+use rye::project;
+use rye::providers::{LLMProvider, is_unavailable};
+use rye::render::render_markdown_text;
+use rye::secrets;
+use rye::speech::{self, Speaker};
+use rye::symbols;
+use rye::tee;
+use rye::usage;
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedReceiver, error::TryRecvError};
+use tracing::info;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(PartialEq)]
+enum Focus {
+    Input,
+    Sidebar,
+}
+
+struct App {
+    conversation: Conversation,
+    conversations: Vec<ConversationInfo>,
+    sidebar_state: ListState,
+    focus: Focus,
+    input: String,
+    // Message submitted from the input box, sent on the next loop iteration
+    outgoing: Option<String>,
+    // Message that looked like it contains secrets, sent as is if submitted
+    // again unchanged
+    flagged: Option<String>,
+    // Lines scrolled up from the bottom of the transcript
+    scroll_offset: u16,
+    // Receives chunks of the in-progress response, if one is streaming
+    stream_rx: Option<UnboundedReceiver<Result<String, String>>>,
+    pending_response: String,
+    // Reads the response aloud when speech is on
+    speaker: Option<Speaker>,
+    request_started: Option<Instant>,
+    // Tracked from focus events so notifications only fire when tabbed away
+    terminal_focused: bool,
+    status: String,
+    running: bool,
+}
+
+impl App {
+    fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let conversation = Conversation::new()?;
+        let mut status = format!("Started new conversation: {}", conversation.id);
+        if let Some(name) = project_context_name() {
+            status.push_str(&format!(" · using {}", name));
+        }
+        let input = draft::load().unwrap_or_default();
+        if !input.is_empty() {
+            status.push_str(" · restored an unsent draft, Ctrl+U clears it");
+        }
+        let mut app = Self {
+            status,
+            conversation,
+            conversations: Vec::new(),
+            sidebar_state: ListState::default(),
+            focus: Focus::Input,
+            input,
+            outgoing: None,
+            flagged: None,
+            scroll_offset: 0,
+            stream_rx: None,
+            pending_response: String::new(),
+            speaker: None,
+            request_started: None,
+            terminal_focused: true,
+            running: true,
+        };
+        app.refresh_conversations()?;
+        Ok(app)
+    }
+
+    fn refresh_conversations(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        // The empty conversation we just created shouldn't show up in the sidebar
+        self.conversations = list_conversations()?
+            .into_iter()
+            .filter(|info| info.file_path != self.conversation.file_path)
+            .filter(|info| project::in_scope(info.project.as_deref()))
+            .collect();
+
+        if self.conversations.is_empty() {
+            self.sidebar_state.select(None);
+        } else if self.sidebar_state.selected().is_none() {
+            self.sidebar_state.select(Some(0));
+        }
+
+        Ok(())
+    }
+
+    fn is_streaming(&self) -> bool {
+        self.stream_rx.is_some()
+    }
+
+    fn transcript_markdown(&self) -> String {
+        let mut markdown = String::new();
+
+        if let Some(ref title) = self.conversation.title {
+            markdown.push_str(&format!("# {}\n\n", title));
+        }
+
+        for (role, content) in &self.conversation.messages {
+            let role_header = if role == "user" {
+                "## You"
+            } else {
+                "## Assistant"
+            };
+            markdown.push_str(&format!("{}\n\n{}\n\n", role_header, content));
+        }
+
+        if self.is_streaming() {
+            markdown.push_str(&format!(
+                "## Assistant\n\n{}{}\n",
+                self.pending_response,
+                symbols::pick("▍", "_")
+            ));
+        }
+
+        markdown
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [sidebar_area, main_area] =
+            Layout::horizontal([Constraint::Percentage(25), Constraint::Percentage(75)])
+                .areas(frame.area());
+        let [transcript_area, input_area, status_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(5),
+            Constraint::Length(1),
+        ])
+        .areas(main_area);
+
+        self.draw_sidebar(frame, sidebar_area);
+        self.draw_transcript(frame, transcript_area);
+        self.draw_input(frame, input_area);
+
+        frame.render_widget(
+            Paragraph::new(symbols::plain(&self.status))
+                .style(Style::default().fg(Color::DarkGray)),
+            status_area,
+        );
+    }
+
+    fn draw_sidebar(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .conversations
+            .iter()
+            .map(|conv| ListItem::new(conv.title.clone().unwrap_or_else(|| conv.id.clone())))
+            .collect();
+
+        let list = List::new(items)
+            .block(focus_block("Conversations", self.focus == Focus::Sidebar))
+            .highlight_style(
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol(symbols::pick("➤ ", "> "));
+
+        frame.render_stateful_widget(list, area, &mut self.sidebar_state);
+    }
+
+    fn draw_transcript(&mut self, frame: &mut Frame, area: Rect) {
+        let title = self
+            .conversation
+            .title
+            .clone()
+            .unwrap_or_else(|| symbols::pick("🥃 Rye", "Rye").to_string());
+        let block = focus_block(&title, false);
+        let inner = block.inner(area);
+
+        // Let termimad do the wrapping so the output matches the line-based CLI
+        let text = render_markdown_text(&self.transcript_markdown(), inner.width as usize);
+
+        let max_scroll = (text.lines.len() as u16).saturating_sub(inner.height);
+        self.scroll_offset = self.scroll_offset.min(max_scroll);
+        let scroll = max_scroll - self.scroll_offset;
+
+        frame.render_widget(Paragraph::new(text).block(block).scroll((scroll, 0)), area);
+    }
+
+    fn draw_input(&self, frame: &mut Frame, area: Rect) {
+        let title = if self.is_streaming() {
+            "Waiting for response..."
+        } else {
+            "Message (Enter to send)"
+        };
+
+        let input = Paragraph::new(Line::from(format!(
+            "{} {}",
+            symbols::pick("➤", ">"),
+            self.input
+        )))
+        .block(focus_block(title, self.focus == Focus::Input))
+        .wrap(Wrap { trim: false });
+        frame.render_widget(input, area);
+
+        if self.focus == Focus::Input {
+            let width = area.width.saturating_sub(2).max(1);
+            let offset = 2 + self.input.width() as u16;
+            frame.set_cursor_position((area.x + 1 + offset % width, area.y + 1 + offset / width));
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.running = false;
+            }
+            KeyCode::Esc => self.running = false,
+            KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.new_conversation()?;
+            }
+            KeyCode::Char('r')
+                if key.modifiers.contains(KeyModifiers::CONTROL) && self.flagged.is_some() =>
+            {
+                self.input = secrets::redact(&self.input, &secrets::scan(&self.input));
+                draft::save(&self.input);
+                self.flagged = None;
+                self.status = "Redacted. Press Enter to send.".to_string();
+            }
+            KeyCode::Char('u')
+                if key.modifiers.contains(KeyModifiers::CONTROL) && self.focus == Focus::Input =>
+            {
+                self.input.clear();
+                draft::clear();
+            }
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Input => Focus::Sidebar,
+                    Focus::Sidebar => Focus::Input,
+                };
+            }
+            KeyCode::PageUp => self.scroll_offset = self.scroll_offset.saturating_add(10),
+            KeyCode::PageDown => self.scroll_offset = self.scroll_offset.saturating_sub(10),
+            _ => match self.focus {
+                Focus::Input => self.handle_input_key(key),
+                Focus::Sidebar => self.handle_sidebar_key(key)?,
+            },
+        }
+
+        Ok(())
+    }
+
+    fn handle_input_key(&mut self, key: KeyEvent) {
+        match key.code {
+            // Saved as it's typed, the draft goes once the message is sent
+            KeyCode::Char(c) => {
+                self.input.push(c);
+                draft::save(&self.input);
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+                draft::save(&self.input);
+            }
+            KeyCode::Enter if !self.is_streaming() => {
+                let input = self.input.trim().to_string();
+                if !input.is_empty() {
+                    self.input.clear();
+                    self.outgoing = Some(input);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_sidebar_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => self.sidebar_state.select_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.sidebar_state.select_next(),
+            KeyCode::Enter if !self.is_streaming() => {
+                if let Some(info) = self
+                    .sidebar_state
+                    .selected()
+                    .and_then(|i| self.conversations.get(i))
+                {
+                    // A locked protected one can't be opened here, the
+                    // status line says how to
+                    match Conversation::load(&info.id) {
+                        Ok(conversation) => {
+                            self.switch_to(conversation);
+                            self.status =
+                                format!("Continuing conversation: {}", self.conversation.id);
+                            self.focus = Focus::Input;
+                            self.refresh_conversations()?;
+                        }
+                        Err(e) => self.status = e.to_string(),
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn new_conversation(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if self.is_streaming() {
+            return Ok(());
+        }
+
+        let conversation = Conversation::new()?;
+        self.switch_to(conversation);
+        self.status = format!("Started new conversation: {}", self.conversation.id);
+        self.focus = Focus::Input;
+        self.refresh_conversations()
+    }
+
+    fn switch_to(&mut self, conversation: Conversation) {
+        let previous = std::mem::replace(&mut self.conversation, conversation);
+        discard_if_empty(&previous);
+        self.scroll_offset = 0;
+    }
+
+    async fn send_message(
+        &mut self,
+        llm_provider: &dyn LLMProvider,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match llm_provider
+            .generate_response_stream(&persona::apply(
+                self.conversation.persona.as_deref(),
+                &self.conversation.messages,
+            ))
+            .await
+        {
+            Ok(mut stream) => {
+                // Forward the stream through a channel so the UI keeps redrawing
+                // and handling keys while the response arrives
+                let (tx, rx) = mpsc::unbounded_channel();
+                tokio::spawn(async move {
+                    while let Some(result) = stream.next().await {
+                        let is_err = result.is_err();
+                        if tx.send(result.map_err(|e| e.to_string())).is_err() || is_err {
+                            break;
+                        }
+                    }
+                });
+                self.pending_response.clear();
+                self.speaker = Speaker::new();
+                self.stream_rx = Some(rx);
+                self.status = "Streaming response...".to_string();
+            }
+            Err(e) if config::get().queue.enabled && is_unavailable(e.as_ref()) => {
+                self.conversation.set_queued(true).await?;
+                self.status = format!("{}. Message queued, `rye flush` sends it later.", e);
+            }
+            Err(e) => {
+                self.status = format!("Error: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn poll_stream(
+        &mut self,
+        llm_provider: &dyn LLMProvider,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(rx) = self.stream_rx.as_mut() else {
+            return Ok(());
+        };
+
+        let finished = loop {
+            match rx.try_recv() {
+                Ok(Ok(chunk)) => {
+                    if let Some(speaker) = self.speaker.as_mut() {
+                        speaker.push(&chunk);
+                    }
+                    tee::write(&chunk);
+                    self.pending_response.push_str(&chunk);
+                }
+                Ok(Err(e)) => {
+                    self.status = format!("Stream error: {}", e);
+                    break true;
+                }
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+
+        if finished {
+            self.finish_response(llm_provider).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn finish_response(
+        &mut self,
+        llm_provider: &dyn LLMProvider,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.stream_rx = None;
+        let full_response = std::mem::take(&mut self.pending_response);
+        if let Some(speaker) = self.speaker.take() {
+            speaker.finish();
+        }
+
+        if !full_response.is_empty() {
+            tee::finish();
+            if let Some(model) = llm_provider.routed_model() {
+                self.conversation.note_model(model).await?;
+            }
+            self.conversation
+                .add_message("assistant", &full_response)
+                .await?;
+            self.status = format!(
+                "Conversation saved to: {}",
+                self.conversation.file_path.display()
+            );
+            if let Some(started) = self.request_started {
+                let usage = llm_provider.last_usage();
+                info!(
+                    elapsed_ms = started.elapsed().as_millis() as u64,
+                    input_tokens = usage.input_tokens,
+                    output_tokens = usage.output_tokens,
+                    "Response complete"
+                );
+                let footer = response_footer(started.elapsed(), llm_provider);
+                self.status = format!("{} · {}", footer, self.status);
+            }
+            usage::record(llm_provider, Some(&self.conversation.id));
+            if let Some(warning) = usage::budget_warning() {
+                self.status = format!("Warning: {} · {}", warning, self.status);
+            }
+
+            // Hook output would draw over the interface, so it's discarded
+            if let Err(e) =
+                hooks::post_response(&full_response, &self.conversation.file_path, false).await
+            {
+                self.status = format!("Warning: post_response hook: {}", e);
+            }
+        }
+
+        // Generate a title after the first exchange, or after the next one for
+        // continued conversations that never got one
+        if self.conversation.title.is_none() {
+            match llm_provider
+                .generate_conversation_title(&self.conversation.messages)
+                .await
+            {
+                Ok(title) => {
+                    if let Err(e) = self.conversation.set_title(title).await {
+                        self.status = format!("Warning: Could not set conversation title: {}", e);
+                    }
+                }
+                Err(e) => {
+                    self.status = format!("Warning: Could not generate title: {}", e);
+                }
+            }
+            self.refresh_conversations()?;
+        }
+
+        if let Err(e) = hooks::on_save(&self.conversation.file_path).await {
+            self.status = format!("Warning: on_save hook: {}", e);
+        }
+
+        if rotate::due(&self.conversation) {
+            match rotate::rotate(llm_provider, &self.conversation).await {
+                Ok(next) => {
+                    self.status = format!(
+                        "This conversation got long, it continues from a summary in {}",
+                        next.file_path.display()
+                    );
+                    self.conversation = next;
+                    self.refresh_conversations()?;
+                }
+                Err(e) => self.status = format!("Warning: Could not start a new part: {}", e),
+            }
+        }
+
+        if let Some(started) = self.request_started.take() {
+            let title = self
+                .conversation
+                .title
+                .as_deref()
+                .unwrap_or(&self.conversation.id);
+            notify_response_complete(title, started.elapsed(), Some(self.terminal_focused));
+        }
+
+        Ok(())
+    }
+}
+
+fn focus_block(title: &str, focused: bool) -> Block<'_> {
+    let border_style = if focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default().fg(Color::DarkGray)
+    };
+
+    Block::default()
+        .borders(Borders::ALL)
+        .border_set(symbols::border())
+        .border_style(border_style)
+        .title(title)
+}
+
+fn discard_if_empty(conversation: &Conversation) {
+    // Same rule as the line-based CLI: never leave empty conversation files behind
+    if conversation.messages.is_empty() {
+        let _ = std::fs::remove_file(&conversation.file_path);
+    }
+}
+
+async fn run_app(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    llm_provider: &dyn LLMProvider,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while app.running {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        app.poll_stream(llm_provider).await?;
+        if let Some(warning) = speech::take_warning() {
+            app.status = warning.replace('\n', " ");
+        }
+
+        if let Some(input) = app.outgoing.take() {
+            // Put a message with possible secrets back for a second look
+            if config::get().secrets.check && app.flagged.as_ref() != Some(&input) {
+                let findings = secrets::scan(&input);
+                if !findings.is_empty() {
+                    let mut kinds: Vec<&str> = findings.iter().map(|f| f.kind).collect();
+                    kinds.sort_unstable();
+                    kinds.dedup();
+                    app.status = format!(
+                        "Warning: This looks like it contains a {}. Enter sends it as is, Ctrl-R redacts.",
+                        kinds.join(", ")
+                    );
+                    app.input = input.clone();
+                    app.flagged = Some(input);
+                    continue;
+                }
+            }
+            app.flagged = None;
+
+            let mut sending = "Sending...";
+            let input = match hooks::pre_send(&input, &app.conversation.file_path).await {
+                // Nobody to ask about what the hook added, so it's redacted
+                Ok(hooked) if !hooked.is_empty() && config::get().secrets.check => {
+                    let findings = secrets::added(&input, &hooked);
+                    if findings.is_empty() {
+                        hooked
+                    } else {
+                        sending = "Redacted what looked like secrets in the pre_send hook's output. Sending...";
+                        secrets::redact(&hooked, &findings)
+                    }
+                }
+                Ok(hooked) if !hooked.is_empty() => hooked,
+                Ok(_) => {
+                    app.status =
+                        "The pre_send hook returned an empty message, nothing sent.".to_string();
+                    continue;
+                }
+                Err(e) => {
+                    app.status = format!("Warning: pre_send hook: {}. Message not sent.", e);
+                    continue;
+                }
+            };
+            // Edited elsewhere, like in Obsidian or on a synced machine: the
+            // message goes back for a look at the conversation as it is now
+            match app.conversation.reload_if_changed() {
+                Ok(true) => {
+                    app.status = format!(
+                        "{} changed on disk and was reloaded. Press Enter to send.",
+                        app.conversation.file_path.display()
+                    );
+                    app.input = input;
+                    app.refresh_conversations()?;
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => app.status = format!("Warning: Could not reload the conversation: {}", e),
+            }
+            app.conversation.add_message("user", &input).await?;
+            draft::clear();
+            app.scroll_offset = 0;
+
+            // Show the user's message before blocking on the request
+            app.status = sending.to_string();
+            terminal.draw(|frame| app.draw(frame))?;
+
+            app.request_started = Some(Instant::now());
+            app.send_message(llm_provider).await?;
+            continue;
+        }
+
+        if event::poll(Duration::from_millis(50))? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => app.handle_key(key)?,
+                Event::FocusGained => app.terminal_focused = true,
+                Event::FocusLost => app.terminal_focused = false,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn run(llm_provider: &dyn LLMProvider) -> Result<(), Box<dyn std::error::Error>> {
+    let mut app = App::new()?;
+
+    let mut terminal = ratatui::init();
+    execute!(io::stdout(), EnableFocusChange)?;
+    speech::hold_warnings(true);
+    let result = run_app(&mut terminal, &mut app, llm_provider).await;
+    speech::hold_warnings(false);
+    execute!(io::stdout(), DisableFocusChange)?;
+    ratatui::restore();
+
+    discard_if_empty(&app.conversation);
+    if !app.conversation.messages.is_empty() {
+        println!(
+            "Conversation saved to: {}",
+            app.conversation.file_path.display()
+        );
+    }
+
+    result
+}
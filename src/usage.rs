@@ -0,0 +1,189 @@
+use crate::providers::{LLMProvider, Latency, Usage};
+use crate::{config, paths};
+use chrono::{DateTime, Datelike, Local, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::debug;
+
+// Every response, titles and follow-up suggestions included, is logged as
+// one JSON line in `usage.jsonl` in the data directory. The `budget.daily`
+// and `budget.monthly` caps (in USD, 0 for none) are checked against it:
+// providers refuse new requests once a cap is spent, unless `--force` is
+// given, and the chat loop warns past `WARN_AT` of a cap. Entries also carry
+// the response's latency (time to first token, total time, jitter between
+// chunks and retries) when the provider measured it.
+
+/// Share of a budget after which the chat loop warns
+const WARN_AT: f64 = 0.8;
+
+static FORCE: AtomicBool = AtomicBool::new(false);
+
+/// One logged response
+#[derive(Serialize, Deserialize)]
+pub struct UsageEntry {
+    pub timestamp: DateTime<Local>,
+    pub model: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    pub cost: f64,
+    pub conversation: Option<String>,
+    // Not known for every response, like those from the cache or a batch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency: Option<Latency>,
+}
+
+/// `usage.jsonl` in the data directory
+pub fn log_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join("usage.jsonl"))
+}
+
+/// Logs the provider's most recent response, made for `conversation`
+pub fn record(llm_provider: &dyn LLMProvider, conversation: Option<&str>) {
+    log(
+        llm_provider,
+        llm_provider.last_usage(),
+        llm_provider.last_latency(),
+        conversation,
+    );
+}
+
+/// Logs a request the provider made on the side, like a title, whose usage
+/// isn't its `last_usage()`
+pub fn record_usage(llm_provider: &dyn LLMProvider, usage: Usage) {
+    log(llm_provider, usage, None, None);
+}
+
+fn log(
+    llm_provider: &dyn LLMProvider,
+    usage: Usage,
+    latency: Option<Latency>,
+    conversation: Option<&str>,
+) {
+    if usage.total() == 0 {
+        return;
+    }
+    let entry = UsageEntry {
+        timestamp: Local::now(),
+        model: llm_provider.model().to_string(),
+        input_tokens: usage.input_tokens,
+        output_tokens: usage.output_tokens,
+        cost: llm_provider.cost(&usage),
+        conversation: conversation.map(str::to_string),
+        latency,
+    };
+    append(&entry);
+}
+
+/// Adds `entry` to the log, for usage not read from a provider
+pub fn append(entry: &UsageEntry) {
+    let result = (|| {
+        let path = log_path().ok_or("Could not determine the data directory")?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(line.as_bytes())?;
+        Ok::<_, Box<dyn std::error::Error>>(())
+    })();
+    if let Err(e) = result {
+        eprintln!("Warning: Could not log usage: {}", e);
+    }
+}
+
+/// Every logged response since `since`, oldest first
+pub fn entries_since(since: DateTime<Local>) -> Vec<UsageEntry> {
+    let Some(file) = log_path().and_then(|path| std::fs::File::open(path).ok()) else {
+        return Vec::new();
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<UsageEntry>(&line).ok())
+        .filter(|entry| entry.timestamp >= since)
+        .collect()
+}
+
+/// Lets requests through even when a budget is spent, for `--force`
+pub fn ignore_budget() {
+    FORCE.store(true, Ordering::Relaxed);
+}
+
+// Each configured cap with its name and what has been spent against it
+fn budgets() -> Vec<(&'static str, f64, f64)> {
+    let budget = &config::get().budget;
+    if budget.daily <= 0.0 && budget.monthly <= 0.0 {
+        return Vec::new();
+    }
+
+    let now = Local::now();
+    let midnight = now.date_naive().and_time(NaiveTime::MIN);
+    let today = Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or(now);
+    let first_of_month = now
+        .date_naive()
+        .with_day(1)
+        .map(|date| date.and_time(NaiveTime::MIN))
+        .and_then(|date| Local.from_local_datetime(&date).earliest())
+        .unwrap_or(today);
+
+    let entries = entries_since(first_of_month.min(today));
+    let spent = |since: DateTime<Local>| -> f64 {
+        entries
+            .iter()
+            .filter(|entry| entry.timestamp >= since)
+            .map(|entry| entry.cost)
+            .sum()
+    };
+
+    let mut budgets = Vec::new();
+    if budget.daily > 0.0 {
+        budgets.push(("daily", budget.daily, spent(today)));
+    }
+    if budget.monthly > 0.0 {
+        budgets.push(("monthly", budget.monthly, spent(first_of_month)));
+    }
+    debug!(?budgets, "Checked budgets");
+    budgets
+}
+
+/// Why a new request must not be sent, if a budget is spent and `--force`
+/// wasn't given
+pub fn budget_exceeded() -> Option<String> {
+    if FORCE.load(Ordering::Relaxed) {
+        return None;
+    }
+    budgets()
+        .into_iter()
+        .find(|(_, cap, spent)| spent >= cap)
+        .map(|(name, cap, spent)| {
+            format!(
+                "The {} budget of ${:.2} is spent (${:.2}). Use --force to send anyway.",
+                name, cap, spent
+            )
+        })
+}
+
+/// A warning once a budget is nearly spent
+pub fn budget_warning() -> Option<String> {
+    budgets()
+        .into_iter()
+        .find(|(_, cap, spent)| *spent >= cap * WARN_AT)
+        .map(|(name, cap, spent)| {
+            format!(
+                "${:.2} of the ${:.2} {} budget spent ({:.0}%)",
+                spent,
+                cap,
+                name,
+                spent / cap * 100.0
+            )
+        })
+}
@@ -0,0 +1,167 @@
+use crate::status::format_tokens;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, TimeZone};
+use crossterm::style::Stylize;
+use rye::usage::{self, UsageEntry};
+use std::collections::BTreeMap;
+
+// `rye usage --since 7d`: spend and volume from the usage log, per day and
+// model, newest day first, followed by totals per model and, for responses
+// logged with their latency, its medians per model.
+
+#[derive(Default)]
+struct Totals {
+    requests: usize,
+    input_tokens: u64,
+    output_tokens: u64,
+    cost: f64,
+    // Of the responses logged with their latency
+    first_token_ms: Vec<u64>,
+    total_ms: Vec<u64>,
+    jitter_ms: Vec<f64>,
+    retries: u32,
+}
+
+// The middle value of `values`, sorting them
+fn median<T: Copy + PartialOrd>(values: &mut [T]) -> Option<T> {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    values.get(values.len() / 2).copied()
+}
+
+impl Totals {
+    fn add(&mut self, entry: &UsageEntry) {
+        self.requests += 1;
+        self.input_tokens += u64::from(entry.input_tokens);
+        self.output_tokens += u64::from(entry.output_tokens);
+        self.cost += entry.cost;
+        if let Some(latency) = &entry.latency {
+            self.first_token_ms.extend(latency.first_token_ms);
+            self.total_ms.push(latency.total_ms);
+            self.jitter_ms.push(latency.jitter_ms);
+            self.retries += latency.retries;
+        }
+    }
+
+    // Median time to first token, total time and jitter, if any response
+    // was logged with its latency
+    fn print_latency(&mut self, label: &str, width: usize) {
+        let timed = self.total_ms.len();
+        let Some(total) = median(&mut self.total_ms) else {
+            return;
+        };
+        let first_token = median(&mut self.first_token_ms).unwrap_or(0);
+        let jitter = median(&mut self.jitter_ms).unwrap_or(0.0);
+        println!(
+            "  {:<width$}  {:>5} timed  first token {:.2}s  total {:.1}s  jitter {:.0}ms  {} {}",
+            label,
+            timed,
+            first_token as f64 / 1000.0,
+            total as f64 / 1000.0,
+            jitter,
+            self.retries,
+            if self.retries == 1 {
+                "retry"
+            } else {
+                "retries"
+            },
+        );
+    }
+
+    fn print(&self, label: &str, width: usize) {
+        println!(
+            "  {:<width$}  {:>5} {}  {:>7} in  {:>7} out  ${:.4}",
+            label,
+            self.requests,
+            if self.requests == 1 {
+                "request "
+            } else {
+                "requests"
+            },
+            format_tokens(self.input_tokens),
+            format_tokens(self.output_tokens),
+            self.cost,
+        );
+    }
+}
+
+/// `7d`, `12h`, `2w` or `30m` ago, or the start of a `YYYY-MM-DD` day.
+/// `flag` names the option in errors.
+pub fn parse_since(since: &str, flag: &str) -> Result<DateTime<Local>, Box<dyn std::error::Error>> {
+    let invalid = || {
+        format!(
+            "Invalid {} '{}', expected e.g. 7d, 12h, 2w or 2025-01-31",
+            flag, since
+        )
+    };
+
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        let start = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        return Local
+            .from_local_datetime(&start)
+            .earliest()
+            .ok_or_else(|| invalid().into());
+    }
+
+    let split = since.char_indices().last().map_or(0, |(i, _)| i);
+    let amount: i64 = since[..split].parse().map_err(|_| invalid())?;
+    let delta = match &since[split..] {
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    }
+    .filter(|delta| *delta >= TimeDelta::zero())
+    .ok_or_else(invalid)?;
+    Ok(Local::now() - delta)
+}
+
+/// Prints the usage logged since `since`
+pub fn run(since: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let start = parse_since(since, "--since")?;
+    let entries = usage::entries_since(start);
+    if entries.is_empty() {
+        println!("No usage logged since {}.", start.format("%Y-%m-%d %H:%M"));
+        return Ok(());
+    }
+
+    let mut days: BTreeMap<NaiveDate, BTreeMap<&str, Totals>> = BTreeMap::new();
+    let mut models: BTreeMap<&str, Totals> = BTreeMap::new();
+    let mut total = Totals::default();
+    for entry in &entries {
+        days.entry(entry.timestamp.date_naive())
+            .or_default()
+            .entry(&entry.model)
+            .or_default()
+            .add(entry);
+        models.entry(&entry.model).or_default().add(entry);
+        total.add(entry);
+    }
+    let width = models.keys().map(|model| model.len()).max().unwrap_or(0);
+
+    for (day, models) in days.iter().rev() {
+        println!("{}", day.format("%Y-%m-%d %a").to_string().bold());
+        for (model, totals) in models {
+            totals.print(model, width);
+        }
+        println!();
+    }
+
+    println!(
+        "{}",
+        format!("Since {}", start.format("%Y-%m-%d %H:%M")).bold()
+    );
+    for (model, totals) in &models {
+        totals.print(model, width);
+    }
+    if models.len() > 1 {
+        total.print("total", width);
+    }
+
+    if models.values().any(|totals| !totals.total_ms.is_empty()) {
+        println!("\n{}", "Latency (medians)".bold());
+        for (model, totals) in &mut models {
+            totals.print_latency(model, width);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal,
+};
+use rye::render::{render_markdown_lines, strip_ansi};
+use rye::symbols::plain;
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Shows the session transcript in a full-screen pager starting one page up
+/// from the bottom. Returns when the user presses `q`, `Esc`, or scrolls past the end.
+pub fn show_transcript(markdown: &str) -> Result<(), Box<dyn std::error::Error>> {
+    show(markdown, true)
+}
+
+/// Renders markdown directly if it fits on screen, otherwise pages it through
+/// $PAGER (defaults to `less -R`), falling back to the built-in viewport.
+pub fn page_markdown(markdown: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let (width, height) = terminal::size()?;
+    let lines = render_markdown_lines(markdown, width as usize);
+
+    if lines.len() < height as usize {
+        for line in &lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
+    if run_external_pager(&lines).is_err() {
+        show(markdown, false)?;
+    }
+
+    Ok(())
+}
+
+fn run_external_pager(lines: &[String]) -> io::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+    let mut parts = pager.split_whitespace();
+    let program = parts
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "PAGER is empty"))?;
+
+    let mut command = Command::new(program);
+    command.args(parts).stdin(Stdio::piped());
+    // Let less pass the color codes through even when PAGER is plain `less`
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "R");
+    }
+
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        for line in lines {
+            // The pager closing early (e.g. `q` before the end) is not an error
+            if writeln!(stdin, "{}", line).is_err() {
+                break;
+            }
+        }
+    }
+    child.wait()?;
+
+    Ok(())
+}
+
+fn show(markdown: &str, from_bottom: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stdout = io::stdout();
+
+    execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+    terminal::enable_raw_mode()?;
+
+    let result = run_viewport(markdown, from_bottom);
+
+    terminal::disable_raw_mode()?;
+    execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen)?;
+
+    result
+}
+
+fn run_viewport(markdown: &str, from_bottom: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut width, mut height) = terminal::size()?;
+    let mut lines = render_markdown_lines(markdown, width as usize);
+    // Leave the last row for the position indicator
+    let mut page = height.saturating_sub(1).max(1) as usize;
+    let mut top = if from_bottom {
+        lines.len().saturating_sub(page * 2)
+    } else {
+        0
+    };
+    // Some while typing a search query after `/`
+    let mut search_input: Option<String> = None;
+    let mut last_search = String::new();
+    let mut message: Option<String> = None;
+
+    loop {
+        let max_top = lines.len().saturating_sub(page);
+        top = top.min(max_top);
+
+        let footer = match (&search_input, &message) {
+            (Some(query), _) => format!("/{}", query),
+            (None, Some(message)) => message.clone(),
+            (None, None) => {
+                let bottom = (top + page).min(lines.len());
+                let first = if lines.is_empty() { 0 } else { top + 1 };
+                plain(&format!(
+                    " lines {}-{} of {} · PgUp/PgDn scroll · / search · q to return ",
+                    first,
+                    bottom,
+                    lines.len()
+                ))
+                .into_owned()
+            }
+        };
+        draw(&lines, top, page, &footer)?;
+        message = None;
+
+        let event = event::read()?;
+
+        if let Some(ref mut query) = search_input {
+            if let Event::Key(key) = event
+                && key.kind == KeyEventKind::Press
+            {
+                match key.code {
+                    KeyCode::Esc => search_input = None,
+                    KeyCode::Backspace => {
+                        query.pop();
+                    }
+                    KeyCode::Char(c) => query.push(c),
+                    KeyCode::Enter => {
+                        last_search = query.clone();
+                        search_input = None;
+                        match find_line(&lines, &last_search, top + 1, true) {
+                            Some(found) => top = found,
+                            None => message = Some(format!("Pattern not found: {}", last_search)),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        match event {
+            Event::Key(key) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                KeyCode::PageUp | KeyCode::Char('b') => top = top.saturating_sub(page),
+                KeyCode::PageDown | KeyCode::Char(' ') => {
+                    // Paging past the bottom returns to the prompt
+                    if top == max_top {
+                        break;
+                    }
+                    top += page;
+                }
+                KeyCode::Up | KeyCode::Char('k') => top = top.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => top += 1,
+                KeyCode::Home | KeyCode::Char('g') => top = 0,
+                KeyCode::End | KeyCode::Char('G') => top = max_top,
+                KeyCode::Char('/') => search_input = Some(String::new()),
+                KeyCode::Char('n') | KeyCode::Char('N') if !last_search.is_empty() => {
+                    let forward = key.code == KeyCode::Char('n');
+                    let start = if forward { top + 1 } else { top };
+                    match find_line(&lines, &last_search, start, forward) {
+                        Some(found) => top = found,
+                        None => message = Some(format!("Pattern not found: {}", last_search)),
+                    }
+                }
+                _ => {}
+            },
+            Event::Resize(new_width, new_height) => {
+                if new_width != width {
+                    lines = render_markdown_lines(markdown, new_width as usize);
+                }
+                (width, height) = (new_width, new_height);
+                page = height.saturating_sub(1).max(1) as usize;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+// Case-insensitive search over the visible text, forward from `start` or backward before it
+fn find_line(lines: &[String], query: &str, start: usize, forward: bool) -> Option<usize> {
+    let query = query.to_lowercase();
+    let matches = |i: &usize| strip_ansi(&lines[*i]).to_lowercase().contains(&query);
+
+    if forward {
+        (start..lines.len()).find(matches)
+    } else {
+        (0..start.min(lines.len())).rev().find(matches)
+    }
+}
+
+fn draw(lines: &[String], top: usize, page: usize, footer: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+
+    queue!(stdout, terminal::Clear(terminal::ClearType::All))?;
+
+    for (row, line) in lines.iter().skip(top).take(page).enumerate() {
+        queue!(stdout, cursor::MoveTo(0, row as u16), Print(line))?;
+    }
+
+    queue!(
+        stdout,
+        cursor::MoveTo(0, page as u16),
+        SetAttribute(Attribute::Reverse),
+        Print(footer),
+        SetAttribute(Attribute::Reset),
+    )?;
+
+    stdout.flush()
+}
@@ -0,0 +1,106 @@
+use crate::{config, hooks};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tracing::debug;
+
+// Speech input for `/voice`: the `voice.record` command captures audio to a
+// file until stopped, then `voice.transcribe` turns it into text. Both are
+// shell commands, so a local whisper.cpp build or a transcription API called
+// with curl work the same way.
+
+/// Where the recording is written, removed again after transcription
+pub fn audio_file() -> PathBuf {
+    std::env::temp_dir().join(format!("rye-voice-{}.wav", std::process::id()))
+}
+
+/// Runs the record command until `stop` completes
+pub async fn record(
+    audio_file: &Path,
+    stop: impl Future<Output = ()>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let command = &config::get().voice.record;
+    let mut child = hooks::shell(command)
+        .env("RYE_AUDIO_FILE", audio_file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Could not start `{}`: {}", command, e))?;
+
+    tokio::select! {
+        status = child.wait() => {
+            // The recorder quit on its own, usually because there's no input device
+            let output = child.wait_with_output().await?;
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("`{}` exited with {}: {}", command, status?, stderr.trim()).into());
+        }
+        _ = stop => {}
+    }
+
+    // Recorders finish the file's header when interrupted, a kill would leave
+    // it truncated
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGINT);
+        }
+    }
+    #[cfg(not(unix))]
+    child.start_kill()?;
+
+    let status = child.wait().await?;
+    debug!(%status, path = %audio_file.display(), "Recording stopped");
+    Ok(())
+}
+
+/// Runs the transcribe command on the recording and returns its output
+pub async fn transcribe(audio_file: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let command = config::get()
+        .voice
+        .transcribe
+        .as_deref()
+        .ok_or("Set voice.transcribe to a command that prints the text of $RYE_AUDIO_FILE")?;
+
+    let output = hooks::shell(command)
+        .env("RYE_AUDIO_FILE", audio_file)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "`{}` failed ({}): {}",
+            command,
+            output.status,
+            stderr.trim()
+        )
+        .into());
+    }
+
+    // whisper.cpp prints one line per segment
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Transcribes an existing audio file for `/audio` and returns a label that
+/// links to the file along with the transcript
+pub async fn transcribe_file(path: &str) -> Result<(String, String), Box<dyn std::error::Error>> {
+    // Absolute, so the link still works from the conversation file
+    let full_path = std::fs::canonicalize(path).map_err(|e| format!("{}: {}", path, e))?;
+    if !full_path.is_file() {
+        return Err(format!("{} isn't a file", path).into());
+    }
+    let text = transcribe(&full_path).await?;
+    if text.is_empty() {
+        return Err(format!("No speech found in {}", path).into());
+    }
+
+    let name = full_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string());
+    let label = format!("Transcript of [{}](<{}>)", name, full_path.display());
+    Ok((label, text))
+}